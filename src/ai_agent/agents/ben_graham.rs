@@ -0,0 +1,438 @@
+use anyhow::{Error, Context, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::graph::state::{AgentState, show_agent_reasoning, PartialAgentStateUpdate};
+use crate::ai_agent::llm::ensemble::{ensemble_models, ensemble_vote, reconcile_votes, EnsembleModel};
+use crate::ai_agent::llm::circuit_breaker::{self, CIRCUIT_OPEN};
+use crate::ai_agent::llm::models::{get_ready_model, is_dry_run, llm_timeout, supports_json_mode, SamplingParams, DEFAULT_AGENT_TEMPERATURE};
+use crate::ai_agent::llm::prompt::{PromptBuilder, SIGNAL_JSON_SCHEMA};
+use crate::ai_agent::llm::model_provider::{budget_allows, chat_with_timeout, ChatMessage, LLMModelConfig, ModelProvider, BUDGET_EXHAUSTED};
+use crate::ai_agent::error::AgentError;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::analysts::LineItemSet;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::ai_agent::data::models::{FinancialMetrics, LineItem, ReportPeriod};
+use crate::app::config::Config;
+
+// Graham only bought well below his own fair value estimate
+const GRAHAM_NUMBER_DISCOUNT: f64 = 0.67;
+
+pub const GRAHAM_LINE_ITEMS: LineItemSet = LineItemSet {
+  names: &["current_assets", "total_liabilities", "outstanding_shares"],
+  period: ReportPeriod::Annual,
+  limit: 1,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenGrahamSignal {
+  signal : Signal,
+  confidence: f64,
+  reasoning: String
+}
+
+impl BenGrahamSignal {
+  pub fn new() -> Self {
+    BenGrahamSignal { signal: Signal::Neutral, confidence: 0.0, reasoning: String::new() }
+  }
+
+  pub fn static_ben_graham_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let signal = BenGrahamSignal::new();
+      signal.ben_graham_agent(state, config).await
+    })
+  }
+
+  pub async fn ben_graham_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Deep-value analysis in the style of Benjamin Graham: earnings stability, balance sheet strength,
+    the Graham number and net current asset value
+     */
+
+    let api_client : API = API::new(config.clone());
+    let data : &HashMap<String, Value> = &state.data;
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let period : ReportPeriod = ReportPeriod::from_metadata(&state.metadata, GRAHAM_LINE_ITEMS.period);
+    let line_items : Vec<String> = GRAHAM_LINE_ITEMS.names_for("ben_graham", &state.metadata);
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let model_name: &str = match state.metadata.get("model_name").and_then(Value::as_str) {
+      Some(model_name) => model_name,
+      None => {
+        log::error!("Metadata missing a model_name key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let model_provider : &str = match state.metadata.get("model_provider").and_then(Value::as_str) {
+      Some(model_provider) => model_provider,
+      None => {
+        log::error!("Metadata missing a model_provider key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut graham_analysis: HashMap<String, HashMap<String, Value>> = HashMap::new();
+
+    let api_ref : &API = &api_client;
+    let line_items_ref : &Vec<String> = &line_items;
+    let fetched = fetch_per_ticker(&tickers, config.ticker_fetch_concurrency, |ticker| async move {
+      log::info!("ben_graham_agent {} Fetching financial metrics", ticker);
+      let metrics: Vec<FinancialMetrics> = api_ref.get_financial_metrics(&ticker, end_date, Some(period.as_str()), Some(10)).await?;
+
+      log::info!("ben_graham_agent {} Gathering financial line items", ticker);
+      let financial_line_items: Vec<LineItem> = api_ref.search_line_items(&ticker, line_items_ref.clone(), end_date, Some(period.as_str()), Some(GRAHAM_LINE_ITEMS.limit)).await?;
+      Ok::<_, AgentError>((metrics, financial_line_items))
+    }).await;
+
+    for (ticker, fetched) in fetched {
+      let ticker: &str = ticker.as_str();
+      let (metrics, financial_line_items) = fetched?;
+
+      log::info!("ben_graham_agent {} Analyzing earnings stability", ticker);
+      let stability_analysis = self.analyze_earnings_stability(&metrics);
+
+      log::info!("ben_graham_agent {} Analyzing financial strength", ticker);
+      let strength_analysis = self.analyze_financial_strength(&metrics);
+
+      log::info!("ben_graham_agent {} Analyzing valuation", ticker);
+      let valuation_analysis = self.analyze_valuation(&metrics, &financial_line_items);
+
+      let graham_output = match valuation_analysis.get("short_circuit").and_then(Value::as_str) {
+        // A Graham number cannot be computed from negative earnings or book value, there is nothing for the LLM to weigh
+        Some(reason) => BenGrahamSignal { signal: Signal::Neutral, confidence: 0.0, reasoning: reason.to_string() },
+        None => {
+          let mut analysis_data : HashMap<String, Value> = HashMap::new();
+          analysis_data.insert("earnings_stability".to_string(), serde_json::to_value(&stability_analysis)?);
+          analysis_data.insert("financial_strength".to_string(), serde_json::to_value(&strength_analysis)?);
+          analysis_data.insert("valuation".to_string(), serde_json::to_value(&valuation_analysis)?);
+
+          log::info!("[Ben Graham Agent] ({}) Generating final signal via LLM...", ticker);
+          let mut output = self.generate_graham_output(ticker, &analysis_data, model_name, model_provider, &config, SamplingParams::from_metadata(&state.metadata, DEFAULT_AGENT_TEMPERATURE), llm_timeout(&state.metadata, &config), is_dry_run(&state.metadata), &ensemble_models(&state.metadata)).await?;
+
+          let below_discount = valuation_analysis.get("below_graham_discount").and_then(Value::as_bool).unwrap_or(false);
+          if output.signal == Signal::Bullish && !below_discount {
+            output.signal = Signal::Neutral;
+            output.reasoning = format!("{} Downgraded to neutral: price is not below {:.0}% of the Graham number.", output.reasoning, GRAHAM_NUMBER_DISCOUNT * 100.0);
+          }
+          output
+        }
+      };
+
+      let mut final_result : HashMap<String, Value> = HashMap::new();
+      final_result.insert("signal".to_string(), Value::from(graham_output.signal.to_string()));
+      final_result.insert("confidence".to_string(), Value::from(graham_output.confidence));
+      final_result.insert("reasoning".to_string(), Value::from(graham_output.reasoning));
+      graham_analysis.insert(ticker.to_string(), final_result);
+    }
+
+    let message_content = serde_json::to_string(&graham_analysis).context("Failed to serialize Ben Graham signals for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Ben Graham Agent");
+    }
+
+    let message = ChatMessage::from_agent("ben_graham_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("ben_graham_agent".to_string(), serde_json::to_value(graham_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+
+    log::info!("[Ben Graham Agent] Analysis complete. Returning state update.");
+    return Ok(PartialAgentStateUpdate::new().with_messages(vec![message]).with_data(updated_data_map));
+  }
+
+  pub fn analyze_earnings_stability(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* Graham wanted positive earnings in every one of many years, ideally growing over the period */
+
+    let eps_values : Vec<f64> = metrics.iter().filter_map(|m| m.earnings_per_share).collect();
+    if eps_values.len() < 2 {
+      return HashMap::from([
+        ("score".to_string(), Value::from(0)),
+        ("max_score".to_string(), Value::from(4)),
+        ("details".to_string(), Value::from("Insufficient EPS history for stability analysis")),
+      ]);
+    }
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    let positive_years = eps_values.iter().filter(|eps| **eps > 0.0).count();
+    if positive_years == eps_values.len() {
+      score += 3;
+      reasoning.push(format!("EPS positive in all {} periods", eps_values.len()));
+    } else if positive_years as f64 >= 0.8 * eps_values.len() as f64 {
+      score += 2;
+      reasoning.push(format!("EPS positive in {} of {} periods", positive_years, eps_values.len()));
+    } else {
+      reasoning.push(format!("EPS negative in {} of {} periods", eps_values.len() - positive_years, eps_values.len()));
+    }
+
+    // Metrics come newest first
+    let latest = eps_values[0];
+    let oldest = eps_values[eps_values.len() - 1];
+    if latest > oldest {
+      score += 1;
+      reasoning.push(format!("EPS grew from {:.2} to {:.2}", oldest, latest));
+    } else {
+      reasoning.push(format!("EPS did not grow ({:.2} to {:.2})", oldest, latest));
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(4)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_financial_strength(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* Current ratio of at least 2 and modest leverage */
+
+    let latest = match metrics.first() {
+      Some(latest) => latest,
+      None => {
+        return HashMap::from([
+          ("score".to_string(), Value::from(0)),
+          ("max_score".to_string(), Value::from(4)),
+          ("details".to_string(), Value::from("No data for financial strength analysis")),
+        ]);
+      }
+    };
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    match latest.current_ratio {
+      Some(cr) if cr >= 2.0 => {
+        score += 2;
+        reasoning.push(format!("Current ratio of {:.2} meets Graham's 2.0 minimum", cr));
+      }
+      Some(cr) if cr >= 1.5 => {
+        score += 1;
+        reasoning.push(format!("Current ratio of {:.2} is acceptable but below 2.0", cr));
+      }
+      Some(cr) => reasoning.push(format!("Weak liquidity with current ratio of {:.2}", cr)),
+      None => reasoning.push("Current ratio data not available".to_string()),
+    }
+
+    match latest.debt_to_equity {
+      Some(de) if de < 0.5 => {
+        score += 2;
+        reasoning.push(format!("Conservative debt-to-equity of {:.2}", de));
+      }
+      Some(de) if de < 1.0 => {
+        score += 1;
+        reasoning.push(format!("Moderate debt-to-equity of {:.2}", de));
+      }
+      Some(de) => reasoning.push(format!("High debt-to-equity of {:.2}", de)),
+      None => reasoning.push("Debt-to-equity data not available".to_string()),
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(4)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_valuation(&self, metrics: &[FinancialMetrics], financial_line_items: &[LineItem]) -> HashMap<String, Value> {
+    /* Graham number = sqrt(22.5 * EPS * BVPS), compared against the share price, plus the net-net check
+    (net current asset value above market cap)
+     */
+
+    let latest = match metrics.first() {
+      Some(latest) => latest,
+      None => return HashMap::from([("details".to_string(), Value::from("No data for valuation analysis"))]),
+    };
+
+    let mut result : HashMap<String, Value> = HashMap::new();
+    let mut reasoning : Vec<String> = Vec::new();
+
+    // Net-net: current assets less all liabilities against the whole market cap
+    if let Some(item) = financial_line_items.first() {
+      let current_assets = item.extra.get("current_assets").and_then(Value::as_f64);
+      let total_liabilities = item.extra.get("total_liabilities").and_then(Value::as_f64);
+      if let (Some(current_assets), Some(total_liabilities), Some(market_cap)) = (current_assets, total_liabilities, latest.market_cap) {
+        let ncav = current_assets - total_liabilities;
+        result.insert("net_current_asset_value".to_string(), Value::from(ncav));
+        if ncav > market_cap {
+          reasoning.push("Net-net: net current asset value exceeds market cap".to_string());
+        } else {
+          reasoning.push(format!("Net current asset value covers {:.0}% of market cap", ncav / market_cap * 100.0));
+        }
+      }
+    }
+
+    let (eps, bvps) = match (latest.earnings_per_share, latest.book_value_per_share) {
+      (Some(eps), Some(bvps)) => (eps, bvps),
+      _ => {
+        reasoning.push("EPS or book value per share not available, cannot compute the Graham number".to_string());
+        result.insert("details".to_string(), Value::from(reasoning.join("; ")));
+        return result;
+      }
+    };
+
+    if eps <= 0.0 || bvps <= 0.0 {
+      let reason = format!("Graham number undefined with EPS of {:.2} and book value per share of {:.2}, defaulting to neutral", eps, bvps);
+      result.insert("short_circuit".to_string(), Value::from(reason.clone()));
+      reasoning.push(reason);
+      result.insert("details".to_string(), Value::from(reasoning.join("; ")));
+      return result;
+    }
+
+    let graham_number = (22.5 * eps * bvps).sqrt();
+    result.insert("graham_number".to_string(), Value::from(graham_number));
+
+    // Price recovered from the valuation ratios reported alongside EPS and book value
+    let price = latest.price_to_book_ratio.map(|pb| pb * bvps).or_else(|| latest.price_to_earnings_ratio.map(|pe| pe * eps));
+    match price {
+      Some(price) if price > 0.0 => {
+        let below_discount = price < GRAHAM_NUMBER_DISCOUNT * graham_number;
+        result.insert("price".to_string(), Value::from(price));
+        result.insert("margin_of_safety".to_string(), Value::from((graham_number - price) / price));
+        result.insert("below_graham_discount".to_string(), Value::from(below_discount));
+        reasoning.push(format!("Price {:.2} against Graham number {:.2} ({:.0}% of it)", price, graham_number, price / graham_number * 100.0));
+      }
+      _ => reasoning.push("Share price not available to compare with the Graham number".to_string()),
+    }
+
+    result.insert("details".to_string(), Value::from(reasoning.join("; ")));
+    return result;
+  }
+
+  #[tracing::instrument(skip_all, fields(ticker = %ticker, model = %model_name))]
+  pub async fn generate_graham_output(&self, ticker: &str, analysis_data: &HashMap<String, Value>, model_name: &str, model_provider: &str, config: &Config, sampling: SamplingParams, timeout: Duration, dry_run: bool, ensemble: &[EnsembleModel]) -> Result<BenGrahamSignal, Error> {
+
+    if dry_run {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, "Dry run"));
+    }
+    // An ensemble makes one call per model
+    if !budget_allows(sampling.max_tokens as u64 * ensemble.len().max(1) as u64) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, BUDGET_EXHAUSTED));
+    }
+
+    let analysis_data_json = serde_json::to_string_pretty(analysis_data).context("Failed to serialize analysis data for LLM prompt")?;
+
+    let system_prompt : &str = r#"You are a Benjamin Graham AI agent, making investment decisions using his principles:
+                                  - Insist on a margin of safety by buying below intrinsic value (e.g., the Graham number or net-net)
+                                  - Emphasize financial strength: low leverage and ample current assets
+                                  - Prefer stable earnings over multiple years
+                                  - Avoid speculative or high-growth assumptions, focus on proven metrics
+
+                                  When providing your reasoning, be thorough and specific by:
+                                  1. Explaining the key valuation metrics that influenced your decision (Graham number, NCAV, P/E)
+                                  2. Highlighting the specific financial strength indicators (current ratio, debt levels)
+                                  3. Referencing the stability or instability of earnings over time
+                                  4. Comparing to Graham's specific thresholds (e.g., "Current ratio of 2.5 exceeds Graham's minimum of 2.0")
+                                  5. Using Benjamin Graham's conservative, analytical voice
+
+                                  Follow these guidelines strictly."#;
+
+    let json_mode : bool = supports_json_mode(model_name);
+
+    let build_messages = |json_mode: bool| PromptBuilder::new()
+      .with_system(system_prompt)
+      .with_instruction("Based on the following data, create the investment signal as Benjamin Graham would:")
+      .with_section(&format!("Analysis Data for {}", ticker), &analysis_data_json)
+      .with_json_schema(SIGNAL_JSON_SCHEMA)
+      .with_json_mode(json_mode)
+      .build();
+    let messages = build_messages(json_mode);
+
+    let provider = ModelProvider::from_str(model_provider).map_err(|_| anyhow!("Unknown model provider: {}", model_provider))?;
+
+    let config_for_call : LLMModelConfig = sampling.to_config(provider, model_name, json_mode);
+
+    // Each ensemble model gets its own json mode, so the prompt always carries the JSON instructions
+    if ensemble.len() > 1 {
+      log::info!("[Ben Graham Agent] ({}) Calling {} ensemble models for Graham analysis...", ticker, ensemble.len());
+      let votes = ensemble_vote(ensemble, build_messages(false), &config_for_call, config, timeout).await;
+      return Ok(match reconcile_votes(&votes) {
+        Some(vote) => BenGrahamSignal { signal: vote.signal, confidence: vote.confidence, reasoning: vote.reasoning },
+        None => BenGrahamSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Every ensemble model failed for ticker {}. Defaulting to neutral.", ticker),
+        },
+      });
+    }
+
+    if circuit_breaker::is_open(&config_for_call) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, CIRCUIT_OPEN));
+    }
+    let client = get_ready_model(&config_for_call, config)?;
+
+    log::info!("[Ben Graham Agent] ({}) Calling LLM for Graham analysis...", ticker);
+
+    let response = match chat_with_timeout(client.as_ref(), messages, &config_for_call, timeout).await? {
+      Some(response) => response,
+      None => return Ok(BenGrahamSignal {
+        signal: Signal::Neutral,
+        confidence: 0.0,
+        reasoning: format!("LLM call for ticker {} timed out after {:.1}s. Defaulting to neutral.", ticker, timeout.as_secs_f64()),
+      }),
+    };
+
+    log::debug!("[Ben Graham Agent] ({}) LLM raw response: {}", ticker, response.content);
+
+    match serde_json::from_str::<BenGrahamSignal>(&response.content) {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::error!("[Ben Graham Agent] ({}) Failed to parse LLM JSON response into BenGrahamSignal: {}. Raw response: '{}'", ticker, err, response.content);
+        if response.is_truncated() {
+          log::warn!("[Ben Graham Agent] ({}) LLM response was cut off at max_tokens, raise max_tokens if this keeps happening", ticker);
+        }
+        Ok(BenGrahamSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Error in LLM analysis or response parsing for ticker {}: {}. Defaulting to neutral.", ticker, err),
+        })
+      }
+    }
+  }
+
+  // Earnings stability and financial strength scores mapped onto the same 70%/30% thresholds as the Buffett agent,
+  // a bullish call still needs the price below the Graham number discount
+  fn dry_run_signal(ticker: &str, analysis_data: &HashMap<String, Value>, reason: &str) -> BenGrahamSignal {
+    let area_score = |key: &str, field: &str| analysis_data.get(key).and_then(|area| area.get(field)).and_then(Value::as_f64).unwrap_or(0.0);
+    let score = area_score("earnings_stability", "score") + area_score("financial_strength", "score");
+    let max_score = area_score("earnings_stability", "max_score") + area_score("financial_strength", "max_score");
+    let below_discount = analysis_data.get("valuation").and_then(|valuation| valuation.get("below_graham_discount")).and_then(Value::as_bool).unwrap_or(false);
+
+    let ratio = if max_score > 0.0 { score / max_score } else { 0.0 };
+    let signal = if ratio >= 0.7 && below_discount {
+      Signal::Bullish
+    } else if ratio <= 0.3 {
+      Signal::Bearish
+    } else {
+      Signal::Neutral
+    };
+
+    log::info!("[Ben Graham Agent] ({}) {}, skipping the LLM call", ticker, reason);
+    BenGrahamSignal {
+      signal,
+      confidence: (ratio * 100.0).round(),
+      reasoning: format!("{}: signal derived from a score of {} out of {} without calling the LLM.", reason, score, max_score),
+    }
+  }
+}
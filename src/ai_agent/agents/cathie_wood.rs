@@ -0,0 +1,423 @@
+use anyhow::{Error, Context, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::graph::state::{AgentState, show_agent_reasoning, PartialAgentStateUpdate};
+use crate::ai_agent::llm::ensemble::{ensemble_models, ensemble_vote, reconcile_votes, EnsembleModel};
+use crate::ai_agent::llm::circuit_breaker::{self, CIRCUIT_OPEN};
+use crate::ai_agent::llm::models::{get_ready_model, is_dry_run, llm_timeout, supports_json_mode, SamplingParams, DEFAULT_AGENT_TEMPERATURE};
+use crate::ai_agent::llm::prompt::{PromptBuilder, SIGNAL_JSON_SCHEMA};
+use crate::ai_agent::llm::model_provider::{budget_allows, chat_with_timeout, ChatMessage, LLMModelConfig, ModelProvider, BUDGET_EXHAUSTED};
+use crate::ai_agent::error::AgentError;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::analysts::LineItemSet;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::ai_agent::data::models::{FinancialMetrics, LineItem, ReportPeriod};
+use crate::app::config::Config;
+
+const HISTORY_LIMIT: i64 = 5;
+
+pub const WOOD_LINE_ITEMS: LineItemSet = LineItemSet {
+  names: &["revenue", "research_and_development", "capital_expenditure", "dividends_and_other_cash_distributions"],
+  period: ReportPeriod::Annual,
+  limit: HISTORY_LIMIT,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CathieWoodSignal {
+  signal : Signal,
+  confidence: f64,
+  reasoning: String
+}
+
+impl CathieWoodSignal {
+  pub fn new() -> Self {
+    CathieWoodSignal { signal: Signal::Neutral, confidence: 0.0, reasoning: String::new() }
+  }
+
+  pub fn static_cathie_wood_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let signal = CathieWoodSignal::new();
+      signal.cathie_wood_agent(state, config).await
+    })
+  }
+
+  pub async fn cathie_wood_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Growth and disruption analysis in the style of Cathie Wood: revenue growth, R&D intensity,
+    gross margin expansion and reinvestment. Negative earnings are expected of companies in this phase and are not scored
+     */
+
+    let api_client : API = API::new(config.clone());
+    let data : &HashMap<String, Value> = &state.data;
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let period : ReportPeriod = ReportPeriod::from_metadata(&state.metadata, WOOD_LINE_ITEMS.period);
+    let line_items : Vec<String> = WOOD_LINE_ITEMS.names_for("cathie_wood", &state.metadata);
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let model_name: &str = match state.metadata.get("model_name").and_then(Value::as_str) {
+      Some(model_name) => model_name,
+      None => {
+        log::error!("Metadata missing a model_name key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let model_provider : &str = match state.metadata.get("model_provider").and_then(Value::as_str) {
+      Some(model_provider) => model_provider,
+      None => {
+        log::error!("Metadata missing a model_provider key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut wood_analysis: HashMap<String, HashMap<String, Value>> = HashMap::new();
+
+    let api_ref : &API = &api_client;
+    let line_items_ref : &Vec<String> = &line_items;
+    let fetched = fetch_per_ticker(&tickers, config.ticker_fetch_concurrency, |ticker| async move {
+      log::info!("cathie_wood_agent {} Fetching financial metrics", ticker);
+      let metrics: Vec<FinancialMetrics> = api_ref.get_financial_metrics(&ticker, end_date, Some(period.as_str()), Some(HISTORY_LIMIT)).await?;
+
+      log::info!("cathie_wood_agent {} Gathering financial line items", ticker);
+      let financial_line_items: Vec<LineItem> = api_ref.search_line_items(&ticker, line_items_ref.clone(), end_date, Some(period.as_str()), Some(WOOD_LINE_ITEMS.limit)).await?;
+      Ok::<_, AgentError>((metrics, financial_line_items))
+    }).await;
+
+    for (ticker, fetched) in fetched {
+      let ticker: &str = ticker.as_str();
+      let (metrics, financial_line_items) = fetched?;
+
+      log::info!("cathie_wood_agent {} Analyzing revenue growth", ticker);
+      let growth_analysis = self.analyze_growth(&metrics);
+
+      log::info!("cathie_wood_agent {} Analyzing innovation", ticker);
+      let innovation_analysis = self.analyze_innovation(&metrics, &financial_line_items);
+
+      log::info!("cathie_wood_agent {} Analyzing reinvestment", ticker);
+      let reinvestment_analysis = self.analyze_reinvestment(&metrics, &financial_line_items);
+
+      let mut analysis_data : HashMap<String, Value> = HashMap::new();
+      analysis_data.insert("revenue_growth".to_string(), serde_json::to_value(&growth_analysis)?);
+      analysis_data.insert("innovation".to_string(), serde_json::to_value(&innovation_analysis)?);
+      analysis_data.insert("reinvestment".to_string(), serde_json::to_value(&reinvestment_analysis)?);
+
+      log::info!("[Cathie Wood Agent] ({}) Generating final signal via LLM...", ticker);
+      let wood_output = self.generate_wood_output(ticker, &analysis_data, model_name, model_provider, &config, SamplingParams::from_metadata(&state.metadata, DEFAULT_AGENT_TEMPERATURE), llm_timeout(&state.metadata, &config), is_dry_run(&state.metadata), &ensemble_models(&state.metadata)).await?;
+
+      let mut final_result : HashMap<String, Value> = HashMap::new();
+      final_result.insert("signal".to_string(), Value::from(wood_output.signal.to_string()));
+      final_result.insert("confidence".to_string(), Value::from(wood_output.confidence));
+      final_result.insert("reasoning".to_string(), Value::from(wood_output.reasoning));
+      wood_analysis.insert(ticker.to_string(), final_result);
+    }
+
+    let message_content = serde_json::to_string(&wood_analysis).context("Failed to serialize Cathie Wood signals for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Cathie Wood Agent");
+    }
+
+    let message = ChatMessage::from_agent("cathie_wood_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("cathie_wood_agent".to_string(), serde_json::to_value(wood_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+
+    log::info!("[Cathie Wood Agent] Analysis complete. Returning state update.");
+    return Ok(PartialAgentStateUpdate::new().with_messages(vec![message]).with_data(updated_data_map));
+  }
+
+  pub fn analyze_growth(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* Rewards fast, accelerating and sustained top-line growth, earnings are deliberately left out */
+
+    // Metrics come newest first
+    let growth_rates : Vec<f64> = metrics.iter().filter_map(|m| m.revenue_growth).collect();
+    let latest = match growth_rates.first() {
+      Some(latest) => *latest,
+      None => {
+        return HashMap::from([
+          ("score".to_string(), Value::from(0)),
+          ("max_score".to_string(), Value::from(4)),
+          ("details".to_string(), Value::from("No revenue growth data available")),
+        ]);
+      }
+    };
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    if latest > 0.25 {
+      score += 2;
+      reasoning.push(format!("Exceptional revenue growth of {:.0}%", latest * 100.0));
+    } else if latest > 0.10 {
+      score += 1;
+      reasoning.push(format!("Solid revenue growth of {:.0}%", latest * 100.0));
+    } else {
+      reasoning.push(format!("Revenue growth of {:.0}% is too slow for a disruptor", latest * 100.0));
+    }
+
+    if let Some(previous) = growth_rates.get(1) {
+      if latest > *previous {
+        score += 1;
+        reasoning.push(format!("Growth accelerating from {:.0}% to {:.0}%", previous * 100.0, latest * 100.0));
+      } else {
+        reasoning.push(format!("Growth decelerating from {:.0}% to {:.0}%", previous * 100.0, latest * 100.0));
+      }
+    }
+
+    if growth_rates.len() > 1 && growth_rates.iter().all(|growth| *growth > 0.0) {
+      score += 1;
+      reasoning.push(format!("Revenue grew in all {} periods", growth_rates.len()));
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(4)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_innovation(&self, metrics: &[FinancialMetrics], financial_line_items: &[LineItem]) -> HashMap<String, Value> {
+    /* R&D intensity and gross margin expansion. Data that is not reported is left out of max_score rather than scored as zero */
+
+    let mut score : i64 = 0;
+    let mut max_score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    let latest_item = financial_line_items.first();
+    let research = latest_item.and_then(|item| item.extra.get("research_and_development")).and_then(Value::as_f64);
+    let revenue = latest_item.and_then(|item| item.extra.get("revenue")).and_then(Value::as_f64);
+    match (research, revenue) {
+      (Some(research), Some(revenue)) if revenue > 0.0 => {
+        max_score += 2;
+        let intensity = research.abs() / revenue;
+        if intensity > 0.15 {
+          score += 2;
+          reasoning.push(format!("High R&D intensity of {:.1}% of revenue", intensity * 100.0));
+        } else if intensity > 0.08 {
+          score += 1;
+          reasoning.push(format!("Moderate R&D intensity of {:.1}% of revenue", intensity * 100.0));
+        } else {
+          reasoning.push(format!("Low R&D intensity of {:.1}% of revenue", intensity * 100.0));
+        }
+      }
+      _ => reasoning.push("R&D spending not reported".to_string()),
+    }
+
+    let gross_margins : Vec<f64> = metrics.iter().filter_map(|m| m.gross_margin).collect();
+    if gross_margins.len() >= 2 {
+      max_score += 2;
+      let latest = gross_margins[0];
+      let oldest = gross_margins[gross_margins.len() - 1];
+      if latest - oldest >= 0.02 {
+        score += 2;
+        reasoning.push(format!("Gross margin expanded from {:.1}% to {:.1}%", oldest * 100.0, latest * 100.0));
+      } else if latest > oldest {
+        score += 1;
+        reasoning.push(format!("Gross margin edged up from {:.1}% to {:.1}%", oldest * 100.0, latest * 100.0));
+      } else {
+        reasoning.push(format!("Gross margin contracted from {:.1}% to {:.1}%", oldest * 100.0, latest * 100.0));
+      }
+    } else {
+      reasoning.push("Insufficient gross margin history".to_string());
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(max_score)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_reinvestment(&self, metrics: &[FinancialMetrics], financial_line_items: &[LineItem]) -> HashMap<String, Value> {
+    /* Cash going back into the business rather than out as dividends, the inverse of what the value agents reward */
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    let latest_item = financial_line_items.first();
+    let dividends = latest_item.and_then(|item| item.extra.get("dividends_and_other_cash_distributions")).and_then(Value::as_f64);
+    let payout_ratio = metrics.first().and_then(|m| m.payout_ratio);
+    match (dividends, payout_ratio) {
+      (_, Some(payout)) if payout > 0.0 && payout < 0.2 => {
+        score += 1;
+        reasoning.push(format!("Small payout ratio of {:.0}%", payout * 100.0));
+      }
+      (_, Some(payout)) if payout >= 0.2 => reasoning.push(format!("Payout ratio of {:.0}% returns cash that could fund growth", payout * 100.0)),
+      (Some(dividends), _) if dividends.abs() > 0.0 => {
+        score += 1;
+        reasoning.push("Pays some dividends".to_string());
+      }
+      _ => {
+        score += 2;
+        reasoning.push("No dividends, all cash is retained for growth".to_string());
+      }
+    }
+
+    let capex = latest_item.and_then(|item| item.extra.get("capital_expenditure")).and_then(Value::as_f64);
+    let revenue = latest_item.and_then(|item| item.extra.get("revenue")).and_then(Value::as_f64);
+    match (capex, revenue) {
+      (Some(capex), Some(revenue)) if revenue > 0.0 => {
+        let intensity = capex.abs() / revenue;
+        if intensity > 0.10 {
+          score += 2;
+          reasoning.push(format!("Heavy reinvestment with capex at {:.1}% of revenue", intensity * 100.0));
+        } else if intensity > 0.05 {
+          score += 1;
+          reasoning.push(format!("Capex at {:.1}% of revenue", intensity * 100.0));
+        } else {
+          reasoning.push(format!("Light capex at {:.1}% of revenue", intensity * 100.0));
+        }
+      }
+      _ => reasoning.push("Capital expenditure data not available".to_string()),
+    }
+
+    if let Some(net_margin) = metrics.first().and_then(|m| m.net_margin) {
+      if net_margin < 0.0 {
+        reasoning.push(format!("Net margin of {:.1}% is treated as investment in growth, not penalized", net_margin * 100.0));
+      }
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(4)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  #[tracing::instrument(skip_all, fields(ticker = %ticker, model = %model_name))]
+  pub async fn generate_wood_output(&self, ticker: &str, analysis_data: &HashMap<String, Value>, model_name: &str, model_provider: &str, config: &Config, sampling: SamplingParams, timeout: Duration, dry_run: bool, ensemble: &[EnsembleModel]) -> Result<CathieWoodSignal, Error> {
+
+    if dry_run {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, "Dry run"));
+    }
+    // An ensemble makes one call per model
+    if !budget_allows(sampling.max_tokens as u64 * ensemble.len().max(1) as u64) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, BUDGET_EXHAUSTED));
+    }
+
+    let analysis_data_json = serde_json::to_string_pretty(analysis_data).context("Failed to serialize analysis data for LLM prompt")?;
+
+    let system_prompt : &str = r#"You are a Cathie Wood AI agent, making investment decisions using her principles:
+                                  - Seek companies positioned to benefit from disruptive innovation
+                                  - Favor exponential revenue growth and large total addressable markets
+                                  - Reward heavy reinvestment in R&D and capacity over dividends and buybacks
+                                  - Accept near-term losses when they fund long-term growth
+                                  - Take a five-year-plus horizon and tolerate short-term volatility
+
+                                  When providing your reasoning, be thorough and specific by:
+                                  1. Identifying the disruptive technology or innovation the company is exposed to
+                                  2. Highlighting the revenue growth rate and whether it is accelerating
+                                  3. Discussing R&D intensity and gross margin trends as signs of a scaling platform
+                                  4. Explaining how reinvestment positions the company for exponential growth
+                                  5. Using Cathie Wood's optimistic, future-focused and conviction-driven voice
+
+                                  Follow these guidelines strictly."#;
+
+    let json_mode : bool = supports_json_mode(model_name);
+
+    let build_messages = |json_mode: bool| PromptBuilder::new()
+      .with_system(system_prompt)
+      .with_instruction("Based on the following data, create the investment signal as Cathie Wood would:")
+      .with_section(&format!("Analysis Data for {}", ticker), &analysis_data_json)
+      .with_json_schema(SIGNAL_JSON_SCHEMA)
+      .with_json_mode(json_mode)
+      .build();
+    let messages = build_messages(json_mode);
+
+    let provider = ModelProvider::from_str(model_provider).map_err(|_| anyhow!("Unknown model provider: {}", model_provider))?;
+
+    let config_for_call : LLMModelConfig = sampling.to_config(provider, model_name, json_mode);
+
+    if ensemble.len() > 1 {
+      log::info!("[Cathie Wood Agent] ({}) Calling {} ensemble models for Wood analysis...", ticker, ensemble.len());
+      let votes = ensemble_vote(ensemble, build_messages(false), &config_for_call, config, timeout).await;
+      return Ok(match reconcile_votes(&votes) {
+        Some(vote) => CathieWoodSignal { signal: vote.signal, confidence: vote.confidence, reasoning: vote.reasoning },
+        None => CathieWoodSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Every ensemble model failed for ticker {}. Defaulting to neutral.", ticker),
+        },
+      });
+    }
+
+    if circuit_breaker::is_open(&config_for_call) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, CIRCUIT_OPEN));
+    }
+    let client = get_ready_model(&config_for_call, config)?;
+
+    log::info!("[Cathie Wood Agent] ({}) Calling LLM for Wood analysis...", ticker);
+
+    let response = match chat_with_timeout(client.as_ref(), messages, &config_for_call, timeout).await? {
+      Some(response) => response,
+      None => return Ok(CathieWoodSignal {
+        signal: Signal::Neutral,
+        confidence: 0.0,
+        reasoning: format!("LLM call for ticker {} timed out after {:.1}s. Defaulting to neutral.", ticker, timeout.as_secs_f64()),
+      }),
+    };
+
+    log::debug!("[Cathie Wood Agent] ({}) LLM raw response: {}", ticker, response.content);
+
+    match serde_json::from_str::<CathieWoodSignal>(&response.content) {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::error!("[Cathie Wood Agent] ({}) Failed to parse LLM JSON response into CathieWoodSignal: {}. Raw response: '{}'", ticker, err, response.content);
+        if response.is_truncated() {
+          log::warn!("[Cathie Wood Agent] ({}) LLM response was cut off at max_tokens, raise max_tokens if this keeps happening", ticker);
+        }
+        Ok(CathieWoodSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Error in LLM analysis or response parsing for ticker {}: {}. Defaulting to neutral.", ticker, err),
+        })
+      }
+    }
+  }
+
+  // Growth, innovation and reinvestment scores mapped onto the same 70%/30% thresholds as the value agents
+  fn dry_run_signal(ticker: &str, analysis_data: &HashMap<String, Value>, reason: &str) -> CathieWoodSignal {
+    let area_score = |key: &str, field: &str| analysis_data.get(key).and_then(|area| area.get(field)).and_then(Value::as_f64).unwrap_or(0.0);
+    let areas = ["revenue_growth", "innovation", "reinvestment"];
+    let score : f64 = areas.iter().map(|area| area_score(area, "score")).sum();
+    let max_score : f64 = areas.iter().map(|area| area_score(area, "max_score")).sum();
+
+    let ratio = if max_score > 0.0 { score / max_score } else { 0.0 };
+    let signal = if ratio >= 0.7 {
+      Signal::Bullish
+    } else if ratio <= 0.3 {
+      Signal::Bearish
+    } else {
+      Signal::Neutral
+    };
+
+    log::info!("[Cathie Wood Agent] ({}) {}, skipping the LLM call", ticker, reason);
+    CathieWoodSignal {
+      signal,
+      confidence: (ratio * 100.0).round(),
+      reasoning: format!("{}: signal derived from a score of {} out of {} without calling the LLM.", reason, score, max_score),
+    }
+  }
+}
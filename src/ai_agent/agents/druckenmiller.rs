@@ -0,0 +1,497 @@
+use anyhow::{Error, Context, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use polars::prelude::DataFrame;
+
+use crate::ai_agent::agents::sentiment_analyst::SentimentAnalystAgent;
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::graph::state::{AgentState, show_agent_reasoning, PartialAgentStateUpdate};
+use crate::ai_agent::llm::ensemble::{ensemble_models, ensemble_vote, reconcile_votes, EnsembleModel};
+use crate::ai_agent::llm::circuit_breaker::{self, CIRCUIT_OPEN};
+use crate::ai_agent::llm::json::{extract_json, repair_json};
+use crate::ai_agent::llm::models::{get_ready_model, is_dry_run, llm_timeout, supports_json_mode, SamplingParams, DEFAULT_AGENT_TEMPERATURE};
+use crate::ai_agent::llm::prompt::{PromptBuilder, SIGNAL_JSON_SCHEMA};
+use crate::ai_agent::llm::model_provider::{budget_allows, chat_with_timeout, ChatMessage, LLMModelConfig, ModelProvider, BUDGET_EXHAUSTED};
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::ai_agent::data::models::{CompanyNews, FinancialMetrics, Price, ReportPeriod};
+use crate::app::config::Config;
+
+// Two years of quarters, enough to tell an inflection from one noisy print
+const QUARTERS_LIMIT: i64 = 8;
+const COMPANY_NEWS_LIMIT: i64 = 50;
+// Lookbacks in trading days: one, three and six months
+const MOMENTUM_WINDOWS: [usize; 3] = [21, 63, 126];
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StanleyDruckenmillerSignal {
+  signal : Signal,
+  confidence: f64,
+  reasoning: String
+}
+
+impl StanleyDruckenmillerSignal {
+  pub fn new() -> Self {
+    StanleyDruckenmillerSignal { signal: Signal::Neutral, confidence: 0.0, reasoning: String::new() }
+  }
+
+  pub fn static_stanley_druckenmiller_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let signal = StanleyDruckenmillerSignal::new();
+      signal.stanley_druckenmiller_agent(state, config).await
+    })
+  }
+
+  pub async fn stanley_druckenmiller_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Momentum analysis in the style of Stanley Druckenmiller: recent price trend, accelerating quarterly growth and the news flow,
+    sized by how asymmetric the upside looks against the downside. Each data source is optional, a ticker with only prices
+    or only fundamentals is scored on what it has
+     */
+
+    let api_client : API = API::new(config.clone());
+    let data : &HashMap<String, Value> = &state.data;
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let start_date: Option<&str> = data.get("start_date").and_then(Value::as_str);
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let model_name: &str = match state.metadata.get("model_name").and_then(Value::as_str) {
+      Some(model_name) => model_name,
+      None => {
+        log::error!("Metadata missing a model_name key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let model_provider : &str = match state.metadata.get("model_provider").and_then(Value::as_str) {
+      Some(model_provider) => model_provider,
+      None => {
+        log::error!("Metadata missing a model_provider key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut druckenmiller_analysis: HashMap<String, HashMap<String, Value>> = HashMap::new();
+
+    // A failed source is logged and left empty rather than failing the ticker
+    let api_ref : &API = &api_client;
+    let fetched = fetch_per_ticker(&tickers, config.ticker_fetch_concurrency, |ticker| async move {
+      log::info!("stanley_druckenmiller_agent {} Fetching price data", ticker);
+      let prices: Vec<Price> = match start_date {
+        Some(start_date) => api_ref.get_price(&ticker, start_date, end_date).await.unwrap_or_else(|e| {
+          log::warn!("stanley_druckenmiller_agent {} Price data unavailable: {}", ticker, e);
+          Vec::new()
+        }),
+        None => {
+          log::warn!("stanley_druckenmiller_agent {} No start date, skipping price data", ticker);
+          Vec::new()
+        }
+      };
+
+      log::info!("stanley_druckenmiller_agent {} Fetching quarterly financial metrics", ticker);
+      let metrics: Vec<FinancialMetrics> = api_ref.get_financial_metrics(&ticker, end_date, Some(ReportPeriod::Quarterly.as_str()), Some(QUARTERS_LIMIT)).await.unwrap_or_else(|e| {
+        log::warn!("stanley_druckenmiller_agent {} Financial metrics unavailable: {}", ticker, e);
+        Vec::new()
+      });
+
+      log::info!("stanley_druckenmiller_agent {} Fetching company news", ticker);
+      let company_news: Vec<CompanyNews> = api_ref.get_company_news(&ticker, end_date, start_date, COMPANY_NEWS_LIMIT).await.unwrap_or_else(|e| {
+        log::warn!("stanley_druckenmiller_agent {} Company news unavailable: {}", ticker, e);
+        Vec::new()
+      });
+      (prices, metrics, company_news)
+    }).await;
+
+    for (ticker, (prices, metrics, company_news)) in fetched {
+      let ticker: &str = ticker.as_str();
+
+      let closes : Vec<f64> = if prices.is_empty() {
+        Vec::new()
+      } else {
+        match api_client.prices_to_df(prices).map_err(Error::from).and_then(|prices_df| Self::closes_from_df(&prices_df)) {
+          Ok(closes) => closes,
+          Err(e) => {
+            log::warn!("stanley_druckenmiller_agent {} Failed to read closes from price data: {}", ticker, e);
+            Vec::new()
+          }
+        }
+      };
+
+      log::info!("stanley_druckenmiller_agent {} Analyzing price momentum over {} closes", ticker, closes.len());
+      let momentum_analysis = self.analyze_price_momentum(&closes);
+
+      log::info!("stanley_druckenmiller_agent {} Analyzing growth acceleration over {} quarters", ticker, metrics.len());
+      let acceleration_analysis = self.analyze_growth_acceleration(&metrics);
+
+      log::info!("stanley_druckenmiller_agent {} Analyzing sentiment from {} news articles", ticker, company_news.len());
+      let sentiment_analysis = self.analyze_sentiment(&company_news);
+
+      log::info!("stanley_druckenmiller_agent {} Analyzing risk/reward", ticker);
+      let risk_reward_analysis = self.analyze_risk_reward(&closes);
+
+      let mut analysis_data : HashMap<String, Value> = HashMap::new();
+      analysis_data.insert("price_momentum".to_string(), serde_json::to_value(&momentum_analysis)?);
+      analysis_data.insert("growth_acceleration".to_string(), serde_json::to_value(&acceleration_analysis)?);
+      analysis_data.insert("sentiment".to_string(), serde_json::to_value(&sentiment_analysis)?);
+      analysis_data.insert("risk_reward".to_string(), serde_json::to_value(&risk_reward_analysis)?);
+
+      log::info!("[Stanley Druckenmiller Agent] ({}) Generating final signal via LLM...", ticker);
+      let druckenmiller_output = self.generate_druckenmiller_output(ticker, &analysis_data, model_name, model_provider, &config, SamplingParams::from_metadata(&state.metadata, DEFAULT_AGENT_TEMPERATURE), llm_timeout(&state.metadata, &config), is_dry_run(&state.metadata), &ensemble_models(&state.metadata)).await?;
+
+      let mut final_result : HashMap<String, Value> = HashMap::new();
+      final_result.insert("signal".to_string(), Value::from(druckenmiller_output.signal.to_string()));
+      final_result.insert("confidence".to_string(), Value::from(druckenmiller_output.confidence));
+      final_result.insert("reasoning".to_string(), Value::from(druckenmiller_output.reasoning));
+      druckenmiller_analysis.insert(ticker.to_string(), final_result);
+    }
+
+    let message_content = serde_json::to_string(&druckenmiller_analysis).context("Failed to serialize Stanley Druckenmiller signals for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Stanley Druckenmiller Agent");
+    }
+
+    let message = ChatMessage::from_agent("stanley_druckenmiller_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("stanley_druckenmiller_agent".to_string(), serde_json::to_value(druckenmiller_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+
+    log::info!("[Stanley Druckenmiller Agent] Analysis complete. Returning state update.");
+    return Ok(PartialAgentStateUpdate::new().with_messages(vec![message]).with_data(updated_data_map));
+  }
+
+  fn closes_from_df(prices_df: &DataFrame) -> Result<Vec<f64>, Error> {
+    return Ok(prices_df.column("close")?.f64()?.into_iter().flatten().collect());
+  }
+
+  pub fn analyze_price_momentum(&self, closes: &[f64]) -> HashMap<String, Value> {
+    /* Returns over one, three and six months, the recent trend weighted the most */
+
+    let mut score : i64 = 0;
+    let mut max_score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+    let mut returns : HashMap<String, Value> = HashMap::new();
+
+    let latest = match closes.last() {
+      Some(latest) => *latest,
+      None => {
+        return HashMap::from([
+          ("score".to_string(), Value::from(0)),
+          ("max_score".to_string(), Value::from(0)),
+          ("details".to_string(), Value::from("No price data available")),
+        ]);
+      }
+    };
+
+    // One month counts double: Druckenmiller moved quickly when the tape turned
+    for (window, weight) in MOMENTUM_WINDOWS.iter().zip([2, 1, 1]) {
+      let start = match closes.len().checked_sub(window + 1).map(|index| closes[index]).filter(|start| *start > 0.0) {
+        Some(start) => start,
+        None => continue,
+      };
+      let change = latest / start - 1.0;
+      returns.insert(format!("return_{}d", window), Value::from(change));
+      max_score += weight;
+      if change > 0.05 {
+        score += weight;
+        reasoning.push(format!("Up {:.1}% over {} days", change * 100.0, window));
+      } else if change < -0.05 {
+        reasoning.push(format!("Down {:.1}% over {} days", -change * 100.0, window));
+      } else {
+        reasoning.push(format!("Flat ({:+.1}%) over {} days", change * 100.0, window));
+      }
+    }
+
+    if max_score == 0 {
+      reasoning.push(format!("Only {} closes, too few for a {}-day return", closes.len(), MOMENTUM_WINDOWS[0]));
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(max_score)),
+      ("returns".to_string(), Value::Object(returns.into_iter().collect())),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_growth_acceleration(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* Whether quarterly revenue and earnings growth is inflecting upward, the change in growth matters more than its level */
+
+    let mut score : i64 = 0;
+    let mut max_score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    let series : [(&str, Vec<f64>); 2] = [
+      ("Revenue", metrics.iter().filter_map(|m| m.revenue_growth).collect()),
+      ("Earnings", metrics.iter().filter_map(|m| m.earnings_growth).collect()),
+    ];
+    for (label, growth) in series.iter() {
+      // Metrics come newest first
+      let (latest, previous) = match (growth.first(), growth.get(1)) {
+        (Some(latest), Some(previous)) => (*latest, *previous),
+        _ => continue,
+      };
+      max_score += 2;
+      let change = latest - previous;
+      if change > 0.0 && latest > 0.0 {
+        score += 2;
+        reasoning.push(format!("{} growth accelerating, {:.1}% from {:.1}% the prior quarter", label, latest * 100.0, previous * 100.0));
+      } else if change > 0.0 {
+        score += 1;
+        reasoning.push(format!("{} decline easing, {:.1}% from {:.1}% the prior quarter", label, latest * 100.0, previous * 100.0));
+      } else {
+        reasoning.push(format!("{} growth decelerating, {:.1}% from {:.1}% the prior quarter", label, latest * 100.0, previous * 100.0));
+      }
+    }
+
+    if max_score == 0 {
+      reasoning.push("Fewer than two quarters of growth data".to_string());
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(max_score)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_sentiment(&self, company_news: &[CompanyNews]) -> HashMap<String, Value> {
+    if company_news.is_empty() {
+      return HashMap::from([
+        ("score".to_string(), Value::from(0)),
+        ("max_score".to_string(), Value::from(0)),
+        ("details".to_string(), Value::from("No recent news")),
+      ]);
+    }
+
+    let signals : Vec<Signal> = company_news.iter().map(SentimentAnalystAgent::news_signal).collect();
+    let positive = signals.iter().filter(|signal| **signal == Signal::Bullish).count();
+    let negative = signals.iter().filter(|signal| **signal == Signal::Bearish).count();
+
+    let score : i64 = if positive > negative * 2 { 2 } else if positive > negative { 1 } else { 0 };
+    let details = format!("{} positive, {} negative and {} neutral articles", positive, negative, signals.len() - positive - negative);
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(2)),
+      ("details".to_string(), Value::from(details)),
+    ]);
+  }
+
+  pub fn analyze_risk_reward(&self, closes: &[f64]) -> HashMap<String, Value> {
+    /* Upside to the period high against downside to the period low, with volatility as a check on how far a bad call runs */
+
+    if closes.len() < MOMENTUM_WINDOWS[0] {
+      return HashMap::from([
+        ("score".to_string(), Value::from(0)),
+        ("max_score".to_string(), Value::from(0)),
+        ("details".to_string(), Value::from("Not enough price history to judge risk/reward")),
+      ]);
+    }
+
+    let latest = closes[closes.len() - 1];
+    let high = closes.iter().cloned().fold(f64::MIN, f64::max);
+    let low = closes.iter().cloned().fold(f64::MAX, f64::min);
+    let daily_returns : Vec<f64> = closes.windows(2).filter(|pair| pair[0] > 0.0).map(|pair| pair[1] / pair[0] - 1.0).collect();
+    let mean = daily_returns.iter().sum::<f64>() / daily_returns.len() as f64;
+    let volatility = (daily_returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / daily_returns.len() as f64).sqrt() * TRADING_DAYS_PER_YEAR.sqrt();
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    // Trading near the low of its range after a strong run means limited downside to the last support
+    let downside = if latest > 0.0 { (latest - low) / latest } else { 0.0 };
+    let upside = if latest > 0.0 { (high - latest) / latest } else { 0.0 };
+    let pullback = if high > 0.0 { (high - latest) / high } else { 0.0 };
+    if pullback < 0.10 {
+      score += 1;
+      reasoning.push(format!("Within {:.1}% of the period high, the trend is intact", pullback * 100.0));
+    } else {
+      reasoning.push(format!("{:.1}% below the period high, {:.1}% upside to regain it against {:.1}% downside to the low", pullback * 100.0, upside * 100.0, downside * 100.0));
+    }
+
+    if volatility < 0.30 {
+      score += 2;
+      reasoning.push(format!("Annualized volatility of {:.0}% leaves room to size up", volatility * 100.0));
+    } else if volatility < 0.50 {
+      score += 1;
+      reasoning.push(format!("Moderate annualized volatility of {:.0}%", volatility * 100.0));
+    } else {
+      reasoning.push(format!("High annualized volatility of {:.0}% limits position size", volatility * 100.0));
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(3)),
+      ("annualized_volatility".to_string(), Value::from(volatility)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  #[tracing::instrument(skip_all, fields(ticker = %ticker, model = %model_name))]
+  pub async fn generate_druckenmiller_output(&self, ticker: &str, analysis_data: &HashMap<String, Value>, model_name: &str, model_provider: &str, config: &Config, sampling: SamplingParams, timeout: Duration, dry_run: bool, ensemble: &[EnsembleModel]) -> Result<StanleyDruckenmillerSignal, Error> {
+
+    if dry_run {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, "Dry run"));
+    }
+    // An ensemble makes one call per model
+    if !budget_allows(sampling.max_tokens as u64 * ensemble.len().max(1) as u64) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, BUDGET_EXHAUSTED));
+    }
+
+    let analysis_data_json = serde_json::to_string_pretty(analysis_data).context("Failed to serialize analysis data for LLM prompt")?;
+
+    let system_prompt : &str = r#"You are a Stanley Druckenmiller AI agent, making investment decisions using his principles:
+                                  - Seek asymmetric risk/reward: large upside with limited, well-defined downside
+                                  - Follow the price action, strong and improving momentum confirms a thesis
+                                  - Focus on where fundamentals are going, accelerating growth matters more than its current level
+                                  - Watch the news flow for a change in the story before it shows in the numbers
+                                  - Be willing to bet big with high conviction, and cut losses quickly when the thesis breaks
+                                  - Preserve capital first, a signal with weak or missing evidence deserves low confidence
+
+                                  When providing your reasoning, be thorough and specific by:
+                                  1. Describing the price trend across the one, three and six month windows
+                                  2. Stating whether revenue and earnings growth are accelerating or decelerating quarter over quarter
+                                  3. Weighing the tone of recent news
+                                  4. Spelling out the upside against the downside and what volatility implies for position size
+                                  5. Scaling confidence to how strongly momentum and fundamentals agree, and noting any missing data
+                                  6. Using Stanley Druckenmiller's decisive, conviction-driven voice
+
+                                  Follow these guidelines strictly."#;
+
+    let json_mode : bool = supports_json_mode(model_name);
+
+    let build_messages = |json_mode: bool| PromptBuilder::new()
+      .with_system(system_prompt)
+      .with_instruction("Based on the following data, create the investment signal as Stanley Druckenmiller would:")
+      .with_section(&format!("Analysis Data for {}", ticker), &analysis_data_json)
+      .with_json_schema(SIGNAL_JSON_SCHEMA)
+      .with_json_mode(json_mode)
+      .build();
+    let messages = build_messages(json_mode);
+
+    let provider = ModelProvider::from_str(model_provider).map_err(|_| anyhow!("Unknown model provider: {}", model_provider))?;
+
+    let config_for_call : LLMModelConfig = sampling.to_config(provider, model_name, json_mode);
+
+    if ensemble.len() > 1 {
+      log::info!("[Stanley Druckenmiller Agent] ({}) Calling {} ensemble models for Druckenmiller analysis...", ticker, ensemble.len());
+      let votes = ensemble_vote(ensemble, build_messages(false), &config_for_call, config, timeout).await;
+      return Ok(match reconcile_votes(&votes) {
+        Some(vote) => StanleyDruckenmillerSignal { signal: vote.signal, confidence: vote.confidence, reasoning: vote.reasoning },
+        None => StanleyDruckenmillerSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Every ensemble model failed for ticker {}. Defaulting to neutral.", ticker),
+        },
+      });
+    }
+
+    if circuit_breaker::is_open(&config_for_call) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, CIRCUIT_OPEN));
+    }
+    let client = get_ready_model(&config_for_call, config)?;
+
+    log::info!("[Stanley Druckenmiller Agent] ({}) Calling LLM for Druckenmiller analysis...", ticker);
+
+    let response = match chat_with_timeout(client.as_ref(), messages.clone(), &config_for_call, timeout).await? {
+      Some(response) => response,
+      None => return Ok(StanleyDruckenmillerSignal {
+        signal: Signal::Neutral,
+        confidence: 0.0,
+        reasoning: format!("LLM call for ticker {} timed out after {:.1}s. Defaulting to neutral.", ticker, timeout.as_secs_f64()),
+      }),
+    };
+
+    log::debug!("[Stanley Druckenmiller Agent] ({}) LLM raw response: {}", ticker, response.content);
+
+    let parsed = match extract_json::<StanleyDruckenmillerSignal>(&response.content) {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::warn!("[Stanley Druckenmiller Agent] ({}) LLM response is not valid JSON ({}), asking the model to reformat it", ticker, err);
+        repair_json::<StanleyDruckenmillerSignal>(client.as_ref(), messages, &response.content, &config_for_call, timeout).await.ok_or(err)
+      }
+    };
+
+    match parsed {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::error!("[Stanley Druckenmiller Agent] ({}) Failed to parse LLM JSON response into StanleyDruckenmillerSignal: {}. Raw response: '{}'", ticker, err, response.content);
+        if response.is_truncated() {
+          log::warn!("[Stanley Druckenmiller Agent] ({}) LLM response was cut off at max_tokens, raise max_tokens if this keeps happening", ticker);
+        }
+        Ok(StanleyDruckenmillerSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Error in LLM analysis or response parsing for ticker {}: {}. Defaulting to neutral.", ticker, err),
+        })
+      }
+    }
+  }
+
+  // Momentum and growth acceleration carry double weight. Confidence is scaled by how much of the evidence was available,
+  // so a ticker scored on prices alone never reaches full conviction
+  fn dry_run_signal(ticker: &str, analysis_data: &HashMap<String, Value>, reason: &str) -> StanleyDruckenmillerSignal {
+    let area_score = |key: &str, field: &str| analysis_data.get(key).and_then(|area| area.get(field)).and_then(Value::as_f64).unwrap_or(0.0);
+    let areas = [("price_momentum", 2.0), ("growth_acceleration", 2.0), ("sentiment", 1.0), ("risk_reward", 1.0)];
+
+    let mut weighted_ratio : f64 = 0.0;
+    let mut available_weight : f64 = 0.0;
+    for (area, weight) in areas.iter() {
+      let max_score = area_score(area, "max_score");
+      if max_score > 0.0 {
+        weighted_ratio += weight * area_score(area, "score") / max_score;
+        available_weight += weight;
+      }
+    }
+    let total_weight : f64 = areas.iter().map(|(_, weight)| weight).sum();
+
+    if available_weight == 0.0 {
+      log::info!("[Stanley Druckenmiller Agent] ({}) {}, skipping the LLM call", ticker, reason);
+      return StanleyDruckenmillerSignal {
+        signal: Signal::Neutral,
+        confidence: 0.0,
+        reasoning: format!("{}: no price, fundamental or news data to score.", reason),
+      };
+    }
+
+    let ratio = weighted_ratio / available_weight;
+    let signal = if ratio >= 0.7 {
+      Signal::Bullish
+    } else if ratio <= 0.3 {
+      Signal::Bearish
+    } else {
+      Signal::Neutral
+    };
+    // Distance from the neutral midpoint, scaled by evidence coverage
+    let conviction = ((ratio - 0.5).abs() * 2.0) * (available_weight / total_weight);
+
+    log::info!("[Stanley Druckenmiller Agent] ({}) {}, skipping the LLM call", ticker, reason);
+    StanleyDruckenmillerSignal {
+      signal,
+      confidence: (conviction * 100.0).round(),
+      reasoning: format!("{}: signal derived from a weighted score of {:.0}% over {:.0}% of the evidence without calling the LLM.", reason, ratio * 100.0, available_weight / total_weight * 100.0),
+    }
+  }
+}
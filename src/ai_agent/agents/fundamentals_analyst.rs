@@ -0,0 +1,278 @@
+use anyhow::{Context, Error};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::result::Result::{Ok};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::data::models::{FinancialMetrics, ReportPeriod};
+use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate, show_agent_reasoning};
+use crate::ai_agent::llm::model_provider::ChatMessage;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::app::config::Config;
+
+const METRICS_LIMIT: i64 = 10;
+
+// Thresholds a metric has to clear to count in favour of its area
+const MIN_RETURN_ON_EQUITY: f64 = 0.15;
+const MIN_NET_MARGIN: f64 = 0.20;
+const MIN_OPERATING_MARGIN: f64 = 0.15;
+const MIN_REVENUE_GROWTH: f64 = 0.10;
+const MIN_EARNINGS_GROWTH: f64 = 0.10;
+const MIN_BOOK_VALUE_GROWTH: f64 = 0.10;
+const MIN_CURRENT_RATIO: f64 = 1.5;
+const MAX_DEBT_TO_EQUITY: f64 = 0.5;
+// Free cash flow per share should cover most of reported earnings per share
+const MIN_FCF_TO_EPS: f64 = 0.8;
+
+pub struct FundamentalsAnalystAgent;
+
+impl FundamentalsAnalystAgent {
+  pub fn new() -> Self {
+    FundamentalsAnalystAgent {}
+  }
+
+  pub fn static_fundamentals_analyst_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let fundamentals_analyst = FundamentalsAnalystAgent::new();
+      fundamentals_analyst.fundamentals_analyst_agent(state, config).await
+    })
+  }
+
+  pub async fn fundamentals_analyst_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Scores profitability, growth and financial health from reported metrics, no LLM involved
+     */
+
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
+    let api = API::new(config);
+
+    let data: &HashMap<String, Value> = &state.data;
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let period : ReportPeriod = ReportPeriod::from_metadata(&state.metadata, ReportPeriod::Ttm);
+
+    let mut fundamental_analysis : HashMap<String, Value> = HashMap::new();
+
+    let api_ref : &API = &api;
+    let fetched = fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      log::info!("fundamentals_analyst_agent {} Fetching financial metrics", ticker);
+      api_ref.get_financial_metrics(&ticker, end_date, Some(period.as_str()), Some(METRICS_LIMIT)).await
+    }).await;
+
+    for (ticker, metrics) in fetched {
+      let metrics : Vec<FinancialMetrics> = metrics?;
+
+      // Metrics come back newest first
+      let analysis = match metrics.first() {
+        Some(latest) => Self::analyze_fundamentals(latest),
+        None => {
+          log::warn!("fundamentals_analyst_agent {} No financial metrics found", ticker);
+          serde_json::json!({
+            "signal": Signal::Neutral.to_string(),
+            "confidence": 0.0,
+            "reasoning": "No financial metrics available",
+          })
+        }
+      };
+      fundamental_analysis.insert(ticker.clone(), analysis);
+    }
+
+    let message_content = serde_json::to_string(&fundamental_analysis).context("Failed to serialize fundamental analysis for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Fundamentals Analyst");
+    }
+
+    let message = ChatMessage::from_agent("fundamentals_analyst_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("fundamentals_analyst_agent".to_string(), serde_json::to_value(fundamental_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+
+    let mut result = PartialAgentStateUpdate::new();
+    result = result.with_messages(vec![message]);
+    result = result.with_data(updated_data_map);
+
+    return Ok(result);
+  }
+
+  pub fn analyze_fundamentals(metrics: &FinancialMetrics) -> Value {
+    let (profitability_signal, profitability) = Self::score_area(&[
+      ("return_on_equity", metrics.return_on_equity, metrics.return_on_equity.map(|roe| roe > MIN_RETURN_ON_EQUITY)),
+      ("net_margin", metrics.net_margin, metrics.net_margin.map(|margin| margin > MIN_NET_MARGIN)),
+      ("operating_margin", metrics.operating_margin, metrics.operating_margin.map(|margin| margin > MIN_OPERATING_MARGIN)),
+    ]);
+
+    let (growth_signal, growth) = Self::score_area(&[
+      ("revenue_growth", metrics.revenue_growth, metrics.revenue_growth.map(|growth| growth > MIN_REVENUE_GROWTH)),
+      ("earnings_growth", metrics.earnings_growth, metrics.earnings_growth.map(|growth| growth > MIN_EARNINGS_GROWTH)),
+      ("book_value_growth", metrics.book_value_growth, metrics.book_value_growth.map(|growth| growth > MIN_BOOK_VALUE_GROWTH)),
+    ]);
+
+    let fcf_to_eps = match (metrics.free_cash_flow_per_share, metrics.earnings_per_share) {
+      (Some(fcf), Some(eps)) if eps > 0.0 => Some(fcf / eps),
+      _ => None,
+    };
+    let (health_signal, health) = Self::score_area(&[
+      ("current_ratio", metrics.current_ratio, metrics.current_ratio.map(|ratio| ratio > MIN_CURRENT_RATIO)),
+      ("debt_to_equity", metrics.debt_to_equity, metrics.debt_to_equity.map(|ratio| ratio < MAX_DEBT_TO_EQUITY)),
+      ("fcf_to_eps", fcf_to_eps, fcf_to_eps.map(|ratio| ratio > MIN_FCF_TO_EPS)),
+    ]);
+
+    let areas : [Signal; 3] = [profitability_signal, growth_signal, health_signal];
+    let bullish = areas.iter().filter(|signal| **signal == Signal::Bullish).count();
+    let bearish = areas.iter().filter(|signal| **signal == Signal::Bearish).count();
+
+    let signal = if bullish > bearish { Signal::Bullish } else if bearish > bullish { Signal::Bearish } else { Signal::Neutral };
+    let confidence = (bullish.max(bearish) as f64 / areas.len() as f64 * 100.0).round();
+
+    return serde_json::json!({
+      "signal": signal.to_string(),
+      "confidence": confidence,
+      "reasoning": {
+        "profitability": profitability,
+        "growth": growth,
+        "financial_health": health,
+      },
+    });
+  }
+
+  // Two or more passing metrics is bullish, none passing is bearish, metrics without data are left out
+  fn score_area(checks: &[(&str, Option<f64>, Option<bool>)]) -> (Signal, Value) {
+    let available = checks.iter().filter(|(_, _, passed)| passed.is_some()).count();
+    let passed = checks.iter().filter(|(_, _, passed)| *passed == Some(true)).count();
+
+    let signal = if available == 0 {
+      Signal::Neutral
+    } else if passed >= 2 {
+      Signal::Bullish
+    } else if passed == 0 {
+      Signal::Bearish
+    } else {
+      Signal::Neutral
+    };
+
+    let values : serde_json::Map<String, Value> = checks.iter().map(|(name, value, _)| (name.to_string(), serde_json::json!(value))).collect();
+    let summary = serde_json::json!({
+      "signal": signal.to_string(),
+      "passed": passed,
+      "available": available,
+      "metrics": values,
+    });
+    return (signal, summary);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn metrics(values: Value) -> FinancialMetrics {
+    let mut body = json!({ "ticker": "AAPL", "report_period": "2024-03-31", "period": "ttm", "currency": "USD" });
+    body.as_object_mut().unwrap().extend(values.as_object().unwrap().clone());
+    serde_json::from_value(body).unwrap()
+  }
+
+  fn area_signal(analysis: &Value, area: &str) -> String {
+    analysis["reasoning"][area]["signal"].as_str().unwrap().to_string()
+  }
+
+  #[test]
+  fn profitability_counts_metrics_strictly_above_their_threshold() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "return_on_equity": 0.16, "net_margin": 0.21, "operating_margin": 0.15 })));
+    assert_eq!(area_signal(&analysis, "profitability"), "bullish");
+    assert_eq!(analysis["reasoning"]["profitability"]["passed"], json!(2));
+
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "return_on_equity": 0.16, "net_margin": 0.20, "operating_margin": 0.15 })));
+    assert_eq!(area_signal(&analysis, "profitability"), "neutral");
+
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "return_on_equity": 0.15, "net_margin": 0.10, "operating_margin": 0.05 })));
+    assert_eq!(area_signal(&analysis, "profitability"), "bearish");
+  }
+
+  #[test]
+  fn growth_needs_two_metrics_above_ten_percent() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "revenue_growth": 0.11, "earnings_growth": 0.12, "book_value_growth": 0.0 })));
+    assert_eq!(area_signal(&analysis, "growth"), "bullish");
+
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "revenue_growth": 0.11, "earnings_growth": 0.10, "book_value_growth": 0.0 })));
+    assert_eq!(area_signal(&analysis, "growth"), "neutral");
+
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "revenue_growth": -0.05, "earnings_growth": 0.10, "book_value_growth": 0.1 })));
+    assert_eq!(area_signal(&analysis, "growth"), "bearish");
+  }
+
+  #[test]
+  fn financial_health_checks_liquidity_leverage_and_cash_backing() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "current_ratio": 1.6, "debt_to_equity": 0.4, "free_cash_flow_per_share": 4.5, "earnings_per_share": 5.0 })));
+    assert_eq!(area_signal(&analysis, "financial_health"), "bullish");
+    assert_eq!(analysis["reasoning"]["financial_health"]["passed"], json!(3));
+
+    // Every threshold is strict, sitting exactly on the line fails all three
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "current_ratio": 1.5, "debt_to_equity": 0.5, "free_cash_flow_per_share": 4.0, "earnings_per_share": 5.0 })));
+    assert_eq!(area_signal(&analysis, "financial_health"), "bearish");
+  }
+
+  #[test]
+  fn fcf_to_eps_is_left_out_when_earnings_are_not_positive() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({ "free_cash_flow_per_share": 4.0, "earnings_per_share": -1.0 })));
+    assert_eq!(analysis["reasoning"]["financial_health"]["available"], json!(0));
+    assert_eq!(area_signal(&analysis, "financial_health"), "neutral");
+  }
+
+  #[test]
+  fn strong_metrics_across_the_board_are_bullish() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({
+      "return_on_equity": 0.30, "net_margin": 0.25, "operating_margin": 0.30,
+      "revenue_growth": 0.15, "earnings_growth": 0.20, "book_value_growth": 0.12,
+      "current_ratio": 2.0, "debt_to_equity": 0.3, "free_cash_flow_per_share": 6.0, "earnings_per_share": 5.0,
+    })));
+    assert_eq!(analysis["signal"], json!("bullish"));
+    assert_eq!(analysis["confidence"], json!(100.0));
+  }
+
+  #[test]
+  fn weak_metrics_across_the_board_are_bearish() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({
+      "return_on_equity": 0.02, "net_margin": 0.01, "operating_margin": 0.03,
+      "revenue_growth": -0.10, "earnings_growth": -0.20, "book_value_growth": 0.0,
+      "current_ratio": 2.0, "debt_to_equity": 0.3, "free_cash_flow_per_share": 6.0, "earnings_per_share": 5.0,
+    })));
+    assert_eq!(analysis["signal"], json!("bearish"));
+    assert_eq!(analysis["confidence"], json!(67.0));
+  }
+
+  #[test]
+  fn a_tie_between_areas_or_missing_data_is_neutral() {
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({
+      "return_on_equity": 0.30, "net_margin": 0.25, "operating_margin": 0.30,
+      "revenue_growth": -0.10, "earnings_growth": -0.20, "book_value_growth": 0.0,
+    })));
+    assert_eq!(analysis["signal"], json!("neutral"));
+    assert_eq!(area_signal(&analysis, "financial_health"), "neutral");
+
+    let analysis = FundamentalsAnalystAgent::analyze_fundamentals(&metrics(json!({})));
+    assert_eq!(analysis["signal"], json!("neutral"));
+    assert_eq!(analysis["confidence"], json!(0.0));
+  }
+}
@@ -0,0 +1,167 @@
+use anyhow::{Context, Error};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::result::Result::{Ok};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::data::models::InsiderTrade;
+use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate, show_agent_reasoning};
+use crate::ai_agent::llm::model_provider::ChatMessage;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::app::config::Config;
+
+const INSIDER_TRADE_LIMIT: i64 = 1000;
+// Board directors see the whole company, their trades count double
+const BOARD_DIRECTOR_WEIGHT: f64 = 2.0;
+const OTHER_INSIDER_WEIGHT: f64 = 1.0;
+// Net buying ratios inside this band are treated as neutral
+const NEUTRAL_BAND: f64 = 0.1;
+
+pub struct InsiderTradesAnalystAgent;
+
+impl InsiderTradesAnalystAgent {
+  pub fn new() -> Self {
+    InsiderTradesAnalystAgent {}
+  }
+
+  pub fn static_insider_trades_analyst_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let insider_trades_analyst = InsiderTradesAnalystAgent::new();
+      insider_trades_analyst.insider_trades_analyst_agent(state, config).await
+    })
+  }
+
+  pub async fn insider_trades_analyst_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Nets the dollar value of insider buying against selling over the run window
+     */
+
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
+    let api = API::new(config);
+
+    let data: &HashMap<String, Value> = &state.data;
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let start_date: &str = match data.get("start_date").and_then(Value::as_str) {
+      Some(start_date) => start_date,
+      _ => {
+        log::error!("Cannot find start date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut insider_analysis : HashMap<String, Value> = HashMap::new();
+    let mut ticker_errors: HashMap<String, String> = HashMap::new();
+
+    let api_ref : &API = &api;
+    let fetched = fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      log::info!("insider_trades_analyst_agent {} Fetching insider trades", ticker);
+      api_ref.get_insider_trade(&ticker, end_date, Some(start_date), INSIDER_TRADE_LIMIT).await
+    }).await;
+
+    for (ticker, insider_trades) in fetched {
+      // A ticker the provider rejects is recorded and skipped instead of failing the other tickers
+      let insider_trades = match insider_trades {
+        Ok(insider_trades) => insider_trades,
+        Err(e) => {
+          log::error!("insider_trades_analyst_agent {} Skipping ticker, data fetch failed: {:#}", ticker, e);
+          ticker_errors.insert(ticker.clone(), format!("{:#}", e));
+          continue;
+        }
+      };
+
+      log::info!("insider_trades_analyst_agent {} Netting {} insider trades", ticker, insider_trades.len());
+      insider_analysis.insert(ticker.clone(), Self::analyze_insider_trades(&insider_trades));
+    }
+
+    let message_content = serde_json::to_string(&insider_analysis).context("Failed to serialize insider trades analysis for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Insider Trades Analyst");
+    }
+
+    let message = ChatMessage::from_agent("insider_trades_analyst_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("insider_trades_analyst_agent".to_string(), serde_json::to_value(insider_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+    if !ticker_errors.is_empty() {
+      updated_data_map.insert("errors".to_string(), serde_json::json!({ "insider_trades_analyst_agent": ticker_errors }));
+    }
+
+    let mut result = PartialAgentStateUpdate::new();
+    result = result.with_messages(vec![message]);
+    result = result.with_data(updated_data_map);
+
+    return Ok(result);
+  }
+
+  pub fn analyze_insider_trades(insider_trades: &[InsiderTrade]) -> Value {
+    // Negative transaction shares are sales, trades without a share count or price are skipped
+    let mut bought_value = 0.0;
+    let mut sold_value = 0.0;
+    let mut weighted_net = 0.0;
+    let mut weighted_gross = 0.0;
+    let mut counted_trades = 0;
+    let mut director_trades = 0;
+
+    for trade in insider_trades {
+      let value = match (trade.transaction_shares, trade.transaction_price_per_share) {
+        (Some(shares), Some(price)) if shares != 0.0 && price > 0.0 => shares * price,
+        _ => continue,
+      };
+      let is_director = trade.is_board_director.unwrap_or(false);
+      let weight = if is_director { BOARD_DIRECTOR_WEIGHT } else { OTHER_INSIDER_WEIGHT };
+
+      if value > 0.0 { bought_value += value } else { sold_value += -value }
+      weighted_net += value * weight;
+      weighted_gross += value.abs() * weight;
+      counted_trades += 1;
+      if is_director {
+        director_trades += 1;
+      }
+    }
+
+    // Weighted net buying over weighted gross activity, in [-1, 1]
+    let score = if weighted_gross > 0.0 { weighted_net / weighted_gross } else { 0.0 };
+
+    let signal = if score > NEUTRAL_BAND { Signal::Bullish } else if score < -NEUTRAL_BAND { Signal::Bearish } else { Signal::Neutral };
+    let confidence = match signal {
+      Signal::Neutral if weighted_gross > 0.0 => ((1.0 - score.abs() / NEUTRAL_BAND) * 50.0).round(),
+      Signal::Neutral => 0.0,
+      _ => (score.abs() * 100.0).round(),
+    };
+
+    return serde_json::json!({
+      "signal": signal.to_string(),
+      "confidence": confidence,
+      "reasoning": {
+        "net_buying_score": (score * 100.0).round() / 100.0,
+        "bought_value": bought_value.round(),
+        "sold_value": sold_value.round(),
+        "trades": counted_trades,
+        "board_director_trades": director_trades,
+      },
+    });
+  }
+}
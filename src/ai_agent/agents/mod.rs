@@ -1,3 +1,12 @@
 pub mod warren_buffet;
 pub mod risk_manager;
-pub mod portfolio_manager;
\ No newline at end of file
+pub mod portfolio_manager;
+pub mod technical_analyst;
+pub mod sentiment_analyst;
+pub mod valuation_analyst;
+pub mod ben_graham;
+pub mod fundamentals_analyst;
+pub mod insider_trades_analyst;
+pub mod cathie_wood;
+pub mod phil_fisher;
+pub mod druckenmiller;
\ No newline at end of file
@@ -0,0 +1,549 @@
+use anyhow::{Error, Context, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use crate::ai_agent::agents::sentiment_analyst::SentimentAnalystAgent;
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::graph::state::{AgentState, show_agent_reasoning, PartialAgentStateUpdate};
+use crate::ai_agent::llm::ensemble::{ensemble_models, ensemble_vote, reconcile_votes, EnsembleModel};
+use crate::ai_agent::llm::circuit_breaker::{self, CIRCUIT_OPEN};
+use crate::ai_agent::llm::json::{extract_json, repair_json};
+use crate::ai_agent::llm::models::{get_ready_model, is_dry_run, llm_timeout, supports_json_mode, SamplingParams, DEFAULT_AGENT_TEMPERATURE};
+use crate::ai_agent::llm::prompt::{PromptBuilder, SIGNAL_JSON_SCHEMA};
+use crate::ai_agent::llm::model_provider::{budget_allows, chat_with_timeout, ChatMessage, LLMModelConfig, ModelProvider, BUDGET_EXHAUSTED};
+use crate::ai_agent::error::AgentError;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::analysts::LineItemSet;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::ai_agent::data::models::{CompanyNews, FinancialMetrics, LineItem, ReportPeriod};
+use crate::app::config::Config;
+
+const HISTORY_LIMIT: i64 = 5;
+const COMPANY_NEWS_LIMIT: i64 = 50;
+// Smallest gross-margin move between periods that counts as expansion rather than noise
+const MARGIN_STEP_TOLERANCE: f64 = 0.002;
+
+pub const FISHER_LINE_ITEMS: LineItemSet = LineItemSet {
+  names: &["revenue", "research_and_development", "gross_profit", "operating_income"],
+  period: ReportPeriod::Annual,
+  limit: HISTORY_LIMIT,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhilFisherSignal {
+  signal : Signal,
+  confidence: f64,
+  reasoning: String
+}
+
+// Gross margins across the available periods, oldest to newest
+#[derive(Debug, Clone, Serialize)]
+pub struct MarginTrend {
+  pub periods: usize,
+  pub expanding_steps: usize,
+  pub total_change: f64,
+  pub consistently_expanding: bool, // every period-over-period step went up
+}
+
+impl PhilFisherSignal {
+  pub fn new() -> Self {
+    PhilFisherSignal { signal: Signal::Neutral, confidence: 0.0, reasoning: String::new() }
+  }
+
+  pub fn static_phil_fisher_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let signal = PhilFisherSignal::new();
+      signal.phil_fisher_agent(state, config).await
+    })
+  }
+
+  pub async fn phil_fisher_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Growth-quality analysis in the style of Phil Fisher: sustained revenue growth, high and steadily improving margins,
+    commitment to R&D and management quality, with company news standing in for his "scuttlebutt" interviews
+     */
+
+    let api_client : API = API::new(config.clone());
+    let data : &HashMap<String, Value> = &state.data;
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let start_date: Option<&str> = data.get("start_date").and_then(Value::as_str);
+    let period : ReportPeriod = ReportPeriod::from_metadata(&state.metadata, FISHER_LINE_ITEMS.period);
+    let line_items : Vec<String> = FISHER_LINE_ITEMS.names_for("phil_fisher", &state.metadata);
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let model_name: &str = match state.metadata.get("model_name").and_then(Value::as_str) {
+      Some(model_name) => model_name,
+      None => {
+        log::error!("Metadata missing a model_name key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let model_provider : &str = match state.metadata.get("model_provider").and_then(Value::as_str) {
+      Some(model_provider) => model_provider,
+      None => {
+        log::error!("Metadata missing a model_provider key");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut fisher_analysis: HashMap<String, HashMap<String, Value>> = HashMap::new();
+    let mut ticker_errors: HashMap<String, String> = HashMap::new();
+
+    let api_ref : &API = &api_client;
+    let line_items_ref : &Vec<String> = &line_items;
+    let fetched = fetch_per_ticker(&tickers, config.ticker_fetch_concurrency, |ticker| async move {
+      log::info!("phil_fisher_agent {} Fetching financial metrics", ticker);
+      let metrics: Vec<FinancialMetrics> = api_ref.get_financial_metrics(&ticker, end_date, Some(period.as_str()), Some(HISTORY_LIMIT)).await?;
+
+      log::info!("phil_fisher_agent {} Gathering financial line items", ticker);
+      let financial_line_items: Vec<LineItem> = api_ref.search_line_items(&ticker, line_items_ref.clone(), end_date, Some(period.as_str()), Some(FISHER_LINE_ITEMS.limit)).await?;
+
+      log::info!("phil_fisher_agent {} Fetching company news", ticker);
+      let company_news: Vec<CompanyNews> = api_ref.get_company_news(&ticker, end_date, start_date, COMPANY_NEWS_LIMIT).await?;
+      Ok::<_, AgentError>((metrics, financial_line_items, company_news))
+    }).await;
+
+    for (ticker, fetched) in fetched {
+      let ticker: &str = ticker.as_str();
+      // A ticker the provider rejects is recorded and skipped instead of failing the other tickers
+      let (metrics, financial_line_items, company_news) = match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+          log::error!("[Phil Fisher Agent] ({}) Skipping ticker, data fetch failed: {:#}", ticker, e);
+          ticker_errors.insert(ticker.to_string(), format!("{:#}", e));
+          continue;
+        }
+      };
+
+      log::info!("phil_fisher_agent {} Analyzing growth quality", ticker);
+      let growth_analysis = self.analyze_growth(&metrics);
+
+      log::info!("phil_fisher_agent {} Analyzing margin trends", ticker);
+      let margin_analysis = self.analyze_margins(&metrics);
+
+      log::info!("phil_fisher_agent {} Analyzing R&D commitment", ticker);
+      let research_analysis = self.analyze_research(&financial_line_items);
+
+      log::info!("phil_fisher_agent {} Analyzing management quality", ticker);
+      let management_analysis = self.analyze_management(&metrics);
+
+      log::info!("phil_fisher_agent {} Analyzing scuttlebutt from {} news articles", ticker, company_news.len());
+      let scuttlebutt_analysis = self.analyze_scuttlebutt(&company_news);
+
+      let mut analysis_data : HashMap<String, Value> = HashMap::new();
+      analysis_data.insert("growth_quality".to_string(), serde_json::to_value(&growth_analysis)?);
+      analysis_data.insert("margins".to_string(), serde_json::to_value(&margin_analysis)?);
+      analysis_data.insert("research".to_string(), serde_json::to_value(&research_analysis)?);
+      analysis_data.insert("management".to_string(), serde_json::to_value(&management_analysis)?);
+      analysis_data.insert("scuttlebutt".to_string(), serde_json::to_value(&scuttlebutt_analysis)?);
+
+      log::info!("[Phil Fisher Agent] ({}) Generating final signal via LLM...", ticker);
+      let fisher_output = self.generate_fisher_output(ticker, &analysis_data, model_name, model_provider, &config, SamplingParams::from_metadata(&state.metadata, DEFAULT_AGENT_TEMPERATURE), llm_timeout(&state.metadata, &config), is_dry_run(&state.metadata), &ensemble_models(&state.metadata)).await?;
+
+      let mut final_result : HashMap<String, Value> = HashMap::new();
+      final_result.insert("signal".to_string(), Value::from(fisher_output.signal.to_string()));
+      final_result.insert("confidence".to_string(), Value::from(fisher_output.confidence));
+      final_result.insert("reasoning".to_string(), Value::from(fisher_output.reasoning));
+      fisher_analysis.insert(ticker.to_string(), final_result);
+    }
+
+    let message_content = serde_json::to_string(&fisher_analysis).context("Failed to serialize Phil Fisher signals for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Phil Fisher Agent");
+    }
+
+    let message = ChatMessage::from_agent("phil_fisher_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("phil_fisher_agent".to_string(), serde_json::to_value(fisher_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+    if !ticker_errors.is_empty() {
+      updated_data_map.insert("errors".to_string(), serde_json::json!({ "phil_fisher_agent": ticker_errors }));
+    }
+
+    log::info!("[Phil Fisher Agent] Analysis complete. Returning state update.");
+    return Ok(PartialAgentStateUpdate::new().with_messages(vec![message]).with_data(updated_data_map));
+  }
+
+  pub fn analyze_growth(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* Fisher wanted growth that lasts for years, so consistency counts as much as the rate */
+
+    // Metrics come newest first
+    let growth_rates : Vec<f64> = metrics.iter().filter_map(|m| m.revenue_growth).collect();
+    if growth_rates.is_empty() {
+      return HashMap::from([
+        ("score".to_string(), Value::from(0)),
+        ("max_score".to_string(), Value::from(3)),
+        ("details".to_string(), Value::from("No revenue growth data available")),
+      ]);
+    }
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    let average = growth_rates.iter().sum::<f64>() / growth_rates.len() as f64;
+    if average > 0.15 {
+      score += 2;
+      reasoning.push(format!("Strong average revenue growth of {:.0}%", average * 100.0));
+    } else if average > 0.07 {
+      score += 1;
+      reasoning.push(format!("Moderate average revenue growth of {:.0}%", average * 100.0));
+    } else {
+      reasoning.push(format!("Average revenue growth of {:.0}% is too slow", average * 100.0));
+    }
+
+    let growing_periods = growth_rates.iter().filter(|growth| **growth > 0.0).count();
+    if growth_rates.len() > 1 && growing_periods == growth_rates.len() {
+      score += 1;
+      reasoning.push(format!("Revenue grew in all {} periods", growth_rates.len()));
+    } else {
+      reasoning.push(format!("Revenue grew in {} of {} periods", growing_periods, growth_rates.len()));
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(3)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  // None with fewer than two reported gross margins
+  pub fn gross_margin_trend(metrics: &[FinancialMetrics]) -> Option<MarginTrend> {
+    let mut margins : Vec<f64> = metrics.iter().filter_map(|m| m.gross_margin).collect();
+    if margins.len() < 2 {
+      return None;
+    }
+    margins.reverse();
+
+    let steps : Vec<f64> = margins.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let expanding_steps = steps.iter().filter(|step| **step > MARGIN_STEP_TOLERANCE).count();
+    Some(MarginTrend {
+      periods: margins.len(),
+      expanding_steps,
+      total_change: margins[margins.len() - 1] - margins[0],
+      consistently_expanding: expanding_steps == steps.len(),
+    })
+  }
+
+  pub fn analyze_margins(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* The multi-year gross margin trend plus the level and stability of operating margins */
+
+    let mut score : i64 = 0;
+    let mut max_score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    let trend = Self::gross_margin_trend(metrics);
+    match &trend {
+      Some(trend) => {
+        max_score += 3;
+        if trend.consistently_expanding && trend.periods >= 3 {
+          score += 3;
+          reasoning.push(format!("Gross margin expanded in every one of the last {} periods, up {:.1} points", trend.periods - 1, trend.total_change * 100.0));
+        } else if trend.total_change > MARGIN_STEP_TOLERANCE {
+          score += if trend.expanding_steps * 2 >= trend.periods - 1 { 2 } else { 1 };
+          reasoning.push(format!("Gross margin up {:.1} points, expanding in {} of {} periods", trend.total_change * 100.0, trend.expanding_steps, trend.periods - 1));
+        } else {
+          reasoning.push(format!("Gross margin down {:.1} points over {} periods", -trend.total_change * 100.0, trend.periods - 1));
+        }
+      }
+      None => reasoning.push("Insufficient gross margin history".to_string()),
+    }
+
+    let operating_margins : Vec<f64> = metrics.iter().filter_map(|m| m.operating_margin).collect();
+    if let Some(latest) = operating_margins.first() {
+      max_score += 1;
+      if *latest > 0.15 {
+        score += 1;
+        reasoning.push(format!("High operating margin of {:.1}%", latest * 100.0));
+      } else {
+        reasoning.push(format!("Operating margin of {:.1}%", latest * 100.0));
+      }
+    }
+    if operating_margins.len() >= 3 {
+      max_score += 1;
+      let mean = operating_margins.iter().sum::<f64>() / operating_margins.len() as f64;
+      let deviation = (operating_margins.iter().map(|margin| (margin - mean).powi(2)).sum::<f64>() / operating_margins.len() as f64).sqrt();
+      if deviation < 0.03 {
+        score += 1;
+        reasoning.push(format!("Stable operating margins, {:.1} point standard deviation", deviation * 100.0));
+      } else {
+        reasoning.push(format!("Volatile operating margins, {:.1} point standard deviation", deviation * 100.0));
+      }
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(max_score)),
+      ("gross_margin_trend".to_string(), serde_json::to_value(&trend).unwrap_or(Value::Null)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_research(&self, financial_line_items: &[LineItem]) -> HashMap<String, Value> {
+    /* R&D as a share of sales, and whether spending kept pace with the business */
+
+    let mut score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+
+    // Line items come newest first
+    let research_and_revenue : Vec<(f64, f64)> = financial_line_items.iter().filter_map(|item| {
+      let research = item.extra.get("research_and_development").and_then(Value::as_f64)?;
+      let revenue = item.extra.get("revenue").and_then(Value::as_f64).filter(|revenue| *revenue > 0.0)?;
+      Some((research.abs(), revenue))
+    }).collect();
+
+    let (latest_research, latest_revenue) = match research_and_revenue.first() {
+      Some(latest) => *latest,
+      None => {
+        return HashMap::from([
+          ("score".to_string(), Value::from(0)),
+          ("max_score".to_string(), Value::from(0)),
+          ("details".to_string(), Value::from("R&D spending not reported")),
+        ]);
+      }
+    };
+
+    let intensity = latest_research / latest_revenue;
+    if (0.03..=0.15).contains(&intensity) {
+      score += 2;
+      reasoning.push(format!("R&D at {:.1}% of sales, a sustained commitment without overreaching", intensity * 100.0));
+    } else if intensity > 0.15 {
+      score += 1;
+      reasoning.push(format!("Very high R&D at {:.1}% of sales", intensity * 100.0));
+    } else {
+      reasoning.push(format!("Low R&D at {:.1}% of sales", intensity * 100.0));
+    }
+
+    let mut max_score : i64 = 2;
+    if let Some((oldest_research, _)) = research_and_revenue.last().filter(|_| research_and_revenue.len() > 1) {
+      max_score += 1;
+      if latest_research > *oldest_research {
+        score += 1;
+        reasoning.push(format!("R&D spending grew over {} periods", research_and_revenue.len()));
+      } else {
+        reasoning.push(format!("R&D spending did not grow over {} periods", research_and_revenue.len()));
+      }
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(max_score)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_management(&self, metrics: &[FinancialMetrics]) -> HashMap<String, Value> {
+    /* Returns on capital and a conservative balance sheet as the measurable side of management quality */
+
+    let mut score : i64 = 0;
+    let mut max_score : i64 = 0;
+    let mut reasoning : Vec<String> = Vec::new();
+    let latest = metrics.first();
+
+    if let Some(roe) = latest.and_then(|m| m.return_on_equity) {
+      max_score += 2;
+      if roe > 0.20 {
+        score += 2;
+        reasoning.push(format!("Excellent return on equity of {:.1}%", roe * 100.0));
+      } else if roe > 0.10 {
+        score += 1;
+        reasoning.push(format!("Decent return on equity of {:.1}%", roe * 100.0));
+      } else {
+        reasoning.push(format!("Weak return on equity of {:.1}%", roe * 100.0));
+      }
+    }
+
+    if let Some(debt_to_equity) = latest.and_then(|m| m.debt_to_equity) {
+      max_score += 1;
+      if debt_to_equity < 0.5 {
+        score += 1;
+        reasoning.push(format!("Conservative debt-to-equity of {:.2}", debt_to_equity));
+      } else {
+        reasoning.push(format!("Debt-to-equity of {:.2}", debt_to_equity));
+      }
+    }
+
+    if max_score == 0 {
+      reasoning.push("No return or leverage data available".to_string());
+    }
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(max_score)),
+      ("details".to_string(), Value::from(reasoning.join("; "))),
+    ]);
+  }
+
+  pub fn analyze_scuttlebutt(&self, company_news: &[CompanyNews]) -> HashMap<String, Value> {
+    /* What the press says about the company stands in for talking to customers, suppliers and competitors */
+
+    if company_news.is_empty() {
+      return HashMap::from([
+        ("score".to_string(), Value::from(0)),
+        ("max_score".to_string(), Value::from(0)),
+        ("details".to_string(), Value::from("No recent news to gauge")),
+      ]);
+    }
+
+    let signals : Vec<Signal> = company_news.iter().map(SentimentAnalystAgent::news_signal).collect();
+    let positive = signals.iter().filter(|signal| **signal == Signal::Bullish).count();
+    let negative = signals.iter().filter(|signal| **signal == Signal::Bearish).count();
+    let positive_share = positive as f64 / signals.len() as f64;
+    let negative_share = negative as f64 / signals.len() as f64;
+
+    let score : i64 = if positive_share > 0.6 && negative_share < 0.2 { 2 } else if positive_share > negative_share { 1 } else { 0 };
+    let details = format!("{} positive, {} negative and {} neutral articles", positive, negative, signals.len() - positive - negative);
+
+    return HashMap::from([
+      ("score".to_string(), Value::from(score)),
+      ("max_score".to_string(), Value::from(2)),
+      ("details".to_string(), Value::from(details)),
+    ]);
+  }
+
+  #[tracing::instrument(skip_all, fields(ticker = %ticker, model = %model_name))]
+  pub async fn generate_fisher_output(&self, ticker: &str, analysis_data: &HashMap<String, Value>, model_name: &str, model_provider: &str, config: &Config, sampling: SamplingParams, timeout: Duration, dry_run: bool, ensemble: &[EnsembleModel]) -> Result<PhilFisherSignal, Error> {
+
+    if dry_run {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, "Dry run"));
+    }
+    // An ensemble makes one call per model
+    if !budget_allows(sampling.max_tokens as u64 * ensemble.len().max(1) as u64) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, BUDGET_EXHAUSTED));
+    }
+
+    let analysis_data_json = serde_json::to_string_pretty(analysis_data).context("Failed to serialize analysis data for LLM prompt")?;
+
+    let system_prompt : &str = r#"You are a Phil Fisher AI agent, making investment decisions using his principles:
+                                  - Buy outstanding companies with the potential to grow sales for years, and hold them for the long term
+                                  - Favor high profit margins that management keeps improving over time
+                                  - Look for a determined commitment to research and development
+                                  - Judge management on integrity, depth and a conservative approach to capital
+                                  - Use "scuttlebutt", what customers, suppliers and competitors say, to confirm the picture
+                                  - Pay a fair price for quality rather than chase bargains
+
+                                  When providing your reasoning, be thorough and specific by:
+                                  1. Assessing how sustained and consistent the revenue growth has been
+                                  2. Discussing the gross margin trend across the available years and the stability of operating margins
+                                  3. Evaluating R&D spending relative to sales and whether it is growing with the business
+                                  4. Commenting on management quality as shown by returns on equity and balance sheet discipline
+                                  5. Weighing what the recent news flow suggests about the company's standing
+                                  6. Using Phil Fisher's patient, methodical and inquisitive voice
+
+                                  Follow these guidelines strictly."#;
+
+    let json_mode : bool = supports_json_mode(model_name);
+
+    let build_messages = |json_mode: bool| PromptBuilder::new()
+      .with_system(system_prompt)
+      .with_instruction("Based on the following data, create the investment signal as Phil Fisher would:")
+      .with_section(&format!("Analysis Data for {}", ticker), &analysis_data_json)
+      .with_json_schema(SIGNAL_JSON_SCHEMA)
+      .with_json_mode(json_mode)
+      .build();
+    let messages = build_messages(json_mode);
+
+    let provider = ModelProvider::from_str(model_provider).map_err(|_| anyhow!("Unknown model provider: {}", model_provider))?;
+
+    let config_for_call : LLMModelConfig = sampling.to_config(provider, model_name, json_mode);
+
+    if ensemble.len() > 1 {
+      log::info!("[Phil Fisher Agent] ({}) Calling {} ensemble models for Fisher analysis...", ticker, ensemble.len());
+      let votes = ensemble_vote(ensemble, build_messages(false), &config_for_call, config, timeout).await;
+      return Ok(match reconcile_votes(&votes) {
+        Some(vote) => PhilFisherSignal { signal: vote.signal, confidence: vote.confidence, reasoning: vote.reasoning },
+        None => PhilFisherSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Every ensemble model failed for ticker {}. Defaulting to neutral.", ticker),
+        },
+      });
+    }
+
+    if circuit_breaker::is_open(&config_for_call) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, CIRCUIT_OPEN));
+    }
+    let client = get_ready_model(&config_for_call, config)?;
+
+    log::info!("[Phil Fisher Agent] ({}) Calling LLM for Fisher analysis...", ticker);
+
+    let response = match chat_with_timeout(client.as_ref(), messages.clone(), &config_for_call, timeout).await? {
+      Some(response) => response,
+      None => return Ok(PhilFisherSignal {
+        signal: Signal::Neutral,
+        confidence: 0.0,
+        reasoning: format!("LLM call for ticker {} timed out after {:.1}s. Defaulting to neutral.", ticker, timeout.as_secs_f64()),
+      }),
+    };
+
+    log::debug!("[Phil Fisher Agent] ({}) LLM raw response: {}", ticker, response.content);
+
+    let parsed = match extract_json::<PhilFisherSignal>(&response.content) {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::warn!("[Phil Fisher Agent] ({}) LLM response is not valid JSON ({}), asking the model to reformat it", ticker, err);
+        repair_json::<PhilFisherSignal>(client.as_ref(), messages, &response.content, &config_for_call, timeout).await.ok_or(err)
+      }
+    };
+
+    match parsed {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::error!("[Phil Fisher Agent] ({}) Failed to parse LLM JSON response into PhilFisherSignal: {}. Raw response: '{}'", ticker, err, response.content);
+        if response.is_truncated() {
+          log::warn!("[Phil Fisher Agent] ({}) LLM response was cut off at max_tokens, raise max_tokens if this keeps happening", ticker);
+        }
+        Ok(PhilFisherSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Error in LLM analysis or response parsing for ticker {}: {}. Defaulting to neutral.", ticker, err),
+        })
+      }
+    }
+  }
+
+  // Area scores mapped onto the same 70%/30% thresholds as the other persona agents
+  fn dry_run_signal(ticker: &str, analysis_data: &HashMap<String, Value>, reason: &str) -> PhilFisherSignal {
+    let area_score = |key: &str, field: &str| analysis_data.get(key).and_then(|area| area.get(field)).and_then(Value::as_f64).unwrap_or(0.0);
+    let areas = ["growth_quality", "margins", "research", "management", "scuttlebutt"];
+    let score : f64 = areas.iter().map(|area| area_score(area, "score")).sum();
+    let max_score : f64 = areas.iter().map(|area| area_score(area, "max_score")).sum();
+
+    let ratio = if max_score > 0.0 { score / max_score } else { 0.0 };
+    let signal = if ratio >= 0.7 {
+      Signal::Bullish
+    } else if ratio <= 0.3 {
+      Signal::Bearish
+    } else {
+      Signal::Neutral
+    };
+
+    log::info!("[Phil Fisher Agent] ({}) {}, skipping the LLM call", ticker, reason);
+    PhilFisherSignal {
+      signal,
+      confidence: (ratio * 100.0).round(),
+      reasoning: format!("{}: signal derived from a score of {} out of {} without calling the LLM.", reason, score, max_score),
+    }
+  }
+}
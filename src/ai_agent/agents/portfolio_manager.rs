@@ -1,23 +1,35 @@
-use crate::ai_agent::{graph::state::{show_agent_reasoning, AgentState, PartialAgentStateUpdate}, llm::model_provider::{ChatMessage, LLMModelConfig}};
+use crate::ai_agent::{graph::state::{show_agent_reasoning, AgentState, PartialAgentStateUpdate}, llm::model_provider::{budget_allows, chat_with_timeout, ChatMessage, LLMModelConfig, BUDGET_EXHAUSTED}};
 use crate::ai_agent::llm::model_provider::{ModelProvider};
-use crate::ai_agent::llm::models::get_model;
+use crate::ai_agent::llm::circuit_breaker::{self, CIRCUIT_OPEN};
+use crate::ai_agent::llm::models::{get_ready_model, is_dry_run, llm_timeout, supports_json_mode, SamplingParams};
+use crate::ai_agent::llm::json::{extract_json, repair_json};
+use crate::ai_agent::llm::prompt::{prompt_overrides, render_prompt, PromptBuilder};
+use crate::ai_agent::data::models::Portfolio;
+use crate::ai_agent::utils::signals::{net_signal_score, signal_confidence};
 use crate::app::config::Config;
 
 use std::{collections::HashMap, result::Result}; 
 use std::str::FromStr;
-use anyhow::{Error, anyhow};
-use serde::{Deserialize, Serialize, Deserializer};
+use anyhow::{Context, Error, anyhow};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use std::{pin::Pin, future::Future};
+use std::time::Duration;
 
-#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+// Serializes as "buy"/"sell"/..., deserializes through FromStr so LLM output in any case or with stray whitespace still parses
+#[derive(Clone, Copy, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
 pub enum Action {
-  Buy, Sell, Short, Cover, Hold
+  Buy,
+  Sell,
+  Short,
+  Cover,
+  Hold
 }
 
 impl Action {
 
-  pub fn _as_str(&self) -> &'static str {
+  pub fn as_str(&self) -> &'static str {
     match self {
       &Action::Buy => "buy",
       &Action::Sell => "sell",
@@ -33,39 +45,52 @@ impl FromStr for Action {
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s.trim().to_lowercase().as_str() {
-      "buy" => Ok(Action::Buy),  // Lowercase to match the lowercase input
-      "sell" => Ok(Action::Sell),    // Lowercase to match the lowercase input
-      "short" => Ok(Action::Short),        // Lowercase to match the lowercase input
-      "cover" => Ok(Action::Cover),        // Lowercase to match the lowercase 
-      "hold" => Ok(Action::Hold),        // Lowercase to match the 
-      _ => Err(format!("Unknown model provider: {}", s)),
+      "buy" => Ok(Action::Buy),
+      "sell" => Ok(Action::Sell),
+      "short" => Ok(Action::Short),
+      "cover" => Ok(Action::Cover),
+      "hold" => Ok(Action::Hold),
+      _ => Err(format!("Unknown action: {}", s)),
     }
   }
 }
 
-fn deserialize_signal<'de, D>(deserializer: D) -> Result<Action, D::Error> where D: Deserializer<'de>,
-{
-    // First, deserialize as a String:
-    let s = String::deserialize(deserializer)?;
-    // Then parse it via your FromStr impl:
-    Action::from_str(&s).map_err(serde::de::Error::custom)
+impl<'de> Deserialize<'de> for Action {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+    let value = String::deserialize(deserializer)?;
+    Action::from_str(&value).map_err(serde::de::Error::custom)
+  }
 }
 
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PortfolioDecision {
-  #[serde(deserialize_with = "deserialize_signal")]
-  action : Action,
-  quantity: i64,
-  confidence: f64,
-  reasoning: String
+  pub action : Action,
+  #[serde(default)] // left out by the model when it sizes the order in dollars
+  pub quantity: f64,
+  pub confidence: f64,
+  pub reasoning: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub notional: Option<f64>, // dollar value of the order at the current price, unset when the ticker has no price
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct PortfolioManagerOutput {
-  decisions : HashMap<String, PortfolioDecision>
+  pub decisions : HashMap<String, PortfolioDecision>,
+  #[serde(default)]
+  pub weighted_signals : HashMap<String, f64>, // confidence-weighted net analyst signal per ticker, in [-1, 1]
 }
 
+// Weighted signals beyond this band buy, or sell existing longs, when the LLM is skipped or fails
+const WEIGHTED_SIGNAL_BAND: f64 = 0.1;
+
+// Matches the risk manager's 20% per-position limit
+const MAX_POSITION_WEIGHT_PCT: f64 = 20.0;
+
+// Trading decisions should be more conservative than the analysts' 0.5
+pub const DEFAULT_DECISION_TEMPERATURE: f32 = 0.2;
+const _: () = assert!(DEFAULT_DECISION_TEMPERATURE < crate::ai_agent::llm::models::DEFAULT_AGENT_TEMPERATURE);
+
 pub struct PortfolioManagerAgent; 
 
 impl PortfolioManagerAgent {
@@ -82,8 +107,8 @@ impl PortfolioManagerAgent {
 
   pub async fn portfolio_management_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
 
-    let portfolio = match state.data.get("portfolio") {
-      Some(portfolio) => portfolio, 
+    let portfolio : Portfolio = match state.data.get("portfolio") {
+      Some(portfolio) => serde_json::from_value(portfolio.clone()).context("Malformed portfolio in state.data")?,
       _ => {
         log::error!("Cannot find portfolio inside state.data"); 
         return Ok(PartialAgentStateUpdate::new());
@@ -110,7 +135,10 @@ impl PortfolioManagerAgent {
 
     let mut position_limits: HashMap<String, f64> = HashMap::new(); 
     let mut current_prices: HashMap<String, f64> = HashMap::new(); 
-    let mut max_shares : HashMap<String, i64> = HashMap::new(); 
+    let mut max_shares : HashMap<String, f64> = HashMap::new(); 
+
+    let allow_fractional : bool = state.metadata.get("allow_fractional").and_then(Value::as_bool).unwrap_or(false);
+    let notional_sizing : bool = state.metadata.get("sizing").and_then(Value::as_str) == Some("notional");
     let mut signals_by_ticker: HashMap<String, HashMap<String, Value>> = HashMap::new(); 
 
     for ticker in &tickers {
@@ -128,10 +156,10 @@ impl PortfolioManagerAgent {
       current_prices.insert(ticker.clone(), current_price); 
 
       let max_share = if current_price > 0.0 {
-        (position_limit / current_price) as i64
+        Self::size_quantity(position_limit / current_price, allow_fractional)
       }
       else {
-        0
+        0.0
       };
 
       max_shares.insert(ticker.clone(), max_share); 
@@ -148,8 +176,8 @@ impl PortfolioManagerAgent {
                   signal_data.insert("signal".to_string(), Value::String(signal.to_string()));
                 }
                 
-                if let Some(confidence) = ticker_signal.get("confidence").and_then(Value::as_f64) {
-                  signal_data.insert("confidence".to_string(), Value::from(confidence));
+                if ticker_signal.get("confidence").is_some() {
+                  signal_data.insert("confidence".to_string(), Value::from(signal_confidence(ticker_signal)));
                 }
                 
                 ticker_signals.insert(agent.clone(), Value::Object(signal_data.into_iter().collect()));
@@ -161,6 +189,58 @@ impl PortfolioManagerAgent {
       signals_by_ticker.insert(ticker.clone(), ticker_signals); 
     }
 
+    if state.metadata.get("output_mode").and_then(Value::as_str) == Some("advisory") {
+      log::info!("Portfolio manager: advisory mode, emitting signals and target weights");
+      let recommendations = Self::advisory_recommendations(&tickers, &signals_by_ticker);
+      let message_content = serde_json::to_string(&recommendations)?;
+
+      if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+        show_agent_reasoning(&message_content, "Portfolio Manager");
+      }
+
+      let mut data = HashMap::new();
+      data.insert("recommendations".to_string(), serde_json::to_value(&recommendations)?);
+
+      let mut result = PartialAgentStateUpdate::new();
+      result = result.with_messages(vec![ChatMessage::from_agent("portfolio_manager", message_content)]);
+      result = result.with_data(data);
+      return Ok(result);
+    }
+
+    // Tickers the risk manager could not price (invalid symbol, no data) or that too few analysts
+    // have an opinion on are held without consulting the LLM
+    let min_analysts : usize = state.metadata.get("min_analysts").and_then(Value::as_u64).unwrap_or(0) as usize;
+    let mut excluded: HashMap<String, String> = HashMap::new();
+
+    for ticker in &tickers {
+      if current_prices.get(ticker).map_or(true, |price| *price <= 0.0) {
+        excluded.insert(ticker.clone(), "Excluded by filters: no price data available, defaulting to hold".to_string());
+        continue;
+      }
+
+      let coverage = signals_by_ticker.get(ticker).map_or(0, |signals| Self::count_active_signals(signals));
+      if coverage < min_analysts {
+        excluded.insert(ticker.clone(), format!("Insufficient analyst coverage: {} of {} required non-neutral signals, defaulting to hold", coverage, min_analysts));
+      }
+    }
+
+    let eligible_tickers: Vec<String> = tickers.iter().filter(|ticker| !excluded.contains_key(*ticker)).cloned().collect();
+
+    if eligible_tickers.is_empty() {
+      log::warn!("Portfolio manager: no eligible tickers after filters, skipping trading decision");
+      let holds = Self::hold_decisions(&excluded);
+      let message = ChatMessage::from_agent("portfolio_manager", serde_json::to_string(&holds)?);
+      let mut data = HashMap::new();
+      data.insert("decisions".to_string(), serde_json::to_value(&holds)?);
+      data.insert("short_circuit_reason".to_string(), Value::from("no eligible tickers after filters"));
+
+      let mut result = PartialAgentStateUpdate::new();
+      result = result.with_messages(vec![message]);
+      result = result.with_data(data);
+      result = result.with_portfolio_output(PortfolioManagerOutput { decisions: holds, weighted_signals: Self::weighted_signals(&tickers, &signals_by_ticker) });
+      return Ok(result);
+    }
+
     log::info!("Portfolio_manager generating trading decision");
 
     let model_name: &str= if let Some(model_name) = state.metadata.get("model_name").and_then(Value::as_str) {
@@ -177,14 +257,19 @@ impl PortfolioManagerAgent {
       return Ok(PartialAgentStateUpdate::new());
     };
 
-    let result = self.generate_trading_decision(config, &tickers, &signals_by_ticker, &current_prices, &max_shares, portfolio, model_name, &model_provider).await?;
+    let sampling : SamplingParams = Self::decision_sampling(&state.metadata);
+    let timeout : Duration = llm_timeout(&state.metadata, &config);
+    let dry_run : bool = is_dry_run(&state.metadata);
+
+    let mut result = self.generate_trading_decision(config, &eligible_tickers, &signals_by_ticker, &current_prices, &max_shares, &portfolio, model_name, &model_provider, allow_fractional, notional_sizing, sampling, timeout, dry_run, &prompt_overrides(&state.metadata)).await?;
+
+    result.decisions.extend(Self::hold_decisions(&excluded));
+    Self::fill_notional(&mut result.decisions, &current_prices);
+    result.weighted_signals = Self::weighted_signals(&tickers, &signals_by_ticker);
 
     let message_content = serde_json::to_string(&result.decisions)?;
 
-    let message = ChatMessage {
-      role: "assistant".to_string(),
-      content: message_content.clone(),
-    };
+    let message = ChatMessage::from_agent("portfolio_manager", message_content.clone());
 
     if let Some(show_reasoning) = state.metadata.get("show_reasoning").and_then(Value::as_bool) {
       if show_reasoning {
@@ -192,25 +277,173 @@ impl PortfolioManagerAgent {
       }
     }
 
-    let mut result = PartialAgentStateUpdate::new();
-    result = result.with_messages(vec![message]);
-    result = result.with_data(state.data.clone());
+    // Decisions travel typed on the state and as data, earlier agents' data (analyst_signals included) stays untouched
+    let mut data = HashMap::new();
+    data.insert("decisions".to_string(), serde_json::to_value(&result.decisions)?);
+    let update = PartialAgentStateUpdate::new().with_messages(vec![message]).with_data(data).with_portfolio_output(result);
+
+    return Ok(update);  
+
+  }
+
+
+  fn advisory_recommendations(tickers: &[String], signals_by_ticker: &HashMap<String, HashMap<String, Value>>) -> HashMap<String, Value> {
+    // Bullish consensus earns a weight up to the per-position cap, everything else stays in cash
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for ticker in tickers {
+      let score = signals_by_ticker.get(ticker).map_or(0.0, |signals| net_signal_score(signals.values()));
+      scores.insert(ticker.clone(), score);
+    }
+
+    let mut weights: HashMap<String, f64> = scores.iter().map(|(ticker, score)| {
+      (ticker.clone(), if *score > 0.0 { score * MAX_POSITION_WEIGHT_PCT } else { 0.0 })
+    }).collect();
+
+    let total_weight: f64 = weights.values().sum();
+    if total_weight > 100.0 {
+      for weight in weights.values_mut() {
+        *weight = *weight * 100.0 / total_weight;
+      }
+    }
+
+    tickers.iter().map(|ticker| {
+      let score = scores.get(ticker).copied().unwrap_or(0.0);
+      let signal = if score > 0.1 { "bullish" } else if score < -0.1 { "bearish" } else { "neutral" };
+      // Rounded down so the scaled weights never add up to more than 100%
+      let weight = (weights.get(ticker).copied().unwrap_or(0.0) * 100.0).floor() / 100.0;
+      (ticker.clone(), serde_json::json!({
+        "signal": signal,
+        "weight_pct": weight,
+        "confidence": (score.abs() * 100.0).round(),
+        "reasoning": format!("Confidence-weighted analyst consensus of {:.2} across {} analysts", score, signals_by_ticker.get(ticker).map_or(0, |signals| signals.len())),
+      }))
+    }).collect()
+  }
+
+  // decision_temperature only tunes this agent and wins over the run-wide temperature, deterministic runs keep 0
+  fn decision_sampling(metadata: &HashMap<String, Value>) -> SamplingParams {
+    let sampling : SamplingParams = SamplingParams::from_metadata(metadata, DEFAULT_DECISION_TEMPERATURE);
+    let deterministic = metadata.get("deterministic").and_then(Value::as_bool).unwrap_or(false);
+    let decision_temperature = metadata.get("decision_temperature").and_then(Value::as_f64).filter(|_| !deterministic);
+    return decision_temperature.map_or(sampling, |temperature| sampling.with_temperature(temperature as f32));
+  }
+
+  fn count_active_signals(signals: &HashMap<String, Value>) -> usize {
+    signals.values().filter(|signal| {
+      match signal.get("signal").and_then(Value::as_str) {
+        Some(value) => !value.eq_ignore_ascii_case("neutral"),
+        None => false,
+      }
+    }).count()
+  }
+
+  fn hold_decisions(reasons: &HashMap<String, String>) -> HashMap<String, PortfolioDecision> {
+    reasons.iter().map(|(ticker, reason)| {
+      (ticker.clone(), PortfolioDecision {
+        action: Action::Hold,
+        quantity: 0.0,
+        confidence: 0.0,
+        reasoning: reason.clone(),
+        notional: None,
+      })
+    }).collect()
+  }
+
+  fn weighted_signals(tickers: &[String], signals_by_ticker: &HashMap<String, HashMap<String, Value>>) -> HashMap<String, f64> {
+    tickers.iter().map(|ticker| {
+      let score = signals_by_ticker.get(ticker).map_or(0.0, |signals| net_signal_score(signals.values()));
+      (ticker.clone(), (score * 100.0).round() / 100.0)
+    }).collect()
+  }
+
+  // Deterministic decisions from the weighted signals: bullish buys up to max_shares, bearish closes any long
+  // position, everything else holds. `reason` says why the LLM was not used
+  fn weighted_decisions(tickers: &[String], signals_by_ticker: &HashMap<String, HashMap<String, Value>>, max_shares: &HashMap<String, f64>,
+                        portfolio: &Portfolio, allow_fractional: bool, reason: &str) -> PortfolioManagerOutput {
+    let weighted_signals = Self::weighted_signals(tickers, signals_by_ticker);
+    let decisions = tickers.iter().map(|ticker| {
+      let score = weighted_signals.get(ticker).copied().unwrap_or(0.0);
+      let long_shares = portfolio.positions.get(ticker).map_or(0.0, |position| position.long);
+
+      let (action, quantity) = if score > WEIGHTED_SIGNAL_BAND {
+        (Action::Buy, Self::size_quantity(max_shares.get(ticker).copied().unwrap_or(0.0), allow_fractional))
+      } else if score < -WEIGHTED_SIGNAL_BAND && long_shares > 0.0 {
+        (Action::Sell, long_shares)
+      } else {
+        (Action::Hold, 0.0)
+      };
+      let action = if quantity > 0.0 { action } else { Action::Hold };
+
+      (ticker.clone(), PortfolioDecision {
+        action,
+        quantity,
+        confidence: (score.abs() * 100.0).round(),
+        reasoning: format!("{}: {} from a confidence-weighted analyst signal of {:.2}", reason, action.as_str(), score),
+        notional: None,
+      })
+    }).collect();
+    PortfolioManagerOutput { decisions, weighted_signals }
+  }
+
+  fn size_quantity(quantity: f64, allow_fractional: bool) -> f64 {
+    // Whole shares unless the brokerage supports fractional quantities
+    if quantity <= 0.0 {
+      return 0.0;
+    }
+    if allow_fractional {
+      return (quantity * 10_000.0).floor() / 10_000.0;
+    }
+    return quantity.floor();
+  }
+
+  // Dollar-sized decisions become shares at the current price, either way rounded to what the brokerage can fill
+  fn order_quantity(decision: &PortfolioDecision, price: f64, notional_sizing: bool, allow_fractional: bool) -> f64 {
+    let quantity = match decision.notional {
+      Some(notional) if notional_sizing && price > 0.0 => notional / price,
+      _ => decision.quantity,
+    };
+    return Self::size_quantity(quantity, allow_fractional);
+  }
 
-    return Ok(result);  
+  // Dollar value of each order at the current price, left unset where no price is known
+  fn fill_notional(decisions: &mut HashMap<String, PortfolioDecision>, current_prices: &HashMap<String, f64>) {
+    for (ticker, decision) in decisions.iter_mut() {
+      decision.notional = current_prices.get(ticker).filter(|price| **price > 0.0).map(|price| decision.quantity * price);
+    }
+  }
 
+  // Kept out of the system template so prompt overrides cannot drop it
+  fn sizing_rules(allow_fractional: bool, notional_sizing: bool) -> &'static str {
+    match (notional_sizing, allow_fractional) {
+      (false, false) => "Quantities are whole shares, the brokerage cannot fill fractional orders.",
+      (false, true) => "Quantities may be fractional shares, up to 4 decimals.",
+      (true, false) => "Size each order in dollars with \"notional\" instead of \"quantity\". It is converted to whole shares at the current price and rounded down, so an amount below one share's price trades nothing. Buys must not exceed the maximum dollar amount for the ticker.",
+      (true, true) => "Size each order in dollars with \"notional\" instead of \"quantity\". It is converted to fractional shares, up to 4 decimals, at the current price. Buys must not exceed the maximum dollar amount for the ticker.",
+    }
   }
 
 
+  #[tracing::instrument(skip_all, fields(tickers = tickers.len(), model = %model_name))]
   pub async fn generate_trading_decision(&self, config: Config, tickers: &[String], signals_by_ticker : &HashMap<String, HashMap<String, Value>>, 
-                                  current_prices: &HashMap<String, f64>, max_shares: &HashMap<String, i64>, portfolio: &Value,
-                                  model_name: &str, model_provider: &str) -> Result<PortfolioManagerOutput, Error> {
+                                  current_prices: &HashMap<String, f64>, max_shares: &HashMap<String, f64>, portfolio: &Portfolio,
+                                  model_name: &str, model_provider: &str, allow_fractional: bool, notional_sizing: bool, sampling: SamplingParams, timeout: Duration, dry_run: bool,
+                                  prompt_overrides: &HashMap<String, String>) -> Result<PortfolioManagerOutput, Error> {
 
-    let portfolio_cash: f64 = portfolio.get("cash").and_then(Value::as_f64).unwrap_or(0.0);
-    let portfolio_position = portfolio.get("positions").cloned().unwrap_or_else(|| Value::Object(serde_json::Map::new()));
-    let margin_requirement: f64 = portfolio.get("margin_requirement").and_then(Value::as_f64).unwrap_or(0.0); 
-    let total_margin_used: f64 = portfolio.get("margin_used").and_then(Value::as_f64).unwrap_or(0.0); 
+    if dry_run {
+      log::info!("Portfolio manager dry run, skipping the LLM call");
+      return Ok(Self::weighted_decisions(tickers, signals_by_ticker, max_shares, portfolio, allow_fractional, "Dry run, LLM not called"));
+    }
+    if !budget_allows(sampling.max_tokens as u64) {
+      log::warn!("Portfolio manager: {}, deciding from the weighted signals", BUDGET_EXHAUSTED);
+      return Ok(Self::weighted_decisions(tickers, signals_by_ticker, max_shares, portfolio, allow_fractional, &format!("{}, LLM not called", BUDGET_EXHAUSTED)));
+    }
 
-    let system_prompt = r#"You are a portfolio manager making final trading decisions based on multiple tickers.
+    let portfolio_cash: f64 = portfolio.cash;
+    let portfolio_position = &portfolio.positions;
+    let margin_requirement: f64 = portfolio.margin_requirement;
+    let total_margin_used: f64 = portfolio.margin_used;
+
+    let default_system_prompt = r#"You are a portfolio manager making final trading decisions based on multiple tickers.
                                         Trading Rules:
                                           - For long positions:
                                             * Only buy if you have available cash
@@ -244,91 +477,326 @@ impl PortfolioManagerAgent {
                                           - margin_requirement: current margin requirement for short positions (e.g., 0.5 means 50%)
                                           - total_margin_used: total margin currently in use"#;
 
-    let human_prompt = format!(r#"Based on the team's analysis, make your trading decisions for each ticker.
-                                        Here are the signals by ticker:
-                                        {}
-
-                                        Current Prices:
-                                        {}
-
-                                        Maximum Shares Allowed For Purchases:
-                                        {}
-
-                                        Portfolio Cash: {:.2}
-                                        Current Positions: {}
-                                        Current Margin Requirement: {:.2}
-                                        Total Margin Used: {:.2}
-
-                                        Output strictly in JSON with the following structure without any explanation:
-                                        {{
-                                          "decisions": {{
-                                            "TICKER1": {{
-                                              "action": "buy/sell/short/cover/hold",
-                                              "quantity": integer,
-                                              "confidence": float between 0 and 100,
-                                              "reasoning": "string"
-                                            }},
-                                            "TICKER2": {{
-                                              ...
-                                            }},
-                                            ...
-                                          }}
-                                        }}
-                                        "#, 
-                                      serde_json::to_string_pretty(signals_by_ticker)?,serde_json::to_string_pretty(current_prices)?,
-                                      serde_json::to_string_pretty(max_shares)?, portfolio_cash, serde_json::to_string_pretty(&portfolio_position)?,
-                                      margin_requirement, total_margin_used);
-
-    let messages = vec![
-      ChatMessage {
-        role: "system".to_string(), 
-        content: system_prompt.to_string()
-      }, 
-      ChatMessage {
-        role: "user".to_string(),
-        content: human_prompt
-      }
-    ]; 
+    let size_field : String = if notional_sizing {
+      r#""notional": float, dollar amount to trade"#.to_string()
+    } else {
+      let quantity_type : &str = if allow_fractional { "number (fractional shares allowed, up to 4 decimals)" } else { "integer" };
+      format!(r#""quantity": {}"#, quantity_type)
+    };
+
+    let output_schema = format!(r#"{{
+  "decisions": {{
+    "TICKER1": {{
+      "action": "buy/sell/short/cover/hold",
+      {},
+      "confidence": float between 0 and 100,
+      "reasoning": "string"
+    }},
+    "TICKER2": {{
+      ...
+    }},
+    ...
+  }}
+}}"#, size_field);
+
+    // Notional runs see the same limits in dollars, whole shares at the current price
+    let (max_title, max_sizes) : (&str, HashMap<String, f64>) = if notional_sizing {
+      let max_notional = max_shares.iter()
+        .map(|(ticker, shares)| (ticker.clone(), (shares * current_prices.get(ticker).copied().unwrap_or(0.0) * 100.0).floor() / 100.0))
+        .collect();
+      ("Maximum Dollar Amount Allowed For Purchases", max_notional)
+    } else {
+      ("Maximum Shares Allowed For Purchases", max_shares.clone())
+    };
+
+    let json_mode : bool = supports_json_mode(model_name);
+
+    // Templates can use {tickers} and {portfolio_cash}
+    let tickers_list : String = tickers.join(", ");
+    let cash : String = format!("{:.2}", portfolio_cash);
+    let vars = [("tickers", tickers_list.as_str()), ("portfolio_cash", cash.as_str())];
+    let system_prompt : String = render_prompt("portfolio_manager.system", default_system_prompt, prompt_overrides, &vars);
+    let instruction : String = render_prompt("portfolio_manager.instruction", "Based on the team's analysis, make your trading decisions for each ticker.", prompt_overrides, &vars);
+
+    let messages = PromptBuilder::new()
+      .with_system(&system_prompt)
+      .with_instruction(&instruction)
+      .with_section("Here are the signals by ticker", &serde_json::to_string_pretty(signals_by_ticker)?)
+      .with_section("Confidence-Weighted Net Signal By Ticker (-1 unanimously bearish, 1 unanimously bullish)", &serde_json::to_string_pretty(&Self::weighted_signals(tickers, signals_by_ticker))?)
+      .with_section("Current Prices", &serde_json::to_string_pretty(current_prices)?)
+      .with_section(max_title, &serde_json::to_string_pretty(&max_sizes)?)
+      .with_section("Order Sizing", Self::sizing_rules(allow_fractional, notional_sizing))
+      .with_section("Portfolio Cash", &format!("{:.2}", portfolio_cash))
+      .with_section("Current Positions", &serde_json::to_string_pretty(&portfolio_position)?)
+      .with_section("Current Margin Requirement", &format!("{:.2}", margin_requirement))
+      .with_section("Total Margin Used", &format!("{:.2}", total_margin_used))
+      .with_json_schema(&output_schema)
+      .with_json_mode(json_mode)
+      .build();
 
     let provider = ModelProvider::from_str(model_provider).map_err(|_| anyhow!("Unknown model provider: {}",model_provider))?;
 
-    let config_for_call : LLMModelConfig = LLMModelConfig { 
-      provider: provider, 
-      model_name: model_name.to_string(), 
-      api_key:Some(config.groq_api_key.to_string()) , 
-      base_url: Some("".to_string()), 
-      temperature: Some(0.5), 
-      max_tokens: Some(1024), 
-      top_p: Some(0.5)
-    };
+    let config_for_call : LLMModelConfig = sampling.to_config(provider, model_name, json_mode);
 
-    let model = get_model(&config_for_call)?; 
+    if circuit_breaker::is_open(&config_for_call) {
+      log::warn!("Portfolio manager: {}, deciding from the weighted signals", CIRCUIT_OPEN);
+      return Ok(Self::weighted_decisions(tickers, signals_by_ticker, max_shares, portfolio, allow_fractional, &format!("{}, LLM not called", CIRCUIT_OPEN)));
+    }
+    let model = get_ready_model(&config_for_call, &config)?; 
 
     log::info!("Calling LLM for portfolio decisions...");
-    let response = model.chat(messages, &config_for_call).await?;
+    let response = match chat_with_timeout(model.as_ref(), messages.clone(), &config_for_call, timeout).await? {
+      Some(response) => response,
+      None => {
+        let reason = format!("LLM call timed out after {:.1}s", timeout.as_secs_f64());
+        return Ok(Self::weighted_decisions(tickers, signals_by_ticker, max_shares, portfolio, allow_fractional, &reason));
+      }
+    };
     log::debug!("LLM response: {}", response.content);
 
 
-    match serde_json::from_str::<PortfolioManagerOutput>(&response.content) {
+    let parsed = match extract_json::<PortfolioManagerOutput>(&response.content) {
       Ok(output) => Ok(output),
+      Err(e) => {
+        log::warn!("Portfolio manager LLM response is not valid JSON ({}), asking the model to reformat it", e);
+        repair_json::<PortfolioManagerOutput>(model.as_ref(), messages, &response.content, &config_for_call, timeout).await.ok_or(e)
+      }
+    };
+
+    match parsed {
+      Ok(mut output) => {
+        for (ticker, decision) in output.decisions.iter_mut() {
+          let price = current_prices.get(ticker).copied().unwrap_or(0.0);
+          decision.quantity = Self::order_quantity(decision, price, notional_sizing, allow_fractional);
+        }
+        Ok(output)
+      },
       Err(e) => {
         log::error!("Failed to parse LLM response: {}", e);
-        
-        // Create a default output
-        let mut decisions = HashMap::new();
-        for ticker in tickers {
-          decisions.insert(ticker.clone(), PortfolioDecision {
-            action: Action::Hold,
-            quantity: 0,
-            confidence: 0.0,
-            reasoning: "Error in portfolio management, defaulting to hold".to_string(),
-          });
+        if response.is_truncated() {
+          log::warn!("Portfolio manager LLM response was cut off at max_tokens, raise max_tokens if this keeps happening");
         }
-        
-        Ok(PortfolioManagerOutput { decisions })
+        Ok(Self::weighted_decisions(tickers, signals_by_ticker, max_shares, portfolio, allow_fractional, "LLM response could not be parsed"))
       }
     }
   }
 
 
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn decision(quantity: f64, notional: Option<f64>) -> PortfolioDecision {
+    PortfolioDecision { action: Action::Buy, quantity, confidence: 70.0, reasoning: String::new(), notional }
+  }
+
+  // A dry run on one ticker, so the weighted-signal path sizes the order without an LLM
+  fn dry_run_state(price: f64, position_limit: f64, metadata: Value) -> AgentState {
+    let tickers = vec!["PMHIGH".to_string()];
+    let mut state = AgentState::new();
+    state.merge_data(serde_json::from_value(json!({
+      "tickers": tickers,
+      "portfolio": Portfolio::new(&tickers, 1000.0, 0.5),
+      "analyst_signals": {
+        "risk_management_agent": { "PMHIGH": { "current_price": price, "remaining_position_limit": position_limit } },
+        "warren_buffett_agent": { "PMHIGH": { "signal": "bullish", "confidence": 90.0 } },
+      },
+    })).unwrap()).unwrap();
+    let mut metadata = metadata;
+    metadata["dry_run"] = json!(true);
+    metadata["model_name"] = json!("test-model");
+    metadata["model_provider"] = json!("OpenAI");
+    state.merge_metadata(serde_json::from_value(metadata).unwrap()).unwrap();
+    state
+  }
+
+  #[test]
+  fn actions_parse_in_any_case_and_with_padding() {
+    for (raw, action) in [("BUY", Action::Buy), (" buy", Action::Buy), ("Sell ", Action::Sell), ("sHoRt", Action::Short), ("\tcover\n", Action::Cover), ("Hold", Action::Hold)] {
+      assert_eq!(serde_json::from_value::<Action>(json!(raw)).unwrap(), action);
+      assert_eq!(raw.parse::<Action>().unwrap(), action);
+    }
+    assert!(serde_json::from_value::<Action>(json!("purchase")).is_err());
+  }
+
+  #[test]
+  fn actions_serialize_lowercase() {
+    assert_eq!(serde_json::to_value(Action::Cover).unwrap(), json!("cover"));
+    let decision : PortfolioDecision = serde_json::from_value(json!({ "action": " SELL", "quantity": 3.0, "confidence": 50.0, "reasoning": "" })).unwrap();
+    assert_eq!(serde_json::to_value(&decision).unwrap()["action"], json!("sell"));
+  }
+
+  #[test]
+  fn decisions_use_the_decision_specific_temperature() {
+    let metadata = |value: Value| -> HashMap<String, Value> { serde_json::from_value(value).unwrap() };
+
+    assert_eq!(PortfolioManagerAgent::decision_sampling(&metadata(json!({}))).temperature, DEFAULT_DECISION_TEMPERATURE);
+    assert_eq!(PortfolioManagerAgent::decision_sampling(&metadata(json!({ "temperature": 0.9, "decision_temperature": 0.1 }))).temperature, 0.1);
+    // The run-wide temperature still applies when no decision temperature is given
+    assert_eq!(PortfolioManagerAgent::decision_sampling(&metadata(json!({ "temperature": 0.9 }))).temperature, 0.9);
+    assert_eq!(PortfolioManagerAgent::decision_sampling(&metadata(json!({ "deterministic": true, "decision_temperature": 0.7 }))).temperature, 0.0);
+    // A zero run-wide temperature is not the deterministic flag, the decision temperature still wins and drops the seed
+    let sampling = PortfolioManagerAgent::decision_sampling(&metadata(json!({ "temperature": 0.0, "decision_temperature": 0.7 })));
+    assert_eq!((sampling.temperature, sampling.seed), (0.7, None));
+  }
+
+  #[tokio::test]
+  async fn filtering_out_every_ticker_short_circuits_to_holds() {
+    // No price means the risk manager could not validate the ticker, and the model metadata is never needed
+    let mut state = dry_run_state(0.0, 1000.0, json!({}));
+    state.metadata.remove("model_name");
+
+    let update = PortfolioManagerAgent::new().portfolio_management_agent(state, Config::for_tests()).await.unwrap();
+    assert_eq!(update.data.unwrap()["short_circuit_reason"], json!("no eligible tickers after filters"));
+    let decision = &update.portfolio_output.unwrap().decisions["PMHIGH"];
+    assert_eq!(decision.action, Action::Hold);
+    assert_eq!(decision.quantity, 0.0);
+    assert!(decision.reasoning.starts_with("Excluded by filters"));
+  }
+
+  #[test]
+  fn every_action_round_trips_through_serde() {
+    for action in [Action::Buy, Action::Sell, Action::Short, Action::Cover, Action::Hold] {
+      let serialized = serde_json::to_value(action).unwrap();
+      assert_eq!(serialized, json!(action.as_str()));
+      assert_eq!(serde_json::from_value::<Action>(serialized).unwrap(), action);
+    }
+  }
+
+  #[tokio::test]
+  async fn a_single_covering_analyst_is_forced_to_hold_under_min_analysts() {
+    let update = PortfolioManagerAgent::new().portfolio_management_agent(dry_run_state(100.0, 1000.0, json!({ "min_analysts": 2 })), Config::for_tests()).await.unwrap();
+    let decision = &update.portfolio_output.unwrap().decisions["PMHIGH"];
+    assert_eq!(decision.action, Action::Hold);
+    assert!(decision.reasoning.starts_with("Insufficient analyst coverage: 1 of 2"), "{}", decision.reasoning);
+
+    // The same single signal is enough once the requirement is met
+    let update = PortfolioManagerAgent::new().portfolio_management_agent(dry_run_state(100.0, 1000.0, json!({ "min_analysts": 1 })), Config::for_tests()).await.unwrap();
+    assert_eq!(update.portfolio_output.unwrap().decisions["PMHIGH"].action, Action::Buy);
+  }
+
+  #[test]
+  fn advisory_weights_stay_within_capital_and_carry_no_quantities() {
+    let tickers: Vec<String> = ["AAPL", "MSFT", "NVDA", "TSLA", "AMZN", "META", "GOOG", "XOM"].iter().map(|ticker| ticker.to_string()).collect();
+    let mut signals: HashMap<String, HashMap<String, Value>> = tickers.iter().map(|ticker| (ticker.clone(), HashMap::from([
+      ("warren_buffett_agent".to_string(), json!({ "signal": "bullish", "confidence": 100.0 })),
+    ]))).collect();
+    signals.insert("XOM".to_string(), HashMap::from([("warren_buffett_agent".to_string(), json!({ "signal": "bearish", "confidence": 90.0 }))]));
+
+    let recommendations = PortfolioManagerAgent::advisory_recommendations(&tickers, &signals);
+    let total: f64 = recommendations.values().map(|recommendation| recommendation["weight_pct"].as_f64().unwrap()).sum();
+    // Seven full-confidence longs ask for 140%, scaled back to fit the capital
+    assert!(total <= 100.0, "weights sum to {}", total);
+    assert!(total > 99.9, "weights sum to {}", total);
+    assert_eq!(recommendations["XOM"]["weight_pct"], json!(0.0));
+    assert_eq!(recommendations["XOM"]["signal"], json!("bearish"));
+    for recommendation in recommendations.values() {
+      assert!(recommendation.get("quantity").is_none());
+    }
+  }
+
+  #[test]
+  fn integer_mode_floors_to_whole_shares() {
+    assert_eq!(PortfolioManagerAgent::size_quantity(12.97, false), 12.0);
+    assert_eq!(PortfolioManagerAgent::size_quantity(0.6, false), 0.0);
+    assert_eq!(PortfolioManagerAgent::size_quantity(-3.0, false), 0.0);
+  }
+
+  #[test]
+  fn fractional_mode_keeps_four_decimals() {
+    assert_eq!(PortfolioManagerAgent::size_quantity(12.97654, true), 12.9765);
+    assert_eq!(PortfolioManagerAgent::size_quantity(0.6, true), 0.6);
+    assert_eq!(PortfolioManagerAgent::size_quantity(-3.0, true), 0.0);
+  }
+
+  #[test]
+  fn notional_orders_convert_at_the_current_price() {
+    let order = decision(0.0, Some(1000.0));
+    assert_eq!(PortfolioManagerAgent::order_quantity(&order, 300.0, true, false), 3.0);
+    assert_eq!(PortfolioManagerAgent::order_quantity(&order, 300.0, true, true), 3.3333);
+    // Below one share's price nothing is bought in integer mode
+    assert_eq!(PortfolioManagerAgent::order_quantity(&decision(0.0, Some(250.0)), 300.0, true, false), 0.0);
+  }
+
+  #[test]
+  fn share_mode_ignores_a_stray_notional() {
+    let order = decision(4.5, Some(1000.0));
+    assert_eq!(PortfolioManagerAgent::order_quantity(&order, 300.0, false, false), 4.0);
+    assert_eq!(PortfolioManagerAgent::order_quantity(&order, 300.0, false, true), 4.5);
+  }
+
+  #[test]
+  fn notional_is_left_unset_without_a_price() {
+    let mut decisions = HashMap::from([
+      ("AAPL".to_string(), decision(2.0, None)),
+      ("MSFT".to_string(), decision(0.0, None)),
+      ("NOPE".to_string(), decision(0.0, None)),
+    ]);
+    let prices = HashMap::from([("AAPL".to_string(), 150.0), ("MSFT".to_string(), 0.0)]);
+    PortfolioManagerAgent::fill_notional(&mut decisions, &prices);
+
+    assert_eq!(decisions["AAPL"].notional, Some(300.0));
+    assert_eq!(decisions["MSFT"].notional, None);
+    assert_eq!(decisions["NOPE"].notional, None);
+
+    let serialized = serde_json::to_value(&decisions["NOPE"]).unwrap();
+    assert!(serialized.get("notional").is_none());
+  }
+
+  #[test]
+  fn dollar_sized_llm_output_parses_without_a_quantity() {
+    let parsed : PortfolioManagerOutput = serde_json::from_value(json!({
+      "decisions": { "AAPL": { "action": "buy", "notional": 2500.0, "confidence": 80.0, "reasoning": "strong consensus" } }
+    })).unwrap();
+    let aapl = &parsed.decisions["AAPL"];
+    assert_eq!(aapl.quantity, 0.0);
+    assert_eq!(aapl.notional, Some(2500.0));
+  }
+
+  #[test]
+  fn sizing_rules_follow_the_toggles() {
+    assert!(PortfolioManagerAgent::sizing_rules(false, false).contains("whole shares"));
+    assert!(PortfolioManagerAgent::sizing_rules(true, false).contains("fractional"));
+    assert!(PortfolioManagerAgent::sizing_rules(false, true).contains("notional"));
+    assert!(PortfolioManagerAgent::sizing_rules(false, true).contains("whole shares"));
+    assert!(PortfolioManagerAgent::sizing_rules(true, true).contains("fractional"));
+  }
+
+  #[test]
+  fn weighted_decisions_respect_the_fractional_toggle() {
+    let tickers = vec!["AAPL".to_string()];
+    let signals = HashMap::from([("AAPL".to_string(), HashMap::from([
+      ("warren_buffett_agent".to_string(), json!({ "signal": "bullish", "confidence": 90.0 })),
+    ]))]);
+    let portfolio = Portfolio::new(&tickers, 10000.0, 0.5);
+    let max_shares = HashMap::from([("AAPL".to_string(), 6.6667)]);
+
+    let whole = PortfolioManagerAgent::weighted_decisions(&tickers, &signals, &max_shares, &portfolio, false, "test");
+    assert_eq!(whole.decisions["AAPL"].action, Action::Buy);
+    assert_eq!(whole.decisions["AAPL"].quantity, 6.0);
+
+    let fractional = PortfolioManagerAgent::weighted_decisions(&tickers, &signals, &max_shares, &portfolio, true, "test");
+    assert_eq!(fractional.decisions["AAPL"].quantity, 6.6667);
+  }
+
+  #[tokio::test]
+  async fn high_priced_stock_buys_a_fraction_under_the_flag() {
+    let agent = PortfolioManagerAgent::new();
+
+    // $1,000 of room on a $4,000 share is a quarter share, which whole-share sizing turns into a hold
+    let whole = agent.portfolio_management_agent(dry_run_state(4000.0, 1000.0, json!({})), Config::for_tests()).await.unwrap();
+    let whole = &whole.portfolio_output.unwrap().decisions["PMHIGH"];
+    assert_eq!(whole.action, Action::Hold);
+    assert_eq!(whole.quantity, 0.0);
+
+    let fractional = agent.portfolio_management_agent(dry_run_state(4000.0, 1000.0, json!({ "allow_fractional": true })), Config::for_tests()).await.unwrap();
+    let fractional = &fractional.portfolio_output.unwrap().decisions["PMHIGH"];
+    assert_eq!(fractional.action, Action::Buy);
+    assert_eq!(fractional.quantity, 0.25);
+    assert_eq!(fractional.notional, Some(1000.0));
+  }
+}
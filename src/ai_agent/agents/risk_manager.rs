@@ -1,16 +1,35 @@
-use anyhow::Error;
+use anyhow::{Context, Error};
 use serde_json:: Value;
-use std::collections::HashMap; 
+use std::collections::{BTreeMap, HashMap}; 
+use polars::prelude::DataFrame;
 use std::result::Result::{Ok};
 use std::future::Future; 
 use std::pin::Pin;
 
 
 use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate, show_agent_reasoning}; 
+use crate::ai_agent::data::models::{Portfolio, PriceGap};
 use crate::ai_agent::llm::model_provider::ChatMessage;
 use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
 use crate::app::config::Config;
 
+// Share of portfolio value a single position may take before the correlation adjustment
+const MAX_POSITION_WEIGHT: f64 = 0.20;
+// A ticker perfectly correlated with the rest of the basket keeps this share of its position limit
+const MIN_CORRELATION_MULTIPLIER: f64 = 0.5;
+// Common trading days two tickers need before their correlation is trusted
+const MIN_OVERLAPPING_RETURNS: usize = 20;
+// Share of portfolio value all positions in one sector may take together, overridden by the `max_sector_weight` run option
+pub const DEFAULT_MAX_SECTOR_WEIGHT: f64 = 0.40;
+
+struct PricedTicker {
+  ticker: String,
+  current_price: f64,
+  price_gaps: Vec<PriceGap>,
+  returns: BTreeMap<String, f64>, // daily close-to-close returns keyed by trading date
+}
+
 pub struct RiskManagerAgent;
 
 impl RiskManagerAgent {
@@ -29,10 +48,11 @@ impl RiskManagerAgent {
     /* Controls position sizing based on real_world risk factors for multiple tickers
      */
 
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
     let api = API::new(config);
 
-    let portfolio = match state.data.get("portfolio") {
-      Some(portfolio) => portfolio, 
+    let portfolio : Portfolio = match state.data.get("portfolio") {
+      Some(portfolio) => serde_json::from_value(portfolio.clone()).context("Malformed portfolio in state.data")?,
       _ => {
         log::error!("Cannot find portfolio inside state.data"); 
         return Ok(PartialAgentStateUpdate::new());
@@ -66,18 +86,34 @@ impl RiskManagerAgent {
       }
     }; 
 
+    let fill_gaps : bool = state.metadata.get("fill_gaps").and_then(Value::as_bool).unwrap_or(false);
+
     let mut risk_analysis : HashMap<String, Value> = HashMap::new();
     let mut current_prices : HashMap<String, f64> = HashMap::new();
+    let mut priced_tickers : Vec<PricedTicker> = Vec::new();
+    let mut ticker_errors : HashMap<String, String> = HashMap::new();
 
-    for ticker in tickers {
-      let prices = api.get_price(&ticker, start_date, end_date).await?; 
+    let api_ref : &API = &api;
+    let fetched = fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      api_ref.get_price(&ticker, start_date, end_date).await
+    }).await;
+
+    for (ticker, prices) in fetched {
+      let prices = match prices {
+        Ok(prices) => prices,
+        Err(e) => {
+          log::error!("Risk management agent, {}, skipping ticker, price fetch failed: {}", ticker, e);
+          ticker_errors.insert(ticker.clone(), e.to_string());
+          continue;
+        }
+      };
 
       if prices.is_empty() {
         log::info!("Risk management agent, {}, Failed no price data found", ticker); 
         continue;
       }
 
-      let prices_df = match api.prices_to_df(prices) {
+      let mut prices_df = match api.prices_to_df(prices) {
         Ok(df) => df, 
         Err(e) => {
           log::error!("Failed to convert prices to DataFrame for {}: {}", ticker, e);
@@ -85,6 +121,24 @@ impl RiskManagerAgent {
         }
       };
 
+      let price_gaps = match api.detect_price_gaps(&prices_df) {
+        Ok(gaps) => gaps,
+        Err(e) => {
+          log::error!("Failed to check price gaps for {}: {}", ticker, e);
+          Vec::new()
+        }
+      };
+
+      if !price_gaps.is_empty() {
+        log::warn!("Risk management agent, {}, found {} gaps in price data", ticker, price_gaps.len());
+        if fill_gaps {
+          match api.fill_price_gaps(&prices_df) {
+            Ok(df) => prices_df = df,
+            Err(e) => log::error!("Failed to forward-fill price gaps for {}: {}", ticker, e),
+          }
+        }
+      }
+
       let current_price = match prices_df.column("close") {
         Ok(column) => {
           let len = column.len(); 
@@ -115,25 +169,64 @@ impl RiskManagerAgent {
 
       current_prices.insert(ticker.clone(), current_price); 
 
-      let current_position_value = portfolio.get("cost_basis").and_then(|cost_basis| cost_basis.get(&ticker)).and_then(Value::as_f64).unwrap_or(0.0);
-
-      let portfolio_cash = portfolio.get("cash").and_then(Value::as_f64).unwrap_or(0.0);
-      
-      let mut total_portfolio_value = portfolio_cash;
-      
-      if let Some(cost_basis) = portfolio.get("cost_basis").and_then(Value::as_object) {
-        for (_, value) in cost_basis {
-          if let Some(position_value) = value.as_f64() {
-            total_portfolio_value += position_value;
-          }
+      let returns = match Self::daily_returns(&prices_df) {
+        Ok(returns) => returns,
+        Err(e) => {
+          log::error!("Failed to compute daily returns for {}: {}", ticker, e);
+          BTreeMap::new()
         }
+      };
+
+      priced_tickers.push(PricedTicker { ticker, current_price, price_gaps, returns });
+    }
+
+    // Highly correlated names are effectively one bet, so their limits shrink with their average correlation to the basket
+    let correlation_matrix = Self::correlation_matrix(&priced_tickers);
+    let average_correlations = Self::average_correlations(&correlation_matrix);
+    if average_correlations.is_empty() {
+      log::info!("Risk management agent, fewer than two tickers with overlapping price history, sizing positions independently");
+    }
+
+    let portfolio_cash = portfolio.cash;
+    let total_portfolio_value = portfolio_cash + portfolio.total_long_cost();
+
+    let max_sector_weight : f64 = state.metadata.get("max_sector_weight").and_then(Value::as_f64)
+      .filter(|weight| *weight > 0.0 && *weight <= 1.0)
+      .unwrap_or(DEFAULT_MAX_SECTOR_WEIGHT);
+    let mut sectors : HashMap<String, String> = HashMap::new();
+    for priced in &priced_tickers {
+      match api.get_company_facts(&priced.ticker).await {
+        Ok(facts) => match facts.sector.filter(|sector| !sector.trim().is_empty()) {
+          Some(sector) => { sectors.insert(priced.ticker.clone(), sector); }
+          None => log::info!("Risk management agent, {}, no sector reported, not subject to the sector cap", priced.ticker),
+        },
+        Err(e) => log::warn!("Risk management agent, {}, cannot fetch company facts for sector grouping: {}", priced.ticker, e),
       }
+    }
+
+    // (average correlation, correlation multiplier, position limit) per ticker
+    let mut position_limits : HashMap<String, (Option<f64>, f64, f64)> = HashMap::new();
+    for priced in &priced_tickers {
+      let average_correlation = average_correlations.get(&priced.ticker).copied();
+      let correlation_multiplier = average_correlation.map_or(1.0, |correlation| 1.0 - (1.0 - MIN_CORRELATION_MULTIPLIER) * correlation.max(0.0));
+      position_limits.insert(priced.ticker.clone(), (average_correlation, correlation_multiplier, total_portfolio_value * MAX_POSITION_WEIGHT * correlation_multiplier));
+    }
+    let remaining_limits : HashMap<String, f64> = position_limits.iter().map(|(ticker, (_, _, limit))| (ticker.clone(), limit - portfolio.long_cost(ticker))).collect();
+    let sector_caps = Self::sector_caps(&sectors, &remaining_limits, &portfolio, total_portfolio_value * max_sector_weight);
+
+    for PricedTicker { ticker, current_price, price_gaps, .. } in priced_tickers {
+      let current_position_value = portfolio.long_cost(&ticker);
 
-      let position_limit = total_portfolio_value * 0.20; 
+      let (average_correlation, correlation_multiplier, position_limit) = position_limits[&ticker];
 
       let remaining_position_limit = position_limit - current_position_value; 
 
-      let max_position_size = remaining_position_limit.min(portfolio_cash); 
+      // Names in a crowded sector share what is left under the sector cap
+      let sector_cap = sectors.get(&ticker).and_then(|sector| sector_caps.get(sector));
+      let sector_multiplier = sector_cap.and_then(|cap| cap.get("multiplier")).and_then(Value::as_f64).unwrap_or(1.0);
+      let sector_limited_remaining = if remaining_position_limit > 0.0 { remaining_position_limit * sector_multiplier } else { remaining_position_limit };
+
+      let max_position_size = sector_limited_remaining.min(portfolio_cash); 
 
 
       // Create risk analysis entry for this ticker
@@ -148,6 +241,25 @@ impl RiskManagerAgent {
       reasoning.insert("position_limit".to_string(), Value::from(position_limit));
       reasoning.insert("remaining_limit".to_string(), Value::from(remaining_position_limit));
       reasoning.insert("available_cash".to_string(), Value::from(portfolio_cash));
+      if let Some(average_correlation) = average_correlation {
+        reasoning.insert("average_correlation".to_string(), Value::from((average_correlation * 100.0).round() / 100.0));
+        reasoning.insert("correlation_multiplier".to_string(), Value::from((correlation_multiplier * 100.0).round() / 100.0));
+        reasoning.insert("correlation_matrix".to_string(), serde_json::to_value(&correlation_matrix)?);
+      }
+      if let (Some(sector), Some(sector_cap)) = (sectors.get(&ticker), sector_cap) {
+        let mut sector_reasoning = sector_cap.clone();
+        sector_reasoning["name"] = Value::from(sector.as_str());
+        reasoning.insert("sector".to_string(), sector_reasoning);
+      }
+
+      if !price_gaps.is_empty() {
+        let largest_gap = price_gaps.iter().map(|gap| gap.missing_trading_days).max().unwrap_or(0);
+        reasoning.insert("data_quality".to_string(), serde_json::json!({
+          "note": format!("{} gaps in price data, largest missing {} trading days", price_gaps.len(), largest_gap),
+          "gaps": serde_json::to_value(&price_gaps)?,
+          "forward_filled": fill_gaps,
+        }));
+      }
       
       ticker_analysis.insert("reasoning".to_string(), Value::Object(reasoning.into_iter().collect()));
       
@@ -157,7 +269,7 @@ impl RiskManagerAgent {
 
     let message_content = serde_json::to_string(&risk_analysis); 
 
-    let message = ChatMessage { role: "assistant".to_string(), content: message_content?};
+    let message = ChatMessage::from_agent("risk_management_agent", message_content?);
 
      // Show reasoning if requested
     if let Some(show_reasoning) = state.metadata.get("show_reasoning").and_then(Value::as_bool) {
@@ -186,6 +298,9 @@ impl RiskManagerAgent {
     
     // Add risk analysis to analyst_signals
     analyst_signals.insert("risk_management_agent".to_string(), serde_json::to_value(risk_analysis)?);
+    if !ticker_errors.is_empty() {
+      updated_data.insert("errors".to_string(), serde_json::json!({ "risk_management_agent": ticker_errors }));
+    }
 
     // Return partial state update
     let mut result = PartialAgentStateUpdate::new();
@@ -195,4 +310,86 @@ impl RiskManagerAgent {
     return Ok(result);  
   }
 
-}
\ No newline at end of file
+  // Per sector: current exposure against the cap, and the share of each ticker's remaining limit that still fits under it
+  fn sector_caps(sectors: &HashMap<String, String>, remaining_limits: &HashMap<String, f64>, portfolio: &Portfolio, sector_limit: f64) -> HashMap<String, Value> {
+    let mut groups : BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (ticker, sector) in sectors {
+      groups.entry(sector.as_str()).or_default().push(ticker.as_str());
+    }
+
+    let mut caps : HashMap<String, Value> = HashMap::new();
+    for (sector, mut tickers) in groups {
+      tickers.sort();
+      let exposure : f64 = tickers.iter().map(|ticker| portfolio.long_cost(ticker)).sum();
+      let room = (sector_limit - exposure).max(0.0);
+      let demand : f64 = tickers.iter().filter_map(|ticker| remaining_limits.get(*ticker)).map(|remaining| remaining.max(0.0)).sum();
+      let multiplier = if demand > room && demand > 0.0 { room / demand } else { 1.0 };
+
+      caps.insert(sector.to_string(), serde_json::json!({
+        "tickers": tickers,
+        "exposure": exposure,
+        "limit": sector_limit,
+        "remaining": room,
+        "multiplier": (multiplier * 100.0).floor() / 100.0,
+      }));
+    }
+    caps
+  }
+
+  fn daily_returns(prices_df: &DataFrame) -> anyhow::Result<BTreeMap<String, f64>> {
+    // Rows are sorted by date, the date part of the timestamp is enough to align tickers
+    let closes = prices_df.column("close")?.f64()?;
+    let times = prices_df.column("time")?.str()?;
+
+    let mut returns : BTreeMap<String, f64> = BTreeMap::new();
+    for i in 1..prices_df.height() {
+      if let (Some(previous), Some(close), Some(time)) = (closes.get(i - 1), closes.get(i), times.get(i)) {
+        if previous > 0.0 {
+          returns.insert(time.chars().take(10).collect(), close / previous - 1.0);
+        }
+      }
+    }
+    return Ok(returns);
+  }
+
+  // Pearson correlation of daily returns over the dates both tickers traded, None without enough overlap
+  fn correlation(a: &BTreeMap<String, f64>, b: &BTreeMap<String, f64>) -> Option<f64> {
+    let pairs : Vec<(f64, f64)> = a.iter().filter_map(|(date, x)| b.get(date).map(|y| (*x, *y))).collect();
+    if pairs.len() < MIN_OVERLAPPING_RETURNS {
+      return None;
+    }
+
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let covariance : f64 = pairs.iter().map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+    let variance_a : f64 = pairs.iter().map(|(x, _)| (x - mean_a).powi(2)).sum();
+    let variance_b : f64 = pairs.iter().map(|(_, y)| (y - mean_b).powi(2)).sum();
+    if variance_a <= 0.0 || variance_b <= 0.0 {
+      return None;
+    }
+    return Some(covariance / (variance_a.sqrt() * variance_b.sqrt()));
+  }
+
+  fn correlation_matrix(priced_tickers: &[PricedTicker]) -> BTreeMap<String, BTreeMap<String, f64>> {
+    let mut matrix : BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+    for (i, a) in priced_tickers.iter().enumerate() {
+      for b in &priced_tickers[i + 1..] {
+        if let Some(correlation) = Self::correlation(&a.returns, &b.returns) {
+          let correlation = (correlation * 100.0).round() / 100.0;
+          matrix.entry(a.ticker.clone()).or_default().insert(b.ticker.clone(), correlation);
+          matrix.entry(b.ticker.clone()).or_default().insert(a.ticker.clone(), correlation);
+        }
+      }
+    }
+    return matrix;
+  }
+
+  fn average_correlations(matrix: &BTreeMap<String, BTreeMap<String, f64>>) -> HashMap<String, f64> {
+    matrix.iter()
+      .filter(|(_, row)| !row.is_empty())
+      .map(|(ticker, row)| (ticker.clone(), row.values().sum::<f64>() / row.len() as f64))
+      .collect()
+  }
+
+}
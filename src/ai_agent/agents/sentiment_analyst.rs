@@ -0,0 +1,234 @@
+use anyhow::{Context, Error};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::result::Result::{Ok};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::data::models::{CompanyNews, InsiderTrade};
+use crate::ai_agent::error::AgentError;
+use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate, show_agent_reasoning};
+use crate::ai_agent::llm::model_provider::ChatMessage;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::app::config::Config;
+
+const INSIDER_TRADE_LIMIT: i64 = 1000;
+const COMPANY_NEWS_LIMIT: i64 = 100;
+// News carries more weight, insider filings are sparse and lag the market
+const INSIDER_WEIGHT: f64 = 0.3;
+const NEWS_WEIGHT: f64 = 0.7;
+// Combined scores inside this band are treated as neutral
+const NEUTRAL_BAND: f64 = 0.1;
+
+const POSITIVE_KEYWORDS: [&str; 12] = ["beat", "beats", "surge", "soar", "record", "upgrade", "growth", "profit", "rally", "outperform", "raises", "strong"];
+const NEGATIVE_KEYWORDS: [&str; 12] = ["miss", "misses", "plunge", "fall", "lawsuit", "downgrade", "loss", "recall", "probe", "underperform", "cuts", "weak"];
+
+pub struct SentimentAnalystAgent;
+
+impl SentimentAnalystAgent {
+  pub fn new() -> Self {
+    SentimentAnalystAgent {}
+  }
+
+  pub fn static_sentiment_analyst_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let sentiment_analyst = SentimentAnalystAgent::new();
+      sentiment_analyst.sentiment_analyst_agent(state, config).await
+    })
+  }
+
+  pub async fn sentiment_analyst_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Generates market sentiment signals from insider trading activity and company news
+     */
+
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
+    let api = API::new(config);
+
+    let data: &HashMap<String, Value> = &state.data;
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let start_date: &str = match data.get("start_date").and_then(Value::as_str) {
+      Some(start_date) => start_date,
+      _ => {
+        log::error!("Cannot find start date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut sentiment_analysis : HashMap<String, Value> = HashMap::new();
+    let mut ticker_errors: HashMap<String, String> = HashMap::new();
+
+    let api_ref : &API = &api;
+    let fetched = fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      log::info!("sentiment_analyst_agent {} Fetching insider trades", ticker);
+      let insider_trades = api_ref.get_insider_trade(&ticker, end_date, Some(start_date), INSIDER_TRADE_LIMIT).await?;
+
+      log::info!("sentiment_analyst_agent {} Fetching company news", ticker);
+      let company_news = api_ref.get_company_news(&ticker, end_date, Some(start_date), COMPANY_NEWS_LIMIT).await?;
+      Ok::<_, AgentError>((insider_trades, company_news))
+    }).await;
+
+    for (ticker, fetched) in fetched {
+      // A ticker the provider rejects is recorded and skipped instead of failing the other tickers
+      let (insider_trades, company_news) = match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+          log::error!("sentiment_analyst_agent {} Skipping ticker, data fetch failed: {:#}", ticker, e);
+          ticker_errors.insert(ticker.clone(), format!("{:#}", e));
+          continue;
+        }
+      };
+
+      log::info!("sentiment_analyst_agent {} Combining {} insider trades and {} news articles", ticker, insider_trades.len(), company_news.len());
+      sentiment_analysis.insert(ticker.clone(), Self::analyze_sentiment(&insider_trades, &company_news));
+    }
+
+    let message_content = serde_json::to_string(&sentiment_analysis).context("Failed to serialize sentiment analysis for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Sentiment Analyst");
+    }
+
+    let message = ChatMessage::from_agent("sentiment_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("sentiment_agent".to_string(), serde_json::to_value(sentiment_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+    if !ticker_errors.is_empty() {
+      updated_data_map.insert("errors".to_string(), serde_json::json!({ "sentiment_agent": ticker_errors }));
+    }
+
+    let mut result = PartialAgentStateUpdate::new();
+    result = result.with_messages(vec![message]);
+    result = result.with_data(updated_data_map);
+
+    return Ok(result);
+  }
+
+  pub fn analyze_sentiment(insider_trades: &[InsiderTrade], company_news: &[CompanyNews]) -> Value {
+    // Insider score: net shares bought over total shares traded, in [-1, 1]
+    let mut bought_shares = 0.0;
+    let mut sold_shares = 0.0;
+    for trade in insider_trades {
+      match trade.transaction_shares {
+        Some(shares) if shares > 0.0 => bought_shares += shares,
+        Some(shares) if shares < 0.0 => sold_shares += -shares,
+        _ => {}
+      }
+    }
+    let traded_shares = bought_shares + sold_shares;
+    let insider_score = if traded_shares > 0.0 { Some((bought_shares - sold_shares) / traded_shares) } else { None };
+
+    // News score: positive minus negative articles over all articles, in [-1, 1]
+    let news_signals : Vec<Signal> = company_news.iter().map(Self::news_signal).collect();
+    let positive_news = news_signals.iter().filter(|signal| **signal == Signal::Bullish).count();
+    let negative_news = news_signals.iter().filter(|signal| **signal == Signal::Bearish).count();
+    let news_score = if news_signals.is_empty() { None } else { Some((positive_news as f64 - negative_news as f64) / news_signals.len() as f64) };
+
+    // Weighted average over the sources that actually have data
+    let weighted : Vec<(f64, f64)> = [(insider_score, INSIDER_WEIGHT), (news_score, NEWS_WEIGHT)].iter()
+      .filter_map(|(score, weight)| score.map(|score| (score, *weight)))
+      .collect();
+    let total_weight : f64 = weighted.iter().map(|(_, weight)| weight).sum();
+    let score = if total_weight > 0.0 { weighted.iter().map(|(score, weight)| score * weight).sum::<f64>() / total_weight } else { 0.0 };
+
+    let signal = if score > NEUTRAL_BAND { Signal::Bullish } else if score < -NEUTRAL_BAND { Signal::Bearish } else { Signal::Neutral };
+    let confidence = match signal {
+      Signal::Neutral if total_weight > 0.0 => ((1.0 - score.abs() / NEUTRAL_BAND) * 50.0).round(),
+      Signal::Neutral => 0.0,
+      _ => (score.abs() * 100.0).round(),
+    };
+
+    return serde_json::json!({
+      "signal": signal.to_string(),
+      "confidence": confidence,
+      "reasoning": {
+        "insider_trading": {
+          "score": insider_score,
+          "shares_bought": bought_shares,
+          "shares_sold": sold_shares,
+          "trades": insider_trades.len(),
+        },
+        "company_news": {
+          "score": news_score,
+          "positive": positive_news,
+          "negative": negative_news,
+          "neutral": news_signals.len() - positive_news - negative_news,
+        },
+        "weighted_score": score,
+      },
+    });
+  }
+
+  pub fn news_signal(news: &CompanyNews) -> Signal {
+    match news.sentiment.as_deref().map(|sentiment| sentiment.trim().to_lowercase()) {
+      Some(sentiment) if sentiment == "positive" || sentiment == "bullish" => Signal::Bullish,
+      Some(sentiment) if sentiment == "negative" || sentiment == "bearish" => Signal::Bearish,
+      Some(sentiment) if !sentiment.is_empty() => Signal::Neutral,
+      // No sentiment from the API, fall back to keywords in the headline
+      _ => {
+        let title = news.title.to_lowercase();
+        let words : Vec<&str> = title.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect();
+        let positive = words.iter().filter(|word| POSITIVE_KEYWORDS.contains(word)).count();
+        let negative = words.iter().filter(|word| NEGATIVE_KEYWORDS.contains(word)).count();
+        if positive > negative { Signal::Bullish } else if negative > positive { Signal::Bearish } else { Signal::Neutral }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn trade(shares: f64) -> InsiderTrade {
+    serde_json::from_value(json!({ "ticker": "AAPL", "transaction_shares": shares })).unwrap()
+  }
+
+  fn news(title: &str, sentiment: Option<&str>) -> CompanyNews {
+    serde_json::from_value(json!({
+      "ticker": "AAPL", "title": title, "author": "", "source": "", "date": "2024-03-01T00:00:00Z", "url": "", "sentiment": sentiment,
+    })).unwrap()
+  }
+
+  #[test]
+  fn insiders_only_selling_is_bearish() {
+    let analysis = SentimentAnalystAgent::analyze_sentiment(&[trade(-500.0), trade(-1200.0), trade(-300.0)], &[]);
+    assert_eq!(analysis["signal"], json!("bearish"));
+    assert_eq!(analysis["confidence"], json!(100.0));
+    assert_eq!(analysis["reasoning"]["insider_trading"]["shares_sold"], json!(2000.0));
+    assert_eq!(analysis["reasoning"]["company_news"]["score"], Value::Null);
+  }
+
+  #[test]
+  fn mixed_trades_and_news_are_neutral() {
+    // Insiders net 0.2 bought, news nets out to 0, the weighted 0.06 sits inside the neutral band
+    let trades = [trade(150.0), trade(-100.0)];
+    let articles = [news("Apple posts record profit", None), news("Regulators open probe into Apple", None), news("Apple event recap", Some("neutral"))];
+    let analysis = SentimentAnalystAgent::analyze_sentiment(&trades, &articles);
+    assert_eq!(analysis["signal"], json!("neutral"));
+    assert_eq!(analysis["confidence"], json!(20.0));
+    assert_eq!(analysis["reasoning"]["company_news"], json!({ "score": 0.0, "positive": 1, "negative": 1, "neutral": 1 }));
+  }
+}
@@ -0,0 +1,412 @@
+use anyhow::{Context, Error};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::result::Result::{Ok};
+use std::future::Future;
+use std::pin::Pin;
+use polars::prelude::DataFrame;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate, show_agent_reasoning};
+use crate::ai_agent::llm::model_provider::ChatMessage;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::app::config::Config;
+
+const RSI_PERIOD: usize = 14;
+const MACD_FAST: usize = 12;
+const MACD_SLOW: usize = 26;
+const MACD_SIGNAL: usize = 9;
+const SMA_SHORT: usize = 50;
+const SMA_LONG: usize = 200;
+const EMA_SHORT: usize = 20;
+const EMA_LONG: usize = 50;
+const ATR_PERIOD: usize = 14;
+const BOLLINGER_PERIOD: usize = 20;
+const BOLLINGER_STD_DEVS: f64 = 2.0;
+
+// Daily bars in date order, high and low line up with close
+#[derive(Debug, Clone, Default)]
+pub struct PriceBars {
+  pub closes: Vec<f64>,
+  pub highs: Vec<f64>,
+  pub lows: Vec<f64>,
+}
+
+pub struct TechnicalAnalystAgent;
+
+impl TechnicalAnalystAgent {
+  pub fn new() -> Self {
+    TechnicalAnalystAgent {}
+  }
+
+  pub fn static_technical_analyst_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let technical_analyst = TechnicalAnalystAgent::new();
+      technical_analyst.technical_analyst_agent(state, config).await
+    })
+  }
+
+  pub async fn technical_analyst_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Generates price-action signals from RSI, MACD, the 50/200-day SMA crossover, the 20/50-day EMA trend
+       and closes outside the Bollinger Bands, with ATR reported for volatility
+     */
+
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
+    let api = API::new(config);
+
+    let data: &HashMap<String, Value> = &state.data;
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let start_date: &str = match data.get("start_date").and_then(Value::as_str) {
+      Some(start_date) => start_date,
+      _ => {
+        log::error!("Cannot find start date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let mut technical_analysis : HashMap<String, Value> = HashMap::new();
+
+    let api_ref : &API = &api;
+    let fetched = fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      log::info!("technical_analyst_agent {} Fetching price data", ticker);
+      api_ref.get_price(&ticker, start_date, end_date).await
+    }).await;
+
+    for (ticker, prices) in fetched {
+      let prices = prices?;
+
+      let bars : PriceBars = if prices.is_empty() {
+        PriceBars::default()
+      } else {
+        let prices_df = match api.prices_to_df(prices) {
+          Ok(df) => df,
+          Err(e) => {
+            log::error!("Failed to convert prices to DataFrame for {}: {}", ticker, e);
+            continue;
+          }
+        };
+        match Self::bars_from_df(&prices_df) {
+          Ok(bars) => bars,
+          Err(e) => {
+            log::error!("Failed to get close/high/low columns for {}: {}", ticker, e);
+            continue;
+          }
+        }
+      };
+
+      log::info!("technical_analyst_agent {} Computing indicators over {} closes", ticker, bars.closes.len());
+      technical_analysis.insert(ticker.clone(), Self::analyze_bars(&bars));
+    }
+
+    let message_content = serde_json::to_string(&technical_analysis).context("Failed to serialize technical analysis for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Technical Analyst");
+    }
+
+    let message = ChatMessage::from_agent("technical_analyst_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("technical_analyst_agent".to_string(), serde_json::to_value(technical_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+
+    let mut result = PartialAgentStateUpdate::new();
+    result = result.with_messages(vec![message]);
+    result = result.with_data(updated_data_map);
+
+    return Ok(result);
+  }
+
+  // Rows with a missing close, high or low are dropped together so the three series stay aligned
+  fn bars_from_df(prices_df: &DataFrame) -> Result<PriceBars, Error> {
+    let closes = prices_df.column("close")?.f64()?;
+    let highs = prices_df.column("high")?.f64()?;
+    let lows = prices_df.column("low")?.f64()?;
+
+    let mut bars = PriceBars::default();
+    for ((close, high), low) in closes.into_iter().zip(highs.into_iter()).zip(lows.into_iter()) {
+      if let (Some(close), Some(high), Some(low)) = (close, high, low) {
+        bars.closes.push(close);
+        bars.highs.push(high);
+        bars.lows.push(low);
+      }
+    }
+    return Ok(bars);
+  }
+
+  pub fn analyze_bars(bars: &PriceBars) -> Value {
+    let closes : &[f64] = &bars.closes;
+    if closes.len() < SMA_LONG {
+      return serde_json::json!({
+        "signal": Signal::Neutral.to_string(),
+        "confidence": 0.0,
+        "reasoning": format!("Insufficient data: {} closes available, {} required for the {}-day SMA", closes.len(), SMA_LONG, SMA_LONG),
+      });
+    }
+    let close = closes[closes.len() - 1];
+
+    let mut indicator_signals : Vec<Signal> = Vec::new();
+
+    // RSI: oversold is a buying opportunity, overbought a selling one
+    let rsi = Self::rsi(closes, RSI_PERIOD);
+    let rsi_signal = match rsi {
+      Some(rsi) if rsi < 30.0 => Signal::Bullish,
+      Some(rsi) if rsi > 70.0 => Signal::Bearish,
+      _ => Signal::Neutral,
+    };
+    indicator_signals.push(rsi_signal);
+
+    // MACD line against its signal line, the line starts once the slow EMA is defined
+    let fast = Self::ema(closes, MACD_FAST);
+    let slow = Self::ema(closes, MACD_SLOW);
+    let macd_line : Vec<f64> = fast.iter().zip(slow.iter()).filter_map(|(f, s)| Some((*f)? - (*s)?)).collect();
+    let signal_line = Self::ema(&macd_line, MACD_SIGNAL);
+    let macd = macd_line.last().copied();
+    let macd_signal_value = signal_line.last().copied().flatten();
+    let macd_signal = match (macd, macd_signal_value) {
+      (Some(macd), Some(signal_value)) if macd > signal_value => Signal::Bullish,
+      (Some(macd), Some(signal_value)) if macd < signal_value => Signal::Bearish,
+      _ => Signal::Neutral,
+    };
+    indicator_signals.push(macd_signal);
+
+    // Golden / death cross
+    let sma_short = Self::sma(closes, SMA_SHORT);
+    let sma_long = Self::sma(closes, SMA_LONG);
+    let sma_signal = if sma_short > sma_long { Signal::Bullish } else if sma_short < sma_long { Signal::Bearish } else { Signal::Neutral };
+    indicator_signals.push(sma_signal);
+
+    // EMA trend: price above the short EMA above the long one, or the mirror image
+    let ema_short = Self::ema(closes, EMA_SHORT).last().copied().flatten();
+    let ema_long = Self::ema(closes, EMA_LONG).last().copied().flatten();
+    let ema_signal = match (ema_short, ema_long) {
+      (Some(short), Some(long)) if close > short && short > long => Signal::Bullish,
+      (Some(short), Some(long)) if close < short && short < long => Signal::Bearish,
+      _ => Signal::Neutral,
+    };
+    indicator_signals.push(ema_signal);
+
+    // A close outside the bands is a breakout when it runs with the EMA trend and a stretch to revert from when it runs against it
+    let bands = Self::bollinger(closes, BOLLINGER_PERIOD, BOLLINGER_STD_DEVS);
+    let (band_signal, band_mode) = match bands {
+      Some((_, upper, _)) if close > upper => {
+        if ema_signal == Signal::Bullish { (Signal::Bullish, "breakout") } else { (Signal::Bearish, "mean_reversion") }
+      }
+      Some((_, _, lower)) if close < lower => {
+        if ema_signal == Signal::Bearish { (Signal::Bearish, "breakout") } else { (Signal::Bullish, "mean_reversion") }
+      }
+      Some(_) => (Signal::Neutral, "inside_bands"),
+      None => (Signal::Neutral, "undefined"),
+    };
+    indicator_signals.push(band_signal);
+
+    // Volatility only, it does not vote
+    let atr = Self::atr(bars, ATR_PERIOD);
+
+    let bullish = indicator_signals.iter().filter(|signal| **signal == Signal::Bullish).count();
+    let bearish = indicator_signals.iter().filter(|signal| **signal == Signal::Bearish).count();
+
+    let signal = if bullish > bearish { Signal::Bullish } else if bearish > bullish { Signal::Bearish } else { Signal::Neutral };
+    let agreeing = match signal {
+      Signal::Bullish => bullish,
+      Signal::Bearish => bearish,
+      Signal::Neutral => indicator_signals.len() - bullish - bearish,
+    };
+    let confidence = (agreeing as f64 / indicator_signals.len() as f64 * 100.0).round();
+
+    return serde_json::json!({
+      "signal": signal.to_string(),
+      "confidence": confidence,
+      "reasoning": {
+        "rsi": { "signal": rsi_signal.to_string(), "value": rsi },
+        "macd": { "signal": macd_signal.to_string(), "macd": macd, "signal_line": macd_signal_value },
+        "sma_crossover": { "signal": sma_signal.to_string(), "sma_50": sma_short, "sma_200": sma_long },
+        "ema_trend": { "signal": ema_signal.to_string(), "ema_20": ema_short, "ema_50": ema_long, "close": close },
+        "bollinger_bands": {
+          "signal": band_signal.to_string(),
+          "mode": band_mode,
+          "middle": bands.map(|(middle, _, _)| middle),
+          "upper": bands.map(|(_, upper, _)| upper),
+          "lower": bands.map(|(_, _, lower)| lower),
+        },
+        "atr": { "value": atr, "percent_of_close": atr.filter(|_| close > 0.0).map(|atr| atr / close * 100.0) },
+      },
+    });
+  }
+
+  fn sma(values: &[f64], period: usize) -> f64 {
+    let window = &values[values.len().saturating_sub(period)..];
+    if window.is_empty() {
+      return 0.0;
+    }
+    return window.iter().sum::<f64>() / window.len() as f64;
+  }
+
+  // None through the warm-up, then seeded with the SMA of the first `period` values
+  fn ema(values: &[f64], period: usize) -> Vec<Option<f64>> {
+    let mut result : Vec<Option<f64>> = vec![None; values.len()];
+    if period == 0 || values.len() < period {
+      return result;
+    }
+
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut ema = values[..period].iter().sum::<f64>() / period as f64;
+    result[period - 1] = Some(ema);
+    for i in period..values.len() {
+      ema = alpha * values[i] + (1.0 - alpha) * ema;
+      result[i] = Some(ema);
+    }
+    return result;
+  }
+
+  fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    // Wilder's smoothing over the full series, undefined until `period` changes exist
+    if period == 0 || closes.len() <= period {
+      return None;
+    }
+
+    let changes : Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let mut avg_gain = changes[..period].iter().filter(|c| **c > 0.0).sum::<f64>() / period as f64;
+    let mut avg_loss = changes[..period].iter().filter(|c| **c < 0.0).map(|c| -c).sum::<f64>() / period as f64;
+
+    for change in &changes[period..] {
+      avg_gain = (avg_gain * (period as f64 - 1.0) + change.max(0.0)) / period as f64;
+      avg_loss = (avg_loss * (period as f64 - 1.0) + (-change).max(0.0)) / period as f64;
+    }
+
+    if avg_loss == 0.0 {
+      return Some(if avg_gain == 0.0 { 50.0 } else { 100.0 });
+    }
+    let rs = avg_gain / avg_loss;
+    return Some(100.0 - 100.0 / (1.0 + rs));
+  }
+
+  // Wilder's ATR. True range needs the previous close, so the first bar has none and the average is
+  // undefined until `period` true ranges exist
+  fn atr(bars: &PriceBars, period: usize) -> Option<f64> {
+    if period == 0 || bars.closes.len() <= period {
+      return None;
+    }
+
+    let true_ranges : Vec<f64> = (1..bars.closes.len()).map(|i| {
+      let previous_close = bars.closes[i - 1];
+      (bars.highs[i] - bars.lows[i]).max((bars.highs[i] - previous_close).abs()).max((bars.lows[i] - previous_close).abs())
+    }).collect();
+
+    let mut atr = true_ranges[..period].iter().sum::<f64>() / period as f64;
+    for true_range in &true_ranges[period..] {
+      atr = (atr * (period as f64 - 1.0) + true_range) / period as f64;
+    }
+    return Some(atr);
+  }
+
+  // (middle, upper, lower) over the last `period` closes using the population standard deviation
+  fn bollinger(closes: &[f64], period: usize, std_devs: f64) -> Option<(f64, f64, f64)> {
+    if period == 0 || closes.len() < period {
+      return None;
+    }
+
+    let window = &closes[closes.len() - period..];
+    let middle = window.iter().sum::<f64>() / period as f64;
+    let variance = window.iter().map(|close| (close - middle).powi(2)).sum::<f64>() / period as f64;
+    let deviation = std_devs * variance.sqrt();
+    return Some((middle, middle + deviation, middle - deviation));
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+  }
+
+  #[test]
+  fn rsi_matches_a_hand_computed_series() {
+    // Changes +1, -0.5, +1, -0.5, +1. Seeded averages 2/3 and 1/6, then Wilder smoothing gives 17/27 and 5/27
+    let closes = [10.0, 11.0, 10.5, 11.5, 11.0, 12.0];
+    assert_close(TechnicalAnalystAgent::rsi(&closes, 3).unwrap(), 100.0 - 100.0 / (1.0 + 17.0 / 5.0));
+  }
+
+  #[test]
+  fn rsi_is_undefined_through_the_warm_up_and_bounded_after() {
+    assert_eq!(TechnicalAnalystAgent::rsi(&[10.0, 11.0, 12.0], 3), None);
+    assert_eq!(TechnicalAnalystAgent::rsi(&[10.0, 11.0, 12.0, 13.0], 3), Some(100.0));
+    assert_eq!(TechnicalAnalystAgent::rsi(&[10.0, 10.0, 10.0, 10.0], 3), Some(50.0));
+  }
+
+  #[test]
+  fn atr_matches_a_hand_computed_series() {
+    // True ranges 1.5, 1.5, 2.5 (the gap up is measured from the previous close), 1.5
+    let bars = PriceBars {
+      closes: vec![10.0, 11.0, 10.0, 12.0, 11.0],
+      highs: vec![10.5, 11.5, 11.0, 12.5, 12.0],
+      lows: vec![9.5, 10.0, 9.5, 10.5, 10.5],
+    };
+    assert_close(TechnicalAnalystAgent::atr(&bars, 3).unwrap(), 31.0 / 18.0);
+
+    let warm_up = PriceBars { closes: bars.closes[..4].to_vec(), highs: bars.highs[..4].to_vec(), lows: bars.lows[..4].to_vec() };
+    assert_close(TechnicalAnalystAgent::atr(&warm_up, 3).unwrap(), 5.5 / 3.0);
+    let too_short = PriceBars { closes: bars.closes[..3].to_vec(), highs: bars.highs[..3].to_vec(), lows: bars.lows[..3].to_vec() };
+    assert_eq!(TechnicalAnalystAgent::atr(&too_short, 3), None);
+  }
+
+  #[test]
+  fn ema_is_seeded_with_the_sma_after_the_warm_up() {
+    assert_eq!(TechnicalAnalystAgent::ema(&[1.0, 2.0, 3.0, 4.0, 5.0], 3), vec![None, None, Some(2.0), Some(3.0), Some(4.0)]);
+    assert_eq!(TechnicalAnalystAgent::ema(&[1.0, 2.0], 3), vec![None, None]);
+  }
+
+  #[test]
+  fn bollinger_bands_use_the_population_deviation() {
+    let closes = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+    assert_eq!(TechnicalAnalystAgent::bollinger(&closes, 8, 2.0), Some((5.0, 9.0, 1.0)));
+    assert_eq!(TechnicalAnalystAgent::bollinger(&closes, 9, 2.0), None);
+  }
+
+  fn bars(closes: Vec<f64>) -> PriceBars {
+    let highs = closes.iter().map(|close| close + 1.0).collect();
+    let lows = closes.iter().map(|close| close - 1.0).collect();
+    PriceBars { closes, highs, lows }
+  }
+
+  #[test]
+  fn a_steady_climb_is_a_bullish_golden_cross() {
+    let analysis = TechnicalAnalystAgent::analyze_bars(&bars((0..250).map(|day| 100.0 * 1.005f64.powi(day)).collect()));
+    let crossover = &analysis["reasoning"]["sma_crossover"];
+    assert_eq!(crossover["signal"], "bullish");
+    assert!(crossover["sma_50"].as_f64().unwrap() > crossover["sma_200"].as_f64().unwrap());
+    assert_eq!(analysis["signal"], "bullish");
+    assert!(analysis["confidence"].as_f64().unwrap() > 50.0);
+  }
+
+  #[test]
+  fn fewer_closes_than_the_long_sma_is_neutral() {
+    let analysis = TechnicalAnalystAgent::analyze_bars(&bars((0..199).map(|day| 100.0 + day as f64).collect()));
+    assert_eq!(analysis["signal"], "neutral");
+    assert_eq!(analysis["confidence"], 0.0);
+    assert!(analysis["reasoning"].as_str().unwrap().starts_with("Insufficient data: 199 closes available"));
+  }
+}
@@ -0,0 +1,339 @@
+use anyhow::{Context, Error};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::result::Result::{Ok};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::data::models::{FinancialMetrics, LineItem, ReportPeriod};
+use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate, show_agent_reasoning};
+use crate::ai_agent::llm::model_provider::ChatMessage;
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::analysts::LineItemSet;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::app::config::Config;
+
+// Relative weight of each method in the ensemble, renormalized over the methods that produced a value
+const OWNER_EARNINGS_WEIGHT: f64 = 0.4;
+const FCF_YIELD_WEIGHT: f64 = 0.3;
+const EV_EBITDA_WEIGHT: f64 = 0.3;
+// Gap between fair value and market cap needed before leaving neutral, and the gap treated as full confidence
+const SIGNAL_THRESHOLD: f64 = 0.15;
+const FULL_CONFIDENCE_GAP: f64 = 0.5;
+
+// Two periods so the working capital change can be computed
+pub const VALUATION_LINE_ITEMS: LineItemSet = LineItemSet {
+  names: &["free_cash_flow", "net_income", "depreciation_and_amortization", "capital_expenditure", "working_capital"],
+  period: ReportPeriod::Ttm,
+  limit: 2,
+};
+
+// Longer projections mostly compound the growth assumption
+const MAX_PROJECTION_YEARS: i32 = 30;
+
+// DCF and yield assumptions, overridable per run through `metadata.valuation_assumptions`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValuationAssumptions {
+  pub growth_rate: f64,
+  pub discount_rate: f64,
+  pub terminal_multiple: f64,
+  pub projection_years: i32,
+  pub required_fcf_yield: f64,
+}
+
+impl Default for ValuationAssumptions {
+  fn default() -> Self {
+    ValuationAssumptions { growth_rate: 0.05, discount_rate: 0.09, terminal_multiple: 12.0, projection_years: 10, required_fcf_yield: 0.05 }
+  }
+}
+
+impl ValuationAssumptions {
+  pub fn from_metadata(metadata: &HashMap<String, Value>) -> Self {
+    match metadata.get("valuation_assumptions") {
+      Some(value) => serde_json::from_value(value.clone()).unwrap_or_else(|e| {
+        log::warn!("Ignoring invalid valuation_assumptions {}: {}", value, e);
+        ValuationAssumptions::default()
+      }),
+      None => ValuationAssumptions::default(),
+    }
+  }
+
+  // Assumptions the DCF cannot give a meaningful value for, the reason is reported instead of a number.
+  // Growth at or above the discount rate makes the projected cash flows grow faster than they are discounted
+  pub fn validate(&self) -> Result<(), String> {
+    if !(1..=MAX_PROJECTION_YEARS).contains(&self.projection_years) {
+      return Err(format!("projection_years must be between 1 and {}, got {}", MAX_PROJECTION_YEARS, self.projection_years));
+    }
+    if self.discount_rate <= self.growth_rate {
+      return Err(format!("discount_rate ({}) must be greater than growth_rate ({})", self.discount_rate, self.growth_rate));
+    }
+    if self.discount_rate <= -1.0 {
+      return Err(format!("discount_rate must be greater than -1, got {}", self.discount_rate));
+    }
+    if self.terminal_multiple < 0.0 {
+      return Err(format!("terminal_multiple must not be negative, got {}", self.terminal_multiple));
+    }
+    return Ok(());
+  }
+
+  // Present value of `base_cash_flow` grown for `projection_years` plus a terminal multiple on the final year
+  pub fn discounted_value(&self, base_cash_flow: f64) -> f64 {
+    let mut present_value : f64 = 0.0;
+    for year in 1..=self.projection_years {
+      let future_cash_flow = base_cash_flow * (1.0 + self.growth_rate).powi(year);
+      present_value += future_cash_flow / (1.0 + self.discount_rate).powi(year);
+    }
+
+    let terminal_cash_flow = base_cash_flow * (1.0 + self.growth_rate).powi(self.projection_years);
+    let terminal_value = terminal_cash_flow * self.terminal_multiple / (1.0 + self.discount_rate).powi(self.projection_years);
+    return present_value + terminal_value;
+  }
+}
+
+pub struct ValuationAnalystAgent;
+
+impl ValuationAnalystAgent {
+  pub fn new() -> Self {
+    ValuationAnalystAgent {}
+  }
+
+  pub fn static_valuation_analyst_agent(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let valuation_analyst = ValuationAnalystAgent::new();
+      valuation_analyst.valuation_analyst_agent(state, config).await
+    })
+  }
+
+  pub async fn valuation_analyst_agent(&self, state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
+    /* Compares an ensemble of fair-value estimates (owner-earnings DCF, FCF yield, EV/EBITDA) against market cap
+     */
+
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
+    let api = API::new(config);
+    let assumptions = ValuationAssumptions::from_metadata(&state.metadata);
+
+    let data: &HashMap<String, Value> = &state.data;
+    let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
+      Some(arr) if !arr.is_empty() => {
+        arr.iter().filter_map(Value::as_str).map(String::from).collect()
+      }
+      _ => {
+        log::error!("Cannot find tickers inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+
+    let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) => end_date,
+      _ => {
+        log::error!("Cannot find end date inside state.data");
+        return Ok(PartialAgentStateUpdate::new());
+      }
+    };
+    let period : ReportPeriod = ReportPeriod::from_metadata(&state.metadata, VALUATION_LINE_ITEMS.period);
+    let line_items : Vec<String> = VALUATION_LINE_ITEMS.names_for("valuation_analyst", &state.metadata);
+
+    let mut valuation_analysis : HashMap<String, Value> = HashMap::new();
+
+    let api_ref : &API = &api;
+    let line_items_ref : &Vec<String> = &line_items;
+    let fetched = fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      log::info!("valuation_analyst_agent {} Fetching financial metrics", ticker);
+      let metrics = api_ref.get_financial_metrics(&ticker, end_date, Some(period.as_str()), Some(8)).await?;
+
+      log::info!("valuation_analyst_agent {} Gathering financial line items", ticker);
+      let financial_line_items = api_ref.search_line_items(&ticker, line_items_ref.clone(), end_date, Some(period.as_str()), Some(VALUATION_LINE_ITEMS.limit)).await?;
+
+      log::info!("valuation_analyst_agent {} Getting market cap", ticker);
+      let market_cap = api_ref.get_market_cap(&ticker, end_date).await.with_context(|| format!("Failed to get market cap for {}", ticker))?;
+      Ok::<_, Error>((metrics, financial_line_items, market_cap))
+    }).await;
+
+    for (ticker, fetched) in fetched {
+      let (metrics, financial_line_items, market_cap) = fetched?;
+
+      log::info!("valuation_analyst_agent {} Calculating fair values", ticker);
+      valuation_analysis.insert(ticker.clone(), Self::analyze_valuation(&metrics, &financial_line_items, market_cap, &assumptions));
+    }
+
+    let message_content = serde_json::to_string(&valuation_analysis).context("Failed to serialize valuation analysis for message")?;
+
+    if state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false) {
+      show_agent_reasoning(&message_content, "Valuation Analyst");
+    }
+
+    let message = ChatMessage::from_agent("valuation_analyst_agent", message_content);
+
+    let mut analyst_signals_sub_map = HashMap::new();
+    analyst_signals_sub_map.insert("valuation_analyst_agent".to_string(), serde_json::to_value(valuation_analysis)?);
+
+    let mut updated_data_map = HashMap::new();
+    updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+
+    let mut result = PartialAgentStateUpdate::new();
+    result = result.with_messages(vec![message]);
+    result = result.with_data(updated_data_map);
+
+    return Ok(result);
+  }
+
+  pub fn analyze_valuation(metrics: &[FinancialMetrics], financial_line_items: &[LineItem], market_cap: Option<f64>, assumptions: &ValuationAssumptions) -> Value {
+    let methods : Vec<(&str, f64, Option<f64>)> = vec![
+      ("owner_earnings_dcf", OWNER_EARNINGS_WEIGHT, Self::owner_earnings_value(financial_line_items, assumptions)),
+      ("fcf_yield", FCF_YIELD_WEIGHT, Self::fcf_yield_value(financial_line_items, assumptions)),
+      ("ev_ebitda", EV_EBITDA_WEIGHT, Self::ev_ebitda_value(metrics)),
+    ];
+
+    let gap = |value: f64| market_cap.filter(|mc| *mc > 0.0).map(|mc| (value - mc) / mc);
+
+    let mut reasoning = serde_json::Map::new();
+    for (name, weight, value) in &methods {
+      reasoning.insert(name.to_string(), serde_json::json!({
+        "value": value,
+        "weight": weight,
+        "gap": value.and_then(gap),
+      }));
+    }
+    // The DCF is left out rather than valued on assumptions it cannot use, say why
+    if let Err(reason) = assumptions.validate() {
+      if let Some(Value::Object(dcf)) = reasoning.get_mut("owner_earnings_dcf") {
+        dcf.insert("details".to_string(), Value::from(format!("Invalid valuation assumptions: {}", reason)));
+      }
+    }
+
+    let total_weight : f64 = methods.iter().filter(|(_, _, value)| value.is_some()).map(|(_, weight, _)| weight).sum();
+    let fair_value = if total_weight > 0.0 {
+      Some(methods.iter().filter_map(|(_, weight, value)| value.map(|value| value * weight)).sum::<f64>() / total_weight)
+    } else {
+      None
+    };
+    let aggregate_gap = fair_value.and_then(gap);
+
+    let (signal, confidence) = match aggregate_gap {
+      Some(gap) if gap > SIGNAL_THRESHOLD => (Signal::Bullish, (gap.min(FULL_CONFIDENCE_GAP) / FULL_CONFIDENCE_GAP * 100.0).round()),
+      Some(gap) if gap < -SIGNAL_THRESHOLD => (Signal::Bearish, (gap.abs().min(FULL_CONFIDENCE_GAP) / FULL_CONFIDENCE_GAP * 100.0).round()),
+      Some(gap) => (Signal::Neutral, ((1.0 - gap.abs() / SIGNAL_THRESHOLD) * 50.0).round()),
+      None => (Signal::Neutral, 0.0),
+    };
+
+    reasoning.insert("aggregate".to_string(), serde_json::json!({
+      "fair_value": fair_value,
+      "market_cap": market_cap,
+      "gap": aggregate_gap,
+      "assumptions": assumptions,
+    }));
+
+    return serde_json::json!({
+      "signal": signal.to_string(),
+      "confidence": confidence,
+      "reasoning": Value::Object(reasoning),
+    });
+  }
+
+  // Net income plus D&A less capex and the growth in working capital, projected and discounted
+  pub fn owner_earnings_value(financial_line_items: &[LineItem], assumptions: &ValuationAssumptions) -> Option<f64> {
+    let latest = financial_line_items.first()?;
+    let net_income = latest.extra.get("net_income").and_then(Value::as_f64)?;
+    let depreciation = latest.extra.get("depreciation_and_amortization").and_then(Value::as_f64)?;
+    let capex = latest.extra.get("capital_expenditure").and_then(Value::as_f64)?;
+
+    let working_capital_change = match (latest.extra.get("working_capital").and_then(Value::as_f64),
+                                        financial_line_items.get(1).and_then(|previous| previous.extra.get("working_capital")).and_then(Value::as_f64)) {
+      (Some(current), Some(previous)) => current - previous,
+      _ => 0.0,
+    };
+
+    let owner_earnings = net_income + depreciation - capex.abs() - working_capital_change;
+    if owner_earnings <= 0.0 || assumptions.validate().is_err() {
+      return None;
+    }
+    return Some(assumptions.discounted_value(owner_earnings));
+  }
+
+  // Value at which the latest free cash flow would earn the required yield
+  pub fn fcf_yield_value(financial_line_items: &[LineItem], assumptions: &ValuationAssumptions) -> Option<f64> {
+    let free_cash_flow = financial_line_items.first()?.extra.get("free_cash_flow").and_then(Value::as_f64)?;
+    if free_cash_flow <= 0.0 || assumptions.required_fcf_yield <= 0.0 {
+      return None;
+    }
+    return Some(free_cash_flow / assumptions.required_fcf_yield);
+  }
+
+  // Current EBITDA at the median historical EV/EBITDA multiple, less net debt
+  pub fn ev_ebitda_value(metrics: &[FinancialMetrics]) -> Option<f64> {
+    let latest = metrics.first()?;
+    let enterprise_value = latest.enterprise_value?;
+    let current_multiple = latest.enterprise_value_to_ebitda_ratio.filter(|ratio| *ratio > 0.0)?;
+    let market_cap = latest.market_cap?;
+    let ebitda = enterprise_value / current_multiple;
+
+    let mut multiples : Vec<f64> = metrics.iter().filter_map(|m| m.enterprise_value_to_ebitda_ratio).filter(|ratio| *ratio > 0.0).collect();
+    multiples.sort_by(|a, b| a.total_cmp(b));
+    let median = if multiples.len() % 2 == 0 {
+      (multiples[multiples.len() / 2 - 1] + multiples[multiples.len() / 2]) / 2.0
+    } else {
+      multiples[multiples.len() / 2]
+    };
+
+    let net_debt = enterprise_value - market_cap;
+    let equity_value = median * ebitda - net_debt;
+    if equity_value <= 0.0 {
+      return None;
+    }
+    return Some(equity_value);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn line_items() -> Vec<LineItem> {
+    vec![serde_json::from_value(json!({
+      "ticker": "VALUE", "report_period": "2024-03-31", "period": "ttm", "currency": "USD",
+      "net_income": 100.0, "depreciation_and_amortization": 20.0, "capital_expenditure": -30.0, "free_cash_flow": 50.0,
+    })).unwrap()]
+  }
+
+  fn assumptions(growth_rate: f64, discount_rate: f64) -> ValuationAssumptions {
+    ValuationAssumptions { growth_rate, discount_rate, ..ValuationAssumptions::default() }
+  }
+
+  #[test]
+  fn discount_at_or_below_growth_is_rejected() {
+    assert!(assumptions(0.05, 0.05).validate().unwrap_err().contains("discount_rate (0.05) must be greater than growth_rate (0.05)"));
+    assert!(assumptions(0.08, 0.05).validate().is_err());
+    assert!(ValuationAssumptions { terminal_multiple: -1.0, ..ValuationAssumptions::default() }.validate().unwrap_err().contains("terminal_multiple"));
+    assert!(ValuationAssumptions { projection_years: 0, ..ValuationAssumptions::default() }.validate().unwrap_err().contains("projection_years"));
+    assert_eq!(ValuationAssumptions::default().validate(), Ok(()));
+  }
+
+  #[test]
+  fn a_rejected_dcf_reports_why_and_the_other_methods_still_count() {
+    let valuation = ValuationAnalystAgent::analyze_valuation(&[], &line_items(), Some(500.0), &assumptions(0.1, 0.09));
+    let dcf = &valuation["reasoning"]["owner_earnings_dcf"];
+    assert_eq!(dcf["value"], Value::Null);
+    assert_eq!(dcf["details"], json!("Invalid valuation assumptions: discount_rate (0.09) must be greater than growth_rate (0.1)"));
+
+    // Only the FCF yield method is left, 50 at a 5% required yield
+    assert_eq!(valuation["reasoning"]["aggregate"]["fair_value"], json!(1000.0));
+    assert_eq!(valuation["signal"], json!("bullish"));
+  }
+
+  #[test]
+  fn sane_assumptions_value_the_dcf() {
+    let sane = ValuationAssumptions { growth_rate: 0.0, discount_rate: 0.1, terminal_multiple: 0.0, projection_years: 1, required_fcf_yield: 0.05 };
+    // Owner earnings 100 + 20 - 30 = 90, one year out at 10%
+    assert!((sane.discounted_value(110.0) - 100.0).abs() < 1e-9);
+
+    let valuation = ValuationAnalystAgent::analyze_valuation(&[], &line_items(), Some(500.0), &sane);
+    let dcf = &valuation["reasoning"]["owner_earnings_dcf"];
+    assert!((dcf["value"].as_f64().unwrap() - 90.0 / 1.1).abs() < 1e-9);
+    assert!(dcf.get("details").is_none());
+  }
+}
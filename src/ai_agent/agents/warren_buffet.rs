@@ -6,17 +6,33 @@ use std::result::Result::Err;
 use std::str::FromStr;
 use std::future::Future;
 use std::pin::Pin;
+use std::time::Duration;
 
+use crate::ai_agent::agents::valuation_analyst::ValuationAssumptions;
 use crate::ai_agent::graph::state::{AgentState, show_agent_reasoning, PartialAgentStateUpdate}; 
-use crate::ai_agent::llm::models::get_model;
+use crate::ai_agent::llm::json::{extract_json, repair_json};
+use crate::ai_agent::llm::ensemble::{ensemble_models, ensemble_vote, reconcile_votes, EnsembleModel};
+use crate::ai_agent::llm::circuit_breaker::{self, CIRCUIT_OPEN};
+use crate::ai_agent::llm::models::{get_ready_model, is_dry_run, llm_timeout, supports_json_mode, SamplingParams, DEFAULT_AGENT_TEMPERATURE};
+use crate::ai_agent::llm::prompt::{prompt_overrides, render_prompt, PromptBuilder, SIGNAL_JSON_SCHEMA};
 use crate::ai_agent::tools::api::API;
-use crate::ai_agent::llm::model_provider::{ChatMessage, LLMModelConfig};
-use crate::ai_agent::data::models::{FinancialMetrics,LineItem, };
+use crate::ai_agent::utils::analysts::LineItemSet;
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::ai_agent::llm::model_provider::{budget_allows, chat_with_timeout, ChatMessage, LLMModelConfig, BUDGET_EXHAUSTED};
+use crate::ai_agent::data::models::{FinancialMetrics, LineItem, ReportPeriod};
 use crate::ai_agent::llm::model_provider::{ModelProvider};
 use crate::app::config::Config;
 
+pub const BUFFETT_LINE_ITEMS: LineItemSet = LineItemSet {
+  names: &["capital_expenditure", "depreciation_and_amortization", "net_income", "outstanding_shares", "total_assets",
+           "total_liabilities", "dividends_and_other_cash_distributions", "issuance_or_purchase_of_equity_shares"],
+  period: ReportPeriod::Ttm,
+  limit: 5,
+};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+// Serializes as "bullish"/"bearish"/"neutral", deserializes through FromStr so LLM output in any case or with stray whitespace still parses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Signal {
   Bullish,
   Bearish,
@@ -25,7 +41,6 @@ pub enum Signal {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WarrenBuffetSignal {
-  #[serde(deserialize_with = "deserialize_signal")]
   signal : Signal, 
   confidence: f64, 
   reasoning: String
@@ -46,20 +61,19 @@ impl FromStr for Signal {
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s.trim().to_lowercase().as_str() {
-      "bearish" => Ok(Signal::Bearish),  // Lowercase to match the lowercase input
-      "bullish" => Ok(Signal::Bullish),    // Lowercase to match the lowercase input
-      "neutral" => Ok(Signal::Neutral),        // Lowercase to match the lowercase input
-      _ => Err(format!("Unknown model provider: {}", s)),
+      "bullish" => Ok(Signal::Bullish),
+      "bearish" => Ok(Signal::Bearish),
+      "neutral" => Ok(Signal::Neutral),
+      _ => Err(format!("Unknown signal: {}", s)),
     }
   }
 }
 
-fn deserialize_signal<'de, D>(deserializer: D) -> Result<Signal, D::Error> where D: Deserializer<'de>,
-{
-    // First, deserialize as a String:
-    let s = String::deserialize(deserializer)?;
-    // Then parse it via your FromStr impl:
-    Signal::from_str(&s).map_err(serde::de::Error::custom)
+impl<'de> Deserialize<'de> for Signal {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+    let value = String::deserialize(deserializer)?;
+    Signal::from_str(&value).map_err(serde::de::Error::custom)
+  }
 }
 
 impl std::fmt::Display for Signal {
@@ -83,7 +97,8 @@ impl WarrenBuffetSignal {
 
   pub async fn warren_buffet_agent(&self,state: AgentState, config: Config) -> Result<PartialAgentStateUpdate, Error> {
 
-    let api_client : API = API::new(config); 
+    let api_client : API = API::new(config.clone()); 
+    let assumptions : ValuationAssumptions = ValuationAssumptions::from_metadata(&state.metadata);
     let data : HashMap<String, Value> = state.data;
     let end_date: &str = match data.get("end_date").and_then(Value::as_str) {
       Some (value) => value,
@@ -92,6 +107,8 @@ impl WarrenBuffetSignal {
         return Ok(PartialAgentStateUpdate::new());
       }
     };
+    let period : ReportPeriod = ReportPeriod::from_metadata(&state.metadata, BUFFETT_LINE_ITEMS.period);
+    let line_items : Vec<String> = BUFFETT_LINE_ITEMS.names_for("warren_buffett", &state.metadata);
     let tickers: Vec<String> = match data.get("tickers").and_then(Value::as_array) {
       Some(arr) if !arr.is_empty() => {
         arr.iter().filter_map(Value::as_str).map(String::from).collect()
@@ -105,33 +122,43 @@ impl WarrenBuffetSignal {
 
     let mut analysis_data: HashMap<String, HashMap<String, Value>> = HashMap::new();
     let mut buffet_analysis: HashMap<String, HashMap<String, Value>> = HashMap::new();
+    let mut ticker_errors: HashMap<String, String> = HashMap::new();
 
     if tickers.is_empty() {
       log::warn!("[Warren Buffett Agent] No tickers provided. Exiting.");
       return Ok(PartialAgentStateUpdate::new()); // Return empty update
     }
 
-    for ticker in tickers {
+    let api_ref : &API = &api_client;
+    let line_items_ref : &Vec<String> = &line_items;
+    let fetched = fetch_per_ticker(&tickers, config.ticker_fetch_concurrency, |ticker| async move {
       log::info!("Warren buffet agent {} fetching financial metrics", ticker); 
 
       let ticker: &str = ticker.as_str(); 
-
-      let metrics: Vec<FinancialMetrics> = api_client.get_financial_metrics(ticker, end_date, Some("ttm"), Some(5)).await?;
+      let metrics: Vec<FinancialMetrics> = api_ref.get_financial_metrics(ticker, end_date, Some(period.as_str()), Some(5)).await?;
 
       log::info!("Warren buffet agent {} gathering financial line items", ticker); 
 
-      let line_items: Vec<String> = vec!["capital_expenditure", "depreciation_and_amortization","net_income",
-                                                "outstanding_shares",
-                                                "total_assets",
-                                                "total_liabilities",
-                                                "dividends_and_other_cash_distributions",
-                                                "issuance_or_purchase_of_equity_shares",].into_iter().map(String::from).collect();
-
-      let financial_line_items: Vec<LineItem> = api_client.search_line_items(ticker, line_items, end_date, Some("ttm"), Some(5)).await?;
+      let financial_line_items: Vec<LineItem> = api_ref.search_line_items(ticker, line_items_ref.clone(), end_date, Some(period.as_str()), Some(BUFFETT_LINE_ITEMS.limit)).await?;
 
       log::info!("Warren buffet agent {} Getting market cap", ticker);
 
-      let market_cap: Option<f64> = api_client.get_market_cap(ticker, &end_date).await.with_context(|| format!("Failed to get market cap for {}", ticker))?;
+      let market_cap: Option<f64> = api_ref.get_market_cap(ticker, &end_date).await.with_context(|| format!("Failed to get market cap for {}", ticker))?;
+      Ok::<_, Error>((metrics, financial_line_items, market_cap))
+    }).await;
+
+    for (ticker, fetched) in fetched {
+      let ticker: &str = ticker.as_str(); 
+
+      // A delisted or mistyped ticker is recorded and skipped instead of failing the other tickers
+      let (metrics, financial_line_items, market_cap) = match fetched {
+        Ok(fetched) => fetched,
+        Err(e) => {
+          log::error!("[Warren Buffett Agent] ({}) Skipping ticker, data fetch failed: {:#}", ticker, e);
+          ticker_errors.insert(ticker.to_string(), format!("{:#}", e));
+          continue;
+        }
+      };
 
       log::info!("warren_buffett_agent {} Analyzing fundamental", ticker); 
 
@@ -139,7 +166,7 @@ impl WarrenBuffetSignal {
 
       log::info!("warren_buffett_agent {} Analyzing consistency", ticker); 
 
-      let consistency_analysis: HashMap<String, Value> = self.analyze_consistency(&financial_line_items)?;
+      let consistency_analysis: HashMap<String, Value> = self.analyze_consistency(&financial_line_items, period)?;
 
 
       log::info!("warren_buffett_agent {} Analyzing moat", ticker); 
@@ -149,7 +176,7 @@ impl WarrenBuffetSignal {
       let mgmt_analysis = self.analyze_management_quality(&financial_line_items)?;
 
       log::info!("warren_buffett_agent {} Calculating intrinsic value", ticker);
-      let intrinsic_value_analysis = self.calculate_intrinsic_value(&financial_line_items)?;
+      let intrinsic_value_analysis = self.calculate_intrinsic_value(&financial_line_items, &assumptions)?;
 
       // Calculate total score
       // Calculate total score
@@ -214,7 +241,7 @@ impl WarrenBuffetSignal {
         return Ok(PartialAgentStateUpdate::new());
       };
 
-      let buffet_output = self.generate_buffet_output(ticker, ticker_data, model_name, model_provider).await?;
+      let buffet_output = self.generate_buffet_output(ticker, ticker_data, model_name, model_provider, &config, SamplingParams::from_metadata(&state.metadata, DEFAULT_AGENT_TEMPERATURE), llm_timeout(&state.metadata, &config), is_dry_run(&state.metadata), &ensemble_models(&state.metadata), &prompt_overrides(&state.metadata)).await?;
 
       let mut final_buffer : HashMap<String, Value> = HashMap::new(); 
 
@@ -229,11 +256,7 @@ impl WarrenBuffetSignal {
 
     let message_content_string = serde_json::to_string(&buffet_analysis).context("Failed to serialize overall Buffett signal results to string for message")?;
     
-    let agent_message = ChatMessage {
-      role: "assistant".to_string(), // Or a custom role like "warren_buffett_agent_signal"
-      content: message_content_string,
-      // name: Some("warren_buffett_agent".to_string()), // If your ChatMessage struct supports a name
-    };
+    let agent_message = ChatMessage::from_agent("warren_buffett_agent", message_content_string);
 
     let show_reasoning = state.metadata.get("show_reasoning").and_then(Value::as_bool).unwrap_or(false);
 
@@ -251,12 +274,16 @@ impl WarrenBuffetSignal {
     analyst_signals_sub_map.insert("warren_buffett_agent".to_string(), final_buffett_analysis_value);
     
     updated_data_map.insert("analyst_signals".to_string(), Value::Object(analyst_signals_sub_map.into_iter().collect()));
+    if !ticker_errors.is_empty() {
+      updated_data_map.insert("errors".to_string(), serde_json::json!({ "warren_buffett_agent": ticker_errors }));
+    }
 
     log::info!("[Warren Buffett Agent] Analysis complete. Returning state update.");
     return Ok(PartialAgentStateUpdate {
       messages: Some(vec![agent_message]),
       data: Some(updated_data_map), // This will be merged into the main AgentState.data
       metadata: None, // No metadata changes made by this agent
+      portfolio_output: None,
     });
   }
 
@@ -335,13 +362,18 @@ impl WarrenBuffetSignal {
 
   }
 
-  pub fn analyze_consistency(&self, financial_line_items: &[LineItem]) -> Result<HashMap<String, Value>, Error> {
+  pub fn analyze_consistency(&self, financial_line_items: &[LineItem], period: ReportPeriod) -> Result<HashMap<String, Value>, Error> {
     // Analyze earning consistency and growth 
+    // Overlapping ttm windows share three quarters, so only annual and quarterly reports compare distinct periods
+    let basis = match period {
+      ReportPeriod::Ttm => "Period basis: ttm, consecutive trailing windows overlap so the trend is only indicative".to_string(),
+      _ => format!("Period basis: {}", period.as_str()),
+    };
 
     if financial_line_items.len() < 4 {
       let result = HashMap::from([
         ("score".to_string(), Value::from(0)), 
-        ("details".to_string(), Value::from("Insufficient historical data"))
+        ("details".to_string(), Value::from(format!("Insufficient historical data; {}", basis)))
       ]);
       return Ok(result);
     }
@@ -377,6 +409,7 @@ impl WarrenBuffetSignal {
     else {
       reasoning.push("Insufficient earnings data for trend analysis".to_string());
     }
+    reasoning.push(basis);
 
     let mut final_response: HashMap<String, Value> = HashMap::new(); 
     final_response.insert("score".to_owned(), Value::from(score));
@@ -505,9 +538,10 @@ impl WarrenBuffetSignal {
     */
 
     if financial_line_items.is_empty() {
-      let _result : HashMap<String, Value> = HashMap::from([
-        ("owner_earnings".to_string(), Value::Null), ("details".to_string(), Value::from(vec![Value::from("Insufficent data for owner earnings calculation")]))
+      let result : HashMap<String, Value> = HashMap::from([
+        ("owner_earnings".to_string(), Value::Null), ("details".to_string(), Value::from(vec![Value::from("Insufficient data for owner earnings calculation")]))
       ]);
+      return Ok(result);
     }
 
     let latest = &financial_line_items[0];
@@ -546,7 +580,15 @@ impl WarrenBuffetSignal {
 
   }
 
-  pub fn calculate_intrinsic_value(&self, financial_line_items: &[LineItem]) -> Result<HashMap<String, Value>, Error> {
+  pub fn calculate_intrinsic_value(&self, financial_line_items: &[LineItem], assumptions: &ValuationAssumptions) -> Result<HashMap<String, Value>, Error> {
+    // Without a value the margin-of-safety check is skipped rather than run against a meaningless one
+    if let Err(reason) = assumptions.validate() {
+      return Ok(HashMap::from([
+        ("intrinsic_value".to_string(), Value::Null),
+        ("details".to_string(), Value::from(vec![Value::from(format!("Invalid valuation assumptions: {}", reason))])),
+      ]));
+    }
+
     if financial_line_items.is_empty() {
       let result : HashMap<String, Value> = HashMap::from([
         ("intrinsic_value".to_string(), Value::Null), ("details".to_string(), Value::from(vec![Value::from("Insufficient data for valuation")]))
@@ -574,32 +616,16 @@ impl WarrenBuffetSignal {
       ]));
     }
 
-    let growth_rate : f64 = 0.05; 
-    let discount_rate : f64 = 0.09; 
-    let terminal_multiple : i64 = 12; 
-    let projection_years : i32 = 10;
-
-    let mut future_values : f64 = 0.0; 
-
-    for year in 1..=projection_years {
-      let future_earnings : f64 = owner_earnings * (1.0 + growth_rate) .powi(year);
-      let present_value : f64 = future_earnings / (1.0 + growth_rate).powi(year); 
-      future_values += present_value; 
-    }
-
-    let terminal_owner_earnings_proj : f64 = owner_earnings * (1.0 + growth_rate).powi(projection_years);
-    let terminal_value_at_proj_end = terminal_owner_earnings_proj * terminal_multiple as f64;
-
-    let terminal_value : f64 = terminal_value_at_proj_end / (1.0 + discount_rate).powi(projection_years); 
-    let intrinsic_value : f64 = future_values + terminal_value;
+    // Projected owner earnings discounted at discount_rate, plus a terminal multiple on the final year
+    let intrinsic_value : f64 = assumptions.discounted_value(owner_earnings);
 
     let mut assumption : HashMap<String, Value> = HashMap::new();
     let mut result : HashMap<String, Value> = HashMap::new();
 
-    assumption.insert("growth_rate".to_string(), Value::from(growth_rate)); 
-    assumption.insert("discount_rate".to_string(), Value::from(discount_rate)); 
-    assumption.insert("terminal_multiple".to_string(), Value::from(terminal_multiple)); 
-    assumption.insert("projection_years".to_string(), Value::from(projection_years)); 
+    assumption.insert("growth_rate".to_string(), Value::from(assumptions.growth_rate)); 
+    assumption.insert("discount_rate".to_string(), Value::from(assumptions.discount_rate)); 
+    assumption.insert("terminal_multiple".to_string(), Value::from(assumptions.terminal_multiple)); 
+    assumption.insert("projection_years".to_string(), Value::from(assumptions.projection_years)); 
 
 
     result.insert("intrinsic_value".to_string(), Value::from(intrinsic_value)); 
@@ -610,11 +636,20 @@ impl WarrenBuffetSignal {
     return Ok(result);
   }
 
-  pub async fn generate_buffet_output(&self, ticker: &str, analysis_data: &HashMap<String, Value>, model_name: &str, model_provider: &str) -> Result<WarrenBuffetSignal, Error> {
+  #[tracing::instrument(skip_all, fields(ticker = %ticker, model = %model_name))]
+  pub async fn generate_buffet_output(&self, ticker: &str, analysis_data: &HashMap<String, Value>, model_name: &str, model_provider: &str, config: &Config, sampling: SamplingParams, timeout: Duration, dry_run: bool, ensemble: &[EnsembleModel], prompt_overrides: &HashMap<String, String>) -> Result<WarrenBuffetSignal, Error> {
+
+    if dry_run {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, "Dry run"));
+    }
+    // An ensemble makes one call per model
+    if !budget_allows(sampling.max_tokens as u64 * ensemble.len().max(1) as u64) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, BUDGET_EXHAUSTED));
+    }
 
     let analysis_data_json = serde_json::to_string_pretty(analysis_data).context("Failed to serialize analysis data for LLM prompt")?;
 
-    let system_prompt : &str = r#"You are a Warren Buffett AI agent. Decide on investment signals based on Warren Buffett's principles:
+    let default_system_prompt : &str = r#"You are a Warren Buffett AI agent. Decide on investment signals based on Warren Buffett's principles:
                                   - Circle of Competence: Only invest in businesses you understand
                                   - Margin of Safety (> 30%): Buy at a significant discount to intrinsic value
                                   - Economic Moat: Look for durable competitive advantages
@@ -635,49 +670,73 @@ impl WarrenBuffetSignal {
 
                                   Follow these guidelines strictly."#;
 
-    let human_prompt : String = format!(r#"Based on the following data, create the investment signal as Warren Buffett would:
-                              Analysis Data for {}:
-                              {}
-
-                              Return the trading signal in the following JSON format exactly without any explanation:
-                              {{
-                                "signal": "bullish" | "bearish" | "neutral",
-                                "confidence": float between 0 and 100,
-                                "reasoning": "string"
-                              }}"#, ticker, analysis_data_json);
+    let json_mode : bool = supports_json_mode(model_name);
 
-    let user_prompt: String = human_prompt;
-
-    let messages = vec![
-      ChatMessage{ role: "system".to_string(), content: system_prompt.to_string()}, 
-      ChatMessage{ role: "user".to_string(), content: user_prompt}
-    ]; 
+    // Templates can use {ticker} and {analysis_data}
+    let vars = [("ticker", ticker), ("analysis_data", analysis_data_json.as_str())];
+    let system_prompt : String = render_prompt("warren_buffett.system", default_system_prompt, prompt_overrides, &vars);
+    let instruction : String = render_prompt("warren_buffett.instruction", "Based on the following data, create the investment signal as Warren Buffett would:", prompt_overrides, &vars);
 
+    let build_messages = |json_mode: bool| PromptBuilder::new()
+      .with_system(&system_prompt)
+      .with_instruction(&instruction)
+      .with_section(&format!("Analysis Data for {}", ticker), &analysis_data_json)
+      .with_json_schema(SIGNAL_JSON_SCHEMA)
+      .with_json_mode(json_mode)
+      .build();
+    let messages = build_messages(json_mode);
 
     let provider = ModelProvider::from_str(model_provider).map_err(|_| anyhow!("Unknown model provider: {}",model_provider))?;
 
-    let config_for_call : LLMModelConfig = LLMModelConfig { 
-      provider: provider, 
-      model_name: model_name.to_string(), 
-      api_key:Some("".to_string()) , 
-      base_url: Some("".to_string()), 
-      temperature: Some(0.5), 
-      max_tokens: Some(1024), 
-      top_p: Some(0.5)
-    };
+    let config_for_call : LLMModelConfig = sampling.to_config(provider, model_name, json_mode);
 
-    let client = get_model(&config_for_call)?;
+    // Each ensemble model gets its own json mode, so the prompt always carries the JSON instructions
+    if ensemble.len() > 1 {
+      log::info!("[Warren Buffett Agent] ({}) Calling {} ensemble models for Buffett analysis...", ticker, ensemble.len());
+      let votes = ensemble_vote(ensemble, build_messages(false), &config_for_call, config, timeout).await;
+      return Ok(match reconcile_votes(&votes) {
+        Some(vote) => WarrenBuffetSignal { signal: vote.signal, confidence: vote.confidence, reasoning: vote.reasoning },
+        None => WarrenBuffetSignal {
+          signal: Signal::Neutral,
+          confidence: 0.0,
+          reasoning: format!("Every ensemble model failed for ticker {}. Defaulting to neutral.", ticker),
+        },
+      });
+    }
+
+    if circuit_breaker::is_open(&config_for_call) {
+      return Ok(Self::dry_run_signal(ticker, analysis_data, CIRCUIT_OPEN));
+    }
+    let client = get_ready_model(&config_for_call, config)?;
 
     log::info!("[Warren Buffett Agent] ({}) Calling LLM for Buffett analysis...", ticker);
 
-    let response = client.chat(messages, &config_for_call).await?; 
+    let response = match chat_with_timeout(client.as_ref(), messages.clone(), &config_for_call, timeout).await? {
+      Some(response) => response,
+      None => return Ok(WarrenBuffetSignal {
+        signal: Signal::Neutral,
+        confidence: 0.0,
+        reasoning: format!("LLM call for ticker {} timed out after {:.1}s. Defaulting to neutral.", ticker, timeout.as_secs_f64()),
+      }),
+    };
 
     log::debug!("[Warren Buffett Agent] ({}) LLM raw response: {}", ticker, response.content);
     
-    match serde_json::from_str::<WarrenBuffetSignal>(&response.content) {
+    let parsed = match extract_json::<WarrenBuffetSignal>(&response.content) {
+      Ok(signal) => Ok(signal),
+      Err(err) => {
+        log::warn!("[Warren Buffett Agent] ({}) LLM response is not valid JSON ({}), asking the model to reformat it", ticker, err);
+        repair_json::<WarrenBuffetSignal>(client.as_ref(), messages, &response.content, &config_for_call, timeout).await.ok_or(err)
+      }
+    };
+
+    match parsed {
       Ok(signal) => return Ok(signal),
       Err(err) => {
         log::error!("[Warren Buffett Agent] ({}) Failed to parse LLM JSON response into WarrenBuffettSignal: {}. Raw response: '{}'",ticker,err,response.content);
+        if response.is_truncated() {
+          log::warn!("[Warren Buffett Agent] ({}) LLM response was cut off at max_tokens, raise max_tokens if this keeps happening", ticker);
+        }
         Ok(WarrenBuffetSignal {
           signal: Signal::Neutral,
           confidence: 0.0,
@@ -688,4 +747,150 @@ impl WarrenBuffetSignal {
 
   }
 
-}
\ No newline at end of file
+  // The rule-based signal computed alongside the score, confidence is the share of the maximum score reached
+  fn dry_run_signal(ticker: &str, analysis_data: &HashMap<String, Value>, reason: &str) -> WarrenBuffetSignal {
+    let signal = match analysis_data.get("signal").and_then(Value::as_str) {
+      Some("bullish") => Signal::Bullish,
+      Some("bearish") => Signal::Bearish,
+      _ => Signal::Neutral,
+    };
+    let score = analysis_data.get("score").and_then(Value::as_f64).unwrap_or(0.0);
+    let max_score = analysis_data.get("max_score").and_then(Value::as_f64).unwrap_or(0.0);
+    let confidence = if max_score > 0.0 { (score / max_score * 100.0).round() } else { 0.0 };
+
+    log::info!("[Warren Buffett Agent] ({}) {}, skipping the LLM call", ticker, reason);
+    WarrenBuffetSignal {
+      signal,
+      confidence,
+      reasoning: format!("{}: signal derived from a score of {} out of {} without calling the LLM.", reason, score, max_score),
+    }
+  }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai_agent::llm::model_provider::with_token_budget;
+  use crate::ai_agent::tools::stub_server::StubServer;
+  use serde_json::json;
+
+  // Financial datasets stand-in with one report per ticker, any request for BUFFBAD answers 404
+  fn financial_data_server() -> StubServer {
+    StubServer::start(|request| {
+      if request.path.contains("BUFFBAD") || request.body.contains("BUFFBAD") {
+        return (404, "{}".to_string());
+      }
+      if request.method == "POST" {
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        let ticker = body["tickers"][0].as_str().unwrap().to_string();
+        return (200, json!({ "search_results": [{
+          "ticker": ticker, "report_period": "2024-03-31", "period": "ttm", "currency": "USD",
+          "net_income": 1.0e9, "depreciation_and_amortization": 2.0e8, "capital_expenditure": -1.0e8, "outstanding_shares": 1.0e8,
+        }] }).to_string());
+      }
+      let ticker = request.path.split("ticker=").nth(1).and_then(|rest| rest.split('&').next()).unwrap_or_default().to_string();
+      (200, json!({ "financial_metrics": [{
+        "ticker": ticker, "report_period": "2024-03-31", "period": "ttm", "currency": "USD",
+        "market_cap": 1.0e10, "return_on_equity": 0.2, "debt_to_equity": 0.3, "operating_margin": 0.25, "current_ratio": 1.8,
+      }] }).to_string())
+    })
+  }
+
+  #[tokio::test]
+  async fn a_failing_ticker_is_recorded_and_the_others_still_signal() {
+    let server = financial_data_server();
+    let mut config = Config::for_tests();
+    config.financial_datasets_api_key = Some("test".to_string());
+    config.financial_datasets_base_url = server.base_url.clone();
+
+    let mut state = AgentState::new();
+    state.merge_data(serde_json::from_value(json!({ "tickers": ["BUFFOK1", "BUFFBAD", "BUFFOK2"], "end_date": "2024-06-30" })).unwrap()).unwrap();
+    state.merge_metadata(serde_json::from_value(json!({ "dry_run": true, "model_name": "test-model", "model_provider": "OpenAI" })).unwrap()).unwrap();
+
+    let update = WarrenBuffetSignal::new().warren_buffet_agent(state, config).await.unwrap();
+    let data = update.data.unwrap();
+    let signals = &data["analyst_signals"]["warren_buffett_agent"];
+    for ticker in ["BUFFOK1", "BUFFOK2"] {
+      assert!(signals[ticker]["signal"].as_str().unwrap().parse::<Signal>().is_ok(), "{} has no signal", ticker);
+    }
+    assert!(signals.get("BUFFBAD").is_none());
+    let error = data["errors"]["warren_buffett_agent"]["BUFFBAD"].as_str().unwrap();
+    assert!(error.contains("404"), "{}", error);
+  }
+
+  #[tokio::test]
+  async fn an_exhausted_budget_falls_back_to_the_rule_based_signal() {
+    let analysis_data : HashMap<String, Value> = serde_json::from_value(json!({ "signal": "bullish", "score": 6.0, "max_score": 8.0 })).unwrap();
+    let sampling = SamplingParams { temperature: 0.0, top_p: 1.0, max_tokens: 1024, seed: None };
+    // No LLM key is configured, so reaching the provider would fail instead of falling back
+    let (output, remaining) = with_token_budget(Some(100), WarrenBuffetSignal::new().generate_buffet_output(
+      "BUDGET", &analysis_data, "gpt-4o", "OpenAI", &Config::for_tests(), sampling, Duration::from_secs(1), false, &[], &HashMap::new())).await;
+
+    let output = output.unwrap();
+    assert_eq!(output.signal, Signal::Bullish);
+    assert_eq!(output.confidence, 75.0);
+    assert!(output.reasoning.starts_with(BUDGET_EXHAUSTED), "{}", output.reasoning);
+    assert_eq!(remaining, Some(100));
+  }
+
+  fn owner_earnings_line_items() -> Vec<LineItem> {
+    vec![serde_json::from_value(json!({
+      "ticker": "BUFFDCF", "report_period": "2024-03-31", "period": "ttm", "currency": "USD",
+      "net_income": 100.0, "depreciation_and_amortization": 20.0, "capital_expenditure": 40.0, "outstanding_shares": 10.0,
+    })).unwrap()]
+  }
+
+  #[test]
+  fn intrinsic_value_rejects_a_discount_rate_at_or_below_growth() {
+    let degenerate = ValuationAssumptions { growth_rate: 0.09, discount_rate: 0.09, ..ValuationAssumptions::default() };
+    let result = WarrenBuffetSignal::new().calculate_intrinsic_value(&owner_earnings_line_items(), &degenerate).unwrap();
+    assert_eq!(result["intrinsic_value"], Value::Null);
+    assert_eq!(result["details"], json!(["Invalid valuation assumptions: discount_rate (0.09) must be greater than growth_rate (0.09)"]));
+  }
+
+  #[test]
+  fn intrinsic_value_with_sane_assumptions_discounts_owner_earnings() {
+    let sane = ValuationAssumptions { growth_rate: 0.0, discount_rate: 0.1, terminal_multiple: 0.0, projection_years: 1, ..ValuationAssumptions::default() };
+    let result = WarrenBuffetSignal::new().calculate_intrinsic_value(&owner_earnings_line_items(), &sane).unwrap();
+    // 100 + 20 - 0.75 * 40 = 90 owner earnings, one year out at 10%
+    assert_eq!(result["owner_earnings"], json!(90.0));
+    assert!((result["intrinsic_value"].as_f64().unwrap() - 90.0 / 1.1).abs() < 1e-9);
+  }
+
+  #[test]
+  fn signals_parse_in_any_case_and_with_padding() {
+    for (raw, signal) in [("BULLISH", Signal::Bullish), (" bearish", Signal::Bearish), ("Neutral ", Signal::Neutral)] {
+      assert_eq!(serde_json::from_value::<Signal>(json!(raw)).unwrap(), signal);
+      assert_eq!(raw.parse::<Signal>().unwrap(), signal);
+    }
+    assert!(serde_json::from_value::<Signal>(json!("positive")).is_err());
+  }
+
+  #[test]
+  fn llm_signal_round_trips_lowercase() {
+    let parsed : WarrenBuffetSignal = serde_json::from_value(json!({ "signal": " Bullish", "confidence": 72.0, "reasoning": "wide moat" })).unwrap();
+    assert_eq!(serde_json::to_value(&parsed).unwrap()["signal"], json!("bullish"));
+  }
+
+  #[test]
+  fn owner_earnings_of_no_line_items_is_insufficient_data() {
+    let result = WarrenBuffetSignal::new().calculate_owner_earnings(&[]).unwrap();
+    assert_eq!(result["owner_earnings"], Value::Null);
+    assert_eq!(result["details"], json!(["Insufficient data for owner earnings calculation"]));
+
+    let valuation = WarrenBuffetSignal::new().calculate_intrinsic_value(&[], &ValuationAssumptions::default()).unwrap();
+    assert_eq!(valuation["intrinsic_value"], Value::Null);
+    assert_eq!(valuation["details"], json!(["Insufficient data for valuation"]));
+  }
+
+  #[test]
+  fn every_signal_round_trips_through_serde() {
+    for signal in [Signal::Bullish, Signal::Bearish, Signal::Neutral] {
+      let serialized = serde_json::to_value(signal).unwrap();
+      assert_eq!(serialized, json!(signal.as_str()));
+      assert_eq!(serde_json::from_value::<Signal>(serialized).unwrap(), signal);
+    }
+  }
+}
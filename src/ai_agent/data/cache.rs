@@ -1,38 +1,198 @@
-use anyhow::{Error, Ok};
+use anyhow::{Context, Error, Ok};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+  inserted_at: u64, // unix seconds
+  data: HashMap<String, Value>,
+}
+
+// Date window a ticker's cached entries are known to be complete for, so a wider request only fetches the missing ends
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Coverage {
+  pub start: Option<NaiveDate>, // None once the API has nothing older
+  pub end: NaiveDate,
+  inserted_at: u64, // of the oldest fetch in the window, the window expires with it
+}
+
+impl Coverage {
+  // Parts of `start..=end` outside the window, oldest first
+  pub fn gaps(&self, start: NaiveDate, end: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut gaps = Vec::new();
+    if let Some(covered_start) = self.start.filter(|covered_start| start < *covered_start) {
+      gaps.push((start, end.min(covered_start.pred_opt().unwrap_or(covered_start))));
+    }
+    if end > self.end {
+      gaps.push((start.max(self.end.succ_opt().unwrap_or(self.end)), end));
+    }
+    gaps
+  }
+
+  // Overlapping or adjacent windows merge, a disjoint one cannot vouch for the gap between them so it replaces the old window
+  fn extend(existing: Option<&Coverage>, start: Option<NaiveDate>, end: NaiveDate, now: u64) -> Coverage {
+    let touches = |coverage: &Coverage| {
+      let starts_before_end = start.map_or(true, |start| start <= coverage.end.succ_opt().unwrap_or(coverage.end));
+      let ends_after_start = coverage.start.map_or(true, |covered_start| covered_start <= end.succ_opt().unwrap_or(end));
+      starts_before_end && ends_after_start
+    };
+    match existing.filter(|coverage| touches(coverage)) {
+      Some(coverage) => Coverage {
+        start: start.zip(coverage.start).map(|(start, covered_start)| start.min(covered_start)),
+        end: end.max(coverage.end),
+        inserted_at: coverage.inserted_at,
+      },
+      None => Coverage { start, end, inserted_at: now },
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheTtl {
+  pub prices: Duration,
+  pub financial_metrics: Duration,
+  pub line_items: Duration,
+  pub insider_trades: Duration,
+  pub company_news: Duration,
+  pub company_facts: Duration,
+  pub market_cap: Duration,
+}
+
+impl CacheTtl {
+  #[cfg(test)]
+  pub fn uniform(ttl: Duration) -> Self {
+    CacheTtl { prices: ttl, financial_metrics: ttl, line_items: ttl, insider_trades: ttl, company_news: ttl, company_facts: ttl, market_cap: ttl }
+  }
+}
+
+impl Default for CacheTtl {
+  fn default() -> Self {
+    // Prices move intraday, reported fundamentals only change with new filings
+    CacheTtl {
+      prices: Duration::from_secs(15 * 60),
+      financial_metrics: Duration::from_secs(24 * 60 * 60),
+      line_items: Duration::from_secs(24 * 60 * 60),
+      insider_trades: Duration::from_secs(60 * 60),
+      company_news: Duration::from_secs(60 * 60),
+      company_facts: Duration::from_secs(7 * 24 * 60 * 60), // sector, exchange and the like rarely change
+      market_cap: Duration::from_secs(24 * 60 * 60),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Cache {
-  price_cache: HashMap<String, Vec<HashMap<String, Value>>>,
-  financial_metric_cache: HashMap<String, Vec<HashMap<String, Value>>>,
-  line_items_cache: HashMap<String, Vec<HashMap<String, Value>>>,
-  insider_trades_cache: HashMap<String, Vec<HashMap<String, Value>>>,
-  company_news_cache: HashMap<String, Vec<HashMap<String, Value>>>,
+  price_cache: HashMap<String, Vec<CacheEntry>>,
+  financial_metric_cache: HashMap<String, Vec<CacheEntry>>,
+  line_items_cache: HashMap<String, Vec<CacheEntry>>,
+  insider_trades_cache: HashMap<String, Vec<CacheEntry>>,
+  company_news_cache: HashMap<String, Vec<CacheEntry>>,
+  #[serde(default)] // absent from cache files written before company facts were cached
+  company_facts_cache: HashMap<String, Vec<CacheEntry>>,
+  #[serde(default)]
+  market_cap_cache: HashMap<String, Vec<CacheEntry>>, // one entry per requested date
+  #[serde(default)] // cache files written before windows were tracked refetch each ticker once
+  price_coverage: HashMap<String, Coverage>,
+  #[serde(default)]
+  financial_metric_coverage: HashMap<String, Coverage>, // keyed by ticker and period basis
+  #[serde(skip)]
+  ttl: CacheTtl, // not persisted, always taken from the current config
+  #[serde(skip, default = "system_clock")]
+  clock: fn() -> u64, // unix seconds, swapped out by tests to expire entries without waiting
+}
+
+fn system_clock() -> fn() -> u64 {
+  || SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
 }
 
 static GLOBAL_CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
 
 impl Cache {
   pub fn new() -> Self {
+    Self::new_with_ttls(CacheTtl::default())
+  }
+
+  pub fn new_with_ttls(ttl: CacheTtl) -> Self {
+    Self::new_with_clock(ttl, system_clock())
+  }
+
+  pub fn new_with_clock(ttl: CacheTtl, clock: fn() -> u64) -> Self {
     Cache {
       price_cache: HashMap::new(),
       financial_metric_cache: HashMap::new(),
       line_items_cache: HashMap::new(),
       insider_trades_cache: HashMap::new(),
       company_news_cache: HashMap::new(),
+      company_facts_cache: HashMap::new(),
+      market_cap_cache: HashMap::new(),
+      price_coverage: HashMap::new(),
+      financial_metric_coverage: HashMap::new(),
+      ttl,
+      clock,
+    }
+  }
+
+  pub fn save_to_disk(&self, path: &Path) -> Result<(), Error> {
+    // Write to a sibling temp file and rename, so a crash mid-write leaves the old cache intact
+    let contents = serde_json::to_string(self).context("Failed to serialize cache")?;
+    let tmp_path = path.with_extension("tmp");
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+      fs::create_dir_all(parent).with_context(|| format!("Failed to create cache directory {}", parent.display()))?;
+    }
+    fs::write(&tmp_path, contents).with_context(|| format!("Failed to write cache to {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, path).with_context(|| format!("Failed to move cache into place at {}", path.display()))?;
+    Ok(())
+  }
+
+  pub fn load_from_disk(path: &Path) -> Result<Self, Error> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read cache from {}", path.display()))?;
+    let cache : Cache = serde_json::from_str(&contents).with_context(|| format!("Failed to parse cache file {}", path.display()))?;
+    Ok(cache)
+  }
+
+  fn now(&self) -> u64 {
+    (self.clock)()
+  }
+
+  fn fresh_entries(&self, entries: &[CacheEntry], ttl: Duration) -> Vec<HashMap<String, Value>> {
+    let now = self.now();
+    entries.iter().filter(|entry| now.saturating_sub(entry.inserted_at) < ttl.as_secs()).map(|entry| entry.data.clone()).collect()
+  }
+
+  fn fresh_coverage(&self, coverage: Option<&Coverage>, ttl: Duration) -> Option<Coverage> {
+    let now = self.now();
+    coverage.filter(|coverage| now.saturating_sub(coverage.inserted_at) < ttl.as_secs()).copied()
+  }
+
+  // A refetched entry replaces the cached one and restarts its TTL, so nothing inside a coverage window expires before the window
+  fn upsert_entries(entries: &mut Vec<CacheEntry>, new_data: Vec<HashMap<String, Value>>, ttl: Duration, now: u64, same: impl Fn(&HashMap<String, Value>, &HashMap<String, Value>) -> bool) {
+    entries.retain(|entry| now.saturating_sub(entry.inserted_at) < ttl.as_secs());
+
+    for new_item in new_data {
+      match entries.iter_mut().find(|entry| same(&entry.data, &new_item)) {
+        Some(entry) => *entry = CacheEntry { inserted_at: now, data: new_item },
+        None => entries.push(CacheEntry { inserted_at: now, data: new_item }),
+      }
     }
   }
 
-  fn merge_data(&self,existing: Vec<HashMap<String, Value>>, new_data: Vec<HashMap<String, Value>>,key_field: &str,) -> Result<Vec<HashMap<String, Value>>, Error> {
-    let mut merged = existing.clone();
+  fn merge_data(&self,existing: Vec<CacheEntry>, new_data: Vec<HashMap<String, Value>>,key_field: &str, ttl: Duration) -> Result<Vec<CacheEntry>, Error> {
+    // Expired entries are dropped so a refetch replaces them instead of being deduped away
+    let now = self.now();
+    let mut merged : Vec<CacheEntry> = existing.into_iter().filter(|entry| now.saturating_sub(entry.inserted_at) < ttl.as_secs()).collect();
 
     for new_item in new_data {
-      let key = new_item.get(key_field).ok_or_else(|| Error::msg(format!("Missing key field: {}", key_field)))?.to_string();
+      let key = new_item.get(key_field).ok_or_else(|| Error::msg(format!("Missing key field: {}", key_field)))?.clone();
 
-      if !merged.iter().any(|item| { 
-        item.get(key_field).map_or(false, |v| v == &Value::String(key.clone()))}) {
-        merged.push(new_item);
+      if !merged.iter().any(|entry| entry.data.get(key_field).map_or(false, |v| v == &key)) {
+        merged.push(CacheEntry { inserted_at: now, data: new_item });
       }
     }
 
@@ -42,7 +202,7 @@ impl Cache {
   pub fn get_prices(&self, ticker: &str) -> Result<Vec<HashMap<String, Value>>, Error> {
     let result = self.price_cache.get(ticker);
     match result {
-      Some(result) =>  {return Ok(result.clone()) },
+      Some(result) =>  {return Ok(self.fresh_entries(result, self.ttl.prices)) },
       None => {
         log::info!("Price does not match with ticker {}", ticker);
         return Ok(Vec::new());
@@ -51,20 +211,29 @@ impl Cache {
   }
 
   pub fn set_prices(&mut self, ticker: &str, data: Vec<HashMap<String, Value>>) -> Result<(), Error> {
-    let result = self.price_cache.get(ticker).cloned().unwrap_or_default();
-
-    let merged_data = self.merge_data(result, data, "time")?;
-    self.price_cache.insert(ticker.to_string(), merged_data);
+    if data.iter().any(|item| !item.contains_key("time")) {
+      return Err(Error::msg("Missing key field: time"));
+    }
+    let now = self.now();
+    let entries = self.price_cache.entry(ticker.to_string()).or_default();
+    Self::upsert_entries(entries, data, self.ttl.prices, now, |cached, new_item| cached.get("time") == new_item.get("time"));
     Ok(())
+  }
 
-   
+  pub fn get_price_coverage(&self, ticker: &str) -> Option<Coverage> {
+    self.fresh_coverage(self.price_coverage.get(ticker), self.ttl.prices)
+  }
+
+  pub fn extend_price_coverage(&mut self, ticker: &str, start: NaiveDate, end: NaiveDate) {
+    let existing = self.get_price_coverage(ticker);
+    self.price_coverage.insert(ticker.to_string(), Coverage::extend(existing.as_ref(), Some(start), end, self.now()));
   }
 
   pub fn get_financial_metrics(&self, ticker: &str) -> Result<Vec<HashMap<String, Value>>, Error> {
     let result = self.financial_metric_cache.get(ticker);
 
     match result {
-      Some(result) => return Ok(result.clone()), 
+      Some(result) => return Ok(self.fresh_entries(result, self.ttl.financial_metrics)), 
       None =>  {
         log::info!("Financial metrics does not match with ticker {}", ticker);
         return Ok(Vec::new());
@@ -73,16 +242,31 @@ impl Cache {
   }
 
   pub fn set_financial_metrics(&mut self, ticker: &str, data: Vec<HashMap<String, Value>>) -> Result<(), Error> {
-    let result = self.financial_metric_cache.get(ticker).cloned().unwrap_or_default();
-    let merged_data = self.merge_data(result, data, "report_period")?;
-    self.financial_metric_cache.insert(ticker.to_string(), merged_data);
+    // The same report date exists once per period basis, so both fields identify an entry
+    if data.iter().any(|item| !item.contains_key("report_period")) {
+      return Err(Error::msg("Missing key field: report_period"));
+    }
+    let now = self.now();
+    let entries = self.financial_metric_cache.entry(ticker.to_string()).or_default();
+    Self::upsert_entries(entries, data, self.ttl.financial_metrics, now, |cached, new_item| {
+      cached.get("report_period") == new_item.get("report_period") && cached.get("period") == new_item.get("period")
+    });
     Ok(())
   }
 
+  pub fn get_financial_metrics_coverage(&self, ticker: &str, period: &str) -> Option<Coverage> {
+    self.fresh_coverage(self.financial_metric_coverage.get(&format!("{}:{}", ticker, period)), self.ttl.financial_metrics)
+  }
+
+  pub fn extend_financial_metrics_coverage(&mut self, ticker: &str, period: &str, start: Option<NaiveDate>, end: NaiveDate) {
+    let existing = self.get_financial_metrics_coverage(ticker, period);
+    self.financial_metric_coverage.insert(format!("{}:{}", ticker, period), Coverage::extend(existing.as_ref(), start, end, self.now()));
+  }
+
 
   pub fn get_line_items(&self, ticker: &str) -> Result<Vec<HashMap<String, Value>>, Error> {
     match self.line_items_cache.get(ticker) {
-      Some(items_vec_ref) => Ok(items_vec_ref.clone()),
+      Some(items_vec_ref) => Ok(self.fresh_entries(items_vec_ref, self.ttl.line_items)),
       None => {
         log::info!("Line items not found in cache for ticker: {}", ticker);
         Ok(Vec::new())
@@ -91,15 +275,27 @@ impl Cache {
   }
 
   pub fn set_line_items(&mut self, ticker: &str, data: Vec<HashMap<String, Value>>) -> Result<(), Error> {
-    let existing_data_for_ticker = self.line_items_cache.get(ticker).cloned().unwrap_or_default();
-    let merged_data = self.merge_data(existing_data_for_ticker, data, "report_period")?;
-    self.line_items_cache.insert(ticker.to_string(), merged_data);
+    // Line items are fetched as named subsets, so new fields are merged into an already cached period instead of skipped
+    let now = self.now();
+    let ttl = self.ttl.line_items;
+    let entries = self.line_items_cache.entry(ticker.to_string()).or_default();
+    entries.retain(|entry| now.saturating_sub(entry.inserted_at) < ttl.as_secs());
+
+    for new_item in data {
+      let report_period = new_item.get("report_period").ok_or_else(|| Error::msg("Missing key field: report_period"))?.clone();
+      let period = new_item.get("period").cloned();
+
+      match entries.iter_mut().find(|entry| entry.data.get("report_period") == Some(&report_period) && entry.data.get("period") == period.as_ref()) {
+        Some(entry) => entry.data.extend(new_item),
+        None => entries.push(CacheEntry { inserted_at: now, data: new_item }),
+      }
+    }
     Ok(())
   }
 
   pub fn get_insider_trades(&self, ticker: &str) -> Result<Vec<HashMap<String, Value>>, Error> {
     match self.insider_trades_cache.get(ticker) {
-      Some(trades_vec_ref) => Ok(trades_vec_ref.clone()),
+      Some(trades_vec_ref) => Ok(self.fresh_entries(trades_vec_ref, self.ttl.insider_trades)),
       None => {
         log::info!("Insider trades not found in cache for ticker: {}", ticker);
         Ok(Vec::new())
@@ -108,15 +304,23 @@ impl Cache {
   }
 
   pub fn set_insider_trades(&mut self, ticker: &str, data: Vec<HashMap<String, Value>>) -> Result<(), Error> {
-    let existing_data_for_ticker = self.insider_trades_cache.get(ticker).cloned().unwrap_or_default();
-    let merged_data = self.merge_data(existing_data_for_ticker, data, "filing_date")?;
-    self.insider_trades_cache.insert(ticker.to_string(), merged_data);
+    // Several trades are often filed on the same day, so only identical records count as duplicates
+    let now = self.now();
+    let ttl = self.ttl.insider_trades;
+    let entries = self.insider_trades_cache.entry(ticker.to_string()).or_default();
+    entries.retain(|entry| now.saturating_sub(entry.inserted_at) < ttl.as_secs());
+
+    for new_item in data {
+      if !entries.iter().any(|entry| entry.data == new_item) {
+        entries.push(CacheEntry { inserted_at: now, data: new_item });
+      }
+    }
     Ok(())
   }
 
   pub fn get_company_news(&self, ticker: &str) -> Result<Vec<HashMap<String, Value>>, Error> {
     match self.company_news_cache.get(ticker) {
-      Some(news_vec_ref) => Ok(news_vec_ref.clone()),
+      Some(news_vec_ref) => Ok(self.fresh_entries(news_vec_ref, self.ttl.company_news)),
       None => {
         log::info!("Company news not found in cache for ticker: {}", ticker);
         Ok(Vec::new())
@@ -126,11 +330,79 @@ impl Cache {
 
   pub fn set_company_news(&mut self, ticker: &str, data: Vec<HashMap<String, Value>>) -> Result<(), Error> {
     let existing_data_for_ticker = self.company_news_cache.get(ticker).cloned().unwrap_or_default();
-    let merged_data = self.merge_data(existing_data_for_ticker, data, "date")?;
+    let merged_data = self.merge_data(existing_data_for_ticker, data, "date", self.ttl.company_news)?;
     self.company_news_cache.insert(ticker.to_string(), merged_data);
     Ok(())
   }
 
+  pub fn get_company_facts(&self, ticker: &str) -> Result<Option<HashMap<String, Value>>, Error> {
+    match self.company_facts_cache.get(ticker) {
+      Some(entries) => Ok(self.fresh_entries(entries, self.ttl.company_facts).into_iter().next()),
+      None => {
+        log::info!("Company facts not found in cache for ticker: {}", ticker);
+        Ok(None)
+      }
+    }
+  }
+
+  pub fn set_company_facts(&mut self, ticker: &str, data: HashMap<String, Value>) -> Result<(), Error> {
+    // A ticker has a single set of facts, a refetch replaces it
+    self.company_facts_cache.insert(ticker.to_string(), vec![CacheEntry { inserted_at: self.now(), data }]);
+    Ok(())
+  }
+
+  pub fn get_market_cap(&self, ticker: &str, date: &str) -> Result<Option<HashMap<String, Value>>, Error> {
+    match self.market_cap_cache.get(ticker) {
+      Some(entries) => Ok(self.fresh_entries(entries, self.ttl.market_cap).into_iter().find(|entry| entry.get("date").and_then(Value::as_str) == Some(date))),
+      None => {
+        log::info!("Market cap not found in cache for ticker: {}", ticker);
+        Ok(None)
+      }
+    }
+  }
+
+  pub fn set_market_cap(&mut self, ticker: &str, data: HashMap<String, Value>) -> Result<(), Error> {
+    // A lookup for the same date replaces the earlier one, a missing market cap is cached as null too
+    let date = data.get("date").cloned().ok_or_else(|| Error::msg("Missing key field: date"))?;
+    let now = self.now();
+    let ttl = self.ttl.market_cap;
+    let entries = self.market_cap_cache.entry(ticker.to_string()).or_default();
+    entries.retain(|entry| now.saturating_sub(entry.inserted_at) < ttl.as_secs() && entry.data.get("date") != Some(&date));
+    entries.push(CacheEntry { inserted_at: now, data });
+    Ok(())
+  }
+
+}
+
+pub fn init_cache(path: Option<&Path>, ttl: CacheTtl) {
+  // Must run before the first get_cache() call, otherwise the default cache is already in place
+  let mut cache = match path {
+    Some(path) if path.exists() => match Cache::load_from_disk(path) {
+      Result::Ok(cache) => {
+        log::info!("Loaded cache from {}", path.display());
+        cache
+      }
+      Err(e) => {
+        log::error!("Failed to load cache from {}, starting empty: {:#}", path.display(), e);
+        Cache::new()
+      }
+    },
+    Some(path) => {
+      log::info!("No cache file at {}, starting empty", path.display());
+      Cache::new()
+    }
+    None => Cache::new(),
+  };
+  cache.ttl = ttl;
+
+  if GLOBAL_CACHE.set(Mutex::new(cache)).is_err() {
+    log::warn!("Global cache was already initialized, ignoring cache settings");
+  }
+}
+
+pub fn save_cache_to_disk(path: &Path) -> Result<(), Error> {
+  let cache_guard = get_cache().lock().unwrap_or_else(|p| p.into_inner());
+  cache_guard.save_to_disk(path)
 }
 
 pub fn get_cache() -> &'static Mutex<Cache> {
@@ -141,3 +413,56 @@ pub fn get_cache() -> &'static Mutex<Cache> {
   })
 }
 
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use std::sync::atomic::{AtomicU64, Ordering};
+
+  // Each test owns its clock, tests run in parallel
+  static EXPIRY_CLOCK: AtomicU64 = AtomicU64::new(1_000_000);
+  static REFRESH_CLOCK: AtomicU64 = AtomicU64::new(1_000_000);
+
+  fn price(time: &str, close: f64) -> HashMap<String, Value> {
+    serde_json::from_value(json!({ "time": time, "close": close })).unwrap()
+  }
+
+  fn date(value: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+  }
+
+  #[test]
+  fn entries_and_coverage_expire_after_their_ttl() {
+    let ttl = CacheTtl { prices: Duration::from_secs(60), ..CacheTtl::default() };
+    let mut cache = Cache::new_with_clock(ttl, || EXPIRY_CLOCK.load(Ordering::SeqCst));
+    cache.set_prices("AAPL", vec![price("2024-01-02T00:00:00", 185.6)]).unwrap();
+    cache.extend_price_coverage("AAPL", date("2024-01-01"), date("2024-01-31"));
+    cache.set_company_facts("AAPL", serde_json::from_value(json!({ "ticker": "AAPL" })).unwrap()).unwrap();
+
+    EXPIRY_CLOCK.fetch_add(59, Ordering::SeqCst);
+    assert_eq!(cache.get_prices("AAPL").unwrap().len(), 1);
+    assert!(cache.get_price_coverage("AAPL").is_some());
+
+    EXPIRY_CLOCK.fetch_add(1, Ordering::SeqCst);
+    assert!(cache.get_prices("AAPL").unwrap().is_empty());
+    assert!(cache.get_price_coverage("AAPL").is_none());
+    // Company facts keep their own, longer TTL
+    assert!(cache.get_company_facts("AAPL").unwrap().is_some());
+  }
+
+  #[test]
+  fn a_refetched_entry_restarts_its_ttl() {
+    let mut cache = Cache::new_with_clock(CacheTtl::uniform(Duration::from_secs(60)), || REFRESH_CLOCK.load(Ordering::SeqCst));
+    cache.set_prices("AAPL", vec![price("2024-01-02T00:00:00", 185.6), price("2024-01-03T00:00:00", 184.2)]).unwrap();
+
+    REFRESH_CLOCK.fetch_add(45, Ordering::SeqCst);
+    cache.set_prices("AAPL", vec![price("2024-01-03T00:00:00", 184.25)]).unwrap();
+
+    REFRESH_CLOCK.fetch_add(30, Ordering::SeqCst);
+    let prices = cache.get_prices("AAPL").unwrap();
+    assert_eq!(prices.len(), 1);
+    assert_eq!(prices[0]["close"], json!(184.25));
+  }
+}
@@ -25,8 +25,10 @@ impl FinancialHeaderData {
 
   pub fn to_header_map(&self) -> HeaderMap {
     let mut headers: HeaderMap = HeaderMap::new();
-    if let Ok(value) = HeaderValue::from_str(&self.api_key) {
-      headers.insert("X-API-KEY", value);
+    if !self.api_key.is_empty() {
+      if let Ok(value) = HeaderValue::from_str(&self.api_key) {
+        headers.insert("X-API-KEY", value);
+      }
     }
 
     // Add Content-Type header
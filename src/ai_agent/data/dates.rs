@@ -0,0 +1,74 @@
+// Serde helpers for the API's date fields, which arrive either as `YYYY-MM-DD` or as a full timestamp
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+  let value = value.trim();
+  if let Ok(date) = NaiveDate::parse_from_str(value, DATE_FORMAT) {
+    return Some(date);
+  }
+  parse_datetime(value).map(|datetime| datetime.date_naive())
+}
+
+pub fn parse_datetime(value: &str) -> Option<DateTime<Utc>> {
+  let value = value.trim();
+  if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+    return Some(datetime.with_timezone(&Utc));
+  }
+  for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+      return Some(datetime.and_utc());
+    }
+  }
+  NaiveDate::parse_from_str(value, DATE_FORMAT).ok().and_then(|date| date.and_hms_opt(0, 0, 0)).map(|datetime| datetime.and_utc())
+}
+
+pub mod flexible_date {
+  use chrono::NaiveDate;
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+    serializer.serialize_str(&date.format(super::DATE_FORMAT).to_string())
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error> where D: Deserializer<'de> {
+    let value = String::deserialize(deserializer)?;
+    super::parse_date(&value).ok_or_else(|| serde::de::Error::custom(format!("invalid date: {}", value)))
+  }
+}
+
+pub mod optional_flexible_date {
+  use chrono::NaiveDate;
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S>(date: &Option<NaiveDate>, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+    match date {
+      Some(date) => super::flexible_date::serialize(date, serializer),
+      None => serializer.serialize_none(),
+    }
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error> where D: Deserializer<'de> {
+    match Option::<String>::deserialize(deserializer)? {
+      Some(value) if !value.trim().is_empty() => {
+        super::parse_date(&value).map(Some).ok_or_else(|| serde::de::Error::custom(format!("invalid date: {}", value)))
+      }
+      _ => Ok(None),
+    }
+  }
+}
+
+pub mod flexible_datetime {
+  use chrono::{DateTime, SecondsFormat, Utc};
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S>(datetime: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+    serializer.serialize_str(&datetime.to_rfc3339_opts(SecondsFormat::Secs, true))
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error> where D: Deserializer<'de> {
+    let value = String::deserialize(deserializer)?;
+    super::parse_datetime(&value).ok_or_else(|| serde::de::Error::custom(format!("invalid datetime: {}", value)))
+  }
+}
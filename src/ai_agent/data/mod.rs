@@ -1,3 +1,4 @@
 pub mod models;
 pub mod data;
-pub mod cache;
\ No newline at end of file
+pub mod cache;
+pub mod dates;
\ No newline at end of file
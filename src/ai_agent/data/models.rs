@@ -1,7 +1,49 @@
 use std::collections::HashMap; 
 use serde::{Serialize, Deserialize};
 use serde_json::{Value};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::str::FromStr;
 
+use crate::ai_agent::data::dates::{flexible_date, flexible_datetime, optional_flexible_date};
+
+// Reporting basis for financial metrics and line items
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportPeriod {
+  Ttm,
+  Annual,
+  Quarterly,
+}
+
+impl ReportPeriod {
+  pub const ALL: [ReportPeriod; 3] = [ReportPeriod::Ttm, ReportPeriod::Annual, ReportPeriod::Quarterly];
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ReportPeriod::Ttm => "ttm",
+      ReportPeriod::Annual => "annual",
+      ReportPeriod::Quarterly => "quarterly",
+    }
+  }
+
+  // Set by the `period` run option, agents fall back to the basis their analysis was written for
+  pub fn from_metadata(metadata: &HashMap<String, Value>, default: ReportPeriod) -> ReportPeriod {
+    metadata.get("period").and_then(Value::as_str).and_then(|period| period.parse().ok()).unwrap_or(default)
+  }
+}
+
+impl FromStr for ReportPeriod {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.trim().to_lowercase().as_str() {
+      "ttm" => Ok(ReportPeriod::Ttm),
+      "annual" => Ok(ReportPeriod::Annual),
+      "quarterly" => Ok(ReportPeriod::Quarterly),
+      _ => Err(format!("Invalid period {}, expected one of: {}", s, ReportPeriod::ALL.map(|period| period.as_str()).join(", "))),
+    }
+  }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Price {
@@ -13,6 +55,13 @@ pub struct Price {
   pub time: String
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceGap {
+  pub from: String,
+  pub to: String,
+  pub missing_trading_days: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceResponse {
   pub ticker: String, 
@@ -22,7 +71,8 @@ pub struct PriceResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinancialMetrics {
   pub ticker: String,
-  pub report_period: String, // Consider chrono::NaiveDate or similar
+  #[serde(with = "flexible_date")]
+  pub report_period: NaiveDate,
   pub period: String,
   pub currency: String,
   pub market_cap: Option<f64>,
@@ -74,7 +124,8 @@ pub struct FinancialMetricsResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LineItem {
   pub ticker: String,
-  pub report_period: String, // Consider chrono::NaiveDate or similar
+  #[serde(with = "flexible_date")]
+  pub report_period: NaiveDate,
   pub period: String,
   pub currency: String,
 
@@ -94,14 +145,16 @@ pub struct InsiderTrade {
   pub name: Option<String>,
   pub title: Option<String>,
   pub is_board_director: Option<bool>,
-  pub transaction_date: Option<String>, // Consider chrono::NaiveDate
+  #[serde(default, with = "optional_flexible_date")]
+  pub transaction_date: Option<NaiveDate>,
   pub transaction_shares: Option<f64>,
   pub transaction_price_per_share: Option<f64>,
   pub transaction_value: Option<f64>,
   pub shares_owned_before_transaction: Option<f64>,
   pub shares_owned_after_transaction: Option<f64>,
   pub security_title: Option<String>,
-  pub filing_date: Option<String> // Consider chrono::NaiveDate
+  #[serde(default, with = "optional_flexible_date")]
+  pub filing_date: Option<NaiveDate>
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -115,7 +168,8 @@ pub struct CompanyNews {
   pub title: String,
   pub author: String,
   pub source: String,
-  pub date: String, // Consider chrono::DateTime<chrono::Utc> or NaiveDate
+  #[serde(with = "flexible_datetime")]
+  pub date: DateTime<Utc>,
   pub url: String,
   pub sentiment: Option<String>,
 }
@@ -136,7 +190,8 @@ pub struct CompanyFacts {
   pub category: Option<String>,
   pub exchange: Option<String>,
   pub is_active: Option<bool>,
-  pub listing_date: Option<String>, // Consider chrono::NaiveDate
+  #[serde(default, with = "optional_flexible_date")]
+  pub listing_date: Option<NaiveDate>,
   pub location: Option<String>,
   pub market_cap: Option<f64>,
   pub number_of_employees: Option<i64>,
@@ -154,41 +209,59 @@ pub struct CompanyFactsResponse {
 }
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Share counts are f64 so fractional runs fit the same shape
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Position {
-  #[serde(default)] // Pydantic default: 0.0
-  pub cash: f64,
-  #[serde(default)] // Pydantic default: 0
-  pub shares: i64,
-  pub ticker: String,
+  pub long: f64,
+  pub short: f64,
+  pub long_cost_basis: f64,
+  pub short_cost_basis: f64,
+  pub short_margin_used: f64,
 }
 
-impl Default for Position {
-  fn default() -> Self {
-    Position {
-      cash: 0.0,
-      shares: 0,
-      ticker: String::new(), // Default ticker to empty; usually provided on creation.
-    }
-  }
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RealizedGains {
+  pub long: f64,
+  pub short: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// No serde defaults on purpose: a misspelled or missing field fails to deserialize instead of reading as zero
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Portfolio {
-  #[serde(default)] // Pydantic default: empty dict
+  pub cash: f64,
+  pub margin_requirement: f64,
+  pub margin_used: f64,
   pub positions: HashMap<String, Position>, // ticker -> Position mapping
-  #[serde(default)] // Pydantic default: 0.0
-  pub total_cash: f64,
+  pub realized_gains: HashMap<String, RealizedGains>,
 }
 
-
-impl Default for Portfolio {
-  fn default() -> Self {
+impl Portfolio {
+  pub fn new(tickers: &[String], initial_cash: f64, margin_requirement: f64) -> Self {
     Portfolio {
-      positions: HashMap::new(),
-      total_cash: 0.0,
+      cash: initial_cash,
+      margin_requirement,
+      margin_used: 0.0,
+      positions: tickers.iter().map(|ticker| (ticker.clone(), Position::default())).collect(),
+      realized_gains: tickers.iter().map(|ticker| (ticker.clone(), RealizedGains::default())).collect(),
     }
   }
+
+  // A supplied book may not list every ticker of the run, those start flat. Positions outside the run are kept, they still hold capital
+  pub fn ensure_tickers(&mut self, tickers: &[String]) {
+    for ticker in tickers {
+      self.positions.entry(ticker.clone()).or_default();
+      self.realized_gains.entry(ticker.clone()).or_default();
+    }
+  }
+
+  // Capital tied up in long positions, at cost
+  pub fn long_cost(&self, ticker: &str) -> f64 {
+    self.positions.get(ticker).map_or(0.0, |position| position.long * position.long_cost_basis)
+  }
+
+  pub fn total_long_cost(&self) -> f64 {
+    self.positions.values().map(|position| position.long * position.long_cost_basis).sum()
+  }
 }
 
 
@@ -196,3 +269,84 @@ impl Default for Portfolio {
 pub struct AgentStateMetaData {
   show_reasoning: bool,
 }
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn report_periods_parse_and_reject_unknown_values() {
+    for period in ReportPeriod::ALL {
+      assert_eq!(period.as_str().parse::<ReportPeriod>(), Ok(period));
+      assert_eq!(serde_json::from_value::<ReportPeriod>(json!(period.as_str())).unwrap(), period);
+    }
+    assert_eq!(" Annual ".parse::<ReportPeriod>(), Ok(ReportPeriod::Annual));
+    assert_eq!("monthly".parse::<ReportPeriod>(), Err("Invalid period monthly, expected one of: ttm, annual, quarterly".to_string()));
+  }
+
+  #[test]
+  fn agents_keep_their_default_period_unless_the_run_sets_one() {
+    let metadata: HashMap<String, Value> = serde_json::from_value(json!({ "period": "quarterly" })).unwrap();
+    assert_eq!(ReportPeriod::from_metadata(&metadata, ReportPeriod::Ttm), ReportPeriod::Quarterly);
+    assert_eq!(ReportPeriod::from_metadata(&HashMap::new(), ReportPeriod::Annual), ReportPeriod::Annual);
+  }
+
+  // The exact shape the service builds for a fresh run and returns as `portfolio`
+  fn service_json() -> Value {
+    json!({
+      "cash": 100000.0,
+      "margin_requirement": 0.5,
+      "margin_used": 0.0,
+      "positions": {
+        "AAPL": { "long": 0.0, "short": 0.0, "long_cost_basis": 0.0, "short_cost_basis": 0.0, "short_margin_used": 0.0 },
+      },
+      "realized_gains": {
+        "AAPL": { "long": 0.0, "short": 0.0 },
+      },
+    })
+  }
+
+  #[test]
+  fn new_portfolio_serializes_to_the_service_shape() {
+    let portfolio = Portfolio::new(&["AAPL".to_string()], 100000.0, 0.5);
+    assert_eq!(serde_json::to_value(&portfolio).unwrap(), service_json());
+  }
+
+  #[test]
+  fn service_json_round_trips() {
+    let mut value = service_json();
+    value["positions"]["AAPL"] = json!({ "long": 12.5, "short": 3.0, "long_cost_basis": 180.25, "short_cost_basis": 190.0, "short_margin_used": 285.0 });
+    value["realized_gains"]["AAPL"] = json!({ "long": 42.0, "short": -7.5 });
+    value["margin_used"] = json!(285.0);
+
+    let portfolio : Portfolio = serde_json::from_value(value.clone()).unwrap();
+    assert_eq!(portfolio.positions["AAPL"].long, 12.5);
+    assert_eq!(portfolio.realized_gains["AAPL"].short, -7.5);
+    assert_eq!(serde_json::to_value(&portfolio).unwrap(), value);
+  }
+
+  #[test]
+  fn missing_or_misspelled_fields_fail() {
+    let mut missing = service_json();
+    missing["positions"]["AAPL"].as_object_mut().unwrap().remove("short_margin_used");
+    assert!(serde_json::from_value::<Portfolio>(missing).is_err());
+
+    let mut misspelled = service_json();
+    let cash = misspelled.as_object_mut().unwrap().remove("cash").unwrap();
+    misspelled["total_cash"] = cash;
+    assert!(serde_json::from_value::<Portfolio>(misspelled).is_err());
+  }
+
+  #[test]
+  fn ensure_tickers_adds_flat_positions_and_keeps_existing_ones() {
+    let mut portfolio : Portfolio = serde_json::from_value(service_json()).unwrap();
+    portfolio.positions.get_mut("AAPL").unwrap().long = 10.0;
+    portfolio.ensure_tickers(&["AAPL".to_string(), "MSFT".to_string()]);
+
+    assert_eq!(portfolio.positions["AAPL"].long, 10.0);
+    assert_eq!(portfolio.positions["MSFT"], Position::default());
+    assert_eq!(portfolio.realized_gains["MSFT"], RealizedGains::default());
+  }
+}
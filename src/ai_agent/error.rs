@@ -0,0 +1,37 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+// Failures raised by the data API client and the LLM providers, kept typed so the HTTP layer can tell them apart
+#[derive(Debug, Error)]
+pub enum AgentError {
+  #[error("{0} is not configured")] // the environment variable holding the key
+  MissingApiKey(String),
+  #[error("rate limited by {0}")]
+  RateLimited(String),
+  #[error("{source_name} answered with status {status}")]
+  UpstreamHttp { source_name: String, status: StatusCode },
+  #[error("unusable LLM response: {0}")]
+  LlmParse(String),
+  #[error("no data available: {0}")]
+  DataUnavailable(String),
+  #[error("circuit open for {0}, failing fast")] // "Provider:model"
+  CircuitOpen(String),
+  #[error("{0} is not supported")]
+  Unsupported(String),
+  #[error(transparent)]
+  Http(#[from] reqwest::Error),
+  #[error(transparent)]
+  Json(#[from] serde_json::Error),
+}
+
+pub type Result<T, E = AgentError> = std::result::Result<T, E>;
+
+impl AgentError {
+  // Maps a non-success response status, 429 is kept apart so callers can back off
+  pub fn from_status(source_name: &str, status: StatusCode) -> Self {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+      return AgentError::RateLimited(source_name.to_string());
+    }
+    AgentError::UpstreamHttp { source_name: source_name.to_string(), status }
+  }
+}
@@ -5,10 +5,18 @@ use anyhow::{Result, Error};
 use std::sync::Arc;
 use std::future::Future; 
 use std::pin::Pin;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use futures::future::join_all;
+use tracing::Instrument;
 
+use crate::ai_agent::graph::reasoning;
 use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate};
 use crate::app::config::Config;
 
+// Longest accepted analyst_timeout_secs, larger values are capped rather than overflowing Duration
+pub const MAX_ANALYST_TIMEOUT_SECS: f64 = 3600.0;
+
 // Define a trait for node functions
 #[async_trait]
 pub trait NodeFunction: Send + Sync {
@@ -25,9 +33,14 @@ impl<F> NodeFunction for F where F: Fn(AgentState, Config) -> Pin<Box<dyn Future
     }
 }
 
+// Picks the next node from the current state
+pub type RouteFunction = Box<dyn Fn(&AgentState) -> String + Send + Sync>;
+
 pub struct StateGraph {
   nodes: HashMap<String, Box<dyn NodeFunction>>,
   edges: HashMap<String, Vec<String>>,
+  conditional_edges: HashMap<String, RouteFunction>,
+  analyst_nodes: HashSet<String>,
   entry_point: Option<String>,
   end_node: String,
 }
@@ -37,6 +50,8 @@ impl StateGraph {
     StateGraph {
       nodes: HashMap::new(),
       edges: HashMap::new(),
+      conditional_edges: HashMap::new(),
+      analyst_nodes: HashSet::new(),
       entry_point: None,
       end_node: "END".to_string(),
     }
@@ -50,16 +65,91 @@ impl StateGraph {
     }
   }
 
+  // Analyst nodes are subject to the per-analyst timeout and fall back to neutral signals
+  pub fn add_analyst_node<F>(&mut self, name: String, func: F) where F: NodeFunction + 'static, {
+    self.analyst_nodes.insert(name.clone());
+    self.add_node(name, func);
+  }
+
   pub fn add_edge(&mut self, from: String, to: String) {
     self.edges.entry(from).or_insert_with(Vec::new).push(to);
   }
 
+  // Takes precedence over the static edges of `from`
+  #[allow(unused)]
+  pub fn add_conditional_edge<P>(&mut self, from: String, predicate: P) where P: Fn(&AgentState) -> String + Send + Sync + 'static, {
+    self.conditional_edges.insert(from, Box::new(predicate));
+  }
+
+  #[allow(unused)]
+  pub fn nodes(&self) -> Vec<String> {
+    self.nodes.keys().cloned().collect()
+  }
+
+  #[allow(unused)]
+  pub fn edges(&self) -> &HashMap<String, Vec<String>> {
+    &self.edges
+  }
+
   pub fn set_entry_point(&mut self, node: &str) {
     self.entry_point = Some(node.to_string());
   }
 
-  pub fn compile(self) -> CompiledGraph {
-    CompiledGraph { graph: Arc::new(self) }
+  // Rejects wiring mistakes up front instead of as a "Dead end" or "Node not found" halfway through a run
+  pub fn compile(self) -> Result<CompiledGraph> {
+    self.validate()?;
+    Ok(CompiledGraph { graph: Arc::new(self) })
+  }
+
+  fn validate(&self) -> Result<()> {
+    let entry_point = self.entry_point.as_ref().ok_or_else(|| anyhow::anyhow!("Graph has no entry point"))?;
+    if !self.nodes.contains_key(entry_point) {
+      return Err(anyhow::anyhow!("Entry point {} is not a node", entry_point));
+    }
+
+    for (from, targets) in &self.edges {
+      if !self.nodes.contains_key(from) {
+        return Err(anyhow::anyhow!("Edge from unknown node: {}", from));
+      }
+      if let Some(target) = targets.iter().find(|target| **target != self.end_node && !self.nodes.contains_key(*target)) {
+        return Err(anyhow::anyhow!("Edge from {} to unknown node: {}", from, target));
+      }
+    }
+
+    for node in self.nodes.keys() {
+      let has_edges = self.edges.get(node).map_or(false, |targets| !targets.is_empty());
+      if !has_edges && !self.conditional_edges.contains_key(node) {
+        return Err(anyhow::anyhow!("Dead end at node: {}", node));
+      }
+    }
+
+    // Conditional edges pick their target at runtime, a path through one is assumed to be able to finish
+    let mut reachable: HashSet<&String> = HashSet::new();
+    let mut pending: Vec<&String> = vec![entry_point];
+    let mut reaches_end = false;
+    while let Some(node) = pending.pop() {
+      if *node == self.end_node {
+        reaches_end = true;
+        continue;
+      }
+      if !reachable.insert(node) {
+        continue;
+      }
+      if self.conditional_edges.contains_key(node) {
+        reaches_end = true;
+      }
+      pending.extend(self.edges.get(node).into_iter().flatten());
+    }
+    if !reaches_end {
+      return Err(anyhow::anyhow!("{} is not reachable from entry point {}", self.end_node, entry_point));
+    }
+
+    if self.conditional_edges.is_empty() {
+      for node in self.nodes.keys().filter(|node| !reachable.contains(node)) {
+        log::warn!("Node {} is not reachable from entry point {} and will never run", node, entry_point);
+      }
+    }
+    Ok(())
   }
 }
 
@@ -69,40 +159,376 @@ pub struct CompiledGraph {
 }
 
 impl CompiledGraph {
-  pub async fn invoke(&self, initial_state: AgentState, config: Config) -> Result<AgentState> {
+  // Sorted so the log line reads the same from run to run
+  pub fn analyst_nodes(&self) -> Vec<String> {
+    let mut analyst_nodes : Vec<String> = self.graph.analyst_nodes.iter().cloned().collect();
+    analyst_nodes.sort();
+    analyst_nodes
+  }
+
+  // Walks the graph from the entry point, a conditional edge picks the next node from the state. A fan-out to branches
+  // that all converge on the same node runs them concurrently
+  pub async fn invoke_parallel(&self, initial_state: AgentState, config: Config) -> Result<AgentState> {
     let mut current_state = initial_state;
-    let mut current_node = self.graph.entry_point.clone().expect("Graph must have an entry point");
-    
+    let mut current_node = self.graph.entry_point.clone().expect("compile checks the entry point");
+
     let mut visited = HashSet::new();
-    
+
     while current_node != self.graph.end_node {
-      // Prevent infinite loops
       if visited.contains(&current_node) {
         return Err(anyhow::anyhow!("Cycle detected in graph execution"));
       }
       visited.insert(current_node.clone());
-      
-      // Get the node function
-      let node_func = self.graph.nodes.get(&current_node).ok_or_else(|| anyhow::anyhow!("Node not found: {}", current_node))?;
-      
-      // Call the node function
-      let update = node_func.call(current_state.clone(), config.clone()).await?;
-      
-      // Update the state
+
+      let (update, elapsed_ms) = self.run_node(&current_node, &current_state, &config).await?;
       current_state.update_from_partial(update)?;
-      
-      // Get next node
-      let next_nodes = self.graph.edges.get(&current_node).ok_or_else(|| anyhow::anyhow!("No edges defined for node: {}", current_node))?;
-      
-      if next_nodes.is_empty() {
-        return Err(anyhow::anyhow!("Dead end at node: {}", current_node));
+      current_state.record_timing(&current_node, elapsed_ms);
+
+      if let Some(next_node) = self.route(&current_node, &current_state)? {
+        current_node = next_node;
+        continue;
+      }
+
+      let next_nodes = self.static_successors(&current_node)?;
+      let join_node = match self.fan_in_target(next_nodes) {
+        Some(join_node) if next_nodes.len() > 1 => join_node,
+        _ => {
+          current_node = next_nodes[0].clone();
+          continue;
+        }
+      };
+
+      for branch in next_nodes {
+        if !visited.insert(branch.clone()) {
+          return Err(anyhow::anyhow!("Cycle detected in graph execution"));
+        }
+      }
+
+      log::info!("Running {} branches of {} concurrently before {}", next_nodes.len(), current_node, join_node);
+      let branch_state = current_state.clone();
+      let updates = join_all(next_nodes.iter().map(|branch| self.run_node(branch, &branch_state, &config))).await;
+
+      // Branches all start from the same snapshot, analyst_signals merge per key so no branch clobbers another
+      for (branch, update) in next_nodes.iter().zip(updates) {
+        let (update, elapsed_ms) = update?;
+        current_state.update_from_partial(update)?;
+        current_state.record_timing(branch, elapsed_ms);
       }
-      
-      // For simplicity, just take the first edge
-      // In a more complex system, you might have conditional routing
-      current_node = next_nodes[0].clone();
+      current_node = join_node;
     }
-    
+
     Ok(current_state)
   }
+
+  // Returns the node's update along with how long the node took in milliseconds
+  async fn run_node(&self, node_name: &str, state: &AgentState, config: &Config) -> Result<(PartialAgentStateUpdate, u64)> {
+    let node_func = self.graph.nodes.get(node_name).ok_or_else(|| anyhow::anyhow!("Node not found: {}", node_name))?;
+    reasoning::publish(node_name, "status", Value::from("running"));
+    let started_at = Instant::now();
+    let span = tracing::info_span!("agent", node = node_name, elapsed_ms = tracing::field::Empty);
+
+    let update = match Self::analyst_timeout(state) {
+      Some(limit) if self.graph.analyst_nodes.contains(node_name) => {
+        match tokio::time::timeout(limit, node_func.call(state.clone(), config.clone()).instrument(span.clone())).await {
+          Ok(update) => update,
+          Err(_) => {
+            log::warn!("Analyst node {} timed out after {:?}, defaulting to neutral", node_name, limit);
+            Ok(Self::timeout_fallback(node_name, state, limit))
+          }
+        }
+      }
+      _ => node_func.call(state.clone(), config.clone()).instrument(span.clone()).await,
+    };
+
+    let elapsed_ms = started_at.elapsed().as_millis() as u64;
+    span.record("elapsed_ms", elapsed_ms);
+    span.in_scope(|| log::debug!("Node {} finished in {} ms", node_name, elapsed_ms));
+    if let Some(signal) = update.as_ref().ok().and_then(|update| update.data.as_ref()).and_then(|data| data.get("analyst_signals")).and_then(|signals| signals.get(node_name)) {
+      reasoning::publish(node_name, "signal", signal.clone());
+    }
+    reasoning::publish(node_name, "status", Value::from(if update.is_ok() { "done" } else { "failed" }));
+    Ok((update?, elapsed_ms))
+  }
+
+  fn route(&self, node_name: &str, state: &AgentState) -> Result<Option<String>> {
+    let route = match self.graph.conditional_edges.get(node_name) {
+      Some(route) => route,
+      None => return Ok(None),
+    };
+
+    let next_node = route(state);
+    if next_node != self.graph.end_node && !self.graph.nodes.contains_key(&next_node) {
+      return Err(anyhow::anyhow!("Conditional edge from {} routed to unknown node: {}", node_name, next_node));
+    }
+    log::debug!("Conditional edge from {} routed to {}", node_name, next_node);
+    Ok(Some(next_node))
+  }
+
+  fn static_successors(&self, node_name: &str) -> Result<&Vec<String>> {
+    let next_nodes = self.graph.edges.get(node_name).ok_or_else(|| anyhow::anyhow!("No edges defined for node: {}", node_name))?;
+    if next_nodes.is_empty() {
+      return Err(anyhow::anyhow!("Dead end at node: {}", node_name));
+    }
+    Ok(next_nodes)
+  }
+
+  fn fan_in_target(&self, branches: &[String]) -> Option<String> {
+    // Every branch must be a plain node with a single static edge into the same successor
+    let mut target: Option<&String> = None;
+    for branch in branches {
+      if self.graph.conditional_edges.contains_key(branch) {
+        return None;
+      }
+      let successors = self.graph.edges.get(branch)?;
+      if successors.len() != 1 || target.map_or(false, |t| t != &successors[0]) {
+        return None;
+      }
+      target = Some(&successors[0]);
+    }
+    target.cloned()
+  }
+
+  fn analyst_timeout(state: &AgentState) -> Option<Duration> {
+    let seconds = state.metadata.get("analyst_timeout_secs").and_then(Value::as_f64)?;
+    if seconds > 0.0 {
+      return Duration::try_from_secs_f64(seconds.min(MAX_ANALYST_TIMEOUT_SECS)).ok();
+    }
+    return None;
+  }
+
+  fn timeout_fallback(node_name: &str, state: &AgentState, limit: Duration) -> PartialAgentStateUpdate {
+    let tickers: Vec<String> = state.data.get("tickers").and_then(Value::as_array)
+      .map(|arr| arr.iter().filter_map(Value::as_str).map(String::from).collect())
+      .unwrap_or_default();
+
+    let mut signals = serde_json::Map::new();
+    for ticker in tickers {
+      signals.insert(ticker, serde_json::json!({
+        "signal": "neutral",
+        "confidence": 0.0,
+        "reasoning": format!("Analyst timed out after {:.1}s, defaulting to neutral", limit.as_secs_f64()),
+      }));
+    }
+
+    // Keep the signals other analysts already produced
+    let mut analyst_signals = state.data.get("analyst_signals").and_then(Value::as_object).cloned().unwrap_or_default();
+    analyst_signals.insert(node_name.to_string(), Value::Object(signals));
+
+    let mut data = HashMap::new();
+    data.insert("analyst_signals".to_string(), Value::Object(analyst_signals));
+    return PartialAgentStateUpdate::new().with_data(data);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai_agent::agents::portfolio_manager::PortfolioManagerAgent;
+  use crate::ai_agent::data::models::Portfolio;
+  use serde_json::json;
+
+  fn state_with(data: Value, metadata: Value) -> AgentState {
+    let mut state = AgentState::new();
+    state.merge_data(serde_json::from_value(data).unwrap()).unwrap();
+    state.merge_metadata(serde_json::from_value(metadata).unwrap()).unwrap();
+    state
+  }
+
+  fn signal_node(name: &'static str, delay: Duration) -> impl Fn(AgentState, Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> + Send + Sync {
+    move |_state, _config| Box::pin(async move {
+      tokio::time::sleep(delay).await;
+      let mut data = HashMap::new();
+      data.insert("analyst_signals".to_string(), json!({ name: { "AAPL": { "signal": "bullish", "confidence": 80.0 } } }));
+      Ok(PartialAgentStateUpdate::new().with_data(data))
+    })
+  }
+
+  // Nodes that do nothing, for graphs only built to be validated
+  fn graph_with(nodes: &[&str], edges: &[(&str, &str)], entry_point: Option<&str>) -> StateGraph {
+    let mut graph = StateGraph::new();
+    for node in nodes {
+      graph.add_node(node.to_string(), signal_node("noop", Duration::ZERO));
+    }
+    for (from, to) in edges {
+      graph.add_edge(from.to_string(), to.to_string());
+    }
+    if let Some(entry_point) = entry_point {
+      graph.set_entry_point(entry_point);
+    }
+    graph
+  }
+
+  fn compile_error(graph: StateGraph) -> String {
+    graph.compile().err().expect("graph should not compile").to_string()
+  }
+
+  #[test]
+  fn a_well_formed_graph_compiles() {
+    assert!(graph_with(&["start", "analyst"], &[("start", "analyst"), ("analyst", "END")], Some("start")).compile().is_ok());
+  }
+
+  #[test]
+  fn compile_rejects_a_missing_or_unknown_entry_point() {
+    assert_eq!(compile_error(graph_with(&["start"], &[("start", "END")], None)), "Graph has no entry point");
+    assert_eq!(compile_error(graph_with(&["start"], &[("start", "END")], Some("begin"))), "Entry point begin is not a node");
+  }
+
+  #[test]
+  fn compile_rejects_edges_to_unknown_nodes() {
+    let error = compile_error(graph_with(&["start"], &[("start", "ghost")], Some("start")));
+    assert_eq!(error, "Edge from start to unknown node: ghost");
+  }
+
+  #[test]
+  fn compile_rejects_a_node_without_outgoing_edges() {
+    let error = compile_error(graph_with(&["start", "analyst"], &[("start", "analyst")], Some("start")));
+    assert_eq!(error, "Dead end at node: analyst");
+  }
+
+  #[test]
+  fn compile_rejects_a_graph_that_never_reaches_end() {
+    let error = compile_error(graph_with(&["start", "a", "b"], &[("start", "a"), ("a", "b"), ("b", "a")], Some("start")));
+    assert_eq!(error, "END is not reachable from entry point start");
+  }
+
+  #[test]
+  fn analyst_timeout_ignores_unset_and_non_positive_values() {
+    assert_eq!(CompiledGraph::analyst_timeout(&state_with(json!({}), json!({}))), None);
+    assert_eq!(CompiledGraph::analyst_timeout(&state_with(json!({}), json!({ "analyst_timeout_secs": 0.0 }))), None);
+    assert_eq!(CompiledGraph::analyst_timeout(&state_with(json!({}), json!({ "analyst_timeout_secs": -5.0 }))), None);
+    assert_eq!(CompiledGraph::analyst_timeout(&state_with(json!({}), json!({ "analyst_timeout_secs": 2.5 }))), Some(Duration::from_millis(2500)));
+  }
+
+  #[test]
+  fn huge_analyst_timeouts_are_capped_instead_of_panicking() {
+    let limit = CompiledGraph::analyst_timeout(&state_with(json!({}), json!({ "analyst_timeout_secs": 1e20 })));
+    assert_eq!(limit, Some(Duration::from_secs(3600)));
+  }
+
+  fn noop_node(_state: AgentState, _config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async { Ok(PartialAgentStateUpdate::new()) })
+  }
+
+  // A dry-run portfolio manager decides from the signals without an LLM
+  fn portfolio_state() -> AgentState {
+    let tickers = vec!["AAPL".to_string()];
+    state_with(json!({
+      "tickers": tickers,
+      "portfolio": Portfolio::new(&tickers, 100000.0, 0.5),
+      "analyst_signals": { "risk_management_agent": { "AAPL": { "current_price": 100.0, "remaining_position_limit": 20000.0 } } },
+    }), json!({ "dry_run": true, "model_name": "test-model", "model_provider": "OpenAI" }))
+  }
+
+  fn path_node(name: &'static str) -> impl Fn(AgentState, Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> + Send + Sync {
+    move |_state, _config| Box::pin(async move {
+      Ok(PartialAgentStateUpdate::new().with_data(HashMap::from([("path".to_string(), json!(name))])))
+    })
+  }
+
+  // start branches on data["mode"], "trade" goes through the trader, anything else straight to the report
+  fn branching_graph() -> CompiledGraph {
+    let mut graph = StateGraph::new();
+    graph.add_node("start".to_string(), noop_node);
+    graph.add_node("trader".to_string(), path_node("trader"));
+    graph.add_node("report".to_string(), path_node("report"));
+    graph.add_conditional_edge("start".to_string(), |state: &AgentState| {
+      match state.data.get("mode").and_then(Value::as_str) {
+        Some("trade") => "trader".to_string(),
+        Some("skip") => "END".to_string(),
+        Some(other) => other.to_string(),
+        None => "report".to_string(),
+      }
+    });
+    graph.add_edge("trader".to_string(), "END".to_string());
+    graph.add_edge("report".to_string(), "END".to_string());
+    graph.set_entry_point("start");
+    graph.compile().unwrap()
+  }
+
+  #[tokio::test]
+  async fn a_conditional_edge_branches_on_the_state() {
+    let graph = branching_graph();
+
+    let traded = graph.invoke_parallel(state_with(json!({ "mode": "trade" }), json!({})), Config::for_tests()).await.unwrap();
+    assert_eq!(traded.data["path"], "trader");
+    assert!(traded.metadata["timings"].get("report").is_none());
+
+    let reported = graph.invoke_parallel(state_with(json!({}), json!({})), Config::for_tests()).await.unwrap();
+    assert_eq!(reported.data["path"], "report");
+    assert!(reported.metadata["timings"].get("trader").is_none());
+
+    let skipped = graph.invoke_parallel(state_with(json!({ "mode": "skip" }), json!({})), Config::for_tests()).await.unwrap();
+    assert!(skipped.data.get("path").is_none());
+  }
+
+  #[tokio::test]
+  async fn a_conditional_edge_to_an_unknown_node_fails_the_run() {
+    let error = branching_graph().invoke_parallel(state_with(json!({ "mode": "ghost" }), json!({})), Config::for_tests()).await.unwrap_err();
+    assert_eq!(error.to_string(), "Conditional edge from start routed to unknown node: ghost");
+  }
+
+  #[tokio::test]
+  async fn decisions_survive_a_node_after_the_portfolio_manager() {
+    let mut graph = StateGraph::new();
+    graph.add_analyst_node("buffett_agent".to_string(), signal_node("buffett_agent", Duration::ZERO));
+    graph.add_node("portfolio_manager".to_string(), PortfolioManagerAgent::static_portfolio_management_agent);
+    graph.add_node("report".to_string(), noop_node);
+    graph.add_edge("buffett_agent".to_string(), "portfolio_manager".to_string());
+    graph.add_edge("portfolio_manager".to_string(), "report".to_string());
+    graph.add_edge("report".to_string(), "END".to_string());
+    graph.set_entry_point("buffett_agent");
+
+    let final_state = graph.compile().unwrap().invoke_parallel(portfolio_state(), Config::for_tests()).await.unwrap();
+
+    let output = final_state.portfolio_output.as_ref().expect("portfolio output survives the later node");
+    assert!(output.decisions.contains_key("AAPL"));
+    assert_eq!(final_state.data["decisions"]["AAPL"]["action"], json!(output.decisions["AAPL"].action));
+  }
+
+  #[tokio::test]
+  async fn analyst_signals_and_decisions_both_reach_the_final_state() {
+    let mut graph = StateGraph::new();
+    graph.add_node("start".to_string(), noop_node);
+    graph.add_analyst_node("buffett_agent".to_string(), signal_node("buffett_agent", Duration::ZERO));
+    graph.add_analyst_node("fisher_agent".to_string(), signal_node("fisher_agent", Duration::ZERO));
+    graph.add_node("portfolio_manager".to_string(), PortfolioManagerAgent::static_portfolio_management_agent);
+    for analyst in ["buffett_agent", "fisher_agent"] {
+      graph.add_edge("start".to_string(), analyst.to_string());
+      graph.add_edge(analyst.to_string(), "portfolio_manager".to_string());
+    }
+    graph.add_edge("portfolio_manager".to_string(), "END".to_string());
+    graph.set_entry_point("start");
+
+    let final_state = graph.compile().unwrap().invoke_parallel(portfolio_state(), Config::for_tests()).await.unwrap();
+
+    let signals = &final_state.data["analyst_signals"];
+    assert_eq!(signals["buffett_agent"]["AAPL"]["signal"], "bullish");
+    assert_eq!(signals["fisher_agent"]["AAPL"]["signal"], "bullish");
+    assert_eq!(signals["risk_management_agent"]["AAPL"]["current_price"], 100.0);
+    assert_eq!(final_state.data["decisions"]["AAPL"]["action"], "buy");
+  }
+
+  #[tokio::test]
+  async fn slow_analyst_falls_back_to_neutral_and_keeps_other_signals() {
+    let mut graph = StateGraph::new();
+    graph.add_node("start".to_string(), |_state: AgentState, _config: Config| -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+      Box::pin(async { Ok(PartialAgentStateUpdate::new()) })
+    });
+    graph.add_analyst_node("fast_agent".to_string(), signal_node("fast_agent", Duration::ZERO));
+    graph.add_analyst_node("slow_agent".to_string(), signal_node("slow_agent", Duration::from_secs(5)));
+    graph.add_edge("start".to_string(), "fast_agent".to_string());
+    graph.add_edge("start".to_string(), "slow_agent".to_string());
+    graph.add_edge("fast_agent".to_string(), "END".to_string());
+    graph.add_edge("slow_agent".to_string(), "END".to_string());
+    graph.set_entry_point("start");
+
+    let state = state_with(json!({ "tickers": ["AAPL"], "analyst_signals": {} }), json!({ "analyst_timeout_secs": 0.05 }));
+    let final_state = graph.compile().unwrap().invoke_parallel(state, Config::for_tests()).await.unwrap();
+
+    let signals = &final_state.data["analyst_signals"];
+    assert_eq!(signals["fast_agent"]["AAPL"]["signal"], "bullish");
+    assert_eq!(signals["slow_agent"]["AAPL"]["signal"], "neutral");
+    assert_eq!(signals["slow_agent"]["AAPL"]["confidence"], 0.0);
+  }
 }
@@ -1,2 +1,3 @@
 pub mod state;
-pub mod graph;
\ No newline at end of file
+pub mod graph;
+pub mod reasoning;
\ No newline at end of file
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+// Events buffered per subscriber before the slowest one starts missing blocks
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReasoningEvent {
+  pub agent: String,
+  pub kind: String, // "status" for node progress, "signal" for a node's analyst signals, "reasoning" for show_agent_reasoning blocks
+  pub content: Value,
+}
+
+static CHANNELS: OnceLock<Mutex<HashMap<String, Sender<ReasoningEvent>>>> = OnceLock::new();
+static RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+  // Set for the duration of a hedge-fund run, receives every reasoning block produced while it runs
+  static REASONING_SINK: Sender<ReasoningEvent>;
+}
+
+fn channels() -> &'static Mutex<HashMap<String, Sender<ReasoningEvent>>> {
+  CHANNELS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn channel(run_id: &str) -> Sender<ReasoningEvent> {
+  let mut channels = channels().lock().unwrap();
+  channels.entry(run_id.to_string()).or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0).clone()
+}
+
+pub fn new_run_id() -> String {
+  let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+  format!("run-{}-{}", millis, RUN_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+// Subscribing before the run starts is allowed, the channel is shared once the run picks up the same id
+pub fn subscribe(run_id: &str) -> Receiver<ReasoningEvent> {
+  channel(run_id).subscribe()
+}
+
+// Removes the run's channel even when the run is cancelled mid-flight
+struct ChannelGuard<'a>(&'a str);
+
+impl Drop for ChannelGuard<'_> {
+  fn drop(&mut self) {
+    channels().lock().unwrap().remove(self.0);
+  }
+}
+
+// Publishes reasoning produced by `future` on the run's channel and closes it once the run finishes
+pub async fn with_reasoning_channel<F: Future>(run_id: &str, future: F) -> F::Output {
+  let _guard = ChannelGuard(run_id);
+  REASONING_SINK.scope(channel(run_id), future).await
+}
+
+pub fn publish(agent: &str, kind: &str, content: Value) {
+  let _ = REASONING_SINK.try_with(|sink| {
+    // No subscribers is the common case, the event is simply dropped
+    let _ = sink.send(ReasoningEvent { agent: agent.to_string(), kind: kind.to_string(), content });
+  });
+}
@@ -5,6 +5,8 @@ use log;
 use std::result::Result::{Ok, Err};
 use anyhow::Error;
 
+use crate::ai_agent::agents::portfolio_manager::PortfolioManagerOutput;
+use crate::ai_agent::graph::reasoning;
 use crate::ai_agent::llm::model_provider::ChatMessage; 
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
@@ -12,6 +14,8 @@ pub struct AgentState {
   pub messages : Vec<ChatMessage>, 
   pub data : HashMap<String, Value>, 
   pub metadata: HashMap<String, Value>,
+  #[serde(default)]
+  pub portfolio_output: Option<PortfolioManagerOutput>, // set by the portfolio manager in orders mode
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)] // Added Default
@@ -19,13 +23,15 @@ pub struct PartialAgentStateUpdate {
   pub messages: Option<Vec<ChatMessage>>,
   pub data: Option<HashMap<String, Value>>,
   pub metadata: Option<HashMap<String, Value>>,
+  #[serde(default)]
+  pub portfolio_output: Option<PortfolioManagerOutput>,
 }
 
 
 impl AgentState {
   pub fn new() -> Self {
     AgentState {
-      messages: Vec::new(), data: HashMap::new(), metadata: HashMap::new(),
+      messages: Vec::new(), data: HashMap::new(), metadata: HashMap::new(), portfolio_output: None,
     }
   }
 
@@ -44,7 +50,14 @@ impl AgentState {
   }
 
   pub fn merge_data(&mut self, data: HashMap<String, Value>) -> Result<(), Error> {
-    let _ = self.data.extend(data);
+    // Agents return cloned data with their own additions, so nested objects such as analyst_signals are merged
+    // key by key instead of replaced, otherwise a later agent's stale copy would drop an earlier agent's entry
+    for (key, value) in data {
+      match self.data.get_mut(&key) {
+        Some(existing) => deep_merge(existing, value),
+        None => { self.data.insert(key, value); }
+      }
+    }
     log::info!("Merge data into a dictionary correctly");
     return Ok(());
   }
@@ -55,6 +68,14 @@ impl AgentState {
     return Ok(());
   }
 
+  // Node durations accumulate under metadata["timings"], keyed by node name
+  pub fn record_timing(&mut self, node_name: &str, elapsed_ms: u64) {
+    let timings = self.metadata.entry("timings".to_string()).or_insert_with(|| Value::Object(serde_json::Map::new()));
+    if let Value::Object(timings) = timings {
+      timings.insert(node_name.to_string(), Value::from(elapsed_ms));
+    }
+  }
+
   pub fn update_from_partial(&mut self, update: PartialAgentStateUpdate) -> Result<(), Error> {
     if let Some(new_messages) = update.messages {
       let _ = self.add_messages(new_messages);
@@ -68,6 +89,10 @@ impl AgentState {
       let _ = self.merge_metadata(new_metadata);
     }
 
+    if let Some(portfolio_output) = update.portfolio_output {
+      self.portfolio_output = Some(portfolio_output);
+    }
+
     return Ok(());
   }
 
@@ -77,7 +102,7 @@ impl AgentState {
 
 impl PartialAgentStateUpdate {
   pub fn new() -> Self {
-    PartialAgentStateUpdate { messages: None, data: None, metadata: None }
+    PartialAgentStateUpdate { messages: None, data: None, metadata: None, portfolio_output: None }
   }
 
   pub fn with_messages(mut self, messages: Vec<ChatMessage>) -> Self {
@@ -94,6 +119,11 @@ impl PartialAgentStateUpdate {
     self.metadata = Some(metadata);
     return self;
   }
+
+  pub fn with_portfolio_output(mut self, portfolio_output: PortfolioManagerOutput) -> Self {
+    self.portfolio_output = Some(portfolio_output);
+    return self;
+  }
 }
 
 pub fn show_agent_reasoning(output_str: &str, agent_name: &str) {
@@ -101,6 +131,7 @@ pub fn show_agent_reasoning(output_str: &str, agent_name: &str) {
 
   match serde_json::from_str::<serde_json::Value>(output_str) {
     Ok(json_value) => { // Successfully parsed the string as JSON
+      reasoning::publish(agent_name, "reasoning", json_value.clone());
       match serde_json::to_string_pretty(&json_value) {
         Ok(pretty_json_string) => log::info!("{}", pretty_json_string),
         Err(e) => {
@@ -110,8 +141,67 @@ pub fn show_agent_reasoning(output_str: &str, agent_name: &str) {
       }
     }
     Err(_) => { // Not a valid JSON string, print as is
+      reasoning::publish(agent_name, "reasoning", Value::from(output_str));
       log::info!("{}", output_str);
     }
   }
   log::info!("{:=<48}", "");
-}
\ No newline at end of file
+}
+
+// Objects merge recursively, anything else (arrays, scalars, mismatched types) is replaced by `source`
+fn deep_merge(target: &mut Value, source: Value) {
+  match (target, source) {
+    (Value::Object(target), Value::Object(source)) => {
+      for (key, value) in source {
+        match target.get_mut(&key) {
+          Some(existing) => deep_merge(existing, value),
+          None => { target.insert(key, value); }
+        }
+      }
+    }
+    (target, source) => *target = source,
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn data(value: Value) -> HashMap<String, Value> {
+    serde_json::from_value(value).unwrap()
+  }
+
+  #[test]
+  fn analyst_signals_from_two_agents_both_survive_the_merge() {
+    let mut state = AgentState::new();
+    state.merge_data(data(json!({ "tickers": ["AAPL"], "analyst_signals": {} }))).unwrap();
+
+    // Each agent returns its own copy of the data with only its entry added
+    let risk_update = PartialAgentStateUpdate::new().with_data(data(json!({
+      "tickers": ["AAPL"],
+      "analyst_signals": { "risk_management_agent": { "AAPL": { "remaining_position_limit": 2000.0 } } },
+    })));
+    let buffett_update = PartialAgentStateUpdate::new().with_data(data(json!({
+      "tickers": ["AAPL"],
+      "analyst_signals": { "warren_buffett_agent": { "AAPL": { "signal": "bullish", "confidence": 80.0 } } },
+    })));
+    state.update_from_partial(risk_update).unwrap();
+    state.update_from_partial(buffett_update).unwrap();
+
+    let signals = &state.data["analyst_signals"];
+    assert_eq!(signals["risk_management_agent"]["AAPL"]["remaining_position_limit"], json!(2000.0));
+    assert_eq!(signals["warren_buffett_agent"]["AAPL"]["signal"], json!("bullish"));
+  }
+
+  #[test]
+  fn non_object_values_are_replaced() {
+    let mut state = AgentState::new();
+    state.merge_data(data(json!({ "tickers": ["AAPL", "MSFT"], "end_date": "2024-01-31" }))).unwrap();
+    state.merge_data(data(json!({ "tickers": ["NVDA"], "end_date": "2024-02-29" }))).unwrap();
+
+    assert_eq!(state.data["tickers"], json!(["NVDA"]));
+    assert_eq!(state.data["end_date"], json!("2024-02-29"));
+  }
+}
@@ -0,0 +1,147 @@
+use crate::ai_agent::llm::model_provider::{llm_http_client, ChatMessage, LLMChatter, LLMModelConfig, LLMResponse};
+
+use reqwest::{header::{HeaderMap},Client, Response};
+use serde::{Deserialize, Serialize};
+use crate::ai_agent::error::{AgentError, Result};
+use async_trait::async_trait;
+use std::result::Result::Ok;
+
+// Anthropic rejects requests without max_tokens
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Anthropic has no response-format flag, prefilling the assistant turn with an opening brace forces a JSON object
+const JSON_PREFILL: &str = "{";
+
+#[derive(Serialize, Debug)]
+struct AnthropicChatRequest {
+  model: String, // e.g., "claude-3-5-haiku-latest"
+  messages: Vec<ChatMessage>, // Only "user" and "assistant" roles are allowed here
+  #[serde(skip_serializing_if = "Option::is_none")]
+  system: Option<String>,
+  max_tokens: u32,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  temperature: Option<f32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  top_p: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicContentBlock {
+  #[serde(default)]
+  text: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicUsage {
+  #[serde(default)]
+  input_tokens: Option<u32>,
+  #[serde(default)]
+  output_tokens: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicChatResponse {
+  content: Vec<AnthropicContentBlock>,
+  #[serde(default)]
+  stop_reason: Option<String>,
+  #[serde(default)]
+  usage: Option<AnthropicUsage>,
+}
+
+pub struct AnthropicProvider {
+  anthropic_url : String,
+  api_key : String,
+  model_name: String,
+  client : Client
+}
+
+impl AnthropicProvider {
+
+  pub fn new(model_name: &str, api_key: Option<String>) -> Result<Self> {
+    let anthropic_url: String = "https://api.anthropic.com/v1/messages".to_string();
+    let api_key : String = match api_key {
+      Some(key) => key,
+      None => std::env::var("ANTHROPIC_API_KEY").map_err(|_| AgentError::MissingApiKey("ANTHROPIC_API_KEY".to_string()))?,
+    };
+    Ok(AnthropicProvider {anthropic_url, api_key, model_name: model_name.to_string(), client: llm_http_client()})
+  }
+
+  fn split_system(messages: Vec<ChatMessage>) -> (Option<String>, Vec<ChatMessage>) {
+    let mut system_parts : Vec<String> = Vec::new();
+    let mut chat_messages : Vec<ChatMessage> = Vec::new();
+
+    for message in messages {
+      if message.role == "system" {
+        system_parts.push(message.content);
+      } else {
+        // The Messages API rejects a participant name
+        chat_messages.push(ChatMessage { name: None, ..message });
+      }
+    }
+
+    let system = if system_parts.is_empty() { None } else { Some(system_parts.join("\n\n")) };
+    return (system, chat_messages);
+  }
+}
+
+#[async_trait]
+impl LLMChatter for AnthropicProvider {
+  async fn chat(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
+    let (system, mut messages) = Self::split_system(messages);
+    if config.json_mode {
+      messages.push(ChatMessage::new("assistant", JSON_PREFILL));
+    }
+    let request: AnthropicChatRequest = AnthropicChatRequest {
+      model: self.model_name.clone(),
+      messages: messages,
+      system: system,
+      max_tokens: config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+      temperature: config.temperature,
+      top_p: if config.temperature.is_some() { None } else { config.top_p }, // newer models reject both together
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-api-key", self.api_key.parse().unwrap());
+    headers.insert("anthropic-version", ANTHROPIC_VERSION.parse().unwrap());
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    let response: Response = self.client.post(&self.anthropic_url).headers(headers).json(&request).send().await?;
+
+    if response.status().is_success() {
+      let anthropic_response : AnthropicChatResponse = response.json().await?;
+      let (prompt_tokens, completion_tokens) = anthropic_response.usage.map_or((None, None), |usage| (usage.input_tokens, usage.output_tokens));
+      let first : AnthropicContentBlock = anthropic_response.content.into_iter().next().ok_or_else(|| AgentError::LlmParse("no content received from Anthropic".to_string()))?;
+      // The completion continues the prefill, so put the brace back
+      let content = if config.json_mode { format!("{}{}", JSON_PREFILL, first.text) } else { first.text };
+      return Ok(LLMResponse{
+        content: content,
+        prompt_tokens,
+        completion_tokens,
+        finish_reason: anthropic_response.stop_reason,
+      });
+    }
+    else {
+      log::error!("Error getting response from Anthropic: {:?}", response.status());
+      return Err(AgentError::from_status("Anthropic", response.status()));
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn system_prompts_are_split_out_and_names_dropped() {
+    let messages = vec![
+      ChatMessage::new("system", "Be brief."),
+      ChatMessage::new("user", "Analyze AAPL."),
+      ChatMessage::from_agent("warren_buffett_agent", "{}"),
+    ];
+    let (system, messages) = AnthropicProvider::split_system(messages);
+    assert_eq!(system.as_deref(), Some("Be brief."));
+    assert_eq!(messages.iter().map(|message| message.role.as_str()).collect::<Vec<_>>(), ["user", "assistant"]);
+    assert!(messages.iter().all(|message| message.name.is_none()));
+    assert!(!serde_json::to_string(&messages).unwrap().contains("\"name\""));
+  }
+}
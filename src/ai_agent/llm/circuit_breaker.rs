@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::ai_agent::error::{AgentError, Result};
+use crate::ai_agent::llm::model_provider::{ChatMessage, ChatStream, LLMChatter, LLMModelConfig, LLMResponse};
+
+// Failures within FAILURE_WINDOW that open the breaker, no call goes out for COOLDOWN after that
+const FAILURE_THRESHOLD: usize = 3;
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+// Reason agents give when they fall back to their rule-based output because the provider's breaker is open
+pub const CIRCUIT_OPEN: &str = "LLM provider circuit open after repeated failures";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+  Closed,
+  Open { until: Instant },
+  HalfOpen { probe_started: Option<Instant> }, // one probe call is let through, its outcome closes or re-opens the breaker
+}
+
+#[derive(Debug)]
+struct Breaker {
+  state: State,
+  failures: VecDeque<Instant>, // consecutive failures, cleared by any success
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+  pub state: &'static str,
+  pub recent_failures: usize,
+  pub retry_in_secs: Option<f64>, // while open
+}
+
+static BREAKERS: OnceLock<Mutex<HashMap<String, Breaker>>> = OnceLock::new();
+
+fn breakers() -> &'static Mutex<HashMap<String, Breaker>> {
+  BREAKERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// One breaker per provider and model, a failing model does not take the provider's other models down with it
+fn breaker_key(config: &LLMModelConfig) -> String {
+  format!("{}:{}", config.provider, config.model_name)
+}
+
+// Whether calls to this model currently fail fast. Read-only, so checking does not use up the half-open probe
+pub fn is_open(config: &LLMModelConfig) -> bool {
+  let breakers = breakers().lock().unwrap_or_else(|p| p.into_inner());
+  match breakers.get(&breaker_key(config)).map(|breaker| breaker.state) {
+    Some(State::Open { until }) => Instant::now() < until,
+    Some(State::HalfOpen { probe_started }) => probe_in_flight(probe_started),
+    _ => false,
+  }
+}
+
+// A probe that never reported back (its stream was dropped mid-way) stops blocking new ones after a cooldown
+fn probe_in_flight(probe_started: Option<Instant>) -> bool {
+  probe_started.map_or(false, |started| started.elapsed() < COOLDOWN)
+}
+
+// Claims the right to call the model, an open breaker past its cooldown half-opens and lets this call probe
+fn try_acquire(key: &str) -> bool {
+  let mut breakers = breakers().lock().unwrap_or_else(|p| p.into_inner());
+  let breaker = match breakers.get_mut(key) {
+    Some(breaker) => breaker,
+    None => return true,
+  };
+  match breaker.state {
+    State::Closed => true,
+    State::Open { until } if Instant::now() < until => false,
+    State::HalfOpen { probe_started } if probe_in_flight(probe_started) => false,
+    State::Open { .. } | State::HalfOpen { .. } => {
+      log::info!("Circuit for {} half-open, probing", key);
+      breaker.state = State::HalfOpen { probe_started: Some(Instant::now()) };
+      true
+    }
+  }
+}
+
+fn record_success_for(key: &str) {
+  let mut breakers = breakers().lock().unwrap_or_else(|p| p.into_inner());
+  if let Some(breaker) = breakers.get_mut(key) {
+    if breaker.state != State::Closed {
+      log::info!("Circuit for {} closed again", key);
+    }
+    breaker.state = State::Closed;
+    breaker.failures.clear();
+  }
+}
+
+fn record_failure_for(key: &str) {
+  let mut breakers = breakers().lock().unwrap_or_else(|p| p.into_inner());
+  let breaker = breakers.entry(key.to_string()).or_insert_with(|| Breaker { state: State::Closed, failures: VecDeque::new() });
+  let now = Instant::now();
+  breaker.failures.push_back(now);
+  while breaker.failures.front().map_or(false, |failed_at| now.duration_since(*failed_at) > FAILURE_WINDOW) {
+    breaker.failures.pop_front();
+  }
+
+  let trips = match breaker.state {
+    State::HalfOpen { .. } => true,
+    State::Closed => breaker.failures.len() >= FAILURE_THRESHOLD,
+    State::Open { .. } => false,
+  };
+  if trips {
+    log::warn!("Circuit for {} open after {} failures, failing fast for {}s", key, breaker.failures.len(), COOLDOWN.as_secs());
+    breaker.state = State::Open { until: now + COOLDOWN };
+  }
+}
+
+// Calls abandoned by chat_with_timeout never finish inside the wrapper, so the timeout is recorded here
+pub fn record_failure(config: &LLMModelConfig) {
+  record_failure_for(&breaker_key(config));
+}
+
+// A stream only succeeded once the caller has read it to the end
+pub fn record_success(config: &LLMModelConfig) {
+  record_success_for(&breaker_key(config));
+}
+
+// Every breaker that has seen a failure, keyed "Provider:model"
+pub fn snapshot() -> BTreeMap<String, BreakerStatus> {
+  let breakers = breakers().lock().unwrap_or_else(|p| p.into_inner());
+  let now = Instant::now();
+  breakers.iter().map(|(key, breaker)| {
+    let (state, retry_in_secs) = match breaker.state {
+      State::Closed => ("closed", None),
+      State::Open { until } if now < until => ("open", Some(until.duration_since(now).as_secs_f64())),
+      State::Open { .. } | State::HalfOpen { .. } => ("half_open", None),
+    };
+    (key.clone(), BreakerStatus { state, recent_failures: breaker.failures.len(), retry_in_secs })
+  }).collect()
+}
+
+// Wraps a provider client so every call goes through the model's breaker
+pub struct CircuitBreaker {
+  inner: Box<dyn LLMChatter>,
+}
+
+impl CircuitBreaker {
+  pub fn wrap(inner: Box<dyn LLMChatter>) -> Box<dyn LLMChatter> {
+    Box::new(CircuitBreaker { inner })
+  }
+}
+
+#[async_trait]
+impl LLMChatter for CircuitBreaker {
+  async fn chat(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
+    let key = breaker_key(config);
+    if !try_acquire(&key) {
+      return Err(AgentError::CircuitOpen(key));
+    }
+    let result = self.inner.chat(messages, config).await;
+    match &result {
+      Ok(_) => record_success_for(&key),
+      Err(_) => record_failure_for(&key),
+    }
+    result
+  }
+
+  // Failed chunks are recorded here, success once chat_with_sink has read the whole stream
+  async fn chat_stream(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<ChatStream> {
+    let key = breaker_key(config);
+    if !try_acquire(&key) {
+      return Err(AgentError::CircuitOpen(key));
+    }
+    let stream = match self.inner.chat_stream(messages, config).await {
+      Ok(stream) => stream,
+      Err(e) => {
+        record_failure_for(&key);
+        return Err(e);
+      }
+    };
+    Ok(Box::pin(stream.inspect(move |chunk| {
+      if chunk.is_err() {
+        record_failure_for(&key);
+      }
+    })))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai_agent::llm::model_provider::ModelProvider;
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use std::sync::Arc;
+
+  // Fails while `failing` is set, counts the calls that reached it
+  struct Flaky {
+    failing: Arc<AtomicBool>,
+    calls: Arc<AtomicUsize>,
+  }
+
+  #[async_trait]
+  impl LLMChatter for Flaky {
+    async fn chat(&self, _messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
+      self.calls.fetch_add(1, Ordering::SeqCst);
+      if self.failing.load(Ordering::SeqCst) {
+        return Err(AgentError::RateLimited(config.provider.to_string()));
+      }
+      Ok(LLMResponse::new("{}".to_string()))
+    }
+  }
+
+  fn flaky(model_name: &str) -> (Box<dyn LLMChatter>, LLMModelConfig, Arc<AtomicBool>, Arc<AtomicUsize>) {
+    let failing = Arc::new(AtomicBool::new(true));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let client = CircuitBreaker::wrap(Box::new(Flaky { failing: failing.clone(), calls: calls.clone() }));
+    let config = LLMModelConfig { provider: ModelProvider::OpenAI, model_name: model_name.to_string(), api_key: None, base_url: None,
+                                  temperature: None, max_tokens: None, top_p: None, json_mode: false, seed: None };
+    (client, config, failing, calls)
+  }
+
+  async fn call(client: &dyn LLMChatter, config: &LLMModelConfig) -> Result<LLMResponse> {
+    client.chat(vec![ChatMessage::new("user", "hi")], config).await
+  }
+
+  fn state_of(config: &LLMModelConfig) -> &'static str {
+    snapshot().get(&breaker_key(config)).map(|status| status.state).unwrap_or("closed")
+  }
+
+  // Stands in for waiting out the cooldown
+  fn expire_cooldown(config: &LLMModelConfig) {
+    let mut breakers = breakers().lock().unwrap();
+    breakers.get_mut(&breaker_key(config)).unwrap().state = State::Open { until: Instant::now() - Duration::from_secs(1) };
+  }
+
+  #[tokio::test]
+  async fn repeated_failures_trip_the_breaker_and_a_probe_resets_it() {
+    let (client, config, failing, calls) = flaky("breaker-trip-test");
+    for _ in 0..FAILURE_THRESHOLD {
+      assert!(matches!(call(client.as_ref(), &config).await, Err(AgentError::RateLimited(_))));
+    }
+    assert_eq!(state_of(&config), "open");
+    assert!(is_open(&config));
+
+    // Open, so the provider is not called at all
+    assert!(matches!(call(client.as_ref(), &config).await, Err(AgentError::CircuitOpen(_))));
+    assert_eq!(calls.load(Ordering::SeqCst), FAILURE_THRESHOLD);
+
+    expire_cooldown(&config);
+    assert!(!is_open(&config));
+    failing.store(false, Ordering::SeqCst);
+    assert!(call(client.as_ref(), &config).await.is_ok());
+    assert_eq!(calls.load(Ordering::SeqCst), FAILURE_THRESHOLD + 1);
+    assert_eq!(state_of(&config), "closed");
+    assert_eq!(snapshot()[&breaker_key(&config)].recent_failures, 0);
+  }
+
+  #[tokio::test]
+  async fn a_failed_probe_opens_the_breaker_again() {
+    let (client, config, _failing, calls) = flaky("breaker-probe-test");
+    for _ in 0..FAILURE_THRESHOLD {
+      let _ = call(client.as_ref(), &config).await;
+    }
+    expire_cooldown(&config);
+
+    assert!(matches!(call(client.as_ref(), &config).await, Err(AgentError::RateLimited(_))));
+    assert_eq!(state_of(&config), "open");
+    assert!(matches!(call(client.as_ref(), &config).await, Err(AgentError::CircuitOpen(_))));
+    assert_eq!(calls.load(Ordering::SeqCst), FAILURE_THRESHOLD + 1);
+  }
+
+  #[tokio::test]
+  async fn a_success_between_failures_keeps_the_breaker_closed() {
+    let (client, config, failing, _calls) = flaky("breaker-reset-test");
+    for _ in 0..FAILURE_THRESHOLD - 1 {
+      let _ = call(client.as_ref(), &config).await;
+    }
+    failing.store(false, Ordering::SeqCst);
+    assert!(call(client.as_ref(), &config).await.is_ok());
+    failing.store(true, Ordering::SeqCst);
+    let _ = call(client.as_ref(), &config).await;
+
+    assert_eq!(state_of(&config), "closed");
+    assert_eq!(snapshot()[&breaker_key(&config)].recent_failures, 1);
+  }
+}
@@ -0,0 +1,92 @@
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::ai_agent::agents::warren_buffet::Signal;
+use crate::ai_agent::error::{AgentError, Result};
+use crate::ai_agent::llm::model_provider::{chat_with_timeout, ChatMessage, LLMModelConfig, ModelProvider};
+use crate::ai_agent::llm::models::{get_ready_model, supports_json_mode};
+use crate::app::config::Config;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnsembleModel {
+  pub provider: String,
+  pub model_name: String,
+}
+
+impl EnsembleModel {
+  fn label(&self) -> String {
+    format!("{}/{}", self.provider, self.model_name)
+  }
+}
+
+// One model's answer to the shared SIGNAL_JSON_SCHEMA prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsembleVote {
+  pub signal: Signal,
+  pub confidence: f64,
+  pub reasoning: String,
+}
+
+// Set by the `ensemble_models` run option, a single entry is just a regular run
+pub fn ensemble_models(metadata: &HashMap<String, Value>) -> Vec<EnsembleModel> {
+  metadata.get("ensemble_models").cloned()
+    .and_then(|models| serde_json::from_value(models).ok())
+    .unwrap_or_default()
+}
+
+// Asks every model concurrently, models that fail, time out or answer with unparseable JSON are left out
+pub async fn ensemble_vote(models: &[EnsembleModel], messages: Vec<ChatMessage>, base_config: &LLMModelConfig, app_config: &Config, timeout: Duration) -> Vec<(String, EnsembleVote)> {
+  let calls = models.iter().map(|model| {
+    let messages = messages.clone();
+    async move {
+      let label = model.label();
+      match ask_model(model, messages, base_config, app_config, timeout).await {
+        Ok(vote) => Some((label, vote)),
+        Err(e) => {
+          log::warn!("Ensemble model {} left out: {}", label, e);
+          None
+        }
+      }
+    }
+  });
+  join_all(calls).await.into_iter().flatten().collect()
+}
+
+async fn ask_model(model: &EnsembleModel, messages: Vec<ChatMessage>, base_config: &LLMModelConfig, app_config: &Config, timeout: Duration) -> Result<EnsembleVote> {
+  let provider = ModelProvider::from_str(&model.provider).map_err(AgentError::Unsupported)?;
+  let config = LLMModelConfig {
+    provider,
+    model_name: model.model_name.clone(),
+    json_mode: supports_json_mode(&model.model_name),
+    ..base_config.clone()
+  };
+
+  let client = get_ready_model(&config, app_config)?;
+  let response = chat_with_timeout(client.as_ref(), messages, &config, timeout).await?
+    .ok_or_else(|| AgentError::LlmParse(format!("no response within {:.1}s", timeout.as_secs_f64())))?;
+  Ok(serde_json::from_str::<EnsembleVote>(&response.content)?)
+}
+
+// Majority signal, a tie between the leading signals is neutral. Confidence is averaged over every vote
+pub fn reconcile_votes(votes: &[(String, EnsembleVote)]) -> Option<EnsembleVote> {
+  if votes.is_empty() {
+    return None;
+  }
+
+  let count = |signal: Signal| votes.iter().filter(|(_, vote)| vote.signal == signal).count();
+  let mut tally = [(Signal::Bullish, count(Signal::Bullish)), (Signal::Bearish, count(Signal::Bearish)), (Signal::Neutral, count(Signal::Neutral))];
+  tally.sort_by(|a, b| b.1.cmp(&a.1));
+  let signal = if tally[0].1 == tally[1].1 { Signal::Neutral } else { tally[0].0 };
+
+  let confidence = votes.iter().map(|(_, vote)| vote.confidence).sum::<f64>() / votes.len() as f64;
+  let reasoning = votes.iter()
+    .map(|(label, vote)| format!("[{}: {}, {:.0}] {}", label, vote.signal, vote.confidence, vote.reasoning))
+    .collect::<Vec<String>>()
+    .join("\n");
+
+  Some(EnsembleVote { signal, confidence: confidence.round(), reasoning })
+}
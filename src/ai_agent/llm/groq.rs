@@ -1,11 +1,14 @@
-use crate::ai_agent::llm::model_provider::{ChatMessage, LLMChatter, LLMModelConfig, LLMResponse}; 
+use crate::ai_agent::llm::model_provider::{collect_stream, llm_http_client, ChatChunk, ChatMessage, ChatStream, CompletionUsage, LLMChatter, LLMModelConfig, LLMResponse};
 
 use reqwest::{header::{HeaderMap},Client, Response};
 use serde::{Deserialize, Serialize};
-use anyhow::{anyhow, Context, Result};
+use crate::ai_agent::error::{AgentError, Result};
 use async_trait::async_trait;
 use std::result::Result::Ok;
+use std::collections::VecDeque;
+use futures::stream;
 
+const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
 
 #[derive(Serialize, Debug)]
 struct GroqChatRequest {
@@ -18,27 +21,18 @@ struct GroqChatRequest {
   max_completion_tokens: Option<u32>, // Matching curl's "max_completion_tokens"
   #[serde(skip_serializing_if = "Option::is_none")]
   top_p: Option<f32>,
-  // stream: bool, // For this example, we'll assume non-streaming. Set to false or omit.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  response_format: Option<ResponseFormat>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  seed: Option<u64>,
+  stream: bool,
   // stop: Option<Vec<String>>, // Example: stop: Some(vec!["\n".to_string()])
 }
 
-#[derive(Deserialize, Debug)]
-struct GroqResponseMessage {
-  // role: String, // Usually "assistant"
-  content: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct GroqChoice {
-  // index: u32,
-  message: GroqResponseMessage,
-  // finish_reason: Option<String>,
-}
-
-#[derive(Deserialize, Debug)]
-struct GroqChatResponse {
-  choices: Vec<GroqChoice>,
-  // You could add other fields like 'id', 'usage', etc., if needed.
+#[derive(Serialize, Debug)]
+struct ResponseFormat {
+  #[serde(rename = "type")]
+  format_type: &'static str,
 }
 
 pub struct GroqProvider {
@@ -50,44 +44,210 @@ pub struct GroqProvider {
 
 impl GroqProvider {
 
-  pub fn new(model_name: &str) -> Self {
-    let groq_url: String = "https://api.groq.com/openai/v1/chat/completions".to_string();
-    let api_key = std::env::var("GROQ_API_KEY").ok().context("Groq API key not found. Provide it or set GROQ_API_KEY env var.").unwrap();
-    GroqProvider {groq_url, api_key, model_name: model_name.to_string(), client: Client::new()}
+  pub fn new(model_name: &str, api_key: Option<String>) -> Result<Self> {
+    let api_key : String = match api_key {
+      Some(key) => key,
+      None => std::env::var("GROQ_API_KEY").map_err(|_| AgentError::MissingApiKey("GROQ_API_KEY".to_string()))?,
+    };
+    Ok(Self::with_endpoint(model_name, api_key, GROQ_BASE_URL.to_string()))
+  }
+
+  // Explicit key and base URL, e.g. to point at a mock or proxy of the OpenAI-compatible API
+  pub fn with_endpoint(model_name: &str, api_key: String, base_url: String) -> Self {
+    let groq_url: String = format!("{}/chat/completions", base_url.trim_end_matches('/'));
+    GroqProvider {groq_url, api_key, model_name: model_name.to_string(), client: llm_http_client()}
   }
 }
 
-#[async_trait]
-impl LLMChatter for GroqProvider {
-  async fn chat(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
-    let request: GroqChatRequest = GroqChatRequest {
+#[derive(Deserialize, Debug)]
+struct GroqStreamDelta {
+  #[serde(default)]
+  content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GroqStreamChoice {
+  delta: GroqStreamDelta,
+  #[serde(default)]
+  finish_reason: Option<String>,
+}
+
+// Groq reports usage on the last chunk under x_groq, OpenAI-compatible proxies at the top level
+#[derive(Deserialize, Debug)]
+struct GroqExtension {
+  #[serde(default)]
+  usage: Option<CompletionUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GroqStreamChunk {
+  choices: Vec<GroqStreamChoice>,
+  #[serde(default)]
+  usage: Option<CompletionUsage>,
+  #[serde(default)]
+  x_groq: Option<GroqExtension>,
+}
+
+struct SseState {
+  response: Response,
+  buffer: String,
+  pending: VecDeque<ChatChunk>,
+  done: bool,
+}
+
+impl GroqProvider {
+
+  fn build_request(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig, stream: bool) -> GroqChatRequest {
+    GroqChatRequest {
       model: self.model_name.clone(),
       messages: messages,
       temperature: config.temperature,
       max_completion_tokens: config.max_tokens,
       top_p: config.top_p,
-      // stream: false,
-      // stop: None,
-    };
+      response_format: if config.json_mode { Some(ResponseFormat { format_type: "json_object" }) } else { None },
+      seed: config.seed,
+      stream: stream,
+    }
+  }
 
+  fn headers(&self) -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert("Authorization", format!("Bearer {}", self.api_key).parse().unwrap());
     headers.insert("Content-Type", "application/json".parse().unwrap());
-    let response: Response = self.client.post(&self.groq_url).headers(headers).json(&request).send().await?; 
-
-    if response.status().is_success() {
-      let groq_response : GroqChatResponse = response.json().await?;
-      // Pull out the first choice (or fail)
-      let first : GroqChoice = groq_response.choices.into_iter().next().ok_or_else(|| anyhow!("No response choices received from Groq"))?;
-      return Ok(LLMResponse{
-        content: first.message.content
-      });
+    headers
+  }
+
+  // Moves every complete `data:` line in the buffer into pending deltas
+  fn drain_sse_lines(state: &mut SseState) -> Result<()> {
+    while let Some(newline) = state.buffer.find('\n') {
+      let line : String = state.buffer.drain(..=newline).collect();
+      let line = line.trim();
+      let data = match line.strip_prefix("data:") {
+        Some(data) => data.trim(),
+        None => continue,
+      };
+      if data == "[DONE]" {
+        state.done = true;
+        return Ok(());
+      }
+      let chunk : GroqStreamChunk = serde_json::from_str(data).map_err(|e| AgentError::LlmParse(format!("invalid Groq stream chunk {}: {}", data, e)))?;
+      let usage = chunk.usage.or(chunk.x_groq.and_then(|extension| extension.usage));
+      let mut finish_reason = None;
+      if let Some(choice) = chunk.choices.into_iter().next() {
+        finish_reason = choice.finish_reason;
+        if let Some(content) = choice.delta.content.filter(|content| !content.is_empty()) {
+          state.pending.push_back(ChatChunk::Delta(content));
+        }
+      }
+      if usage.is_some() || finish_reason.is_some() {
+        let (prompt_tokens, completion_tokens) = usage.map_or((None, None), |usage| (usage.prompt_tokens, usage.completion_tokens));
+        state.pending.push_back(ChatChunk::Finished { prompt_tokens, completion_tokens, finish_reason });
+      }
     }
-    else {
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl LLMChatter for GroqProvider {
+  async fn chat(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
+    let stream = self.chat_stream(messages, config).await?;
+    collect_stream(stream).await
+  }
+
+  async fn chat_stream(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<ChatStream> {
+    let request: GroqChatRequest = self.build_request(messages, config, true);
+    let response: Response = self.client.post(&self.groq_url).headers(self.headers()).json(&request).send().await?;
+
+    if !response.status().is_success() {
       log::error!("Error getting response from Groq: {:?}", response.status());
-      return Ok(LLMResponse {content: "Error message for connecting to GROQ".to_string()});
+      return Err(AgentError::from_status("Groq", response.status()));
     }
 
+    let state = SseState { response, buffer: String::new(), pending: VecDeque::new(), done: false };
+    let deltas = stream::unfold(state, |mut state| async move {
+      loop {
+        if let Some(delta) = state.pending.pop_front() {
+          return Some((Ok(delta), state));
+        }
+        if state.done {
+          return None;
+        }
+        match state.response.chunk().await {
+          Ok(Some(bytes)) => {
+            state.buffer.push_str(&String::from_utf8_lossy(&bytes));
+            if let Err(e) = Self::drain_sse_lines(&mut state) {
+              state.done = true;
+              return Some((Err(e), state));
+            }
+          }
+          Ok(None) => state.done = true,
+          Err(e) => {
+            state.done = true;
+            return Some((Err(e.into()), state));
+          }
+        }
+      }
+    });
+    Ok(Box::pin(deltas))
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai_agent::llm::model_provider::ModelProvider;
+  use crate::ai_agent::llm::models::SamplingParams;
+  use crate::ai_agent::tools::stub_server::StubServer;
+  use serde_json::{json, Value};
+  use std::collections::HashMap;
 
+  const STREAM_BODY: &str = concat!(
+    "data: {\"choices\":[{\"delta\":{\"content\":\"{\\\"signal\\\": \"}}]}\n\n",
+    "data: {\"choices\":[{\"delta\":{\"content\":\"\\\"bullish\\\"}\"},\"finish_reason\":\"stop\"}],\"x_groq\":{\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":5}}}\n\n",
+    "data: [DONE]\n\n",
+  );
+
+  fn deterministic_config() -> LLMModelConfig {
+    let metadata: HashMap<String, Value> = serde_json::from_value(json!({ "deterministic": true })).unwrap();
+    SamplingParams::from_metadata(&metadata, 0.5).to_config(ModelProvider::Groq, "llama-test", true)
   }
-}
\ No newline at end of file
+
+  #[tokio::test]
+  async fn chat_joins_the_streamed_content_and_usage() {
+    let server = StubServer::start(|_| (200, STREAM_BODY.to_string()));
+    let groq = GroqProvider::with_endpoint("llama-test", "test-key".to_string(), format!("{}/", server.base_url));
+
+    let response = groq.chat(vec![ChatMessage::new("user", "Analyze AAPL")], &deterministic_config()).await.unwrap();
+    assert_eq!(response.content, "{\"signal\": \"bullish\"}");
+    assert_eq!(response.prompt_tokens, Some(12));
+    assert_eq!(response.completion_tokens, Some(5));
+    assert_eq!(response.finish_reason.as_deref(), Some("stop"));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!((requests[0].method.as_str(), requests[0].path.as_str()), ("POST", "/chat/completions"));
+    let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["model"], json!("llama-test"));
+    assert_eq!(body["stream"], json!(true));
+    assert_eq!(body["response_format"], json!({ "type": "json_object" }));
+    // Deterministic mode pins the sampling so repeated runs agree
+    assert_eq!(body["temperature"], json!(0.0));
+    assert!(body["seed"].is_u64());
+  }
+
+  #[tokio::test]
+  async fn non_success_status_is_an_error() {
+    let server = StubServer::start(|_| (401, "{\"error\":{\"message\":\"invalid key\"}}".to_string()));
+    let groq = GroqProvider::with_endpoint("llama-test", "bad-key".to_string(), server.base_url.clone());
+
+    let result = groq.chat(vec![ChatMessage::new("user", "Analyze AAPL")], &deterministic_config()).await;
+    assert!(matches!(result, Err(AgentError::UpstreamHttp { status, .. }) if status.as_u16() == 401), "{:?}", result.map(|response| response.content));
+  }
+
+  #[test]
+  fn an_explicit_key_skips_the_environment() {
+    assert!(GroqProvider::new("llama-test", Some("test-key".to_string())).is_ok());
+  }
+}
@@ -0,0 +1,151 @@
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+use crate::ai_agent::llm::model_provider::{chat_with_timeout, ChatMessage, LLMChatter, LLMModelConfig};
+
+const REPAIR_PROMPT: &str = "Your previous answer could not be parsed as JSON. Reply again with the same answer as a single valid JSON object only, no markdown fences or commentary.";
+
+// Models often wrap their JSON in markdown fences or prose, tries the raw text, the fenced body, then the first balanced {...} block
+pub fn extract_json<T: DeserializeOwned>(raw: &str) -> Result<T, serde_json::Error> {
+  let raw = raw.trim();
+  let mut error = match serde_json::from_str::<T>(raw) {
+    Ok(value) => return Ok(value),
+    Err(e) => e,
+  };
+
+  let unfenced = strip_fences(raw);
+  if unfenced != raw {
+    match serde_json::from_str::<T>(unfenced) {
+      Ok(value) => return Ok(value),
+      Err(e) => error = e,
+    }
+  }
+
+  if let Some(block) = first_object(unfenced) {
+    match serde_json::from_str::<T>(block) {
+      Ok(value) => return Ok(value),
+      Err(e) => error = e,
+    }
+  }
+  Err(error)
+}
+
+// Body of the first ``` fence, the language tag on the opening line is dropped
+fn strip_fences(raw: &str) -> &str {
+  let start = match raw.find("```") {
+    Some(start) => start + 3,
+    None => return raw,
+  };
+  let body_start = match raw[start..].find('\n') {
+    Some(newline) => start + newline + 1,
+    None => return raw,
+  };
+  match raw[body_start..].find("```") {
+    Some(end) => raw[body_start..body_start + end].trim(),
+    None => raw[body_start..].trim(),
+  }
+}
+
+// Braces inside strings do not count towards the nesting depth
+fn first_object(raw: &str) -> Option<&str> {
+  let start = raw.find('{')?;
+  let mut depth = 0usize;
+  let mut in_string = false;
+  let mut escaped = false;
+
+  for (offset, c) in raw[start..].char_indices() {
+    if in_string {
+      match c {
+        _ if escaped => escaped = false,
+        '\\' => escaped = true,
+        '"' => in_string = false,
+        _ => {}
+      }
+      continue;
+    }
+    match c {
+      '"' => in_string = true,
+      '{' => depth += 1,
+      '}' => {
+        depth -= 1;
+        if depth == 0 {
+          return Some(&raw[start..start + offset + 1]);
+        }
+      }
+      _ => {}
+    }
+  }
+  None
+}
+
+// One follow-up call asking the model to restate `previous` as valid JSON, None when that fails as well
+pub async fn repair_json<T: DeserializeOwned>(client: &dyn LLMChatter, mut messages: Vec<ChatMessage>, previous: &str, config: &LLMModelConfig, timeout: Duration) -> Option<T> {
+  messages.push(ChatMessage::new("assistant", previous));
+  messages.push(ChatMessage::new("user", REPAIR_PROMPT));
+
+  match chat_with_timeout(client, messages, config, timeout).await {
+    Ok(Some(response)) => match extract_json::<T>(&response.content) {
+      Ok(value) => Some(value),
+      Err(e) => {
+        log::warn!("{} model {} still answered with invalid JSON after a repair request: {}", config.provider, config.model_name, e);
+        None
+      }
+    },
+    Ok(None) => None,
+    Err(e) => {
+      log::warn!("JSON repair request to {} model {} failed: {}", config.provider, config.model_name, e);
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Deserialize;
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Answer {
+    signal: String,
+    confidence: f64,
+  }
+
+  fn bullish() -> Answer {
+    Answer { signal: "bullish".to_string(), confidence: 80.0 }
+  }
+
+  #[test]
+  fn plain_json_parses_as_is() {
+    assert_eq!(extract_json::<Answer>(r#"  {"signal": "bullish", "confidence": 80.0}  "#).unwrap(), bullish());
+  }
+
+  #[test]
+  fn fenced_json_is_unwrapped() {
+    let raw = "```json\n{\"signal\": \"bullish\", \"confidence\": 80.0}\n```";
+    assert_eq!(extract_json::<Answer>(raw).unwrap(), bullish());
+    let untagged = "```\n{\"signal\": \"bullish\", \"confidence\": 80.0}\n```";
+    assert_eq!(extract_json::<Answer>(untagged).unwrap(), bullish());
+  }
+
+  #[test]
+  fn prose_wrapped_json_is_found() {
+    let raw = "Here is my analysis:\n{\"signal\": \"bullish\", \"confidence\": 80.0}\nLet me know if you need more.";
+    assert_eq!(extract_json::<Answer>(raw).unwrap(), bullish());
+    let fenced_in_prose = "Sure!\n```json\n{\"signal\": \"bullish\", \"confidence\": 80.0}\n```\nThanks.";
+    assert_eq!(extract_json::<Answer>(fenced_in_prose).unwrap(), bullish());
+  }
+
+  #[test]
+  fn braces_inside_strings_do_not_end_the_block() {
+    let raw = r#"Result: {"reasoning": "margin } held {", "nested": {"a": 1}} trailing }"#;
+    let value: serde_json::Value = extract_json(raw).unwrap();
+    assert_eq!(value["reasoning"], "margin } held {");
+    assert_eq!(value["nested"]["a"], 1);
+  }
+
+  #[test]
+  fn text_without_json_is_an_error() {
+    assert!(extract_json::<Answer>("I cannot decide today.").is_err());
+    assert!(extract_json::<Answer>("{\"signal\": \"bullish\"").is_err());
+  }
+}
@@ -1,3 +1,9 @@
 pub mod models;
 pub mod model_provider;
-pub mod groq;
\ No newline at end of file
+pub mod groq;
+pub mod openai;
+pub mod anthropic;
+pub mod prompt;
+pub mod ensemble;
+pub mod json;
+pub mod circuit_breaker;
\ No newline at end of file
@@ -1,8 +1,36 @@
 use serde::{Serialize, Deserialize};
 use std::str::FromStr;
 use std::fmt;
-use anyhow::{Result};
+use crate::ai_agent::error::Result;
+use crate::ai_agent::llm::circuit_breaker;
 use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use reqwest::Client;
+use tokio::sync::mpsc::UnboundedSender;
+
+// No overall request timeout, responses stream and chat_with_timeout bounds the whole call
+const LLM_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const LLM_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const LLM_POOL_MAX_IDLE_PER_HOST: usize = 8;
+
+static LLM_HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+// Shared by every provider instance, cloning a reqwest Client only clones a handle to the same pool
+pub fn llm_http_client() -> Client {
+  LLM_HTTP_CLIENT.get_or_init(|| {
+    Client::builder()
+      .connect_timeout(LLM_CONNECT_TIMEOUT)
+      .pool_idle_timeout(LLM_POOL_IDLE_TIMEOUT)
+      .pool_max_idle_per_host(LLM_POOL_MAX_IDLE_PER_HOST)
+      .build()
+      .expect("Failed to build the LLM HTTP client")
+  }).clone()
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ModelProvider {
@@ -16,6 +44,10 @@ pub enum ModelProvider {
 
 impl ModelProvider {
 
+  pub fn all() -> Vec<ModelProvider> {
+    vec![ModelProvider::Anthropic, ModelProvider::DeepSeek, ModelProvider::Gemini, ModelProvider::Groq, ModelProvider::OpenAI, ModelProvider::Ollama]
+  }
+
   pub fn _as_str(&self) -> &'static str {
     match self {
       &ModelProvider::Anthropic => "Anthropic",
@@ -26,6 +58,36 @@ impl ModelProvider {
       &ModelProvider::OpenAI => "OpenAI"
     }
   }
+
+  // Environment variable holding the provider's key, None for local providers that need none
+  pub fn api_key_var(&self) -> Option<&'static str> {
+    match self {
+      ModelProvider::Anthropic => Some("ANTHROPIC_API_KEY"),
+      ModelProvider::DeepSeek => Some("DEEPSEEK_API_KEY"),
+      ModelProvider::Gemini => Some("GOOGLE_API_KEY"),
+      ModelProvider::Groq => Some("GROQ_API_KEY"),
+      ModelProvider::OpenAI => Some("OPENAI_API_KEY"),
+      ModelProvider::Ollama => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderReadiness {
+  Ready,          // client implemented and API key configured
+  MissingKey,     // client implemented but the API key is not set
+  NotImplemented, // no client for this provider yet
+}
+
+impl ProviderReadiness {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ProviderReadiness::Ready => "ready",
+      ProviderReadiness::MissingKey => "missing_key",
+      ProviderReadiness::NotImplemented => "not_implemented",
+    }
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,19 +98,96 @@ pub struct LLMModelConfig {
   pub base_url: Option<String>, // Useful for Ollama or other self-hosted/proxy setups
   pub temperature: Option<f32>,
   pub max_tokens: Option<u32>,
-  pub top_p : Option<f32>
+  pub top_p : Option<f32>,
+  #[serde(default)]
+  pub json_mode: bool, // ask the provider for structured JSON output, see LLMModel::has_json_mode
+  #[serde(default)]
+  pub seed: Option<u64>, // sent to providers that support seeded sampling (OpenAI, Groq), ignored by the rest
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
   pub role: String, // e.g., "user", "assistant", "system"
   pub content: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>, // which agent wrote an assistant message, OpenAI-compatible APIs take it as the participant name
+}
+
+impl ChatMessage {
+  pub fn new(role: &str, content: impl Into<String>) -> Self {
+    ChatMessage { role: role.to_string(), content: content.into(), name: None }
+  }
+
+  // Output of an agent node, tagged with the node name
+  pub fn from_agent(agent: &str, content: impl Into<String>) -> Self {
+    ChatMessage { role: "assistant".to_string(), content: content.into(), name: Some(agent.to_string()) }
+  }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LLMResponse {
   pub content: String,
-  // You might include other details like token usage, finish reason, etc.
+  #[serde(default)]
+  pub prompt_tokens: Option<u32>,
+  #[serde(default)]
+  pub completion_tokens: Option<u32>,
+  #[serde(default)]
+  pub finish_reason: Option<String>, // as reported by the provider, e.g. "stop", "length" or Anthropic's "max_tokens"
+}
+
+impl LLMResponse {
+  pub fn new(content: String) -> Self {
+    LLMResponse { content, prompt_tokens: None, completion_tokens: None, finish_reason: None }
+  }
+
+  // Cut off by max_tokens, the content is usually incomplete JSON
+  pub fn is_truncated(&self) -> bool {
+    matches!(self.finish_reason.as_deref(), Some("length") | Some("max_tokens"))
+  }
+
+  fn apply(&mut self, chunk: ChatChunk) {
+    match chunk {
+      ChatChunk::Delta(delta) => self.content.push_str(&delta),
+      ChatChunk::Finished { prompt_tokens, completion_tokens, finish_reason } => {
+        self.prompt_tokens = prompt_tokens.or(self.prompt_tokens);
+        self.completion_tokens = completion_tokens.or(self.completion_tokens);
+        self.finish_reason = finish_reason.or(self.finish_reason.take());
+      }
+    }
+  }
+}
+
+// `usage` object of OpenAI-compatible responses
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionUsage {
+  #[serde(default)]
+  pub prompt_tokens: Option<u32>,
+  #[serde(default)]
+  pub completion_tokens: Option<u32>,
+}
+
+// Token usage summed over every LLM call of a run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TokenUsage {
+  pub calls: u64,
+  pub prompt_tokens: u64,
+  pub completion_tokens: u64,
+  pub total_tokens: u64,
+  pub truncated_calls: u64,
+}
+
+impl TokenUsage {
+  fn record(&mut self, response: &LLMResponse) {
+    let prompt_tokens = response.prompt_tokens.unwrap_or(0) as u64;
+    let completion_tokens = response.completion_tokens.unwrap_or(0) as u64;
+    self.calls += 1;
+    self.prompt_tokens += prompt_tokens;
+    self.completion_tokens += completion_tokens;
+    self.total_tokens += prompt_tokens + completion_tokens;
+    if response.is_truncated() {
+      self.truncated_calls += 1;
+    }
+  }
 }
 
 impl fmt::Display for ModelProvider {
@@ -80,12 +219,195 @@ impl FromStr for ModelProvider {
   }
 }
 
+// Content deltas of a streamed completion, followed by usage and finish reason once the provider reports them
+pub enum ChatChunk {
+  Delta(String),
+  Finished { prompt_tokens: Option<u32>, completion_tokens: Option<u32>, finish_reason: Option<String> },
+}
+
+pub type ChatStream = Pin<Box<dyn Stream<Item = Result<ChatChunk>> + Send>>;
+
+tokio::task_local! {
+  // Set by streaming endpoints, receives every content delta produced while the task runs
+  static TOKEN_SINK: UnboundedSender<String>;
+  // Set for the duration of a hedge-fund run, sums the usage of every LLM call made while it runs
+  static USAGE_TOTALS: Arc<Mutex<TokenUsage>>;
+  // Set when a run has a `token_budget`, completion tokens left for the rest of the run
+  static TOKEN_BUDGET: Arc<AtomicU64>;
+}
+
+// Reason agents give when they fall back to their rule-based output because the run's budget ran out
+pub const BUDGET_EXHAUSTED: &str = "Token budget exhausted";
+
 #[async_trait]
 pub trait LLMChatter : Send + Sync {
   async fn chat(&self, messages: Vec<ChatMessage>,config : &LLMModelConfig) -> Result<LLMResponse>;
-    
+
+  // Providers without streaming support yield the whole response once
+  async fn chat_stream(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<ChatStream> {
+    let response = self.chat(messages, config).await?;
+    let finished = ChatChunk::Finished { prompt_tokens: response.prompt_tokens, completion_tokens: response.completion_tokens, finish_reason: response.finish_reason };
+    Ok(Box::pin(stream::iter(vec![Ok(ChatChunk::Delta(response.content)), Ok(finished)])))
+  }
+}
+
+pub async fn collect_stream(mut stream: ChatStream) -> Result<LLMResponse> {
+  let mut response = LLMResponse::new(String::new());
+  while let Some(chunk) = stream.next().await {
+    response.apply(chunk?);
+  }
+  Ok(response)
+}
+
+pub async fn with_token_sink<F: Future>(sink: UnboundedSender<String>, future: F) -> F::Output {
+  TOKEN_SINK.scope(sink, future).await
+}
+
+// Runs `future` and returns the token usage of every LLM call it made
+pub async fn with_usage_tracking<F: Future>(future: F) -> (F::Output, TokenUsage) {
+  let totals = Arc::new(Mutex::new(TokenUsage::default()));
+  let output = USAGE_TOTALS.scope(totals.clone(), future).await;
+  let usage = totals.lock().unwrap().clone();
+  (output, usage)
+}
+
+// Shares `budget` completion tokens between every LLM call the future makes, returns what is left of it
+pub async fn with_token_budget<F: Future>(budget: Option<u64>, future: F) -> (F::Output, Option<u64>) {
+  let budget = match budget {
+    Some(budget) => budget,
+    None => return (future.await, None),
+  };
+  let remaining = Arc::new(AtomicU64::new(budget));
+  let output = TOKEN_BUDGET.scope(remaining.clone(), future).await;
+  (output, Some(remaining.load(Ordering::Relaxed)))
+}
+
+// Whether `max_tokens` more completion tokens still fit in the run's budget, always true for runs without one.
+// Agents running in parallel check before any of them is charged, so a run can overshoot by their in-flight calls
+pub fn budget_allows(max_tokens: u64) -> bool {
+  TOKEN_BUDGET.try_with(|remaining| remaining.load(Ordering::Relaxed) >= max_tokens).unwrap_or(true)
+}
+
+fn record_usage(response: &LLMResponse, config: &LLMModelConfig) {
+  if response.is_truncated() {
+    log::warn!("{} model {} stopped at max_tokens ({:?}), the response is likely incomplete", config.provider, config.model_name, config.max_tokens);
+  }
+  let _ = USAGE_TOTALS.try_with(|totals| totals.lock().unwrap().record(response));
+  let completion_tokens = response.completion_tokens.unwrap_or(0) as u64;
+  let _ = TOKEN_BUDGET.try_with(|remaining| {
+    let _ = remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| Some(left.saturating_sub(completion_tokens)));
+  });
+}
+
+// Streams the completion to the current token sink when there is one, otherwise a plain chat call
+pub async fn chat_with_sink(client: &dyn LLMChatter, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
+  let sink = match TOKEN_SINK.try_with(|sink| sink.clone()) {
+    Ok(sink) => sink,
+    Err(_) => {
+      let response = client.chat(messages, config).await?;
+      record_usage(&response, config);
+      return Ok(response);
+    }
+  };
+
+  let mut stream = client.chat_stream(messages, config).await?;
+  let mut response = LLMResponse::new(String::new());
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk?;
+    if let ChatChunk::Delta(delta) = &chunk {
+      let _ = sink.send(delta.clone()); // the client may have disconnected, keep collecting regardless
+    }
+    response.apply(chunk);
+  }
+  circuit_breaker::record_success(config);
+  record_usage(&response, config);
+  Ok(response)
+}
+
+// None when the provider did not answer within `limit`, callers fall back the same way they do on parse failures
+pub async fn chat_with_timeout(client: &dyn LLMChatter, messages: Vec<ChatMessage>, config: &LLMModelConfig, limit: Duration) -> Result<Option<LLMResponse>> {
+  match tokio::time::timeout(limit, chat_with_sink(client, messages, config)).await {
+    Ok(response) => Ok(Some(response?)),
+    Err(_) => {
+      log::warn!("{} model {} did not respond within {:.1}s", config.provider, config.model_name, limit.as_secs_f64());
+      circuit_breaker::record_failure(config);
+      Ok(None)
+    }
+  }
 }
 
 
+#[cfg(test)]
+mod tests {
+  use super::*;
 
+  // Answers every call with a fixed completion token count
+  struct FixedUsage(u32);
+
+  #[async_trait]
+  impl LLMChatter for FixedUsage {
+    async fn chat(&self, _messages: Vec<ChatMessage>, _config: &LLMModelConfig) -> Result<LLMResponse> {
+      let mut response = LLMResponse::new("{}".to_string());
+      response.completion_tokens = Some(self.0);
+      Ok(response)
+    }
+  }
+
+  fn test_config() -> LLMModelConfig {
+    LLMModelConfig { provider: ModelProvider::OpenAI, model_name: "budget-test".to_string(), api_key: None, base_url: None,
+                     temperature: None, max_tokens: Some(50), top_p: None, json_mode: false, seed: None }
+  }
 
+  #[test]
+  fn message_names_round_trip_and_are_omitted_when_absent() {
+    let named = ChatMessage::from_agent("warren_buffett_agent", "{}");
+    let json = serde_json::to_value(&named).unwrap();
+    assert_eq!(json, serde_json::json!({ "role": "assistant", "content": "{}", "name": "warren_buffett_agent" }));
+    let parsed : ChatMessage = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed.name.as_deref(), Some("warren_buffett_agent"));
+
+    let plain = serde_json::to_value(ChatMessage::new("user", "hi")).unwrap();
+    assert_eq!(plain, serde_json::json!({ "role": "user", "content": "hi" }));
+    assert_eq!(serde_json::from_value::<ChatMessage>(plain).unwrap().name, None);
+  }
+
+  #[tokio::test]
+  async fn analysts_after_the_budget_is_spent_are_refused() {
+    let client = FixedUsage(60);
+    let (allowed, remaining) = with_token_budget(Some(100), async {
+      let mut allowed = Vec::new();
+      for _ in 0..3 {
+        let fits = budget_allows(50);
+        allowed.push(fits);
+        if fits {
+          chat_with_sink(&client, vec![ChatMessage::new("user", "hi")], &test_config()).await.unwrap();
+        }
+      }
+      allowed
+    }).await;
+
+    // The first call leaves 40 tokens, too few for the next 50-token call
+    assert_eq!(allowed, [true, false, false]);
+    assert_eq!(remaining, Some(40));
+  }
+
+  #[tokio::test]
+  async fn spending_past_the_budget_stops_at_zero() {
+    let client = FixedUsage(60);
+    let (_, remaining) = with_token_budget(Some(100), async {
+      for _ in 0..2 {
+        chat_with_sink(&client, vec![ChatMessage::new("user", "hi")], &test_config()).await.unwrap();
+      }
+      assert!(!budget_allows(1));
+    }).await;
+    assert_eq!(remaining, Some(0));
+  }
+
+  #[tokio::test]
+  async fn runs_without_a_budget_are_never_refused() {
+    let (fits, remaining) = with_token_budget(None, async { budget_allows(u64::MAX) }).await;
+    assert!(fits);
+    assert_eq!(remaining, None);
+    assert!(budget_allows(u64::MAX));
+  }
+}
@@ -1,11 +1,18 @@
 use serde::{Serialize, Deserialize};
 use std::env; // For environment variables
 use std::sync::OnceLock;
-use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::ai_agent::error::{AgentError, Result};
+use serde_json::Value;
 
 
-use crate::ai_agent::llm::model_provider::{LLMModelConfig, ModelProvider, LLMChatter};
+use crate::ai_agent::llm::model_provider::{LLMModelConfig, ModelProvider, LLMChatter, ProviderReadiness};
 use crate::ai_agent::llm::groq::GroqProvider;
+use crate::ai_agent::llm::openai::OpenAIProvider;
+use crate::ai_agent::llm::anthropic::AnthropicProvider;
+use crate::ai_agent::llm::circuit_breaker::CircuitBreaker;
+use crate::app::config::Config;
 
 // --- LLMModelDescriptor (equivalent to Python's LLMModel class) ---
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -106,6 +113,8 @@ fn ollama_models_data() -> Vec<LLMModel> {
 pub static AVAILABLE_MODELS: OnceLock<Vec<LLMModel>> = OnceLock::new();
 pub static OLLAMA_MODELS: OnceLock<Vec<LLMModel>> = OnceLock::new();
 
+const OLLAMA_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
 
 pub fn get_available_models() -> &'static [LLMModel] {
   AVAILABLE_MODELS.get_or_init(available_models_data).as_slice()
@@ -115,6 +124,49 @@ pub fn get_ollama_models() -> &'static [LLMModel] {
   OLLAMA_MODELS.get_or_init(ollama_models_data).as_slice()
 }
 
+#[derive(Deserialize, Debug)]
+struct OllamaTag {
+  name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaTagsResponse {
+  models: Vec<OllamaTag>,
+}
+
+pub fn ollama_base_url() -> String {
+  let ollama_host = env::var("OLLAMA_HOST").unwrap_or_else(|_| "localhost".to_string());
+  format!("http://{}:11434", ollama_host)
+}
+
+// Lists the models pulled into a local Ollama instance
+pub async fn fetch_ollama_models(base_url: &str) -> Result<Vec<LLMModel>> {
+  let url = format!("{}/api/tags", base_url.trim_end_matches('/'));
+  let client = reqwest::Client::builder().timeout(OLLAMA_DISCOVERY_TIMEOUT).build()?;
+  let response = client.get(&url).send().await?.error_for_status()?;
+  let tags : OllamaTagsResponse = response.json().await?;
+
+  let models = tags.models.into_iter()
+    .map(|tag| LLMModel::new(&format!("[ollama] {}", tag.name), &tag.name, ModelProvider::Ollama))
+    .collect();
+  Ok(models)
+}
+
+// Call once at startup, before anything reads the Ollama list. Keeps the static list when Ollama is down or has no models.
+pub async fn refresh_ollama_models() {
+  let base_url = ollama_base_url();
+  match fetch_ollama_models(&base_url).await {
+    Ok(models) if !models.is_empty() => {
+      log::info!("Discovered {} Ollama models at {}", models.len(), base_url);
+      if OLLAMA_MODELS.set(models).is_err() {
+        log::warn!("Ollama model list was already initialized, discovered models ignored");
+      }
+    }
+    Ok(_) => log::info!("Ollama at {} has no models pulled, using the default list", base_url),
+    Err(e) => log::info!("Ollama not reachable at {} ({}), using the default list", base_url, e),
+  }
+}
+
 pub fn get_llm_order() -> Vec<(String, String, String)> {
   get_available_models().iter().map(|m| m.to_choice_tuple()).collect()
 }
@@ -130,41 +182,282 @@ pub fn get_model_info(model_name: &str) -> Option<&'static LLMModel> {
       .find(|&model_desc| model_desc.model_name == model_name)
 }
 
+// Models missing from the known lists are treated as prompt-only JSON
+pub fn supports_json_mode(model_name: &str) -> bool {
+  get_model_info(model_name).map_or(false, LLMModel::has_json_mode)
+}
+
 pub fn get_model(config: &LLMModelConfig) -> Result<Box<dyn LLMChatter>> {
   log::info!("Initializing LLM client for provider: {}, model: {}", config.provider,config.model_name);
 
   match config.provider {
     ModelProvider::Groq => {
-      let client = GroqProvider::new(&config.model_name);
+      let client = match config.base_url.as_deref().filter(|url| !url.trim().is_empty()) {
+        Some(base_url) => GroqProvider::with_endpoint(&config.model_name, config.api_key.clone().unwrap_or_default(), base_url.to_string()),
+        None => GroqProvider::new(&config.model_name, config.api_key.clone())?,
+      };
       return Ok(Box::new(client))
     }
     ModelProvider::OpenAI => {
-      // let api_key = get_api_key_for_provider(&config.provider, &config.api_key)?;
-      // Ok(Box::new(OpenAIProviderClient::new(api_key)))
-      Err(anyhow!("OpenAI client not yet implemented"))
+      let client = OpenAIProvider::new(&config.model_name, config.api_key.clone())?;
+      return Ok(Box::new(client))
     }
     ModelProvider::Anthropic => {
-      // let api_key = get_api_key_for_provider(&config.provider, &config.api_key)?;
-      // Ok(Box::new(AnthropicProviderClient::new(api_key)))
-      Err(anyhow!("Anthropic client not yet implemented"))
+      let client = AnthropicProvider::new(&config.model_name, config.api_key.clone())?;
+      return Ok(Box::new(client))
     }
     ModelProvider::DeepSeek => {
       // let api_key = get_api_key_for_provider(&config.provider, &config.api_key)?;
       // Ok(Box::new(DeepSeekProviderClient::new(api_key)))
-      Err(anyhow!("DeepSeek client not yet implemented"))
+      Err(AgentError::Unsupported("DeepSeek client".to_string()))
     }
     ModelProvider::Gemini => {
       // let api_key = get_api_key_for_provider(&config.provider, &config.api_key)?;
       // Ok(Box::new(GeminiProviderClient::new(api_key)))
-      Err(anyhow!("Gemini client not yet implemented"))
+      Err(AgentError::Unsupported("Gemini client".to_string()))
     }
     ModelProvider::Ollama => {
-      let ollama_host = env::var("OLLAMA_HOST").unwrap_or_else(|_| "localhost".to_string());
-      let default_base_url = format!("http://{}:11434", ollama_host);
-      let base_url = config.base_url.as_ref().cloned().unwrap_or(default_base_url);
+      let base_url = config.base_url.as_ref().cloned().unwrap_or_else(ollama_base_url);
       // Ok(Box::new(OllamaProviderClient::new(base_url, config.model_name.clone())))
       log::info!("Ollama configured with base_url: {}", base_url);
-      Err(anyhow!("Ollama client not yet implemented"))
+      Err(AgentError::Unsupported("Ollama client".to_string()))
+    }
+  }
+}
+
+
+pub fn is_provider_implemented(provider: &ModelProvider) -> bool {
+  match provider {
+    ModelProvider::Groq => true,
+    ModelProvider::OpenAI => true,
+    ModelProvider::Anthropic => true,
+    _ => false,
+  }
+}
+
+pub fn get_api_key_for_provider(provider: &ModelProvider, app_config: &Config) -> Option<String> {
+  let key : &Option<String> = match provider {
+    ModelProvider::Anthropic => &app_config.antropic_api_key,
+    ModelProvider::DeepSeek => &app_config.deepseek_api_key,
+    ModelProvider::Gemini => &app_config.google_api_key,
+    ModelProvider::Groq => &app_config.groq_api_key,
+    ModelProvider::OpenAI => &app_config.openai_api_key,
+    ModelProvider::Ollama => return Some(String::new()), // Local server, no key required
+  };
+
+  return key.clone();
+}
+
+pub fn get_provider_readiness(provider: &ModelProvider, app_config: &Config) -> ProviderReadiness {
+  if !is_provider_implemented(provider) {
+    return ProviderReadiness::NotImplemented;
+  }
+  match get_api_key_for_provider(provider, app_config) {
+    Some(_) => ProviderReadiness::Ready,
+    None => ProviderReadiness::MissingKey,
+  }
+}
+
+pub const DEFAULT_LLM_TIMEOUT_SECS: f64 = 60.0;
+// Longest accepted LLM timeout, larger values are capped rather than overflowing Duration
+pub const MAX_LLM_TIMEOUT_SECS: f64 = 3600.0;
+
+// Per-run `llm_timeout_secs` metadata overrides the configured default, an unusable LLM_TIMEOUT_SECS falls back to 60s
+pub fn llm_timeout(metadata: &HashMap<String, Value>, app_config: &Config) -> Duration {
+  let usable = |seconds: &f64| seconds.is_finite() && *seconds > 0.0;
+  let seconds = metadata.get("llm_timeout_secs").and_then(Value::as_f64).filter(usable)
+    .or(Some(app_config.llm_timeout_secs).filter(usable))
+    .unwrap_or(DEFAULT_LLM_TIMEOUT_SECS);
+  return Duration::try_from_secs_f64(seconds.min(MAX_LLM_TIMEOUT_SECS)).unwrap_or(Duration::from_secs_f64(DEFAULT_LLM_TIMEOUT_SECS));
+}
+
+pub const DEFAULT_AGENT_TEMPERATURE: f32 = 0.5;
+// Widest range the providers accept
+pub const MAX_TEMPERATURE: f32 = 2.0;
+pub const DEFAULT_AGENT_TOP_P: f32 = 0.5;
+pub const DEFAULT_AGENT_MAX_TOKENS: u32 = 1024;
+// Fixed seed for deterministic runs, any constant works as long as it never changes between runs
+pub const DETERMINISTIC_SEED: u64 = 42;
+
+// Sampling settings for an agent's LLM call. `temperature`, `top_p` and `max_tokens` in the run metadata override the defaults,
+// `deterministic` pins temperature to 0, and any zero temperature sets DETERMINISTIC_SEED so identical inputs give identical requests
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+  pub temperature: f32,
+  pub top_p: f32,
+  pub max_tokens: u32,
+  pub seed: Option<u64>,
+}
+
+impl SamplingParams {
+  pub fn from_metadata(metadata: &HashMap<String, Value>, default_temperature: f32) -> Self {
+    let read_f32 = |key: &str| metadata.get(key).and_then(Value::as_f64).filter(|value| value.is_finite() && *value >= 0.0).map(|value| value as f32);
+    let max_tokens = metadata.get("max_tokens").and_then(Value::as_u64).filter(|tokens| *tokens > 0).map(|tokens| tokens.min(u32::MAX as u64) as u32);
+
+    let params = SamplingParams {
+      temperature: read_f32("temperature").unwrap_or(default_temperature),
+      top_p: read_f32("top_p").filter(|top_p| *top_p > 0.0 && *top_p <= 1.0).unwrap_or(DEFAULT_AGENT_TOP_P),
+      max_tokens: max_tokens.unwrap_or(DEFAULT_AGENT_MAX_TOKENS),
+      seed: None,
+    };
+    if metadata.get("deterministic").and_then(Value::as_bool).unwrap_or(false) {
+      return params.with_temperature(0.0);
+    }
+    params.with_temperature(params.temperature)
+  }
+
+  // The seed follows the temperature, only zero-temperature calls are pinned
+  pub fn with_temperature(self, temperature: f32) -> Self {
+    SamplingParams { temperature, seed: (temperature == 0.0).then_some(DETERMINISTIC_SEED), ..self }
+  }
+
+  pub fn to_config(&self, provider: ModelProvider, model_name: &str, json_mode: bool) -> LLMModelConfig {
+    LLMModelConfig {
+      provider,
+      model_name: model_name.to_string(),
+      api_key: None,
+      base_url: None,
+      temperature: Some(self.temperature),
+      max_tokens: Some(self.max_tokens),
+      top_p: Some(self.top_p),
+      json_mode,
+      seed: self.seed,
+    }
+  }
+}
+
+// Set by the `dry_run` run option, agents then skip the LLM and derive their output from the computed scores
+pub fn is_dry_run(metadata: &HashMap<String, Value>) -> bool {
+  metadata.get("dry_run").and_then(Value::as_bool).unwrap_or(false)
+}
+
+// Tried in order when the requested provider has no client yet, the first one with a configured key answers instead
+const FALLBACK_MODELS: [(ModelProvider, &str); 3] = [
+  (ModelProvider::OpenAI, "gpt-4o"),
+  (ModelProvider::Anthropic, "claude-3-5-sonnet-latest"),
+  (ModelProvider::Groq, "llama3-70b-8192"),
+];
+
+pub fn get_ready_model(config: &LLMModelConfig, app_config: &Config) -> Result<Box<dyn LLMChatter>> {
+  let config = resolve_ready_config(config, app_config)?;
+  get_model(&config).map(CircuitBreaker::wrap)
+}
+
+// An unimplemented provider is skipped for the fallback chain, a missing key is a configuration error and never skipped
+pub fn resolve_ready_config(config: &LLMModelConfig, app_config: &Config) -> Result<LLMModelConfig> {
+  let mut config = match get_provider_readiness(&config.provider, app_config) {
+    ProviderReadiness::Ready => config.clone(),
+    ProviderReadiness::MissingKey => return Err(AgentError::MissingApiKey(config.provider.api_key_var().unwrap_or_default().to_string())),
+    ProviderReadiness::NotImplemented => {
+      let fallback = FALLBACK_MODELS.iter().find(|(provider, _)| get_provider_readiness(provider, app_config) == ProviderReadiness::Ready);
+      let Some((provider, model_name)) = fallback else {
+        return Err(AgentError::Unsupported(format!("{} client", config.provider)));
+      };
+      log::warn!("No {} client yet, falling back to {}:{}", config.provider, provider, model_name);
+      LLMModelConfig { provider: provider.clone(), model_name: model_name.to_string(), api_key: None, base_url: None, ..config.clone() }
+    }
+  };
+  // Agents pass an empty key to mean "use the configured one"
+  if config.api_key.as_deref().map_or(true, |key| key.trim().is_empty()) {
+    config.api_key = get_api_key_for_provider(&config.provider, app_config);
+  }
+  Ok(config)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn metadata(value: Value) -> HashMap<String, Value> {
+    serde_json::from_value(value).unwrap()
+  }
+
+  #[test]
+  fn provider_readiness_separates_ready_missing_key_and_unimplemented() {
+    let mut config = Config::for_tests();
+    config.groq_api_key = Some("test-key".to_string());
+
+    for provider in ModelProvider::all() {
+      let expected = match provider {
+        ModelProvider::Groq => ProviderReadiness::Ready,
+        ModelProvider::OpenAI | ModelProvider::Anthropic => ProviderReadiness::MissingKey,
+        ModelProvider::DeepSeek | ModelProvider::Gemini | ModelProvider::Ollama => ProviderReadiness::NotImplemented,
+      };
+      assert_eq!(get_provider_readiness(&provider, &config), expected, "{}", provider);
+    }
+  }
+
+  #[test]
+  fn unready_providers_are_refused_with_a_clear_error() {
+    let config = Config::for_tests();
+    let sampling = SamplingParams::from_metadata(&HashMap::new(), DEFAULT_AGENT_TEMPERATURE);
+
+    let missing_key = get_ready_model(&sampling.to_config(ModelProvider::OpenAI, "gpt-4o", false), &config).err().unwrap();
+    assert!(matches!(missing_key, AgentError::MissingApiKey(ref var) if var == "OPENAI_API_KEY"), "{}", missing_key);
+
+    let unimplemented = get_ready_model(&sampling.to_config(ModelProvider::Gemini, "gemini-pro", false), &config).err().unwrap();
+    assert!(matches!(unimplemented, AgentError::Unsupported(_)), "{}", unimplemented);
+  }
+
+  #[test]
+  fn unimplemented_providers_fall_back_to_the_first_ready_one() {
+    let mut config = Config::for_tests();
+    config.antropic_api_key = Some("anthropic-key".to_string());
+    config.groq_api_key = Some("groq-key".to_string());
+    let sampling = SamplingParams::from_metadata(&HashMap::new(), DEFAULT_AGENT_TEMPERATURE);
+
+    // OpenAI has no key so the chain moves on, the sampling settings carry over
+    for provider in [ModelProvider::Gemini, ModelProvider::DeepSeek, ModelProvider::Ollama] {
+      let resolved = resolve_ready_config(&sampling.to_config(provider, "requested-model", false), &config).unwrap();
+      assert_eq!((resolved.provider, resolved.model_name.as_str(), resolved.api_key.as_deref()), (ModelProvider::Anthropic, "claude-3-5-sonnet-latest", Some("anthropic-key")));
+      assert_eq!((resolved.temperature, resolved.top_p, resolved.max_tokens), (Some(sampling.temperature), Some(sampling.top_p), Some(sampling.max_tokens)));
+    }
+
+    // A requested provider that is implemented but unkeyed is not swapped out
+    let missing_key = resolve_ready_config(&sampling.to_config(ModelProvider::OpenAI, "gpt-4o", false), &config).err().unwrap();
+    assert!(matches!(missing_key, AgentError::MissingApiKey(ref var) if var == "OPENAI_API_KEY"), "{}", missing_key);
+
+    let ready = resolve_ready_config(&sampling.to_config(ModelProvider::Groq, "llama3-8b-8192", false), &config).unwrap();
+    assert_eq!((ready.provider, ready.model_name.as_str(), ready.api_key.as_deref()), (ModelProvider::Groq, "llama3-8b-8192", Some("groq-key")));
+  }
+
+  #[test]
+  fn zero_temperature_calls_build_identical_seeded_configs() {
+    for run in [json!({ "deterministic": true, "temperature": 0.9 }), json!({ "temperature": 0 })] {
+      let config = |run: &Value| SamplingParams::from_metadata(&metadata(run.clone()), DEFAULT_AGENT_TEMPERATURE).to_config(ModelProvider::OpenAI, "gpt-4o", true);
+      let (first, second) = (config(&run), config(&run));
+      assert_eq!(first.seed, Some(DETERMINISTIC_SEED));
+      assert_eq!(first.temperature, Some(0.0));
+      assert_eq!(serde_json::to_value(&first).unwrap(), serde_json::to_value(&second).unwrap());
+    }
+    assert_eq!(SamplingParams::from_metadata(&metadata(json!({ "temperature": 0.3 })), DEFAULT_AGENT_TEMPERATURE).seed, None);
+  }
+
+  #[test]
+  fn run_llm_timeout_overrides_the_config() {
+    let config = Config::for_tests();
+    assert_eq!(llm_timeout(&metadata(json!({ "llm_timeout_secs": 12.5 })), &config), Duration::from_millis(12500));
+    assert_eq!(llm_timeout(&metadata(json!({})), &config), Duration::from_secs(60));
+    assert_eq!(llm_timeout(&metadata(json!({ "llm_timeout_secs": -1.0 })), &config), Duration::from_secs(60));
+  }
+
+  #[test]
+  fn huge_llm_timeouts_are_capped_instead_of_panicking() {
+    let mut config = Config::for_tests();
+    assert_eq!(llm_timeout(&metadata(json!({ "llm_timeout_secs": 1e20 })), &config), Duration::from_secs(3600));
+
+    config.llm_timeout_secs = 1e300;
+    assert_eq!(llm_timeout(&metadata(json!({})), &config), Duration::from_secs(3600));
+  }
+
+  #[test]
+  fn unusable_configured_timeouts_fall_back_to_the_default() {
+    let mut config = Config::for_tests();
+    for configured in [0.0, -30.0, f64::NAN, f64::INFINITY] {
+      config.llm_timeout_secs = configured;
+      assert_eq!(llm_timeout(&metadata(json!({})), &config), Duration::from_secs(60));
     }
   }
 }
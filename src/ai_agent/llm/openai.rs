@@ -0,0 +1,103 @@
+use crate::ai_agent::llm::model_provider::{llm_http_client, ChatMessage, CompletionUsage, LLMChatter, LLMModelConfig, LLMResponse};
+
+use reqwest::{header::{HeaderMap},Client, Response};
+use serde::{Deserialize, Serialize};
+use crate::ai_agent::error::{AgentError, Result};
+use async_trait::async_trait;
+use std::result::Result::Ok;
+
+
+#[derive(Serialize, Debug)]
+struct OpenAIChatRequest {
+  messages: Vec<ChatMessage>, // Reusing the generic ChatMessage from model_provider
+  model: String,              // e.g., "gpt-4o"
+  #[serde(skip_serializing_if = "Option::is_none")]
+  temperature: Option<f32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  max_tokens: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  top_p: Option<f32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  response_format: Option<ResponseFormat>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  seed: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+struct ResponseFormat {
+  #[serde(rename = "type")]
+  format_type: &'static str,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIResponseMessage {
+  content: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIChoice {
+  message: OpenAIResponseMessage,
+  #[serde(default)]
+  finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIChatResponse {
+  choices: Vec<OpenAIChoice>,
+  #[serde(default)]
+  usage: Option<CompletionUsage>,
+}
+
+pub struct OpenAIProvider {
+  openai_url : String,
+  api_key : String,
+  model_name: String,
+  client : Client
+}
+
+impl OpenAIProvider {
+
+  pub fn new(model_name: &str, api_key: Option<String>) -> Result<Self> {
+    let openai_url: String = "https://api.openai.com/v1/chat/completions".to_string();
+    let api_key : String = match api_key {
+      Some(key) => key,
+      None => std::env::var("OPENAI_API_KEY").map_err(|_| AgentError::MissingApiKey("OPENAI_API_KEY".to_string()))?,
+    };
+    Ok(OpenAIProvider {openai_url, api_key, model_name: model_name.to_string(), client: llm_http_client()})
+  }
+}
+
+#[async_trait]
+impl LLMChatter for OpenAIProvider {
+  async fn chat(&self, messages: Vec<ChatMessage>, config: &LLMModelConfig) -> Result<LLMResponse> {
+    let request: OpenAIChatRequest = OpenAIChatRequest {
+      model: self.model_name.clone(),
+      messages: messages,
+      temperature: config.temperature,
+      max_tokens: config.max_tokens,
+      top_p: config.top_p,
+      response_format: if config.json_mode { Some(ResponseFormat { format_type: "json_object" }) } else { None },
+      seed: config.seed,
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Authorization", format!("Bearer {}", self.api_key).parse().unwrap());
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    let response: Response = self.client.post(&self.openai_url).headers(headers).json(&request).send().await?;
+
+    if response.status().is_success() {
+      let openai_response : OpenAIChatResponse = response.json().await?;
+      let first : OpenAIChoice = openai_response.choices.into_iter().next().ok_or_else(|| AgentError::LlmParse("no response choices received from OpenAI".to_string()))?;
+      return Ok(LLMResponse{
+        content: first.message.content,
+        prompt_tokens: openai_response.usage.as_ref().and_then(|usage| usage.prompt_tokens),
+        completion_tokens: openai_response.usage.as_ref().and_then(|usage| usage.completion_tokens),
+        finish_reason: first.finish_reason,
+      });
+    }
+    else {
+      log::error!("Error getting response from OpenAI: {:?}", response.status());
+      return Err(AgentError::from_status("OpenAI", response.status()));
+    }
+  }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use serde_json::Value;
+
+use crate::ai_agent::llm::model_provider::ChatMessage;
+
+static PROMPT_TEMPLATES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+// Reads every `<agent>.<part>.txt` file in `dir`, e.g. `warren_buffett.system.txt`. Must run before the first prompt is rendered
+pub fn load_prompt_templates(dir: &Path) {
+  let mut templates : HashMap<String, String> = HashMap::new();
+  match fs::read_dir(dir) {
+    Ok(entries) => {
+      for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let key = match path.file_stem().and_then(|stem| stem.to_str()) {
+          Some(key) if path.extension().map_or(false, |extension| extension == "txt") => key.to_string(),
+          _ => continue,
+        };
+        match fs::read_to_string(&path) {
+          Ok(template) => { templates.insert(key, template); }
+          Err(e) => log::error!("Failed to read prompt template {}: {}", path.display(), e),
+        }
+      }
+      log::info!("Loaded {} prompt templates from {}", templates.len(), dir.display());
+    }
+    Err(_) => log::info!("No prompt templates at {}, using the embedded prompts", dir.display()),
+  }
+
+  if PROMPT_TEMPLATES.set(templates).is_err() {
+    log::warn!("Prompt templates were already loaded, ignoring {}", dir.display());
+  }
+}
+
+// Set by the `prompt_overrides` run option, template text keyed like the template files (`warren_buffett.system`)
+pub fn prompt_overrides(metadata: &HashMap<String, Value>) -> HashMap<String, String> {
+  metadata.get("prompt_overrides").cloned()
+    .and_then(|overrides| serde_json::from_value(overrides).ok())
+    .unwrap_or_default()
+}
+
+// A request override wins over a template file, which wins over the embedded default. Only the `{name}` placeholders in `vars`
+// are filled, any other braces are left as they are
+pub fn render_prompt(key: &str, default: &str, overrides: &HashMap<String, String>, vars: &[(&str, &str)]) -> String {
+  let template : &str = overrides.get(key).map(String::as_str)
+    .or_else(|| PROMPT_TEMPLATES.get().and_then(|templates| templates.get(key)).map(String::as_str))
+    .unwrap_or(default);
+
+  let mut prompt = template.to_string();
+  for (name, value) in vars {
+    prompt = prompt.replace(&format!("{{{}}}", name), value);
+  }
+  return prompt;
+}
+
+// Output format shared by every analyst that produces a bullish/bearish/neutral signal
+pub const SIGNAL_JSON_SCHEMA: &str = r#"{
+  "signal": "bullish" | "bearish" | "neutral",
+  "confidence": float between 0 and 100,
+  "reasoning": "string"
+}"#;
+
+pub struct PromptBuilder {
+  system_prompt: Option<String>,
+  instruction: String,
+  sections: Vec<(String, String)>,
+  json_schema: Option<String>,
+  json_mode: bool,
+}
+
+impl PromptBuilder {
+  pub fn new() -> Self {
+    PromptBuilder { system_prompt: None, instruction: String::new(), sections: Vec::new(), json_schema: None, json_mode: false }
+  }
+
+  pub fn with_system(mut self, prompt: &str) -> Self {
+    self.system_prompt = Some(prompt.to_string());
+    return self;
+  }
+
+  pub fn with_instruction(mut self, instruction: &str) -> Self {
+    self.instruction = instruction.to_string();
+    return self;
+  }
+
+  pub fn with_section(mut self, title: &str, content: &str) -> Self {
+    self.sections.push((title.to_string(), content.to_string()));
+    return self;
+  }
+
+  pub fn with_json_schema(mut self, schema: &str) -> Self {
+    self.json_schema = Some(schema.to_string());
+    return self;
+  }
+
+  // The provider enforces JSON output itself, so the schema goes in without the formatting boilerplate
+  pub fn with_json_mode(mut self, json_mode: bool) -> Self {
+    self.json_mode = json_mode;
+    return self;
+  }
+
+  pub fn build_user_prompt(&self) -> String {
+    let mut prompt = self.instruction.clone();
+
+    for (title, content) in &self.sections {
+      prompt.push_str(&format!("\n\n{}:\n{}", title, content));
+    }
+
+    if let Some(schema) = &self.json_schema {
+      if self.json_mode {
+        // OpenAI-compatible JSON mode requires the word "JSON" somewhere in the prompt
+        prompt.push_str(&format!("\n\nJSON schema:\n{}", schema));
+        return prompt;
+      }
+      prompt.push_str(&format!("\n\nReturn your output strictly in the following JSON format without any explanation:\n{}", schema));
+    }
+
+    return prompt;
+  }
+
+  pub fn build(self) -> Vec<ChatMessage> {
+    let mut messages = Vec::new();
+    let user_prompt = self.build_user_prompt();
+
+    if let Some(system_prompt) = self.system_prompt {
+      messages.push(ChatMessage::new("system", system_prompt));
+    }
+    messages.push(ChatMessage::new("user", user_prompt));
+
+    return messages;
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn builder_emits_system_then_user_with_sections_and_schema() {
+    let messages = PromptBuilder::new()
+      .with_system("You are an analyst.")
+      .with_instruction("Analyze AAPL.")
+      .with_section("Analysis Data", "{\"score\": 7}")
+      .with_json_schema(SIGNAL_JSON_SCHEMA)
+      .build();
+
+    let roles: Vec<&str> = messages.iter().map(|message| message.role.as_str()).collect();
+    assert_eq!(roles, vec!["system", "user"]);
+    assert_eq!(messages[0].content, "You are an analyst.");
+    assert!(messages[1].content.starts_with("Analyze AAPL.\n\nAnalysis Data:\n{\"score\": 7}"));
+    assert!(messages[1].content.ends_with(&format!("without any explanation:\n{}", SIGNAL_JSON_SCHEMA)));
+  }
+
+  #[test]
+  fn json_mode_keeps_the_schema_without_the_formatting_boilerplate() {
+    let prompt = PromptBuilder::new().with_instruction("Analyze AAPL.").with_json_schema(SIGNAL_JSON_SCHEMA).with_json_mode(true).build_user_prompt();
+    assert!(prompt.ends_with(&format!("JSON schema:\n{}", SIGNAL_JSON_SCHEMA)));
+    assert!(!prompt.contains("without any explanation"));
+  }
+
+  #[test]
+  fn builder_without_a_system_prompt_sends_only_the_user_message() {
+    let messages = PromptBuilder::new().with_instruction("Analyze AAPL.").build();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].role, "user");
+    assert_eq!(messages[0].content, "Analyze AAPL.");
+  }
+
+  #[test]
+  fn an_override_template_replaces_the_default_prompt() {
+    let vars = [("ticker", "AAPL"), ("analysis_data", "{\"score\": 7}")];
+    let default = "You are Warren Buffett, analyze {ticker}.";
+    assert_eq!(render_prompt("prompt_test.system", default, &HashMap::new(), &vars), "You are Warren Buffett, analyze AAPL.");
+
+    let overrides = HashMap::from([("prompt_test.system".to_string(), "Be brief about {ticker}: {analysis_data} {unknown}".to_string())]);
+    assert_eq!(render_prompt("prompt_test.system", default, &overrides, &vars), "Be brief about AAPL: {\"score\": 7} {unknown}");
+    // Overrides only apply to their own key
+    assert_eq!(render_prompt("prompt_test.instruction", "Decide on {ticker}.", &overrides, &vars), "Decide on AAPL.");
+  }
+
+  #[test]
+  fn overrides_are_read_from_the_run_metadata() {
+    let metadata : HashMap<String, Value> = HashMap::from([("prompt_overrides".to_string(), serde_json::json!({ "warren_buffett.system": "Custom" }))]);
+    assert_eq!(prompt_overrides(&metadata), HashMap::from([("warren_buffett.system".to_string(), "Custom".to_string())]));
+
+    let malformed : HashMap<String, Value> = HashMap::from([("prompt_overrides".to_string(), serde_json::json!(["Custom"]))]);
+    assert!(prompt_overrides(&malformed).is_empty());
+    assert!(prompt_overrides(&HashMap::new()).is_empty());
+  }
+}
@@ -3,4 +3,5 @@ pub mod data;
 pub mod llm; 
 pub mod tools; 
 pub mod utils;
-pub mod graph;
\ No newline at end of file
+pub mod graph;
+pub mod error;
\ No newline at end of file
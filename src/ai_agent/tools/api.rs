@@ -1,26 +1,54 @@
 use crate::ai_agent::data::models::{
-    CompanyFactsResponse, CompanyNews, CompanyNewsResponse, FinancialMetrics,
+    CompanyFacts, CompanyFactsResponse, CompanyNews, CompanyNewsResponse, FinancialMetrics,
     FinancialMetricsResponse, InsiderTrade, InsiderTradeResponse, LineItem, LineItemResponse,
-    Price, PriceResponse,
+    Price, PriceGap, PriceResponse,
 };
 use crate::ai_agent::data::data::{FinancialHeaderData, LineItemBodyData};
-use crate::ai_agent::data::cache::{self, Cache};
+use crate::ai_agent::data::cache::{self, Cache, Coverage};
+use crate::ai_agent::data::dates::parse_date;
+use crate::ai_agent::error::AgentError;
+use crate::ai_agent::tools::rate_limiter::RateLimiter;
 use crate::app::config::Config;
 
 
-use reqwest::{Client, Error, Response};
-use reqwest::header::{HeaderMap, HeaderValue};
-use std::sync::Mutex;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use reqwest::header::HeaderMap;
+use std::sync::{Mutex, OnceLock};
 use std::result::Result::{Ok, Err};
 use std::option::Option;
-use chrono::NaiveDate;
-use std::collections::HashMap;
+use chrono::{Datelike, NaiveDate, Utc, Weekday};
+use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 use polars::prelude::{Series, NamedFrom, DataFrame, TimeUnit, StringMethods, IntoSeries, SortMultipleOptions};
-use std::env;
-
-
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+
+// Rows requested per price page
+const PRICE_PAGE_LIMIT: usize = 5000;
+// Pool and timeout settings for the shared data API client, requests that hit REQUEST_TIMEOUT go through send_with_retry
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const POOL_MAX_IDLE_PER_HOST: usize = 16;
+
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+// One pooled client for every API instance, so a multi-ticker run reuses connections and TLS sessions
+fn http_client() -> &'static Client {
+  HTTP_CLIENT.get_or_init(|| {
+    Client::builder()
+      .connect_timeout(CONNECT_TIMEOUT)
+      .timeout(REQUEST_TIMEOUT)
+      .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+      .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+      .build()
+      .expect("Failed to build the data API HTTP client")
+  })
+}
 
 pub struct API {
   header_key : &'static str,
@@ -35,215 +63,367 @@ impl API {
     }
   }
 
-  pub async fn get_price(&self,ticker: &str,start_date: &str,end_date: &str,) -> Result<Vec<Price>, Error> {
-    let cache : &'static Mutex<Cache> = cache::get_cache();
-
-    {
-      let cache_guard = cache.lock().unwrap();
-      let result = cache_guard.get_prices(ticker);
-
-      match result {
-        Ok(data) if !data.is_empty() => {
-          let prices : Vec<Price> = data.into_iter().filter_map( |h_map|{
-            match serde_json::to_value(h_map) {
-              Ok(json_value) => match serde_json::from_value(json_value) {
-                Ok(price_struct) => Some(price_struct), 
-                Err(e) => {
-                  log::warn!("Failed to deserialize cached price item for {}: {}", ticker, e);
-                  None
-                }
-              }, 
-              Err(e) => {
-                log::warn!("Failed to convert cached HashMap to Value for {}: {}", ticker, e);
-                None
-              }
-            }
-          }).collect();
+  async fn send_with_retry(&self, request: RequestBuilder) -> Result<Response, AgentError> {
+    // Retries rate limits, server errors and connection failures, other client errors are returned as is
+    let max_retries : u32 = self.config.api_max_retries;
+    let mut attempt : u32 = 0;
 
-          if !prices.is_empty() {
-            log::info!("Returning prices for ticker {} from cache.", ticker);
-            // TODO: Optionally filter 'prices' by start_date and end_date if cache stores more than requested.
-            return Ok(prices);
+    loop {
+      self.rate_limiter().acquire().await;
+
+      let current = match request.try_clone() {
+        Some(current) => current,
+        None => return Ok(request.send().await?), // streaming bodies cannot be replayed
+      };
+
+      let retry_reason : String = match current.send().await {
+        Ok(response) => {
+          let status = response.status();
+          if !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()) || attempt >= max_retries {
+            return Ok(response);
           }
-          else {
-            log::info!("Cached data for {} was empty or failed deserialization.", ticker);
+          format!("status {}", status)
+        }
+        Err(e) => {
+          if !(e.is_connect() || e.is_timeout() || e.is_request()) || attempt >= max_retries {
+            return Err(e.into());
           }
-        },
-        Ok(_) => {
-          log::info!("Cache miss (empty data) for prices (ticker: {}).", ticker);
-        },
+          e.to_string()
+        }
+      };
+
+      let delay = self.retry_delay(attempt);
+      attempt += 1;
+      log::warn!("API request failed ({}), retrying in {:?} (attempt {} of {})", retry_reason, delay, attempt, max_retries);
+      tokio::time::sleep(delay).await;
+    }
+  }
+
+  fn rate_limiter(&self) -> &'static RateLimiter {
+    // The first API instance to send a request sets the limit for the whole process
+    RATE_LIMITER.get_or_init(|| {
+      log::info!("Limiting financial data API to {} requests per second", self.config.api_requests_per_second);
+      RateLimiter::new(self.config.api_requests_per_second)
+    })
+  }
+
+  fn retry_delay(&self, attempt: u32) -> Duration {
+    // Exponential backoff plus up to one base delay of jitter so concurrent analysts don't retry in lockstep
+    let base_ms : u64 = self.config.api_retry_base_delay_ms.max(1);
+    let backoff_ms : u64 = base_ms.saturating_mul(1u64 << attempt.min(16));
+    let jitter_ms : u64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos() as u64).unwrap_or(0) % base_ms;
+    Duration::from_millis(backoff_ms + jitter_ms)
+  }
+
+  // Every request authenticates with the key from the loaded Config, without one the call fails before anything is sent
+  fn auth_headers(&self) -> Result<HeaderMap, AgentError> {
+    match &self.config.financial_datasets_api_key {
+      Some(api_key) => Ok(FinancialHeaderData::new(api_key.clone()).to_header_map()),
+      None => Err(AgentError::MissingApiKey("FINANCIAL_DATASETS_API_KEY".to_string())),
+    }
+  }
+
+  // Reachability only, any HTTP answer counts and just connection failures or timeouts are errors. Not rate limited or retried
+  pub async fn ping(&self, timeout: Duration) -> Result<(), AgentError> {
+    http_client().get(&self.config.financial_datasets_base_url).timeout(timeout).send().await?;
+    Ok(())
+  }
+
+  fn endpoint(&self, path: &str) -> String {
+    format!("{}/{}", self.config.financial_datasets_base_url, path)
+  }
+
+  fn price_url(&self, ticker: &str, start_date: &str, end_date: &str) -> String {
+    self.endpoint(&format!("prices/?ticker={}&interval=day&interval_multiplier=1&start_date={}&end_date={}&limit={}", ticker, start_date, end_date, PRICE_PAGE_LIMIT))
+  }
+
+  pub async fn get_price(&self,ticker: &str,start_date: &str,end_date: &str,) -> Result<Vec<Price>, AgentError> {
+    let cache : &'static Mutex<Cache> = cache::get_cache();
+    let range_start : Option<NaiveDate> = parse_date(start_date);
+    let range_end : Option<NaiveDate> = parse_date(end_date);
+
+    // Nothing trades after today, so the covered window never reaches into the future
+    let (start, end) : (NaiveDate, NaiveDate) = match (range_start, range_end.map(|end| end.min(Utc::now().date_naive()))) {
+      (Some(start), Some(end)) if start <= end => (start, end),
+      _ => {
+        log::info!("Prices for {} from {} to {} fall outside the cached windows, fetching them as is.", ticker, start_date, end_date);
+        let prices : Vec<Price> = self.fetch_prices(ticker, start_date, end_date).await?;
+        self.cache_prices(ticker, &prices, None);
+        return Ok(prices);
+      }
+    };
+
+    let (cached, gaps) : (Vec<Price>, Vec<(NaiveDate, NaiveDate)>) = {
+      let cache_guard = cache.lock().unwrap();
+      let cached : Vec<Price> = match cache_guard.get_prices(ticker) {
+        Ok(data) => data.into_iter().filter_map(|h_map| self.convert_cached_item_to_model(h_map, "price", ticker)).collect(),
         Err(e) => {
-          log::error!("Error accessing cache for prices (ticker: {}): {}. Proceeding to API call.",ticker,e);
+          log::error!("Error accessing cache for prices (ticker: {}): {}. Proceeding to API call.", ticker, e);
+          Vec::new()
         }
+      };
+      let gaps = match cache_guard.get_price_coverage(ticker) {
+        Some(coverage) => coverage.gaps(start, end),
+        None => vec![(start, end)],
+      };
+      (cached, gaps)
+    };
+
+    if gaps.is_empty() {
+      log::info!("Returning prices for ticker {} from cache.", ticker);
+      return Ok(Self::prices_in_range(cached, range_start, range_end));
+    }
+
+    // Only the parts of the window the cache has not seen are fetched, then merged with what it already holds
+    let mut prices_by_time : BTreeMap<String, Price> = cached.into_iter().map(|price| (price.time.clone(), price)).collect();
+    for (gap_start, gap_end) in gaps {
+      log::info!("Fetching prices for {} from {} to {}.", ticker, gap_start, gap_end);
+      let fetched : Vec<Price> = self.fetch_prices(ticker, &gap_start.format("%Y-%m-%d").to_string(), &gap_end.format("%Y-%m-%d").to_string()).await?;
+      self.cache_prices(ticker, &fetched, Some((gap_start, gap_end)));
+      for price in fetched {
+        prices_by_time.insert(price.time.clone(), price);
       }
     }
 
-    log::info!("End date for get_price: {}", end_date);
-    let url : String = format!("https://api.financialdatasets.ai/prices/?ticker={}&interval=day&interval_multiplier=1&start_date={}&end_date=2025-06-01", ticker, start_date);
-    log::debug!("API URL: {}", url);
-    let api_key: String = self.config.financial_datasets_api_key.to_string();
-    log::debug!("Get price API key: {}", api_key);
-    let headers: HeaderMap = FinancialHeaderData::new(api_key).to_header_map();
+    return Ok(Self::prices_in_range(prices_by_time.into_values().collect(), range_start, range_end));
+  }
 
-    let client: Client = Client::new();
-    let response: Response = client.get(&url).headers(headers).send().await?;
+  async fn fetch_prices(&self, ticker: &str, start_date: &str, end_date: &str) -> Result<Vec<Price>, AgentError> {
+    let headers: HeaderMap = self.auth_headers()?;
+    let client : &Client = http_client();
+    let range_start : Option<NaiveDate> = parse_date(start_date);
+
+    // Each page is capped at PRICE_PAGE_LIMIT rows, walk end_date backward until the window is exhausted
+    let mut prices_by_time : BTreeMap<String, Price> = BTreeMap::new();
+    let mut page_end : String = end_date.to_string();
+
+    loop {
+      let url : String = self.price_url(ticker, start_date, &page_end);
+      log::debug!("API URL: {}", url);
+      let response: Response = self.send_with_retry(client.get(&url).headers(headers.clone())).await?;
+
+      if !response.status().is_success() {
+        log::error!("Error getting prices for a specific company: {} with status code: {}", ticker, response.status());
+        return Err(AgentError::from_status("financial datasets prices", response.status()));
+      }
 
-    if response.status().is_success() {
       let price_response: PriceResponse = response.json().await?;
-      let prices : Vec<Price> = price_response.prices;
+      let batch : Vec<Price> = price_response.prices;
+      let batch_len : usize = batch.len();
 
+      let oldest : Option<NaiveDate> = batch.iter().filter_map(|price| parse_date(&price.time)).min();
+      for price in batch {
+        prices_by_time.insert(price.time.clone(), price);
+      }
 
-      if !prices.is_empty() {
-        // Convert Vec<Price> to Vec<HashMap<String, Value>> for the current cache structure
-        let data_to_cache_maps: Vec<HashMap<String, Value>> = prices.iter().filter_map(|p_struct| {
-          match serde_json::to_value(p_struct) { // Price to serde_json::Value
-            Ok(json_val) => match serde_json::from_value(json_val) { // Value to HashMap
-              Ok(h_map) => Some(h_map),
-              Err(e) => {
-                log::error!("Failed to deserialize Price to HashMap for caching {}: {}", ticker, e);
-                None
-              }
-            },
-            Err(e) => {
-              log::error!("Failed to serialize Price to Value for caching {}: {}", ticker, e);
-              None
-            }
-          }
-        }).collect();
-      
-      
-        if !data_to_cache_maps.is_empty() {
-          let mut cache_guard = cache.lock().unwrap(); // Re-acquire lock for writing
-          if let Err(e) = cache_guard.set_prices(ticker, data_to_cache_maps) {
-            log::error!("Error saving prices to cache for ticker {}: {}",ticker,e);
-          } else {
-            log::info!("Prices for ticker {} saved to cache.", ticker);
-          }
-        } 
+      if batch_len < PRICE_PAGE_LIMIT {
+        break;
       }
 
-      return Ok(prices);
-    } 
-    else {
-      log::error!("Error getting prices for a specific company: {} with status code: {}", ticker, response.status());
-      return Err(response.error_for_status().unwrap_err());
+      // Stop once the oldest row reaches the window start or the page made no progress
+      let next_end : NaiveDate = match oldest.and_then(|date| date.pred_opt()) {
+        Some(date) => date,
+        None => break,
+      };
+      if range_start.map_or(false, |start| next_end < start) || parse_date(&page_end).map_or(false, |end| next_end >= end) {
+        break;
+      }
+      page_end = next_end.format("%Y-%m-%d").to_string();
     }
+
+    return Ok(prices_by_time.into_values().collect());
   }
 
+  // `window` is recorded as covered even when it held no trading days, so it is not asked for again
+  fn cache_prices(&self, ticker: &str, prices: &[Price], window: Option<(NaiveDate, NaiveDate)>) {
+    let data_to_cache_maps : Vec<HashMap<String, Value>> = prices.iter().filter_map(|price| self.convert_model_to_cache_item(price, "price", ticker)).collect();
 
-  pub async fn get_financial_metrics(&self, ticker: &str, end_date: &str, period: Option<&str>, limit: Option<i64>) -> Result<Vec<FinancialMetrics>, Error> {
-    let period: &str = period.unwrap_or("ttm");
-    let limit : i64 = limit.unwrap_or(10);
+    let mut cache_guard = cache::get_cache().lock().unwrap();
+    if !data_to_cache_maps.is_empty() {
+      if let Err(e) = cache_guard.set_prices(ticker, data_to_cache_maps) {
+        log::error!("Error saving prices to cache for ticker {}: {}", ticker, e);
+        return;
+      }
+      log::info!("Prices for ticker {} saved to cache.", ticker);
+    }
+    if let Some((start, end)) = window {
+      cache_guard.extend_price_coverage(ticker, start, end);
+    }
+  }
 
-    let cache : &'static Mutex<Cache> = cache::get_cache();
+  fn prices_in_range(prices: Vec<Price>, start: Option<NaiveDate>, end: Option<NaiveDate>) -> Vec<Price> {
+    let mut prices : Vec<Price> = prices.into_iter().filter(|price| {
+      match parse_date(&price.time) {
+        Some(date) => start.map_or(true, |start| date >= start) && end.map_or(true, |end| date <= end),
+        None => false,
+      }
+    }).collect();
+    prices.sort_by(|a, b| a.time.cmp(&b.time));
+    return prices;
+  }
 
-    {
-      let cache_guard  = cache.lock().unwrap(); 
-      let result = cache_guard.get_financial_metrics(ticker);
 
-      match result {
-        Ok(data) if !data.is_empty() => {
-          let metrics : Vec<FinancialMetrics> = data.into_iter().filter_map( |h_map|{
-            match serde_json::to_value(h_map) {
-              Ok(json_value) => match serde_json::from_value(json_value) {
-                Ok(price_struct) => Some(price_struct), 
-                Err(e) => {
-                  log::warn!("Failed to deserialize cached price item for {}: {}", ticker, e);
-                  None
-                }
-              }, 
-              Err(e) => {
-                log::warn!("Failed to convert cached HashMap to Value for {}: {}", ticker, e);
-                None
-              }
-            }
-          }).collect();
+  // The cache tracks which report dates it holds every report for, per period basis. A later end date only fetches the reports
+  // filed since, and a larger limit only the older ones it is short of
+  pub async fn get_financial_metrics(&self, ticker: &str, end_date: &str, period: Option<&str>, limit: Option<i64>) -> Result<Vec<FinancialMetrics>, AgentError> {
+    let period: &str = period.unwrap_or("ttm");
+    let limit : i64 = limit.unwrap_or(10);
+    let limit_usize : usize = limit.max(0) as usize;
 
-          if !metrics.is_empty() {
-            log::info!("Returning prices for ticker {} from cache.", ticker);
-            // TODO: Optionally filter 'prices' by start_date and end_date if cache stores more than requested.
-            return Ok(metrics);
-          }
-          else {
-            log::info!("Cached data for {} was empty or failed deserialization.", ticker);
-          }
-        }, 
-        Ok(_) => {
-          log::info!("Cache miss (empty data) for prices (ticker: {}).", ticker);
-        }, 
+    let end : NaiveDate = match parse_date(end_date) {
+      Some(end) => end.min(Utc::now().date_naive()),
+      None => return self.fetch_financial_metrics(ticker, period, limit, &format!("report_period_lte={}", end_date)).await,
+    };
+
+    let (cached, coverage) : (Vec<FinancialMetrics>, Option<Coverage>) = {
+      let cache_guard = cache::get_cache().lock().unwrap();
+      let cached : Vec<FinancialMetrics> = match cache_guard.get_financial_metrics(ticker) {
+        Ok(data) => data.into_iter()
+          .filter_map(|h_map| self.convert_cached_item_to_model::<FinancialMetrics>(h_map, "financial_metrics", ticker))
+          .filter(|metric| metric.period == period)
+          .collect(),
         Err(e) => {
-          log::error!("Error accessing cache for prices (ticker: {}): {}. Proceeding to API call.",ticker,e);
+          log::error!("Error accessing cache for financial metrics (ticker: {}): {}. Proceeding to API call.", ticker, e);
+          Vec::new()
         }
+      };
+      (cached, cache_guard.get_financial_metrics_coverage(ticker, period))
+    };
+
+    // A window that starts after the requested end date says nothing about it
+    let coverage : Coverage = match coverage.filter(|coverage| coverage.start.map_or(true, |start| start <= end)) {
+      Some(coverage) => coverage,
+      None => {
+        let metrics = self.fetch_financial_metrics(ticker, period, limit, &format!("report_period_lte={}", end)).await?;
+        let start = Self::oldest_report(&metrics, limit_usize);
+        cache::get_cache().lock().unwrap().extend_financial_metrics_coverage(ticker, period, start, end);
+        return Ok(metrics);
       }
+    };
+
+    let mut metrics : Vec<FinancialMetrics> = cached.into_iter()
+      .filter(|metric| metric.report_period <= end && coverage.start.map_or(true, |start| metric.report_period >= start))
+      .collect();
+
+    if end > coverage.end {
+      log::info!("Fetching {} financial metrics for {} reported after {}.", period, ticker, coverage.end);
+      let newer = self.fetch_financial_metrics(ticker, period, limit, &format!("report_period_gt={}&report_period_lte={}", coverage.end, end)).await?;
+      if newer.len() >= limit_usize {
+        // Enough new reports to answer on their own, the old window no longer touches the new one
+        cache::get_cache().lock().unwrap().extend_financial_metrics_coverage(ticker, period, Self::oldest_report(&newer, limit_usize), end);
+        return Ok(newer);
+      }
+      cache::get_cache().lock().unwrap().extend_financial_metrics_coverage(ticker, period, coverage.start, end);
+      metrics.extend(newer);
     }
 
-    let url : String = format!("https://api.financialdatasets.ai/financial-metrics/?ticker={}&report_period_lte={}&limit={}&period={}", ticker, end_date, limit, period);
-    let api_key: String = self.config.financial_datasets_api_key.clone();
-    let headers: HeaderMap = FinancialHeaderData::new(api_key).to_header_map();
+    let missing : usize = limit_usize.saturating_sub(metrics.len());
+    match coverage.start {
+      Some(covered_start) if missing > 0 => {
+        log::info!("Fetching {} older {} financial metrics for {} reported before {}.", missing, period, ticker, covered_start);
+        let older = self.fetch_financial_metrics(ticker, period, missing as i64, &format!("report_period_lt={}", covered_start)).await?;
+        cache::get_cache().lock().unwrap().extend_financial_metrics_coverage(ticker, period, Self::oldest_report(&older, missing), covered_start);
+        metrics.extend(older);
+      }
+      _ if end <= coverage.end => log::info!("Returning financial metrics for ticker {} from cache.", ticker),
+      _ => {}
+    }
 
-    let client : Client = Client::new();
+    metrics.sort_by(|a, b| b.report_period.cmp(&a.report_period));
+    metrics.truncate(limit_usize);
+    return Ok(metrics);
+  }
 
-    let response : Response = client.get(&url).headers(headers).send().await?;
+  // Oldest report a full page reaches, None when the page came back short because nothing older exists
+  fn oldest_report(metrics: &[FinancialMetrics], requested: usize) -> Option<NaiveDate> {
+    if metrics.len() < requested {
+      return None;
+    }
+    metrics.iter().map(|metric| metric.report_period).min()
+  }
 
-    if response.status().is_success() {
-      let metric_response : FinancialMetricsResponse = response.json().await?;
-      let metrics: Vec<FinancialMetrics> = metric_response.financial_metrics;
-
-      if !metrics.is_empty() {
-        // Convert Vec<Price> to Vec<HashMap<String, Value>> for the current cache structure
-        let data_to_cache_maps: Vec<HashMap<String, Value>> = metrics.iter().filter_map(|p_struct| {
-          match serde_json::to_value(p_struct) { // Price to serde_json::Value
-            Ok(json_val) => match serde_json::from_value(json_val) { // Value to HashMap
-              Ok(h_map) => Some(h_map),
-              Err(e) => {
-                log::error!("Failed to deserialize Price to HashMap for caching {}: {}", ticker, e);
-                None
-              }
-            },
-            Err(e) => {
-              log::error!("Failed to serialize Price to Value for caching {}: {}", ticker, e);
-              None
-            }
-          }
-        }).collect();
-      
-      
-        if !data_to_cache_maps.is_empty() {
-          let mut cache_guard = cache.lock().unwrap(); // Re-acquire lock for writing
-          if let Err(e) = cache_guard.set_financial_metrics(ticker, data_to_cache_maps) {
-            log::error!("Error saving prices to cache for ticker {}: {}",ticker,e);
-          } else {
-            log::info!("Prices for ticker {} saved to cache.", ticker);
-          }
-        } 
-      }
+  async fn fetch_financial_metrics(&self, ticker: &str, period: &str, limit: i64, report_period_filter: &str) -> Result<Vec<FinancialMetrics>, AgentError> {
+    let url : String = self.endpoint(&format!("financial-metrics/?ticker={}&{}&limit={}&period={}", ticker, report_period_filter, limit, period));
+    let headers: HeaderMap = self.auth_headers()?;
+
+    let client : &Client = http_client();
 
-      return Ok(metrics);
+    let response : Response = self.send_with_retry(client.get(&url).headers(headers)).await?;
+
+    if !response.status().is_success() {
+      log::error!("Error getting financial metrics for a specific company: {}", ticker);
+      return Err(AgentError::from_status("financial datasets metrics", response.status()));
     }
-    else {
-      log::error!("Error getting prices for a specific company: {}", ticker);
-      return Err(response.error_for_status().unwrap_err());
+
+    let metric_response : FinancialMetricsResponse = response.json().await?;
+    let metrics: Vec<FinancialMetrics> = metric_response.financial_metrics;
+
+    let data_to_cache_maps : Vec<HashMap<String, Value>> = metrics.iter().filter_map(|metric| self.convert_model_to_cache_item(metric, "financial_metrics", ticker)).collect();
+    if !data_to_cache_maps.is_empty() {
+      let mut cache_guard = cache::get_cache().lock().unwrap();
+      if let Err(e) = cache_guard.set_financial_metrics(ticker, data_to_cache_maps) {
+        log::error!("Error saving financial metrics to cache for ticker {}: {}", ticker, e);
+      } else {
+        log::info!("Financial metrics for ticker {} saved to cache.", ticker);
+      }
     }
 
+    return Ok(metrics);
   }
 
 
-  pub async fn search_line_items(&self, ticker: &str, line_items: Vec<String>, end_date : &str, period: Option<&str>, limit: Option<i64>) -> Result<Vec<LineItem>, Error> {
+  pub async fn search_line_items(&self, ticker: &str, line_items: Vec<String>, end_date : &str, period: Option<&str>, limit: Option<i64>) -> Result<Vec<LineItem>, AgentError> {
     let period: &str = period.unwrap_or("ttm");
     let limit : i64 = limit.unwrap_or(10);
 
     let limit_usize : usize = limit as usize;
 
-    let url : &'static str = "https://api.financialdatasets.ai/financials/search/line-items";
+    let cache : &'static Mutex<Cache> = cache::get_cache();
+    let end_date_parsed : Option<NaiveDate> = parse_date(end_date);
+
+    {
+      let cache_guard = cache.lock().unwrap_or_else(|p| p.into_inner());
+      match cache_guard.get_line_items(ticker) {
+        Ok(data) if !data.is_empty() => {
+          let mut cached_items : Vec<LineItem> = data.into_iter().filter_map(|h_map| {
+            match serde_json::to_value(h_map).and_then(serde_json::from_value::<LineItem>) {
+              Ok(item) => Some(item),
+              Err(e) => {
+                log::warn!("Failed to deserialize cached line item for {}: {}", ticker, e);
+                None
+              }
+            }
+          }).filter(|item| item.period == period && end_date_parsed.map_or(true, |end| item.report_period <= end)).collect();
+
+          cached_items.sort_by(|a, b| b.report_period.cmp(&a.report_period));
+          cached_items.truncate(limit_usize);
 
-    let api_key: String = self.config.financial_datasets_api_key.clone();
-    let headers: HeaderMap = FinancialHeaderData::new(api_key).to_header_map();
+          // A period missing any requested field means the cache only holds part of the set
+          let complete = !cached_items.is_empty() && cached_items.iter().all(|item| line_items.iter().all(|name| item.extra.contains_key(name)));
+          if complete {
+            log::info!("Returning line items for ticker {} from cache.", ticker);
+            return Ok(cached_items);
+          }
+          log::info!("Cached line items for {} do not cover all requested fields, fetching from API.", ticker);
+        },
+        Ok(_) => {
+          log::info!("Cache miss (empty data) for line items (ticker: {}).", ticker);
+        },
+        Err(e) => {
+          log::error!("Error accessing cache for line items (ticker: {}): {}. Proceeding to API call.", ticker, e);
+        }
+      }
+    }
 
-    let body : LineItemBodyData = LineItemBodyData { tickers: vec![ticker.to_string()], line_items:line_items, end_date: end_date.to_string(), period: period.to_string(), limit: limit };
+    let url : String = self.endpoint("financials/search/line-items");
 
-    let client : Client = Client::new(); 
+    let headers: HeaderMap = self.auth_headers()?;
 
-    let response : Response = client.post(url).headers(headers).json(&body).send().await?;
+    let body : LineItemBodyData = LineItemBodyData { tickers: vec![ticker.to_string()], line_items:line_items.clone(), end_date: end_date.to_string(), period: period.to_string(), limit: limit };
+
+    let client : &Client = http_client();
+
+    let response : Response = self.send_with_retry(client.post(&url).headers(headers).json(&body)).await?;
 
     if response.status().is_success() {
       let line_response : LineItemResponse = response.json().await?; 
@@ -252,16 +432,36 @@ impl API {
       }
 
       let limited_results: Vec<LineItem> = line_response.search_results.into_iter().take(limit_usize).collect();
+
+      let data_to_cache_maps: Vec<HashMap<String, Value>> = limited_results.iter().filter_map(|item| {
+        match serde_json::to_value(item).and_then(serde_json::from_value) {
+          Ok(h_map) => Some(h_map),
+          Err(e) => {
+            log::error!("Failed to convert LineItem to HashMap for caching {}: {}", ticker, e);
+            None
+          }
+        }
+      }).collect();
+
+      if !data_to_cache_maps.is_empty() {
+        let mut cache_guard = cache.lock().unwrap_or_else(|p| p.into_inner());
+        if let Err(e) = cache_guard.set_line_items(ticker, data_to_cache_maps) {
+          log::error!("Error saving line items to cache for ticker {}: {}", ticker, e);
+        } else {
+          log::info!("Line items for ticker {} saved to cache.", ticker);
+        }
+      }
+
       return Ok(limited_results);
     }
     else {
       log::error!("Error searching line items for ticker {}: API request failed with status {}",ticker,response.status());
-      Err(response.error_for_status().unwrap_err())
+      Err(AgentError::from_status("financial datasets line items", response.status()))
     }
     
   }
 
-  pub async fn get_insider_trade(&self, ticker: &str, end_date : &str, start_date: Option<&str>, limit: i64 ) -> Result<Vec<InsiderTrade>, Error> {
+  pub async fn get_insider_trade(&self, ticker: &str, end_date : &str, start_date: Option<&str>, limit: i64 ) -> Result<Vec<InsiderTrade>, AgentError> {
     let target_end_date = match NaiveDate::parse_from_str(end_date, "%Y-%m-%d") {
       Ok(d) => d,
       Err(e) => {
@@ -290,8 +490,7 @@ impl API {
 
           // Filter by date range
           trades.retain(|trade| {
-            let trade_date_str = trade.transaction_date.as_deref().or(trade.filing_date.as_deref()) .unwrap_or_default(); 
-            if let Ok(trade_date) = NaiveDate::parse_from_str(trade_date_str.split('T').next().unwrap_or(""), "%Y-%m-%d") {
+            if let Some(trade_date) = trade.transaction_date.or(trade.filing_date) {
                 let after_start = target_start_date_opt.map_or(true, |start| trade_date >= start);
                 let before_end = trade_date <= target_end_date;
                 return after_start && before_end;
@@ -301,9 +500,9 @@ impl API {
 
           // Sort
           trades.sort_by(|a, b| {
-            let date_a = a.transaction_date.as_deref().or(a.filing_date.as_deref()).unwrap_or_default();
-            let date_b = b.transaction_date.as_deref().or(b.filing_date.as_deref()).unwrap_or_default();
-            date_b.cmp(date_a) // reverse=True
+            let date_a = a.transaction_date.or(a.filing_date);
+            let date_b = b.transaction_date.or(b.filing_date);
+            date_b.cmp(&date_a) // reverse=True
           });
 
           if !trades.is_empty() {
@@ -318,38 +517,29 @@ impl API {
     log::info!("Fetching insider trades for {} from API.", ticker);
     let mut all_fetched_trades: Vec<InsiderTrade> = Vec::new();
     let mut current_page_end_date_str: String = end_date.to_string();
-    let client: Client = Client::new();
+    let client : &Client = http_client();
 
     loop {
-      let mut url = format!(
-        "https://api.financialdatasets.ai/insider-trades/?ticker={}&filing_date_lte={}&limit={}",
+      let mut url = self.endpoint(&format!(
+        "insider-trades/?ticker={}&filing_date_lte={}&limit={}",
         ticker, current_page_end_date_str, limit
-      );
+      ));
       if let Some(start_date_val_str) = start_date {
         url.push_str(&format!("&filing_date_gte={}", start_date_val_str));
       }
 
-      let mut headers = HeaderMap::new();
-      if let Ok(api_key) = env::var("FINANCIAL_DATASETS_API_KEY") {
-        if let Ok(header_val) = HeaderValue::from_str(&api_key) {
-          headers.insert("X-API-KEY", header_val);
-        }
-      }
+      let headers: HeaderMap = self.auth_headers()?;
 
       log::debug!("Fetching insider trades from URL: {}", url);
-      let response = client.get(&url).headers(headers).send().await?;
-
-      let mut current_batch_trades: Vec<InsiderTrade> = Vec::new(); 
+      let response = self.send_with_retry(client.get(&url).headers(headers)).await?;
 
-      if response.status().is_success() {
-        let response_model: InsiderTradeResponse = response.json().await?;
-        current_batch_trades = response_model.insider_trades;
+      if !response.status().is_success() {
+        log::error!("Error getting insider trades for ticker {}: API request failed with status {}", ticker, response.status());
+        return Err(AgentError::from_status("financial datasets insider trades", response.status()));
       }
 
-      else if !response.status().is_success() {
-        let err_text = response.text().await.unwrap_or_default();
-        log::error!("API error for insider trades ({}): - {}", ticker, err_text);
-      }
+      let response_model: InsiderTradeResponse = response.json().await?;
+      let current_batch_trades: Vec<InsiderTrade> = response_model.insider_trades;
 
       if current_batch_trades.is_empty() {
         break; // No more data
@@ -363,10 +553,10 @@ impl API {
       }
 
       // Update end_date to the oldest filing date from current batch for next iteration
-      if let Some(oldest_trade_in_batch) = current_batch_trades.iter().min_by_key(|t| t.filing_date.as_deref().unwrap_or_default()) {
-        if let Some(filing_date_str) = &oldest_trade_in_batch.filing_date {
-          current_page_end_date_str = filing_date_str.split('T').next().unwrap_or("").to_string();
-          if let (Some(start_date_val), Ok(current_page_end_naive_date)) = (target_start_date_opt, NaiveDate::parse_from_str(&current_page_end_date_str, "%Y-%m-%d")) {
+      if let Some(oldest_trade_in_batch) = current_batch_trades.iter().filter(|t| t.filing_date.is_some()).min_by_key(|t| t.filing_date) {
+        if let Some(filing_date) = oldest_trade_in_batch.filing_date {
+          current_page_end_date_str = filing_date.format("%Y-%m-%d").to_string();
+          if let (Some(start_date_val), current_page_end_naive_date) = (target_start_date_opt, filing_date) {
             if current_page_end_naive_date <= start_date_val {
               break; // Reached or passed the overall start_date
             }
@@ -383,14 +573,20 @@ impl API {
       }
     }
 
-    if all_fetched_trades.is_empty() {
-      return Ok(Vec::new());
-    } else {
-      return Ok(all_fetched_trades);
+    let data_to_cache: Vec<HashMap<String, Value>> = all_fetched_trades.iter().filter_map(|trade| self.convert_model_to_cache_item(trade, "InsiderTrade", ticker)).collect();
+
+    if !data_to_cache.is_empty() {
+      let mut cache_guard = cache_mutex.lock().unwrap_or_else(|p| p.into_inner());
+      if let Err(e) = cache_guard.set_insider_trades(ticker, data_to_cache) {
+        log::error!("Failed to cache insider trades for {}: {}", ticker, e);
+      } else {
+        log::info!("Cached insider trades for {}.", ticker);
+      }
     }
+    Ok(all_fetched_trades)
   } 
 
-  pub async fn get_company_news(&self,ticker: &str,end_date_str: &str,start_date_opt: Option<&str>,limit_per_page: i64,) -> Result<Vec<CompanyNews>, Error> {
+  pub async fn get_company_news(&self,ticker: &str,end_date_str: &str,start_date_opt: Option<&str>,limit_per_page: i64,) -> Result<Vec<CompanyNews>, AgentError> {
     let target_end_date = match NaiveDate::parse_from_str(end_date_str, "%Y-%m-%d") {
       Ok(d) => d,
       Err(e) => {
@@ -418,12 +614,10 @@ impl API {
 
             // Filter by date range
             news_items.retain(|news| {
-              if let Ok(news_date) = NaiveDate::parse_from_str(news.date.split('T').next().unwrap_or(""), "%Y-%m-%d") {
-                let after_start = target_start_date_opt.map_or(true, |start| news_date >= start);
-                let before_end = news_date <= target_end_date;
-                return after_start && before_end;
-              }
-              false
+              let news_date = news.date.date_naive();
+              let after_start = target_start_date_opt.map_or(true, |start| news_date >= start);
+              let before_end = news_date <= target_end_date;
+              after_start && before_end
             });
 
             // Sort
@@ -441,40 +635,29 @@ impl API {
     log::info!("Fetching company news for {} from API.", ticker);
     let mut all_fetched_news: Vec<CompanyNews> = Vec::new();
     let mut current_page_end_date_str: String = end_date_str.to_string(); // API uses 'end_date' for news
-    let client = Client::new();
+    let client : &Client = http_client();
 
     loop {
-      let mut url = format!(
-        "https://api.financialdatasets.ai/news/?ticker={}&end_date={}&limit={}", // API endpoint for news
+      let mut url = self.endpoint(&format!(
+        "news/?ticker={}&end_date={}&limit={}", // API endpoint for news
         ticker, current_page_end_date_str, limit_per_page
-      );
+      ));
       if let Some(start_date_val_str) = start_date_opt {
         url.push_str(&format!("&start_date={}", start_date_val_str)); // API uses 'start_date'
       }
 
-      let mut headers = HeaderMap::new();
-        if let Ok(api_key) = env::var("FINANCIAL_DATASETS_API_KEY") {
-          if let Ok(header_val) = HeaderValue::from_str(&api_key) {
-            headers.insert("X-API-KEY", header_val);
-          }
-      }
+      let headers: HeaderMap = self.auth_headers()?;
 
       log::debug!("Fetching company news from URL: {}", url);
-      let response = client.get(&url).headers(headers).send().await?;
+      let response = self.send_with_retry(client.get(&url).headers(headers)).await?;
 
-      let mut current_batch_news : Vec<CompanyNews> = Vec::new();
-
-      if response.status().is_success() { 
-        let response_model: CompanyNewsResponse = response.json().await?;
-        current_batch_news = response_model.news;
+      if !response.status().is_success() {
+        log::error!("Error getting company news for ticker {}: API request failed with status {}", ticker, response.status());
+        return Err(AgentError::from_status("financial datasets news", response.status()));
       }
 
-      else {
-        let err_text = response.text().await.unwrap_or_default();
-        log::error!("API error for company news ({}):  - {}", ticker, err_text);
-      }
-
-      
+      let response_model: CompanyNewsResponse = response.json().await?;
+      let current_batch_news: Vec<CompanyNews> = response_model.news;
 
       all_fetched_news.extend(current_batch_news.clone());
 
@@ -482,16 +665,18 @@ impl API {
           break;
       }
       
-      if let Some(oldest_news_in_batch) = current_batch_news.iter().min_by_key(|n| &n.date) {
-        current_page_end_date_str = oldest_news_in_batch.date.split('T').next().unwrap_or("").to_string();
-        if let (Some(start_date_val), Ok(current_page_end_naive_date)) = (target_start_date_opt, NaiveDate::parse_from_str(&current_page_end_date_str, "%Y-%m-%d")) {
-            if current_page_end_naive_date <= start_date_val {
-                break;
-            }
-        } else if target_start_date_opt.is_none() {
-            if current_page_end_date_str.is_empty() { break; }
-        }
-      } else {
+      // Compare parsed timestamps rather than the raw strings, and stop once a page no longer moves the window back,
+      // a full page published on the page's own end date would otherwise be fetched forever
+      let current_page_end_date = NaiveDate::parse_from_str(&current_page_end_date_str, "%Y-%m-%d").unwrap_or(target_end_date);
+      let oldest_news_date = match current_batch_news.iter().map(|n| n.date).min() {
+        Some(oldest) => oldest.date_naive(),
+        None => break,
+      };
+      if oldest_news_date >= current_page_end_date {
+        break;
+      }
+      current_page_end_date_str = oldest_news_date.format("%Y-%m-%d").to_string();
+      if target_start_date_opt.map_or(false, |start_date| oldest_news_date <= start_date) {
         break;
       }
     }
@@ -514,7 +699,7 @@ impl API {
     Ok(all_fetched_news)
   }
 
-  pub async fn get_market_cap(&self,ticker: &str,end_date: &str,) -> Result<Option<f64>, Error> { // "YYYY-MM-DD" // Market cap can be None
+  pub async fn get_market_cap(&self,ticker: &str,end_date: &str,) -> Result<Option<f64>, AgentError> { // "YYYY-MM-DD" // Market cap can be None
     let target_end_date = match NaiveDate::parse_from_str(end_date, "%Y-%m-%d") {
       Ok(d) => d,
       Err(e) => {
@@ -523,35 +708,94 @@ impl API {
       }
     };
     
+    {
+      let cache_guard = cache::get_cache().lock().unwrap_or_else(|p| p.into_inner());
+      if let Ok(Some(cached)) = cache_guard.get_market_cap(ticker, end_date) {
+        log::info!("Returning market cap for {} as of {} from cache.", ticker, end_date);
+        return Ok(cached.get("market_cap").and_then(Value::as_f64));
+      }
+    }
+
+    let market_cap = self.fetch_market_cap(ticker, target_end_date).await?;
+
+    let mut cache_guard = cache::get_cache().lock().unwrap_or_else(|p| p.into_inner());
+    let entry : HashMap<String, Value> = HashMap::from([
+      ("date".to_string(), Value::from(end_date)),
+      ("market_cap".to_string(), market_cap.map_or(Value::Null, Value::from)),
+    ]);
+    if let Err(e) = cache_guard.set_market_cap(ticker, entry) {
+      log::error!("Failed to cache market cap for {}: {}", ticker, e);
+    }
+    return Ok(market_cap);
+  }
+
+  async fn fetch_market_cap(&self, ticker: &str, target_end_date: NaiveDate) -> Result<Option<f64>, AgentError> {
+    let end_date : String = target_end_date.format("%Y-%m-%d").to_string();
     let today = chrono::Local::now().date_naive();
 
     if target_end_date == today {
       log::info!("Fetching market cap for {} from company facts (today's date).", ticker);
-      let url = format!("https://api.financialdatasets.ai/company/facts/?ticker={}", ticker);
-      
-      let mut headers = HeaderMap::new();
-      if let Ok(api_key) = env::var("FINANCIAL_DATASETS_API_KEY") {
-        if let Ok(header_val) = HeaderValue::from_str(&api_key) {
-          headers.insert("X-API-KEY", header_val);
+      match self.get_company_facts(ticker).await {
+        Ok(CompanyFacts { market_cap: Some(market_cap), .. }) => return Ok(Some(market_cap)),
+        Ok(_) => log::info!("Company facts for {} carry no market cap, falling back to financial metrics.", ticker),
+        Err(e @ AgentError::UpstreamHttp { .. }) | Err(e @ AgentError::RateLimited(_)) | Err(e @ AgentError::DataUnavailable(_)) => log::error!("Error fetching company facts for market cap ({}): {}", ticker, e),
+        Err(e) => return Err(e),
+      }
+    }
+
+    // Historical dates use the market cap of the closest report period at or before end_date
+    let metrics : Vec<FinancialMetrics> = self.get_financial_metrics(ticker, &end_date, Some("ttm"), Some(10)).await?;
+    let market_cap = metrics.iter()
+      .filter(|metric| metric.report_period <= target_end_date)
+      .filter_map(|metric| metric.market_cap.map(|market_cap| (metric.report_period, market_cap)))
+      .max_by_key(|(report_period, _)| *report_period)
+      .map(|(_, market_cap)| market_cap);
+
+    if market_cap.is_none() {
+      log::info!("No market cap available for {} as of {}.", ticker, end_date);
+    }
+    return Ok(market_cap);
+  }
+
+  pub async fn get_company_facts(&self, ticker: &str) -> Result<CompanyFacts, AgentError> {
+    {
+      let cache_guard = cache::get_cache().lock().unwrap_or_else(|p| p.into_inner());
+      if let Ok(Some(cached)) = cache_guard.get_company_facts(ticker) {
+        if let Some(facts) = self.convert_cached_item_to_model::<CompanyFacts>(cached, "CompanyFacts", ticker) {
+          log::info!("Returning company facts for {} from cache.", ticker);
+          return Ok(facts);
         }
       }
+    }
 
-      let client: Client = Client::new();
-      let response: Response = client.get(&url).headers(headers).send().await?;
+    let facts : CompanyFacts = self.fetch_company_facts(ticker).await?;
+
+    if let Ok(Value::Object(map)) = serde_json::to_value(&facts) {
+      let mut cache_guard = cache::get_cache().lock().unwrap_or_else(|p| p.into_inner());
+      if let Err(e) = cache_guard.set_company_facts(ticker, map.into_iter().collect()) {
+        log::error!("Error saving company facts to cache for ticker {}: {}", ticker, e);
+      }
+    }
+    return Ok(facts);
+  }
 
-      if response.status().is_success() {
-        // Assuming CompanyFactsResponse and CompanyFacts models are defined
-        let facts_response: CompanyFactsResponse = response.json().await?;
-        return Ok(facts_response.company_facts.market_cap);
-      } 
-      else {
-        log::error!("Error fetching company facts for market cap ({}): {}",ticker, response.status());
-        // Fall through to try financial_metrics if appropriate, or return error/None
-        // For now, let's return None on API error here to match Python's print then None
-        return Ok(None);
+  // Always goes to the API, also used to check that the financial datasets key works
+  pub async fn fetch_company_facts(&self, ticker: &str) -> Result<CompanyFacts, AgentError> {
+    let url = self.endpoint(&format!("company/facts/?ticker={}", ticker));
+    let headers: HeaderMap = self.auth_headers()?;
+    let client : &Client = http_client();
+    let response: Response = self.send_with_retry(client.get(&url).headers(headers)).await?;
+
+    if !response.status().is_success() {
+      log::error!("Error fetching company facts for {}: {}", ticker, response.status());
+      if response.status() == StatusCode::NOT_FOUND {
+        return Err(AgentError::DataUnavailable(format!("company facts for {}", ticker)));
       }
+      return Err(AgentError::from_status("financial datasets company facts", response.status()));
     }
-    return Ok(None);
+
+    let facts : CompanyFacts = response.json::<CompanyFactsResponse>().await?.company_facts;
+    return Ok(facts);
   }
 
 
@@ -609,6 +853,108 @@ impl API {
   }
 
 
+  fn weekdays_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    // Trading days strictly between two dates, ignoring exchange holidays
+    let mut count = 0;
+    let mut day = from.succ_opt();
+    while let Some(current) = day {
+      if current >= to {
+        break;
+      }
+      if current.weekday() != Weekday::Sat && current.weekday() != Weekday::Sun {
+        count += 1;
+      }
+      day = current.succ_opt();
+    }
+    return count;
+  }
+
+  fn df_to_prices(&self, df: &DataFrame) -> anyhow::Result<Vec<Price>> {
+    let opens = df.column("open")?.f64()?;
+    let closes = df.column("close")?.f64()?;
+    let highs = df.column("high")?.f64()?;
+    let lows = df.column("low")?.f64()?;
+    let volumes = df.column("volume")?.i64()?;
+    let times = df.column("time")?.str()?;
+
+    let mut prices: Vec<Price> = Vec::with_capacity(df.height());
+    for i in 0..df.height() {
+      prices.push(Price {
+        open: opens.get(i).unwrap_or(0.0),
+        close: closes.get(i).unwrap_or(0.0),
+        high: highs.get(i).unwrap_or(0.0),
+        low: lows.get(i).unwrap_or(0.0),
+        volume: volumes.get(i).unwrap_or(0),
+        time: times.get(i).unwrap_or("").to_string(),
+      });
+    }
+    return Ok(prices);
+  }
+
+  pub fn detect_price_gaps(&self, df: &DataFrame) -> anyhow::Result<Vec<PriceGap>> {
+    // A single missing weekday is usually an exchange holiday, anything longer is a data gap
+    let times = df.column("time")?.str()?;
+    let dates: Vec<NaiveDate> = times.into_iter().flatten()
+      .filter_map(|time| NaiveDate::parse_from_str(time.get(..10).unwrap_or(time), "%Y-%m-%d").ok())
+      .collect();
+
+    let mut gaps: Vec<PriceGap> = Vec::new();
+    for window in dates.windows(2) {
+      let missing = Self::weekdays_between(window[0], window[1]);
+      if missing > 1 {
+        gaps.push(PriceGap {
+          from: window[0].format("%Y-%m-%d").to_string(),
+          to: window[1].format("%Y-%m-%d").to_string(),
+          missing_trading_days: missing,
+        });
+      }
+    }
+    return Ok(gaps);
+  }
+
+  pub fn fill_price_gaps(&self, df: &DataFrame) -> anyhow::Result<DataFrame> {
+    // Forward-fill the last close into every missing weekday of a detected gap
+    let gaps = self.detect_price_gaps(df)?;
+    if gaps.is_empty() {
+      return Ok(df.clone());
+    }
+
+    let mut prices = self.df_to_prices(df)?;
+    let mut filled: Vec<Price> = Vec::new();
+
+    for gap in &gaps {
+      let from = NaiveDate::parse_from_str(&gap.from, "%Y-%m-%d")?;
+      let to = NaiveDate::parse_from_str(&gap.to, "%Y-%m-%d")?;
+      let previous = match prices.iter().find(|p| p.time.starts_with(&gap.from)) {
+        Some(price) => price.clone(),
+        None => continue,
+      };
+      let time_suffix = previous.time.get(10..).unwrap_or("").to_string();
+
+      let mut day = from.succ_opt();
+      while let Some(current) = day {
+        if current >= to {
+          break;
+        }
+        if current.weekday() != Weekday::Sat && current.weekday() != Weekday::Sun {
+          filled.push(Price {
+            open: previous.close,
+            close: previous.close,
+            high: previous.close,
+            low: previous.close,
+            volume: 0,
+            time: format!("{}{}", current.format("%Y-%m-%d"), time_suffix),
+          });
+        }
+        day = current.succ_opt();
+      }
+    }
+
+    log::info!("Forward-filled {} missing trading days across {} price gaps", filled.len(), gaps.len());
+    prices.extend(filled);
+    return self.prices_to_df(prices);
+  }
+
   pub async fn get_price_data(&self, ticker: &str, start_date: &str, end_date: &str ) -> anyhow::Result<DataFrame> {
     let prices: Vec<Price> =  self.get_price(ticker, start_date, &end_date).await?;
 
@@ -617,9 +963,9 @@ impl API {
     return Ok(df);
   }
 
-  pub fn convert_model_to_cache_item(&self, news: &CompanyNews, _type_tag: &str, _ticker: &str ) -> Option<HashMap<String, Value>> {    // unused, but keeps the interface consistent
+  pub fn convert_model_to_cache_item<T: Serialize>(&self, item: &T, _type_tag: &str, _ticker: &str ) -> Option<HashMap<String, Value>> {
     // 1) Serialize the model to a serde_json::Value
-    let val = serde_json::to_value(news).ok()?;
+    let val = serde_json::to_value(item).ok()?;
     // 2) Expect it to be an Object and clone into a HashMap
     val.as_object()
       .cloned()
@@ -627,7 +973,6 @@ impl API {
   }
 
   pub fn convert_cached_item_to_model<T: DeserializeOwned>(&self, map: HashMap<String, Value>, _type_tag: &str, _ticker: &str) -> Option<T> {
-    // unused, but keeps your signature consistent
     // Rebuild a serde_json::Value::Object, then
     // attempt to deserialize into your model
     let json_map: serde_json::Map<String, Value> = map.into_iter().collect();
@@ -637,4 +982,257 @@ impl API {
     serde_json::from_value(value).ok()
   }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai_agent::data::models::ReportPeriod;
+  use crate::ai_agent::tools::stub_server::StubServer;
+
+  fn stub_api(server: &StubServer) -> API {
+    let mut config = Config::for_tests();
+    config.financial_datasets_api_key = Some("test".to_string());
+    config.financial_datasets_base_url = server.base_url.clone();
+    return API::new(config);
+  }
+
+  fn price(date: &str, close: f64) -> Price {
+    Price { open: close, close, high: close, low: close, volume: 1000, time: format!("{}T00:00:00", date) }
+  }
+
+  #[test]
+  fn price_url_uses_the_requested_window() {
+    let url = API::new(Config::for_tests()).price_url("AAPL", "2023-01-03", "2023-03-31");
+    assert!(url.starts_with("http://127.0.0.1:9/prices/?ticker=AAPL&"));
+    assert!(url.contains("&start_date=2023-01-03&end_date=2023-03-31&"));
+    assert!(!url.contains("2025-06-01"));
+  }
+
+  #[test]
+  fn multi_day_price_gaps_are_detected_and_filled() {
+    let api = API::new(Config::for_tests());
+    // Wednesday to Friday are missing, the lone Wednesday after is treated as a holiday
+    let prices = vec![price("2024-06-03", 10.0), price("2024-06-04", 11.0), price("2024-06-10", 12.0), price("2024-06-11", 13.0), price("2024-06-13", 14.0)];
+    let df = api.prices_to_df(prices).unwrap();
+
+    let gaps = api.detect_price_gaps(&df).unwrap();
+    assert_eq!(gaps.len(), 1);
+    assert_eq!((gaps[0].from.as_str(), gaps[0].to.as_str(), gaps[0].missing_trading_days), ("2024-06-04", "2024-06-10", 3));
+
+    let filled = api.fill_price_gaps(&df).unwrap();
+    assert_eq!(filled.height(), 8);
+    assert!(api.detect_price_gaps(&filled).unwrap().is_empty());
+    let filled_prices = api.df_to_prices(&filled).unwrap();
+    let friday = filled_prices.iter().find(|p| p.time.starts_with("2024-06-07")).unwrap();
+    assert_eq!(friday.close, 11.0);
+    assert_eq!(friday.volume, 0);
+  }
+
+  fn metric(ticker: &str, report_period: &str, market_cap: f64) -> Value {
+    serde_json::json!({ "ticker": ticker, "report_period": report_period, "period": "ttm", "currency": "USD", "market_cap": market_cap })
+  }
+
+  #[tokio::test]
+  async fn each_period_reaches_the_api_in_the_query_and_body() {
+    let server = StubServer::start(|request| match request.method.as_str() {
+      "POST" => (200, serde_json::json!({ "search_results": [] }).to_string()),
+      _ => (200, serde_json::json!({ "financial_metrics": [] }).to_string()),
+    });
+    let api = stub_api(&server);
+
+    for period in ReportPeriod::ALL {
+      let ticker = format!("PERIOD{}", period.as_str().to_uppercase());
+      api.get_financial_metrics(&ticker, "2024-06-30", Some(period.as_str()), Some(4)).await.unwrap();
+      api.search_line_items(&ticker, vec!["net_income".to_string()], "2024-06-30", Some(period.as_str()), Some(4)).await.unwrap();
+    }
+
+    let requests = server.requests();
+    for period in ReportPeriod::ALL {
+      let ticker = format!("PERIOD{}", period.as_str().to_uppercase());
+      let metrics = requests.iter().find(|request| request.path.starts_with(&format!("/financial-metrics/?ticker={}&", ticker))).unwrap();
+      assert!(metrics.path.ends_with(&format!("&period={}", period.as_str())), "{}", metrics.path);
+
+      let line_items = requests.iter().find(|request| request.method == "POST" && request.body.contains(&ticker)).unwrap();
+      let body: Value = serde_json::from_str(&line_items.body).unwrap();
+      assert_eq!(body["period"], Value::from(period.as_str()));
+    }
+  }
+
+  #[tokio::test]
+  async fn todays_market_cap_comes_from_company_facts() {
+    let server = StubServer::start(|request| match request.path.starts_with("/company/facts/") {
+      true => (200, serde_json::json!({ "company_facts": { "ticker": "MCAPTODAY", "name": "Today Inc", "market_cap": 3.0e12 } }).to_string()),
+      false => (404, "{}".to_string()),
+    });
+    let today = chrono::Local::now().date_naive().format("%Y-%m-%d").to_string();
+
+    assert_eq!(stub_api(&server).get_market_cap("MCAPTODAY", &today).await.unwrap(), Some(3.0e12));
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn past_market_cap_comes_from_the_closest_earlier_report() {
+    let server = StubServer::start(|request| match request.path.starts_with("/financial-metrics/") {
+      true => (200, serde_json::json!({ "financial_metrics": [
+        metric("MCAPPAST", "2024-09-30", 3.1e12), metric("MCAPPAST", "2024-03-31", 2.8e12), metric("MCAPPAST", "2023-12-31", 2.5e12),
+      ] }).to_string()),
+      false => (404, "{}".to_string()),
+    });
+
+    assert_eq!(stub_api(&server).get_market_cap("MCAPPAST", "2024-06-30").await.unwrap(), Some(2.8e12));
+    assert!(server.requests().iter().all(|request| !request.path.starts_with("/company/facts/")));
+  }
+
+  #[tokio::test]
+  async fn market_cap_is_none_without_any_source() {
+    let server = StubServer::start(|_| (200, serde_json::json!({ "financial_metrics": [] }).to_string()));
+    assert_eq!(stub_api(&server).get_market_cap("MCAPNONE", "2024-06-30").await.unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn insider_trades_not_found_is_an_error() {
+    let server = StubServer::start(|_| (404, "{}".to_string()));
+    let api = stub_api(&server);
+    let result = api.get_insider_trade("INSIDER404", "2024-06-30", Some("2024-01-01"), 100).await;
+    assert!(matches!(result, Err(AgentError::UpstreamHttp { status: StatusCode::NOT_FOUND, .. })), "{:?}", result);
+  }
+
+  #[tokio::test]
+  async fn company_news_server_error_is_an_error() {
+    let server = StubServer::start(|_| (500, "{}".to_string()));
+    let api = stub_api(&server);
+    let result = api.get_company_news("NEWS500", "2024-06-30", Some("2024-01-01"), 100).await;
+    assert!(matches!(result, Err(AgentError::UpstreamHttp { status: StatusCode::INTERNAL_SERVER_ERROR, .. })), "{:?}", result);
+  }
+
+  #[tokio::test]
+  async fn a_missing_key_fails_before_any_request() {
+    let server = StubServer::start(|_| (200, "{}".to_string()));
+    let mut config = Config::for_tests();
+    config.financial_datasets_base_url = server.base_url.clone();
+    let api = API::new(config);
+
+    let error = api.search_line_items("NOKEY", vec!["net_income".to_string()], "2024-06-30", None, None).await.unwrap_err();
+    assert!(matches!(&error, AgentError::MissingApiKey(name) if name == "FINANCIAL_DATASETS_API_KEY"), "{:?}", error);
+    assert_eq!(error.to_string(), "FINANCIAL_DATASETS_API_KEY is not configured");
+    assert!(matches!(api.get_financial_metrics("NOKEY", "2024-06-30", None, None).await, Err(AgentError::MissingApiKey(_))));
+    assert!(server.requests().is_empty());
+  }
+
+  fn query_param(path: &str, name: &str) -> Option<String> {
+    let query = path.split_once('?')?.1;
+    query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('=').map(String::from))
+  }
+
+  #[tokio::test]
+  async fn a_wider_price_window_only_fetches_the_days_not_cached() {
+    // Every weekday in the requested window, closing at its day of the month
+    let server = StubServer::start(|request| {
+      let start = parse_date(&query_param(&request.path, "start_date").unwrap()).unwrap();
+      let end = parse_date(&query_param(&request.path, "end_date").unwrap()).unwrap();
+      let prices : Vec<Price> = start.iter_days().take_while(|day| *day <= end)
+        .filter(|day| !matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+        .map(|day| price(&day.format("%Y-%m-%d").to_string(), day.day() as f64))
+        .collect();
+      (200, serde_json::json!({ "ticker": "DELTAPX", "prices": prices }).to_string())
+    });
+    let api = stub_api(&server);
+
+    assert_eq!(api.get_price("DELTAPX", "2024-03-01", "2024-03-15").await.unwrap().len(), 11);
+    let prices = api.get_price("DELTAPX", "2024-03-08", "2024-03-29").await.unwrap();
+    assert_eq!(prices.len(), 16);
+    assert!(prices.first().unwrap().time.starts_with("2024-03-08") && prices.last().unwrap().time.starts_with("2024-03-29"));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(query_param(&requests[1].path, "start_date").as_deref(), Some("2024-03-16"));
+    assert_eq!(query_param(&requests[1].path, "end_date").as_deref(), Some("2024-03-29"));
+
+    // Inside what is now covered, nothing is fetched
+    assert_eq!(api.get_price("DELTAPX", "2024-03-04", "2024-03-20").await.unwrap().len(), 13);
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[tokio::test]
+  async fn metrics_only_fetch_the_reports_outside_the_cached_window() {
+    // Quarterly reports, answered newest first within the query's report_period filters and limit
+    let server = StubServer::start(|request| {
+      let filter = |name: &str| query_param(&request.path, name).and_then(|date| parse_date(&date));
+      let limit : usize = query_param(&request.path, "limit").unwrap().parse().unwrap();
+      let metrics : Vec<Value> = ["2024-03-31", "2023-12-31", "2023-09-30", "2023-06-30", "2023-03-31"].iter()
+        .filter(|date| {
+          let date = parse_date(date).unwrap();
+          filter("report_period_lte").map_or(true, |bound| date <= bound) && filter("report_period_gt").map_or(true, |bound| date > bound)
+            && filter("report_period_lt").map_or(true, |bound| date < bound)
+        })
+        .take(limit)
+        .map(|date| metric("DELTAFM", date, 1.0e9))
+        .collect();
+      (200, serde_json::json!({ "financial_metrics": metrics }).to_string())
+    });
+    let api = stub_api(&server);
+    let report_periods = |metrics: Vec<FinancialMetrics>| -> Vec<String> { metrics.iter().map(|metric| metric.report_period.to_string()).collect() };
+
+    assert_eq!(report_periods(api.get_financial_metrics("DELTAFM", "2023-12-31", Some("ttm"), Some(2)).await.unwrap()), ["2023-12-31", "2023-09-30"]);
+    // A later end date only asks for the reports filed since
+    assert_eq!(report_periods(api.get_financial_metrics("DELTAFM", "2024-06-30", Some("ttm"), Some(2)).await.unwrap()), ["2024-03-31", "2023-12-31"]);
+    // A larger limit only asks for the older reports it is short of
+    assert_eq!(report_periods(api.get_financial_metrics("DELTAFM", "2024-06-30", Some("ttm"), Some(4)).await.unwrap()), ["2024-03-31", "2023-12-31", "2023-09-30", "2023-06-30"]);
+    assert_eq!(report_periods(api.get_financial_metrics("DELTAFM", "2024-06-30", Some("ttm"), Some(4)).await.unwrap()).len(), 4);
+
+    let paths : Vec<String> = server.requests().into_iter().map(|request| request.path).collect();
+    assert_eq!(paths.len(), 3, "{:?}", paths);
+    assert!(paths[0].contains("report_period_lte=2023-12-31&limit=2"), "{}", paths[0]);
+    assert!(paths[1].contains("report_period_gt=2023-12-31&report_period_lte=2024-06-30"), "{}", paths[1]);
+    assert!(paths[2].contains("report_period_lt=2023-09-30&limit=1"), "{}", paths[2]);
+  }
+
+  #[tokio::test]
+  async fn company_facts_are_fetched_once_per_ticker() {
+    let server = StubServer::start(|_| (200, serde_json::json!({ "company_facts": { "ticker": "FACTSONCE", "name": "Once Inc", "sector": "Technology" } }).to_string()));
+    let api = stub_api(&server);
+
+    assert_eq!(api.get_company_facts("FACTSONCE").await.unwrap().name, "Once Inc");
+    assert_eq!(api.get_company_facts("FACTSONCE").await.unwrap().sector.as_deref(), Some("Technology"));
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  // One article per listed day of January 2024 with a T-suffixed timestamp, answered newest first within the window and page limit
+  fn news_page(request: &crate::ai_agent::tools::stub_server::StubRequest, ticker: &str, days: &[u32]) -> (u16, String) {
+    let end = parse_date(&query_param(&request.path, "end_date").unwrap()).unwrap();
+    let start = parse_date(&query_param(&request.path, "start_date").unwrap()).unwrap();
+    let limit : usize = query_param(&request.path, "limit").unwrap().parse().unwrap();
+    let news : Vec<Value> = days.iter().rev().enumerate()
+      .map(|(i, day)| (NaiveDate::from_ymd_opt(2024, 1, *day).unwrap(), i))
+      .filter(|(date, _)| *date >= start && *date <= end)
+      .take(limit)
+      .map(|(date, i)| serde_json::json!({
+        "ticker": ticker, "title": format!("Story {}", i), "author": "", "source": "", "url": "", "sentiment": null,
+        "date": format!("{}T{:02}:30:00Z", date.format("%Y-%m-%d"), 9 + i % 8),
+      }))
+      .collect();
+    (200, serde_json::json!({ "news": news }).to_string())
+  }
+
+  #[tokio::test]
+  async fn company_news_pages_backward_until_the_start_date() {
+    let server = StubServer::start(|request| news_page(request, "NEWSPAGES", &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]));
+    let news = stub_api(&server).get_company_news("NEWSPAGES", "2024-01-10", Some("2024-01-01"), 3).await.unwrap();
+
+    // Each page ends on the oldest day of the page before, the short last page stops the walk
+    let end_dates : Vec<String> = server.requests().iter().map(|request| query_param(&request.path, "end_date").unwrap()).collect();
+    assert_eq!(end_dates, ["2024-01-10", "2024-01-08", "2024-01-06", "2024-01-04", "2024-01-02"]);
+    let days : std::collections::BTreeSet<u32> = news.iter().map(|article| article.date.day()).collect();
+    assert_eq!(days, (1..=10).collect());
+  }
+
+  #[tokio::test]
+  async fn a_full_page_from_a_single_day_ends_the_walk() {
+    let server = StubServer::start(|request| news_page(request, "NEWSSAMEDAY", &[5, 5, 5, 5]));
+    let news = stub_api(&server).get_company_news("NEWSSAMEDAY", "2024-01-05", Some("2024-01-01"), 3).await.unwrap();
+
+    assert_eq!(news.len(), 3);
+    assert_eq!(server.requests().len(), 1);
+  }
+}
@@ -1 +1,4 @@
-pub mod api;
\ No newline at end of file
+pub mod api;
+pub mod rate_limiter;
+#[cfg(test)]
+pub mod stub_server;
@@ -0,0 +1,44 @@
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+struct Bucket {
+  tokens: f64,
+  last_refill: Instant,
+}
+
+// Token bucket shared by every API call, bursts up to one second's worth of requests
+pub struct RateLimiter {
+  requests_per_second: f64,
+  bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+  pub fn new(requests_per_second: f64) -> Self {
+    let requests_per_second = if requests_per_second > 0.0 { requests_per_second } else { 1.0 };
+    RateLimiter {
+      requests_per_second,
+      bucket: Mutex::new(Bucket { tokens: requests_per_second.max(1.0), last_refill: Instant::now() }),
+    }
+  }
+
+  pub async fn acquire(&self) {
+    loop {
+      let wait = {
+        let mut bucket = self.bucket.lock().await;
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.requests_per_second;
+        bucket.tokens = (bucket.tokens + refill).min(self.requests_per_second.max(1.0));
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+          bucket.tokens -= 1.0;
+          return;
+        }
+        Duration::from_secs_f64((1.0 - bucket.tokens) / self.requests_per_second)
+      };
+
+      log::debug!("Rate limit reached, waiting {:?} before the next API request", wait);
+      tokio::time::sleep(wait).await;
+    }
+  }
+}
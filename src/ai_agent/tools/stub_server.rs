@@ -0,0 +1,71 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+// A minimal HTTP/1.1 server for tests, every connection gets one answer from `handler` and is closed
+#[derive(Debug, Clone)]
+pub struct StubRequest {
+  pub method: String,
+  pub path: String, // including the query string
+  pub body: String,
+}
+
+pub struct StubServer {
+  pub base_url: String,
+  requests: Arc<Mutex<Vec<StubRequest>>>,
+}
+
+impl StubServer {
+  pub fn start<F>(handler: F) -> Self where F: Fn(&StubRequest) -> (u16, String) + Send + 'static {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests: Arc<Mutex<Vec<StubRequest>>> = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+
+    std::thread::spawn(move || {
+      for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let Some(request) = Self::read_request(&mut stream) else { continue };
+        let (status, body) = handler(&request);
+        recorded.lock().unwrap().push(request);
+        let response = format!("HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+        let _ = stream.write_all(response.as_bytes());
+      }
+    });
+
+    return StubServer { base_url, requests };
+  }
+
+  // Every request answered so far, in arrival order
+  pub fn requests(&self) -> Vec<StubRequest> {
+    self.requests.lock().unwrap().clone()
+  }
+
+  fn read_request(stream: &mut std::net::TcpStream) -> Option<StubRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut content_length = 0;
+    loop {
+      let mut header = String::new();
+      reader.read_line(&mut header).ok()?;
+      let header = header.trim();
+      if header.is_empty() {
+        break;
+      }
+      if let Some((name, value)) = header.split_once(':') {
+        if name.eq_ignore_ascii_case("content-length") {
+          content_length = value.trim().parse().unwrap_or(0);
+        }
+      }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).ok()?;
+    return Some(StubRequest { method, path, body: String::from_utf8_lossy(&body).to_string() });
+  }
+}
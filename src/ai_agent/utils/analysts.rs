@@ -1,29 +1,176 @@
 use std::collections::HashMap;
 use serde_json::Value; 
-use anyhow::{Result, Error};
+use anyhow::{anyhow, Result, Error};
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::{OnceLock, RwLock};
 
-use crate::ai_agent::agents::warren_buffet::{Signal, WarrenBuffetSignal};
+use crate::ai_agent::agents::warren_buffet::{Signal, WarrenBuffetSignal, BUFFETT_LINE_ITEMS};
+use crate::ai_agent::agents::technical_analyst::TechnicalAnalystAgent;
+use crate::ai_agent::agents::sentiment_analyst::SentimentAnalystAgent;
+use crate::ai_agent::agents::valuation_analyst::{ValuationAnalystAgent, VALUATION_LINE_ITEMS};
+use crate::ai_agent::agents::ben_graham::{BenGrahamSignal, GRAHAM_LINE_ITEMS};
+use crate::ai_agent::agents::fundamentals_analyst::FundamentalsAnalystAgent;
+use crate::ai_agent::agents::insider_trades_analyst::InsiderTradesAnalystAgent;
+use crate::ai_agent::agents::cathie_wood::{CathieWoodSignal, WOOD_LINE_ITEMS};
+use crate::ai_agent::agents::phil_fisher::{PhilFisherSignal, FISHER_LINE_ITEMS};
+use crate::ai_agent::agents::druckenmiller::StanleyDruckenmillerSignal;
+use crate::ai_agent::data::models::ReportPeriod;
 use crate::ai_agent::graph::state::{PartialAgentStateUpdate, AgentState};
 use crate::app::config::Config; 
 
 pub type AgentFunction = fn(AgentState, Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>>;
 pub type NodeFunctionPair = (String, AgentFunction);
 
+// Financial line items an analyst reads, with the period basis and history it asks for
+#[derive(Debug, Clone, Copy)]
+pub struct LineItemSet {
+  pub names: &'static [&'static str],
+  pub period: ReportPeriod,
+  pub limit: i64,
+}
+
+impl LineItemSet {
+  // The analyst's own fields plus extras from the `line_items` run option, either a list applied to every
+  // analyst or an object of lists keyed by analyst. Own fields cannot be removed, the analysis reads them
+  pub fn names_for(&self, analyst_key: &str, metadata: &HashMap<String, Value>) -> Vec<String> {
+    let extras : Vec<&Value> = match metadata.get("line_items") {
+      Some(Value::Array(names)) => names.iter().collect(),
+      Some(Value::Object(by_analyst)) => by_analyst.get(analyst_key).and_then(Value::as_array).map(|names| names.iter().collect()).unwrap_or_default(),
+      _ => Vec::new(),
+    };
+
+    let mut names : Vec<String> = self.names.iter().map(|name| name.to_string()).collect();
+    for extra in extras.into_iter().filter_map(Value::as_str).map(str::trim).filter(|name| !name.is_empty()) {
+      if !names.iter().any(|name| name == extra) {
+        names.push(extra.to_string());
+      }
+    }
+    names
+  }
+}
+
+// One line-item search per period basis, covering every given analyst
+#[derive(Debug, Clone)]
+pub struct LineItemRequest {
+  pub period: ReportPeriod,
+  pub limit: i64,
+  pub names: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct AnalystConfig {
   pub display_name: String,
   pub agent_function : AgentFunction,
   pub order : usize,
+  pub line_items : Option<LineItemSet>,
 }
 
+// Node names the workflow reserves for itself, an analyst's node is its key with an `_agent` suffix
+const RESERVED_NODES: [&str; 3] = ["start_node", "risk_management_agent", "portfolio_manager"];
+
+static REGISTRY: OnceLock<RwLock<HashMap<String, AnalystConfig>>> = OnceLock::new();
+
+fn registry() -> &'static RwLock<HashMap<String, AnalystConfig>> {
+  REGISTRY.get_or_init(|| RwLock::new(builtin_analysts()))
+}
+
+// Built-in analysts plus any registered at runtime
 pub fn get_analyst_config() -> HashMap<String, AnalystConfig> {
+  registry().read().unwrap_or_else(|p| p.into_inner()).clone()
+}
+
+// Adds a custom analyst to every workflow built afterwards. The agent should insert its signals under
+// analyst_signals.<key>_agent like the built-in ones, so the risk and portfolio managers pick them up
+pub fn register_analyst(key: &str, display_name: &str, order: usize, agent_function: AgentFunction, line_items: Option<LineItemSet>) -> Result<()> {
+  let key = key.trim();
+  if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+    return Err(anyhow!("Analyst key '{}' must be non-empty and only use letters, digits and underscores", key));
+  }
+  if RESERVED_NODES.contains(&format!("{}_agent", key).as_str()) {
+    return Err(anyhow!("Analyst key '{}' clashes with a reserved workflow node", key));
+  }
+
+  let mut registry = registry().write().unwrap_or_else(|p| p.into_inner());
+  if let Some((existing, _)) = registry.iter().find(|(existing, analyst)| existing.eq_ignore_ascii_case(key) || analyst.display_name.eq_ignore_ascii_case(display_name)) {
+    return Err(anyhow!("Analyst '{}' ({}) is already registered as {}", key, display_name, existing));
+  }
+  registry.insert(key.to_string(), AnalystConfig { display_name: display_name.to_string(), agent_function, order, line_items });
+  log::info!("Registered analyst {} ({}) with order {}", key, display_name, order);
+  Ok(())
+}
+
+fn builtin_analysts() -> HashMap<String, AnalystConfig> {
   let mut config: HashMap<String, AnalystConfig> = HashMap::new();
 
   config.insert("warren_buffett".to_string(), AnalystConfig { 
     display_name: "Warren Buffett".to_string(), 
     agent_function: WarrenBuffetSignal::static_warren_buffet_agent, 
-    order: 8 
+    order: 8,
+    line_items: Some(BUFFETT_LINE_ITEMS),
+  });
+
+  config.insert("technical_analyst".to_string(), AnalystConfig {
+    display_name: "Technical Analyst".to_string(),
+    agent_function: TechnicalAnalystAgent::static_technical_analyst_agent,
+    order: 9,
+    line_items: None,
+  });
+
+  config.insert("sentiment".to_string(), AnalystConfig {
+    display_name: "Sentiment Analyst".to_string(),
+    agent_function: SentimentAnalystAgent::static_sentiment_analyst_agent,
+    order: 10,
+    line_items: None,
+  });
+
+  config.insert("valuation_analyst".to_string(), AnalystConfig {
+    display_name: "Valuation Analyst".to_string(),
+    agent_function: ValuationAnalystAgent::static_valuation_analyst_agent,
+    order: 11,
+    line_items: Some(VALUATION_LINE_ITEMS),
+  });
+
+  config.insert("ben_graham".to_string(), AnalystConfig {
+    display_name: "Ben Graham".to_string(),
+    agent_function: BenGrahamSignal::static_ben_graham_agent,
+    order: 12,
+    line_items: Some(GRAHAM_LINE_ITEMS),
+  });
+
+  config.insert("fundamentals_analyst".to_string(), AnalystConfig {
+    display_name: "Fundamentals Analyst".to_string(),
+    agent_function: FundamentalsAnalystAgent::static_fundamentals_analyst_agent,
+    order: 13,
+    line_items: None,
+  });
+
+  config.insert("insider_trades_analyst".to_string(), AnalystConfig {
+    display_name: "Insider Trades Analyst".to_string(),
+    agent_function: InsiderTradesAnalystAgent::static_insider_trades_analyst_agent,
+    order: 14,
+    line_items: None,
+  });
+
+  config.insert("cathie_wood".to_string(), AnalystConfig {
+    display_name: "Cathie Wood".to_string(),
+    agent_function: CathieWoodSignal::static_cathie_wood_agent,
+    order: 15,
+    line_items: Some(WOOD_LINE_ITEMS),
+  });
+
+  config.insert("phil_fisher".to_string(), AnalystConfig {
+    display_name: "Phil Fisher".to_string(),
+    agent_function: PhilFisherSignal::static_phil_fisher_agent,
+    order: 16,
+    line_items: Some(FISHER_LINE_ITEMS),
+  });
+
+  config.insert("stanley_druckenmiller".to_string(), AnalystConfig {
+    display_name: "Stanley Druckenmiller".to_string(),
+    agent_function: StanleyDruckenmillerSignal::static_stanley_druckenmiller_agent,
+    order: 17,
+    line_items: None,
   });
 
   return config;
@@ -48,6 +195,25 @@ pub fn get_analyst_order() -> Vec<(String, String)> {
   return order_vec;
 }
 
+// Matches each selection against analyst keys and display names, ignoring case. Unmatched names are returned as the error
+pub fn resolve_analyst_keys(selected: &[String]) -> Result<Vec<String>, Vec<String>> {
+  let config = get_analyst_config();
+  let mut keys: Vec<String> = Vec::new();
+  let mut unmatched: Vec<String> = Vec::new();
+
+  for name in selected {
+    let wanted = name.trim();
+    let found = config.iter().find(|(key, analyst)| key.eq_ignore_ascii_case(wanted) || analyst.display_name.eq_ignore_ascii_case(wanted));
+    match found {
+      Some((key, _)) if !keys.contains(key) => keys.push(key.clone()),
+      Some(_) => {}
+      None => unmatched.push(name.clone()),
+    }
+  }
+
+  if unmatched.is_empty() { Ok(keys) } else { Err(unmatched) }
+}
+
 pub fn get_analyst_nodes() -> HashMap<String, NodeFunctionPair> {
   let config = get_analyst_config();
   let mut nodes = HashMap::new();
@@ -58,3 +224,57 @@ pub fn get_analyst_nodes() -> HashMap<String, NodeFunctionPair> {
   
   return nodes;
 }
+
+// Union of the line items the given analysts read, grouped by the period each resolves to for this run.
+// Fetched once per ticker up front so overlapping fields are not requested by every analyst
+pub fn line_item_requests(analyst_keys: &[String], metadata: &HashMap<String, Value>) -> Vec<LineItemRequest> {
+  let config = get_analyst_config();
+  let mut requests : Vec<LineItemRequest> = Vec::new();
+
+  for key in analyst_keys {
+    let line_items = match config.get(key).and_then(|analyst| analyst.line_items) {
+      Some(line_items) => line_items,
+      None => continue,
+    };
+    let period = ReportPeriod::from_metadata(metadata, line_items.period);
+    let names = line_items.names_for(key, metadata);
+
+    match requests.iter_mut().find(|request| request.period == period) {
+      Some(request) => {
+        request.limit = request.limit.max(line_items.limit);
+        for name in names {
+          if !request.names.contains(&name) {
+            request.names.push(name);
+          }
+        }
+      }
+      None => requests.push(LineItemRequest { period, limit: line_items.limit, names }),
+    }
+  }
+  requests
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+
+  #[test]
+  fn analysts_resolve_by_key() {
+    assert_eq!(resolve_analyst_keys(&names(&["warren_buffett", "technical_analyst"])), Ok(names(&["warren_buffett", "technical_analyst"])));
+  }
+
+  #[test]
+  fn analysts_resolve_by_display_name_in_any_case() {
+    assert_eq!(resolve_analyst_keys(&names(&["Warren Buffett", " phil fisher ", "WARREN_BUFFETT"])), Ok(names(&["warren_buffett", "phil_fisher"])));
+  }
+
+  #[test]
+  fn unknown_analysts_are_all_reported() {
+    assert_eq!(resolve_analyst_keys(&names(&["Warren Buffet", "Ben Graham", "charlie"])), Err(names(&["Warren Buffet", "charlie"])));
+  }
+}
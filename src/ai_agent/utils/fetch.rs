@@ -0,0 +1,23 @@
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use tracing::Instrument;
+
+// Runs `fetch` for up to `concurrency` tickers at a time, results come back in the order of `tickers`
+// Each fetch runs inside a `ticker` span so its log lines carry the ticker
+pub async fn fetch_per_ticker<T, F, Fut>(tickers: &[String], concurrency: usize, fetch: F) -> Vec<(String, T)>
+where
+  F: Fn(String) -> Fut,
+  Fut: Future<Output = T>,
+{
+  let mut fetched : Vec<(usize, String, T)> = stream::iter(tickers.iter().cloned().enumerate())
+    .map(|(index, ticker)| {
+      let fetching = fetch(ticker.clone()).instrument(tracing::info_span!("ticker", ticker = %ticker));
+      async move { (index, ticker, fetching.await) }
+    })
+    .buffer_unordered(concurrency.max(1))
+    .collect()
+    .await;
+
+  fetched.sort_by_key(|(index, _, _)| *index);
+  fetched.into_iter().map(|(_, ticker, result)| (ticker, result)).collect()
+}
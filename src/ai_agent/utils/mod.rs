@@ -1 +1,3 @@
-pub mod analysts;
\ No newline at end of file
+pub mod analysts;
+pub mod fetch;
+pub mod signals;
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+// Analysts at or above this confidence on opposite sides of a ticker are flagged as a conflict
+pub const CONFLICT_CONFIDENCE_THRESHOLD: f64 = 70.0;
+
+pub fn signal_confidence(signal: &Value) -> f64 {
+  // Some agents store confidence as a string, accept both representations
+  match signal.get("confidence") {
+    Some(Value::Number(number)) => number.as_f64().unwrap_or(0.0),
+    Some(Value::String(text)) => text.trim().parse::<f64>().unwrap_or(0.0),
+    _ => 0.0,
+  }
+}
+
+pub fn signal_direction(signal: &Value) -> String {
+  signal.get("signal").and_then(Value::as_str).unwrap_or("neutral").trim().to_lowercase()
+}
+
+// Per-ticker view of the analyst signals, skipping the risk manager which carries limits rather than opinions
+pub fn signals_by_ticker(analyst_signals: &Value) -> BTreeMap<String, Vec<(String, Value)>> {
+  let mut by_ticker: BTreeMap<String, Vec<(String, Value)>> = BTreeMap::new();
+
+  if let Some(agents) = analyst_signals.as_object() {
+    for (agent, signals) in agents {
+      if agent == "risk_management_agent" {
+        continue;
+      }
+      if let Some(tickers) = signals.as_object() {
+        for (ticker, signal) in tickers {
+          by_ticker.entry(ticker.clone()).or_default().push((agent.clone(), signal.clone()));
+        }
+      }
+    }
+  }
+
+  return by_ticker;
+}
+
+// Confidence-weighted net signal in [-1, 1]: bullish confidence counts positive, bearish negative
+pub fn net_signal_score<'a, I>(signals: I) -> f64 where I: IntoIterator<Item = &'a Value> {
+  let mut total = 0.0;
+  let mut count = 0;
+
+  for signal in signals {
+    let confidence = signal_confidence(signal).clamp(0.0, 100.0) / 100.0;
+    match signal_direction(signal).as_str() {
+      "bullish" => total += confidence,
+      "bearish" => total -= confidence,
+      _ => {}
+    }
+    count += 1;
+  }
+
+  if count == 0 {
+    return 0.0;
+  }
+  return total / count as f64;
+}
+
+pub fn detect_signal_conflicts(analyst_signals: &Value) -> Vec<Value> {
+  let mut conflicts: Vec<Value> = Vec::new();
+
+  for (ticker, signals) in signals_by_ticker(analyst_signals) {
+    let mut bullish: Vec<Value> = Vec::new();
+    let mut bearish: Vec<Value> = Vec::new();
+
+    for (agent, signal) in &signals {
+      let confidence = signal_confidence(signal);
+      if confidence < CONFLICT_CONFIDENCE_THRESHOLD {
+        continue;
+      }
+      let entry = serde_json::json!({"agent": agent, "confidence": confidence});
+      match signal_direction(signal).as_str() {
+        "bullish" => bullish.push(entry),
+        "bearish" => bearish.push(entry),
+        _ => {}
+      }
+    }
+
+    if !bullish.is_empty() && !bearish.is_empty() {
+      log::warn!("Conflicting high-confidence analyst signals for {}", ticker);
+      conflicts.push(serde_json::json!({
+        "ticker": ticker,
+        "bullish": bullish,
+        "bearish": bearish,
+      }));
+    }
+  }
+
+  return conflicts;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsensusWeighting {
+  Equal,      // one analyst, one vote
+  #[default]
+  Confidence, // votes scaled by each analyst's confidence
+}
+
+// Per-ticker tally of analyst directions with a consensus score in [-1, 1]
+pub fn signal_consensus(analyst_signals: &Value, weighting: ConsensusWeighting) -> Value {
+  let mut consensus = serde_json::Map::new();
+
+  for (ticker, signals) in signals_by_ticker(analyst_signals) {
+    let (mut bullish, mut bearish, mut neutral) = (0, 0, 0);
+    for (_, signal) in &signals {
+      match signal_direction(signal).as_str() {
+        "bullish" => bullish += 1,
+        "bearish" => bearish += 1,
+        _ => neutral += 1,
+      }
+    }
+
+    let analysts = signals.len();
+    let average_confidence = signals.iter().map(|(_, signal)| signal_confidence(signal)).sum::<f64>() / analysts.max(1) as f64;
+    let score = match weighting {
+      ConsensusWeighting::Equal => (bullish as f64 - bearish as f64) / analysts.max(1) as f64,
+      ConsensusWeighting::Confidence => net_signal_score(signals.iter().map(|(_, signal)| signal)),
+    };
+    let direction = if bullish > bearish && bullish > neutral {
+      "bullish"
+    } else if bearish > bullish && bearish > neutral {
+      "bearish"
+    } else {
+      "neutral"
+    };
+
+    consensus.insert(ticker, serde_json::json!({
+      "signal": direction,
+      "score": score,
+      "bullish": bullish,
+      "bearish": bearish,
+      "neutral": neutral,
+      "analysts": analysts,
+      "average_confidence": average_confidence,
+    }));
+  }
+
+  return Value::Object(consensus);
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn opposing_high_confidence_signals_are_flagged() {
+    let analyst_signals = json!({
+      "warren_buffett_agent": { "AAPL": { "signal": "bullish", "confidence": 85.0 }, "MSFT": { "signal": "bullish", "confidence": 90.0 } },
+      "technical_analyst_agent": { "AAPL": { "signal": "Bearish", "confidence": "75" }, "MSFT": { "signal": "bearish", "confidence": 40.0 } },
+      "risk_management_agent": { "AAPL": { "remaining_position_limit": 1000.0, "current_price": 150.0 } },
+    });
+
+    let conflicts = detect_signal_conflicts(&analyst_signals);
+    // MSFT's bearish call is below the threshold, so only AAPL conflicts
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0]["ticker"], json!("AAPL"));
+    assert_eq!(conflicts[0]["bullish"], json!([{ "agent": "warren_buffett_agent", "confidence": 85.0 }]));
+    assert_eq!(conflicts[0]["bearish"], json!([{ "agent": "technical_analyst_agent", "confidence": 75.0 }]));
+  }
+
+  #[test]
+  fn agreeing_signals_are_not_conflicts() {
+    let analyst_signals = json!({
+      "warren_buffett_agent": { "AAPL": { "signal": "bullish", "confidence": 85.0 } },
+      "technical_analyst_agent": { "AAPL": { "signal": "bullish", "confidence": 95.0 } },
+      "sentiment_agent": { "AAPL": { "signal": "neutral", "confidence": 99.0 } },
+    });
+    assert!(detect_signal_conflicts(&analyst_signals).is_empty());
+  }
+
+  #[test]
+  fn consensus_counts_votes_and_weights_the_score() {
+    let analyst_signals = json!({
+      "warren_buffett_agent": { "AAPL": { "signal": "bullish", "confidence": 90.0 } },
+      "technical_analyst_agent": { "AAPL": { "signal": "bullish", "confidence": 30.0 } },
+      "sentiment_agent": { "AAPL": { "signal": "bearish", "confidence": 60.0 } },
+      "risk_management_agent": { "AAPL": { "remaining_position_limit": 1000.0, "current_price": 150.0 } },
+    });
+
+    let equal = signal_consensus(&analyst_signals, ConsensusWeighting::Equal);
+    assert_eq!(equal["AAPL"]["signal"], json!("bullish"));
+    assert_eq!((equal["AAPL"]["bullish"].clone(), equal["AAPL"]["bearish"].clone(), equal["AAPL"]["neutral"].clone()), (json!(2), json!(1), json!(0)));
+    assert_eq!(equal["AAPL"]["analysts"], json!(3));
+    assert!((equal["AAPL"]["score"].as_f64().unwrap() - 1.0 / 3.0).abs() < 1e-9);
+    assert!((equal["AAPL"]["average_confidence"].as_f64().unwrap() - 60.0).abs() < 1e-9);
+
+    let weighted = signal_consensus(&analyst_signals, ConsensusWeighting::Confidence);
+    assert!((weighted["AAPL"]["score"].as_f64().unwrap() - 0.2).abs() < 1e-9);
+  }
+
+  #[test]
+  fn a_split_vote_is_neutral() {
+    let analyst_signals = json!({
+      "warren_buffett_agent": { "MSFT": { "signal": "bullish", "confidence": 70.0 } },
+      "technical_analyst_agent": { "MSFT": { "signal": "bearish", "confidence": 70.0 } },
+    });
+    let consensus = signal_consensus(&analyst_signals, ConsensusWeighting::default());
+    assert_eq!(consensus["MSFT"]["signal"], json!("neutral"));
+    assert_eq!(consensus["MSFT"]["score"], json!(0.0));
+  }
+}
@@ -1,16 +1,40 @@
 use std::env;
+use std::time::Duration;
+
+use crate::ai_agent::data::cache::CacheTtl;
+use crate::ai_agent::llm::models::DEFAULT_LLM_TIMEOUT_SECS;
+use crate::app::services::decision_log::DecisionLogFormat;
 
 use log; 
 
+const DEFAULT_FINANCIAL_DATASETS_BASE_URL: &str = "https://api.financialdatasets.ai";
+
 #[allow(unused)]
 #[derive(Clone)]
 pub struct Config {
-  pub antropic_api_key: String,
-  pub deepseek_api_key: String,
-  pub groq_api_key : String, 
-  pub google_api_key : String, 
-  pub financial_datasets_api_key : String,
-  pub openai_api_key : String,
+  // None when the variable is unset or blank, callers fail with a "<VAR> is not configured" error instead of sending a placeholder
+  pub antropic_api_key: Option<String>,
+  pub deepseek_api_key: Option<String>,
+  pub groq_api_key : Option<String>,
+  pub google_api_key : Option<String>,
+  pub financial_datasets_api_key : Option<String>,
+  pub financial_datasets_base_url : String,
+  pub openai_api_key : Option<String>,
+  pub default_initial_cash : f64,
+  pub default_margin_requirement : f64,
+  pub cache_file : Option<String>,
+  pub cache_ttl : CacheTtl,
+  pub api_max_retries : u32,
+  pub api_retry_base_delay_ms : u64,
+  pub api_requests_per_second : f64,
+  pub run_history_size : usize,
+  pub llm_timeout_secs : f64,
+  pub ticker_fetch_concurrency : usize,
+  pub watchlist_file : Option<String>,
+  pub prompts_dir : String,
+  pub idempotency_ttl : Duration,
+  pub decision_log_file : Option<String>,
+  pub decision_log_format : DecisionLogFormat,
 }
 
 impl Config {
@@ -22,38 +46,131 @@ impl Config {
       Err(_) => log::error!("No .env file found"),
     }
 
-    let antropic_api_key: String =  env::var("ANTHROPIC_API_KEY").unwrap_or_else(|_| {
-      log::error!("Warning: anthropic api key not found, using default http://localhost:8000");
-      "http://localhost:8000".to_string()
-    });
-    let deepseek_api_key : String = env::var("DEEPSEEK_API_KEY").unwrap_or_else(|_| {
-      log::error!("Warning: TTS_URL not found, using default http://localhost:8000");
-      "ws://localhost:8000".to_string()
-    });
-    let groq_api_key : String = env::var("GROQ_API_KEY").unwrap_or_else(|_| {
-      log::error!("Warning: TTS_URL not found, using default http://localhost:8000");
-      "ws://localhost:8000".to_string()
-    });
-
-    let google_api_key : String = env::var("GOOGLE_API_KEY").unwrap_or_else(|_| {
-      log::error!("Warning: TTS_URL not found, using default http://localhost:8000");
-      "ws://localhost:8000".to_string()
-    });
-
-    let financial_datasets_api_key : String = env::var("FINANCIAL_DATASETS_API_KEY").unwrap_or_else(|_| {
-      log::error!("Warning: TTS_URL not found, using default http://localhost:8000");
-      "ws://localhost:8000".to_string()
-    });
-
-    let openai_api_key : String =  env::var("OPENAI_API_KEY").unwrap_or_else(|_| {
-      log::error!("Warning: TTS_URL not found, using default http://localhost:8000");
-      "ws://localhost:8000".to_string()
-    });
-    
+    let antropic_api_key : Option<String> = Self::api_key("ANTHROPIC_API_KEY");
+    let deepseek_api_key : Option<String> = Self::api_key("DEEPSEEK_API_KEY");
+    let groq_api_key : Option<String> = Self::api_key("GROQ_API_KEY");
+    let google_api_key : Option<String> = Self::api_key("GOOGLE_API_KEY");
+    let financial_datasets_api_key : Option<String> = Self::api_key("FINANCIAL_DATASETS_API_KEY");
+
+    // Point at a mock server or caching proxy instead of production
+    let financial_datasets_base_url : String = env::var("FINANCIAL_DATASETS_BASE_URL").ok()
+      .map(|url| url.trim().trim_end_matches('/').to_string())
+      .filter(|url| !url.is_empty())
+      .unwrap_or_else(|| DEFAULT_FINANCIAL_DATASETS_BASE_URL.to_string());
+
+    let openai_api_key : Option<String> = Self::api_key("OPENAI_API_KEY");
+
+    let default_initial_cash : f64 = Self::parse_env("DEFAULT_INITIAL_CASH", 100000.0);
+    let default_margin_requirement : f64 = Self::parse_env("DEFAULT_MARGIN_REQUIREMENT", 0.0);
+
+    // Cache persistence is opt-in, leave CACHE_FILE unset to keep the cache in memory only
+    let cache_file : Option<String> = env::var("CACHE_FILE").ok().filter(|path| !path.trim().is_empty());
+
+    let default_ttl : CacheTtl = CacheTtl::default();
+    let cache_ttl : CacheTtl = CacheTtl {
+      prices: Duration::from_secs(Self::parse_env("CACHE_TTL_PRICES_SECS", default_ttl.prices.as_secs())),
+      financial_metrics: Duration::from_secs(Self::parse_env("CACHE_TTL_FINANCIAL_METRICS_SECS", default_ttl.financial_metrics.as_secs())),
+      line_items: Duration::from_secs(Self::parse_env("CACHE_TTL_LINE_ITEMS_SECS", default_ttl.line_items.as_secs())),
+      insider_trades: Duration::from_secs(Self::parse_env("CACHE_TTL_INSIDER_TRADES_SECS", default_ttl.insider_trades.as_secs())),
+      company_news: Duration::from_secs(Self::parse_env("CACHE_TTL_COMPANY_NEWS_SECS", default_ttl.company_news.as_secs())),
+      company_facts: Duration::from_secs(Self::parse_env("CACHE_TTL_COMPANY_FACTS_SECS", default_ttl.company_facts.as_secs())),
+      market_cap: Duration::from_secs(Self::parse_env("CACHE_TTL_MARKET_CAP_SECS", default_ttl.market_cap.as_secs())),
+    };
+
+    let api_max_retries : u32 = Self::parse_env("API_MAX_RETRIES", 3);
+    let api_retry_base_delay_ms : u64 = Self::parse_env("API_RETRY_BASE_DELAY_MS", 500);
+
+    // Raise on higher financialdatasets.ai tiers
+    let api_requests_per_second : f64 = Self::parse_env("API_REQUESTS_PER_SECOND", 5.0);
+
+    // Past runs kept for /agent/runs, the oldest is dropped once the limit is hit
+    let run_history_size : usize = Self::parse_env("RUN_HISTORY_SIZE", 100);
+
+    // A stalled provider gives up after this long and the agent falls back to a neutral signal
+    let llm_timeout_secs : f64 = Self::parse_env("LLM_TIMEOUT_SECS", DEFAULT_LLM_TIMEOUT_SECS);
+
+    // Tickers an agent fetches data for at once, every request still goes through the rate limiter
+    let ticker_fetch_concurrency : usize = Self::parse_env("TICKER_FETCH_CONCURRENCY", 4usize).max(1);
+
+    // Named ticker baskets for the `watchlist` request field, the built-in lists are used when unset
+    let watchlist_file : Option<String> = env::var("WATCHLIST_FILE").ok().filter(|path| !path.trim().is_empty());
+
+    // Agent prompt templates, any template missing from the directory keeps the embedded prompt
+    let prompts_dir : String = env::var("PROMPTS_DIR").ok().filter(|path| !path.trim().is_empty()).unwrap_or_else(|| "prompts".to_string());
+
+    // How long a finished run answers retries that carry the same idempotency key
+    let idempotency_ttl : Duration = Duration::from_secs(Self::parse_env("IDEMPOTENCY_TTL_SECS", 600));
+
+    // Decision logging is opt-in, leave DECISION_LOG_FILE unset to skip it. DECISION_LOG_FORMAT is jsonl (default) or csv
+    let decision_log_file : Option<String> = env::var("DECISION_LOG_FILE").ok().filter(|path| !path.trim().is_empty());
+    let decision_log_format : DecisionLogFormat = Self::parse_env("DECISION_LOG_FORMAT", DecisionLogFormat::default());
 
     return Config {
-      antropic_api_key, deepseek_api_key, groq_api_key, google_api_key, financial_datasets_api_key, openai_api_key
+      antropic_api_key, deepseek_api_key, groq_api_key, google_api_key, financial_datasets_api_key, financial_datasets_base_url, openai_api_key,
+      default_initial_cash, default_margin_requirement, cache_file, cache_ttl,
+      api_max_retries, api_retry_base_delay_ms, api_requests_per_second, run_history_size, llm_timeout_secs, ticker_fetch_concurrency, watchlist_file, prompts_dir,
+      idempotency_ttl, decision_log_file, decision_log_format
     }
   }
 
-}
\ No newline at end of file
+  // Fixed settings for unit tests, nothing is read from the environment and no keys are configured
+  #[cfg(test)]
+  pub fn for_tests() -> Self {
+    Config {
+      antropic_api_key: None, deepseek_api_key: None, groq_api_key: None, google_api_key: None, financial_datasets_api_key: None,
+      financial_datasets_base_url: "http://127.0.0.1:9".to_string(), openai_api_key: None,
+      default_initial_cash: 100000.0, default_margin_requirement: 0.0, cache_file: None, cache_ttl: CacheTtl::default(),
+      api_max_retries: 0, api_retry_base_delay_ms: 1, api_requests_per_second: 1000.0, run_history_size: 100, llm_timeout_secs: DEFAULT_LLM_TIMEOUT_SECS,
+      ticker_fetch_concurrency: 4, watchlist_file: None, prompts_dir: "prompts".to_string(),
+      idempotency_ttl: Duration::from_secs(600), decision_log_file: None, decision_log_format: DecisionLogFormat::default(),
+    }
+  }
+
+  fn parse_env<T: std::str::FromStr + std::fmt::Display>(name: &str, default: T) -> T {
+    match env::var(name) {
+      Ok(value) => value.trim().parse::<T>().unwrap_or_else(|_| {
+        log::error!("Warning: {} is not a valid value ({}), using default {}", name, value, default);
+        default
+      }),
+      Err(_) => default,
+    }
+  }
+
+  fn api_key(name: &str) -> Option<String> {
+    let key = env::var(name).ok().map(|key| key.trim().to_string()).filter(|key| !key.is_empty());
+    if key.is_none() {
+      log::warn!("{} is not set, features that need it will fail until it is configured", name);
+    }
+    return key;
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn capital_defaults_come_from_the_environment() {
+    env::set_var("DEFAULT_INITIAL_CASH", " 25000 ");
+    env::set_var("DEFAULT_MARGIN_REQUIREMENT", "not-a-number");
+    let config = Config::load();
+    env::remove_var("DEFAULT_INITIAL_CASH");
+    env::remove_var("DEFAULT_MARGIN_REQUIREMENT");
+
+    assert_eq!(config.default_initial_cash, 25000.0);
+    // An unparsable value keeps the built-in default
+    assert_eq!(config.default_margin_requirement, 0.0);
+  }
+
+  #[test]
+  fn blank_api_keys_count_as_unset() {
+    env::set_var("CONFIG_TEST_BLANK_API_KEY", "   ");
+    env::set_var("CONFIG_TEST_PADDED_API_KEY", " secret ");
+    assert_eq!(Config::api_key("CONFIG_TEST_BLANK_API_KEY"), None);
+    assert_eq!(Config::api_key("CONFIG_TEST_PADDED_API_KEY"), Some("secret".to_string()));
+    assert_eq!(Config::api_key("CONFIG_TEST_UNSET_API_KEY"), None);
+    env::remove_var("CONFIG_TEST_BLANK_API_KEY");
+    env::remove_var("CONFIG_TEST_PADDED_API_KEY");
+  }
+}
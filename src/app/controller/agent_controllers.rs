@@ -1,4 +1,3 @@
-use std::result::Result::{Ok};
 use std::sync::Arc;
 use std::collections::HashMap; 
 use anyhow::Error;
@@ -6,56 +5,200 @@ use serde_json::Value;
 
 use crate::app::services;
 use crate::app::services::service::{HedgeFundServices};
+use crate::app::models::options::HedgeFundOptions;
+use crate::ai_agent::llm::model_provider::with_token_sink;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::task::JoinHandle;
+use tokio::sync::broadcast;
+use crate::ai_agent::graph::reasoning::{self, ReasoningEvent};
+use crate::app::services::backtest::{BacktestResult, Cadence, StopLoss};
+use crate::app::services::idempotency::IdempotencyStore;
+use crate::app::services::run_store::RunRecord;
+use crate::app::services::watchlists::Watchlist;
+use crate::ai_agent::data::models::{CompanyFacts, CompanyNews, FinancialMetrics, InsiderTrade};
 
 pub struct AgentController {
-  services : Arc<HedgeFundServices>
+  services : Arc<HedgeFundServices>,
+  idempotency : Arc<IdempotencyStore>,
 }
 
 impl AgentController {
-  pub fn new(services: Arc<HedgeFundServices>) -> Self {
-    AgentController {services: services}
+  pub fn new(services: Arc<HedgeFundServices>, idempotency: Arc<IdempotencyStore>) -> Self {
+    AgentController {services: services, idempotency: idempotency}
   }
 
   pub async fn get_available_analysts(&self) -> Result<Vec<HashMap<String, String>>, Error> {
-    let analysts = match self.services.get_available_analysts() {
-      Ok(analysts) => analysts,
-      Err(e) => {
-        let error: Vec<HashMap<String, String>> = Vec::new();
-        log::error!("Cannot find an analysts with error: {}", e);
-        error
-      },
-    };
-    return Ok(analysts);
+    let result = self.services.get_available_analysts();
+    if let Err(e) = &result {
+      log::error!("Cannot find an analysts with error: {}", e);
+    }
+    return result;
+  }
+
+  pub async fn get_watchlists(&self) -> Result<Vec<Watchlist>, Error> {
+    return Ok(self.services.get_watchlists());
   }
 
   pub async fn get_available_model(&self) -> Result<(Vec<HashMap<String, String>>, Vec<HashMap<String, String>>), Error> {
-    let models = match self.services.get_available_models() {
-      Ok(models) => models, 
-      Err(e) => {
-        let error1: Vec<HashMap<String, String>> = Vec::new();
-        let error2: Vec<HashMap<String, String>> = Vec::new();
-        log::error!("Cannot find an analysts with error: {}", e);
-        (error1, error2)
-      }
-    }; 
+    let result = self.services.get_available_models();
+    if let Err(e) = &result {
+      log::error!("Cannot find available models with error: {}", e);
+    }
+    return result;
+  }
 
-    return Ok(models);
+  pub async fn get_provider_status(&self) -> Result<Vec<HashMap<String, String>>, Error> {
+    let result = self.services.get_provider_status();
+    if let Err(e) = &result {
+      log::error!("Cannot get provider status with error: {}", e);
+    }
+    return result;
+  }
+
+  pub async fn readiness(&self) -> Result<(bool, Value), Error> {
+    let result = self.services.readiness().await;
+    match &result {
+      Ok((false, checks)) => log::warn!("Readiness check failed: {}", checks),
+      Err(e) => log::error!("Cannot check readiness with error: {}", e),
+      _ => {}
+    }
+    return result;
+  }
+
+  pub async fn validate_model(&self, model_name: &str, model_provider: &str) -> Result<Value, Error> {
+    let result = self.services.validate_model(model_name, model_provider).await;
+    if let Err(e) = &result {
+      log::error!("Cannot validate model {} ({}) with error: {}", model_name, model_provider, e);
+    }
+    return result;
+  }
+
+  pub async fn signals(&self, tickers: Vec<String>, start_date: Option<&str>, end_date: Option<&str>,
+                       initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>,
+                       selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions) -> Result<HashMap<String, Value>, Error> {
+
+    let result = self.services.signals(tickers, start_date, end_date, initial_cash, margin_requirement, show_reasoning, selected_analysts, model_name, model_provider, options).await;
+    if let Err(e) = &result {
+      log::error!("Signals run failed with error: {}", e);
+    }
+    return result;
   }
 
   pub async fn hedge_fund(&self, tickers: Vec<String>, start_date: Option<&str>, end_date: Option<&str>, 
                           initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>, 
-                          selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>) -> Result<HashMap<String, Value>, Error> {
-
-    let result =  match self.services.hedge_fund(tickers, start_date, end_date, initial_cash, margin_requirement, show_reasoning, selected_analysts, model_name, model_provider).await {
-      Ok(data) => data,
-      Err(e) => {
-        log::error!("Cannot find an analysts with error: {}", e);
-        let error: HashMap<String, Value> = HashMap::new();
-        error
-      }
+                          selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions) -> Result<HashMap<String, Value>, Error> {
+
+    let idempotency_key = options.idempotency_key.clone().filter(|key| !key.trim().is_empty());
+    let services = self.services.clone();
+    let (start_date, end_date) = (start_date.map(str::to_string), end_date.map(str::to_string));
+    let run = async move {
+      services.hedge_fund(tickers, start_date.as_deref(), end_date.as_deref(), initial_cash, margin_requirement, show_reasoning, selected_analysts, model_name, model_provider, options).await
+    };
+
+    let result = match idempotency_key {
+      Some(key) => self.idempotency.run(&key, run).await,
+      None => run.await,
     };
+    if let Err(e) = &result {
+      log::error!("Hedge fund run failed with error: {}", e);
+    }
+    return result;
+  }
+
+  // Runs the hedge fund in the background, forwarding LLM content deltas as they arrive
+  pub fn hedge_fund_stream(&self, tickers: Vec<String>, start_date: Option<String>, end_date: Option<String>,
+                           initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>,
+                           selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions)
+                           -> (UnboundedReceiver<String>, JoinHandle<Result<HashMap<String, Value>, Error>>) {
+    let (sink, tokens) = unbounded_channel::<String>();
+    let services = self.services.clone();
+
+    let handle = actix_web::rt::spawn(async move {
+      let run = services.hedge_fund(tickers, start_date.as_deref(), end_date.as_deref(), initial_cash, margin_requirement, show_reasoning, selected_analysts, model_name, model_provider, options);
+      let result = with_token_sink(sink, run).await;
+      if let Err(e) = &result {
+        log::error!("Streaming hedge fund run failed with error: {}", e);
+      }
+      result
+    });
+
+    return (tokens, handle);
+  }
+
+  // Subscribes to the run's events before it starts so none are missed, aborting the handle cancels the run
+  pub fn hedge_fund_events(&self, tickers: Vec<String>, start_date: Option<String>, end_date: Option<String>,
+                           initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>,
+                           selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, mut options: HedgeFundOptions)
+                           -> (broadcast::Receiver<ReasoningEvent>, JoinHandle<Result<HashMap<String, Value>, Error>>) {
+    let run_id = options.run_id.get_or_insert_with(reasoning::new_run_id).clone();
+    let events = reasoning::subscribe(&run_id);
+    let services = self.services.clone();
+
+    let handle = actix_web::rt::spawn(async move {
+      let result = services.hedge_fund(tickers, start_date.as_deref(), end_date.as_deref(), initial_cash, margin_requirement, show_reasoning, selected_analysts, model_name, model_provider, options).await;
+      if let Err(e) = &result {
+        log::error!("WebSocket hedge fund run {} failed with error: {}", run_id, e);
+      }
+      result
+    });
+
+    return (events, handle);
+  }
 
-    return Ok(result);
+  pub async fn get_company_facts(&self, ticker: &str) -> Result<CompanyFacts, Error> {
+    let result = self.services.get_company_facts(ticker).await;
+    if let Err(e) = &result {
+      log::error!("Cannot get company facts for {} with error: {}", ticker, e);
+    }
+    return result;
   }
 
-}
\ No newline at end of file
+  pub async fn get_insider_trades(&self, ticker: &str, start_date: Option<&str>, end_date: Option<&str>) -> Result<Vec<InsiderTrade>, Error> {
+    let result = self.services.get_insider_trades(ticker, start_date, end_date).await;
+    if let Err(e) = &result {
+      log::error!("Cannot get insider trades for {} with error: {}", ticker, e);
+    }
+    return result;
+  }
+
+  pub async fn get_company_news(&self, ticker: &str, start_date: Option<&str>, end_date: Option<&str>, limit: Option<i64>) -> Result<Vec<CompanyNews>, Error> {
+    let result = self.services.get_company_news(ticker, start_date, end_date, limit).await;
+    if let Err(e) = &result {
+      log::error!("Cannot get company news for {} with error: {}", ticker, e);
+    }
+    return result;
+  }
+
+  pub async fn get_financial_metrics(&self, ticker: &str, end_date: Option<&str>, period: Option<&str>, limit: Option<i64>) -> Result<Vec<FinancialMetrics>, Error> {
+    let result = self.services.get_financial_metrics(ticker, end_date, period, limit).await;
+    if let Err(e) = &result {
+      log::error!("Cannot get financial metrics for {} with error: {}", ticker, e);
+    }
+    return result;
+  }
+
+  pub fn list_runs(&self) -> Vec<RunRecord> {
+    return self.services.list_runs();
+  }
+
+  pub fn get_run(&self, id: &str) -> Result<RunRecord, Error> {
+    return self.services.get_run(id);
+  }
+
+  // Reasoning blocks and node progress of the run tagged `run_id`
+  pub fn reasoning_stream(&self, run_id: &str) -> broadcast::Receiver<ReasoningEvent> {
+    return reasoning::subscribe(run_id);
+  }
+
+  pub async fn backtest(&self, tickers: Vec<String>, start_date: &str, end_date: &str, cadence: Option<Cadence>,
+                        initial_cash: Option<f64>, margin_requirement: Option<f64>, selected_analysts: Option<Vec<String>>,
+                        model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions,
+                        stop_loss: Option<StopLoss>) -> Result<BacktestResult, Error> {
+    let result = self.services.backtest(tickers, start_date, end_date, cadence, initial_cash, margin_requirement, selected_analysts, model_name, model_provider, options, stop_loss).await;
+    if let Err(e) = &result {
+      log::error!("Backtest failed with error: {}", e);
+    }
+    return result;
+  }
+
+}
@@ -7,20 +7,24 @@ use crate::app::routes::routes::Routes;
 use super::controller::agent_controllers::AgentController;
 use super::services::agent_service::AgentService;
 use super::services::service::HedgeFundServices;
+use super::services::idempotency::IdempotencyStore;
+use super::services::run_store::RunStore;
+use super::services::watchlists::Watchlists;
 
 #[derive(Clone)]
 pub struct AppState {
-  pub agent_controller: Arc<AgentController>
+  pub agent_controller: Arc<AgentController>,
+  pub run_store: Arc<RunStore>,
 }
 
 impl AppState {
 
   #[allow(unused)]
-  pub fn new(app_config: &Config) -> Self {
+  pub fn new(app_config: &Config, run_store: Arc<RunStore>, watchlists: Arc<Watchlists>, idempotency: Arc<IdempotencyStore>) -> Self {
     let agent_service : AgentService = AgentService::new(app_config.clone());
-    let hedge_fund_service: Arc<HedgeFundServices> = Arc::new(HedgeFundServices::new(agent_service));
-    let agent_controller : Arc<AgentController> = Arc::new(AgentController::new(hedge_fund_service.clone()));
-    AppState { agent_controller }
+    let hedge_fund_service: Arc<HedgeFundServices> = Arc::new(HedgeFundServices::new(agent_service, run_store.clone(), watchlists));
+    let agent_controller : Arc<AgentController> = Arc::new(AgentController::new(hedge_fund_service.clone(), idempotency));
+    AppState { agent_controller, run_store }
   }
 }
 
@@ -31,14 +35,16 @@ pub struct CreateApp {
 }
 
 impl CreateApp {
-  pub fn new(app_settings: Config) -> Self {
-    let app_state: AppState = AppState::new(&app_settings);
+  // The run store, watchlists and idempotency keys are passed in so every worker shares them
+  pub fn new(app_settings: Config, run_store: Arc<RunStore>, watchlists: Arc<Watchlists>, idempotency: Arc<IdempotencyStore>) -> Self {
+    let app_state: AppState = AppState::new(&app_settings, run_store, watchlists, idempotency);
     CreateApp { app_state, app_settings  }
   }
 
   pub fn build_app(&self,) -> App<impl actix_web::dev::ServiceFactory<actix_web::dev::ServiceRequest,Config = (),Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,Error = actix_web::Error,InitError = (),>,> {
     App::new()
     .app_data(web::Data::new(self.app_state.agent_controller.clone()))
+    .app_data(web::Data::new(self.app_state.run_store.clone()))
     .configure(Routes::configure)
   }
 }
@@ -0,0 +1,111 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use reqwest::StatusCode as UpstreamStatus;
+use std::fmt;
+
+use crate::ai_agent::error::AgentError;
+
+// Failures surfaced to HTTP clients, each with a stable error code
+#[derive(Debug, Clone)]
+pub enum ServiceError {
+  InvalidInput(String),        // the request itself is wrong
+  NotFound(String),
+  UpstreamAuth(String),        // a data or LLM provider key is missing or was rejected
+  RateLimited(String),         // a provider kept answering 429
+  UpstreamUnavailable(String), // a provider could not be reached or timed out
+  Upstream(String),            // a provider answered with an unexpected error status
+  Internal(String),
+}
+
+impl ServiceError {
+  pub fn code(&self) -> &'static str {
+    match self {
+      ServiceError::InvalidInput(_) => "invalid_request",
+      ServiceError::NotFound(_) => "not_found",
+      ServiceError::UpstreamAuth(_) => "upstream_auth_failed",
+      ServiceError::RateLimited(_) => "rate_limited",
+      ServiceError::UpstreamUnavailable(_) => "upstream_unavailable",
+      ServiceError::Upstream(_) => "upstream_error",
+      ServiceError::Internal(_) => "internal_error",
+    }
+  }
+
+  pub fn message(&self) -> &str {
+    match self {
+      ServiceError::InvalidInput(message)
+      | ServiceError::NotFound(message)
+      | ServiceError::UpstreamAuth(message)
+      | ServiceError::RateLimited(message)
+      | ServiceError::UpstreamUnavailable(message)
+      | ServiceError::Upstream(message)
+      | ServiceError::Internal(message) => message,
+    }
+  }
+
+  // Picks the most specific cause in the error chain, anything unrecognised is internal
+  pub fn from_error(error: &anyhow::Error) -> Self {
+    let message = format!("{:#}", error);
+    for cause in error.chain() {
+      if let Some(service_error) = cause.downcast_ref::<ServiceError>() {
+        return service_error.clone();
+      }
+      if let Some(agent_error) = cause.downcast_ref::<AgentError>() {
+        return Self::from_agent_error(agent_error, message);
+      }
+      if let Some(request_error) = cause.downcast_ref::<reqwest::Error>() {
+        return Self::from_request_error(request_error, message);
+      }
+    }
+    return ServiceError::Internal(message);
+  }
+
+  fn from_agent_error(error: &AgentError, message: String) -> Self {
+    match error {
+      AgentError::MissingApiKey(_) => ServiceError::UpstreamAuth(message),
+      AgentError::RateLimited(_) => ServiceError::RateLimited(message),
+      AgentError::CircuitOpen(_) => ServiceError::UpstreamUnavailable(message),
+      AgentError::UpstreamHttp { status, .. } if *status == UpstreamStatus::UNAUTHORIZED || *status == UpstreamStatus::FORBIDDEN => ServiceError::UpstreamAuth(message),
+      AgentError::UpstreamHttp { .. } | AgentError::LlmParse(_) | AgentError::Json(_) => ServiceError::Upstream(message),
+      AgentError::DataUnavailable(_) => ServiceError::NotFound(message),
+      AgentError::Unsupported(_) => ServiceError::InvalidInput(message),
+      AgentError::Http(request_error) => Self::from_request_error(request_error, message),
+    }
+  }
+
+  fn from_request_error(error: &reqwest::Error, message: String) -> Self {
+    match error.status() {
+      Some(UpstreamStatus::UNAUTHORIZED) | Some(UpstreamStatus::FORBIDDEN) => ServiceError::UpstreamAuth(message),
+      Some(UpstreamStatus::TOO_MANY_REQUESTS) => ServiceError::RateLimited(message),
+      Some(_) => ServiceError::Upstream(message),
+      None if error.is_connect() || error.is_timeout() => ServiceError::UpstreamUnavailable(message),
+      None => ServiceError::Upstream(message),
+    }
+  }
+}
+
+impl fmt::Display for ServiceError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.code(), self.message())
+  }
+}
+
+impl std::error::Error for ServiceError {}
+
+impl ResponseError for ServiceError {
+  fn status_code(&self) -> StatusCode {
+    match self {
+      ServiceError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+      ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+      ServiceError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+      ServiceError::UpstreamAuth(_) | ServiceError::Upstream(_) => StatusCode::BAD_GATEWAY,
+      ServiceError::UpstreamUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+      ServiceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+
+  fn error_response(&self) -> HttpResponse {
+    HttpResponse::build(self.status_code()).json(serde_json::json!({
+      "error": self.message(),
+      "code": self.code(),
+    }))
+  }
+}
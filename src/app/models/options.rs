@@ -0,0 +1,80 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+
+use crate::ai_agent::agents::valuation_analyst::ValuationAssumptions;
+use crate::ai_agent::data::models::{Portfolio, ReportPeriod};
+use crate::ai_agent::llm::ensemble::EnsembleModel;
+use crate::ai_agent::utils::signals::ConsensusWeighting;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+  Advisory, // signals and suggested portfolio weights
+  #[default]
+  Orders,   // concrete share quantities
+}
+
+impl OutputMode {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      OutputMode::Advisory => "advisory",
+      OutputMode::Orders => "orders",
+    }
+  }
+}
+
+// How the portfolio manager expresses order size, shares directly or a dollar amount converted at the current price
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizingMode {
+  #[default]
+  Shares,
+  Notional,
+}
+
+impl SizingMode {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      SizingMode::Shares => "shares",
+      SizingMode::Notional => "notional",
+    }
+  }
+}
+
+// Extra line items on top of each analyst's own set, for every analyst or per analyst key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LineItemExtras {
+  All(Vec<String>),
+  ByAnalyst(HashMap<String, Vec<String>>),
+}
+
+// Optional run-level tuning knobs, flattened into the hedge-fund request body
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HedgeFundOptions {
+  pub allow_fractional: Option<bool>, // whole shares only when unset, for traditional brokerages
+  pub sizing: Option<SizingMode>,
+  pub decision_temperature: Option<f32>,
+  pub temperature: Option<f32>, // analyst LLM calls, the portfolio manager keeps decision_temperature when set
+  pub top_p: Option<f32>,
+  pub max_tokens: Option<u32>,
+  pub deterministic: Option<bool>, // temperature 0 and a fixed seed on providers that support one, for reproducible runs
+  pub analyst_timeout_secs: Option<f64>,
+  pub llm_timeout_secs: Option<f64>,
+  pub min_analysts: Option<u32>,
+  pub max_sector_weight: Option<f64>, // cap on combined long exposure per sector, 0.40 when unset
+  pub fill_gaps: Option<bool>,
+  pub period: Option<ReportPeriod>, // reporting basis for every agent's financial data, each agent keeps its own default when unset
+  pub line_items: Option<LineItemExtras>,
+  pub output_mode: Option<OutputMode>,
+  pub consensus_weighting: Option<ConsensusWeighting>,
+  pub valuation_assumptions: Option<ValuationAssumptions>,
+  pub dry_run: Option<bool>, // derive signals and decisions from the computed scores without calling any LLM
+  pub prompt_overrides: Option<HashMap<String, String>>, // template text keyed like the prompt files, e.g. "warren_buffett.system"
+  pub token_budget: Option<u64>, // completion tokens for the whole run, agents fall back to their dry-run output once it is spent
+  pub ensemble_models: Option<Vec<EnsembleModel>>, // two or more models vote on each Buffett and Graham signal
+  pub watchlist: Option<String>, // named basket from /agent/watchlists, unioned with the explicit tickers
+  pub run_id: Option<String>, // subscribe to /agent/reasoning/{run_id} to follow the run live
+  pub idempotency_key: Option<String>, // retries of /agent/investment with the same key share one run
+  pub portfolio: Option<Portfolio>, // starting book, e.g. the `portfolio` of an earlier run, instead of a flat one built from initial_cash
+}
@@ -1,12 +1,24 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use actix_ws::{Message, MessageStream, Session};
 use std::{sync::Arc};
+use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use actix_web::web::Bytes;
+use futures::stream;
+use chrono::NaiveDate;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 
-use crate::{ app::{controller::agent_controllers::AgentController}};
+use crate::ai_agent::graph::graph::MAX_ANALYST_TIMEOUT_SECS;
+use crate::ai_agent::graph::reasoning::ReasoningEvent;
+use crate::ai_agent::llm::models::{MAX_LLM_TIMEOUT_SECS, MAX_TEMPERATURE};
+use crate::ai_agent::utils::analysts::get_analyst_order;
+use crate::{ app::{controller::agent_controllers::AgentController, models::{errors::ServiceError, options::HedgeFundOptions}, services::backtest::{Cadence, StopLoss}}};
 
 #[derive(Deserialize, Serialize)]
 pub struct AgentHedgeFundRequest {
-  tickers: Vec<String>,
+  #[serde(default)]
+  tickers: Vec<String>, // may be left out when a watchlist is given
   start_date: Option<String>,
   end_date: Option<String>,
   initial_cash: Option<f64>,
@@ -15,8 +27,207 @@ pub struct AgentHedgeFundRequest {
   selected_analysts: Option<Vec<String>>,
   model_name: Option<String>,
   model_provider: Option<String>,
+  #[serde(flatten)]
+  options: HedgeFundOptions,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+  field: String,
+  message: String,
+}
+
+impl FieldError {
+  fn new(field: &str, message: impl Into<String>) -> Self {
+    FieldError { field: field.to_string(), message: message.into() }
+  }
 }
 
+impl AgentHedgeFundRequest {
+  // Checks the payload up front so bad input is rejected with a 400 instead of failing deep inside the run
+  pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let errors = validate_run(&self.tickers, self.start_date.as_deref(), self.end_date.as_deref(), self.initial_cash, self.margin_requirement,
+                              self.selected_analysts.as_deref(), &self.options);
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+  }
+}
+
+// Shared by every request that starts a run, tuning knobs are rejected here rather than silently replaced by defaults later
+fn validate_run(tickers: &[String], start_date: Option<&str>, end_date: Option<&str>, initial_cash: Option<f64>, margin_requirement: Option<f64>,
+                selected_analysts: Option<&[String]>, options: &HedgeFundOptions) -> Vec<FieldError> {
+  let mut errors : Vec<FieldError> = Vec::new();
+
+  if tickers.is_empty() && options.watchlist.is_none() {
+    errors.push(FieldError::new("tickers", "at least one ticker or a watchlist is required"));
+  }
+  for ticker in tickers {
+    let plausible = !ticker.is_empty() && ticker.len() <= 10
+      && ticker.starts_with(|c: char| c.is_ascii_alphanumeric())
+      && ticker.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !plausible {
+      errors.push(FieldError::new("tickers", format!("'{}' is not a valid ticker symbol", ticker)));
+    }
+  }
+
+  let mut parse_date = |field: &str, value: Option<&str>| -> Option<NaiveDate> {
+    let value = value?;
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+      Ok(date) => Some(date),
+      Err(_) => {
+        errors.push(FieldError::new(field, format!("'{}' is not a valid YYYY-MM-DD date", value)));
+        None
+      }
+    }
+  };
+  let start_date = parse_date("start_date", start_date);
+  let end_date = parse_date("end_date", end_date);
+  if let (Some(start_date), Some(end_date)) = (start_date, end_date) {
+    if start_date > end_date {
+      errors.push(FieldError::new("start_date", format!("start_date {} is after end_date {}", start_date, end_date)));
+    }
+  }
+
+  if let Some(initial_cash) = initial_cash {
+    if !initial_cash.is_finite() || initial_cash < 0.0 {
+      errors.push(FieldError::new("initial_cash", "must be greater than or equal to 0"));
+    }
+  }
+  if let Some(margin_requirement) = margin_requirement {
+    if !(0.0..=1.0).contains(&margin_requirement) {
+      errors.push(FieldError::new("margin_requirement", "must be between 0 and 1"));
+    }
+  }
+  if let Some(max_sector_weight) = options.max_sector_weight {
+    if !(max_sector_weight > 0.0 && max_sector_weight <= 1.0) {
+      errors.push(FieldError::new("max_sector_weight", "must be greater than 0 and at most 1"));
+    }
+  }
+
+  if let Some(portfolio) = &options.portfolio {
+    // The supplied book carries its own cash and margin requirement
+    if initial_cash.is_some() {
+      errors.push(FieldError::new("initial_cash", "cannot be combined with portfolio, set portfolio.cash instead"));
+    }
+    if margin_requirement.is_some() {
+      errors.push(FieldError::new("margin_requirement", "cannot be combined with portfolio, set portfolio.margin_requirement instead"));
+    }
+    if !portfolio.cash.is_finite() || portfolio.cash < 0.0 {
+      errors.push(FieldError::new("portfolio.cash", "must be greater than or equal to 0"));
+    }
+    if !(0.0..=1.0).contains(&portfolio.margin_requirement) {
+      errors.push(FieldError::new("portfolio.margin_requirement", "must be between 0 and 1"));
+    }
+    for (ticker, position) in &portfolio.positions {
+      let amounts = [position.long, position.short, position.long_cost_basis, position.short_cost_basis, position.short_margin_used];
+      if amounts.iter().any(|amount| !amount.is_finite() || *amount < 0.0) {
+        errors.push(FieldError::new("portfolio.positions", format!("{} has a negative or non-finite amount", ticker)));
+      }
+    }
+  }
+
+  let mut check_seconds = |field: &str, value: Option<f64>, max_secs: f64| {
+    if let Some(seconds) = value {
+      if !(seconds > 0.0 && seconds <= max_secs) {
+        errors.push(FieldError::new(field, format!("must be greater than 0 and at most {} seconds", max_secs)));
+      }
+    }
+  };
+  check_seconds("analyst_timeout_secs", options.analyst_timeout_secs, MAX_ANALYST_TIMEOUT_SECS);
+  check_seconds("llm_timeout_secs", options.llm_timeout_secs, MAX_LLM_TIMEOUT_SECS);
+
+  if let Some(min_analysts) = options.min_analysts {
+    // More than the run has analysts would hold every ticker
+    let analysts = selected_analysts.filter(|selected| !selected.is_empty()).map_or_else(|| get_analyst_order().len(), |selected| selected.len());
+    if min_analysts as usize > analysts {
+      errors.push(FieldError::new("min_analysts", format!("must be at most the {} analysts in the run", analysts)));
+    }
+  }
+  if options.token_budget == Some(0) {
+    errors.push(FieldError::new("token_budget", "must be greater than 0, leave it unset for no budget"));
+  }
+  if options.max_tokens == Some(0) {
+    errors.push(FieldError::new("max_tokens", "must be greater than 0"));
+  }
+  for (field, temperature) in [("temperature", options.temperature), ("decision_temperature", options.decision_temperature)] {
+    if let Some(temperature) = temperature {
+      if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+        errors.push(FieldError::new(field, format!("must be between 0 and {}", MAX_TEMPERATURE)));
+      }
+    }
+  }
+  if let Some(top_p) = options.top_p {
+    if !(top_p > 0.0 && top_p <= 1.0) {
+      errors.push(FieldError::new("top_p", "must be greater than 0 and at most 1"));
+    }
+  }
+
+  errors
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AgentBacktestRequest {
+  #[serde(default)]
+  tickers: Vec<String>,
+  start_date: String,
+  end_date: String,
+  cadence: Option<Cadence>,
+  initial_cash: Option<f64>,
+  margin_requirement: Option<f64>,
+  selected_analysts: Option<Vec<String>>,
+  model_name: Option<String>,
+  model_provider: Option<String>,
+  stop_loss: Option<StopLoss>, // closes positions that move pct against their entry, or their best mark for trailing stops
+  #[serde(flatten)]
+  options: HedgeFundOptions,
+}
+
+impl AgentBacktestRequest {
+  pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+    let mut errors = validate_run(&self.tickers, Some(&self.start_date), Some(&self.end_date), self.initial_cash, self.margin_requirement,
+                                  self.selected_analysts.as_deref(), &self.options);
+    if let Some(stop_loss) = self.stop_loss {
+      if !(stop_loss.pct > 0.0 && stop_loss.pct < 1.0) {
+        errors.push(FieldError::new("stop_loss.pct", "must be greater than 0 and less than 1"));
+      }
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+  }
+}
+
+
+#[derive(Deserialize)]
+pub struct ValidateModelRequest {
+  model_name: String,
+  model_provider: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompanyFactsQuery {
+  ticker: String,
+}
+
+#[derive(Deserialize)]
+pub struct InsiderTradesQuery {
+  ticker: String,
+  start_date: Option<String>,
+  end_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CompanyNewsQuery {
+  ticker: String,
+  start_date: Option<String>,
+  end_date: Option<String>,
+  limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct FinancialMetricsQuery {
+  ticker: String,
+  end_date: Option<String>,
+  period: Option<String>,
+  limit: Option<i64>,
+}
 
 pub struct Routes;
 
@@ -29,9 +240,24 @@ impl Routes {
 
   pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/").route(web::get().to(Self::health)));
+    cfg.service(web::resource("/readyz").route(web::get().to(Self::readiness)));
     cfg.service(web::resource("/agent/analysts").route(web::get().to(Self::get_analysts)));
+    cfg.service(web::resource("/agent/watchlists").route(web::get().to(Self::get_watchlists)));
     cfg.service(web::resource("/agent/models").route(web::get().to(Self::get_models)));
+    cfg.service(web::resource("/agent/models/validate").route(web::post().to(Self::validate_model)));
+    cfg.service(web::resource("/agent/providers").route(web::get().to(Self::get_providers)));
     cfg.service(web::resource("/agent/investment").route(web::post().to(Self::hedge_fund)));
+    cfg.service(web::resource("/agent/signals").route(web::post().to(Self::signals)));
+    cfg.service(web::resource("/agent/investment/stream").route(web::post().to(Self::hedge_fund_stream)));
+    cfg.service(web::resource("/agent/ws").route(web::get().to(Self::hedge_fund_ws)));
+    cfg.service(web::resource("/agent/reasoning/{run_id}").route(web::get().to(Self::reasoning)));
+    cfg.service(web::resource("/agent/company-facts").route(web::get().to(Self::get_company_facts)));
+    cfg.service(web::resource("/agent/insider-trades").route(web::get().to(Self::get_insider_trades)));
+    cfg.service(web::resource("/agent/news").route(web::get().to(Self::get_company_news)));
+    cfg.service(web::resource("/agent/financial-metrics").route(web::get().to(Self::get_financial_metrics)));
+    cfg.service(web::resource("/agent/runs").route(web::get().to(Self::list_runs)));
+    cfg.service(web::resource("/agent/runs/{id}").route(web::get().to(Self::get_run)));
+    cfg.service(web::resource("/agent/backtest").route(web::post().to(Self::backtest)));
   }
 
   async fn health() -> impl Responder {
@@ -42,21 +268,55 @@ impl Routes {
     }))
   }
 
+  // Liveness stays on `/`, this one checks the dependencies and answers 503 while any is down
+  async fn readiness(controller: web::Data<Arc<AgentController>>) -> impl Responder {
+    match controller.readiness().await {
+      Ok((true, checks)) => HttpResponse::Ok().json(checks),
+      Ok((false, checks)) => HttpResponse::ServiceUnavailable().json(checks),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
   async fn get_analysts(controller: web::Data<Arc<AgentController>>) -> impl Responder {
     match controller.get_available_analysts().await {
       Ok(analysts) => HttpResponse::Ok().json(analysts),
-      Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn get_watchlists(controller: web::Data<Arc<AgentController>>) -> impl Responder {
+    match controller.get_watchlists().await {
+      Ok(watchlists) => HttpResponse::Ok().json(watchlists),
+      Err(e) => ServiceError::from_error(&e).error_response(),
     }
   }
 
   async fn get_models(controller: web::Data<Arc<AgentController>>) -> impl Responder {
     match controller.get_available_model().await {
       Ok(model) => HttpResponse::Ok().json(model),
-      Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()})),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn validate_model(controller: web::Data<Arc<AgentController>>, request: web::Json<ValidateModelRequest>) -> impl Responder {
+    match controller.validate_model(&request.model_name, &request.model_provider).await {
+      Ok(result) => HttpResponse::Ok().json(result),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn get_providers(controller: web::Data<Arc<AgentController>>) -> impl Responder {
+    match controller.get_provider_status().await {
+      Ok(providers) => HttpResponse::Ok().json(providers),
+      Err(e) => ServiceError::from_error(&e).error_response(),
     }
   }
 
   async fn hedge_fund(controller: web::Data<Arc<AgentController>>, request: web::Json<AgentHedgeFundRequest>) -> impl Responder {
+    if let Err(errors) = request.validate() {
+      return Self::validation_error(errors);
+    }
+
     // let tickers = request.tickers.clone
     let tickers = request.tickers.clone();
     let start_date = request.start_date.as_deref();
@@ -66,17 +326,392 @@ impl Routes {
     let model_name = request.model_name.clone();
     let model_provider = request.model_provider.clone();
 
-    let result = controller.hedge_fund(tickers, start_date, end_date, request.initial_cash, request.margin_requirement, request.show_reasoning, selected_analysts, model_name, model_provider).await;
+    let result = controller.hedge_fund(tickers, start_date, end_date, request.initial_cash, request.margin_requirement, request.show_reasoning, selected_analysts, model_name, model_provider, request.options.clone()).await;
+
+    match result {
+      Ok(data) => HttpResponse::Ok().json(data),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+
+
+  }
+
+  // Same body as /agent/investment, runs the analysts and risk manager but skips the portfolio manager
+  async fn signals(controller: web::Data<Arc<AgentController>>, request: web::Json<AgentHedgeFundRequest>) -> impl Responder {
+    if let Err(errors) = request.validate() {
+      return Self::validation_error(errors);
+    }
+
+    let request = request.into_inner();
+    let result = controller.signals(request.tickers, request.start_date.as_deref(), request.end_date.as_deref(), request.initial_cash, request.margin_requirement,
+                                    request.show_reasoning, request.selected_analysts, request.model_name, request.model_provider, request.options).await;
 
     match result {
       Ok(data) => HttpResponse::Ok().json(data),
-      Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
-          "error": e.to_string(),
-      }))
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn hedge_fund_stream(controller: web::Data<Arc<AgentController>>, request: web::Json<AgentHedgeFundRequest>) -> impl Responder {
+    // Server-sent events: one `data:` event per LLM content delta, then a `result` event with the final output
+    if let Err(errors) = request.validate() {
+      return Self::validation_error(errors);
+    }
+
+    let request = request.into_inner();
+    let (tokens, handle) = controller.hedge_fund_stream(request.tickers, request.start_date, request.end_date, request.initial_cash, request.margin_requirement,
+                                                        request.show_reasoning, request.selected_analysts, request.model_name, request.model_provider, request.options);
+
+    let events = stream::unfold((tokens, Some(handle)), |(mut tokens, handle)| async move {
+      if let Some(token) = tokens.recv().await {
+        let event = format!("data: {}\n\n", Value::from(token));
+        return Some((Ok::<Bytes, actix_web::Error>(Bytes::from(event)), (tokens, handle)));
+      }
+
+      // The token channel closes once the run finishes
+      let payload = match handle?.await {
+        Ok(Ok(result)) => serde_json::json!(result),
+        Ok(Err(e)) => Self::error_payload(ServiceError::from_error(&e)),
+        Err(e) => Self::error_payload(ServiceError::Internal(e.to_string())),
+      };
+      let event = format!("event: result\ndata: {}\n\n", payload);
+      Some((Ok(Bytes::from(event)), (tokens, None)))
+    });
+
+    HttpResponse::Ok()
+      .content_type("text/event-stream")
+      .insert_header(("Cache-Control", "no-cache"))
+      .streaming(events)
+  }
+
+  async fn hedge_fund_ws(req: HttpRequest, body: web::Payload, controller: web::Data<Arc<AgentController>>) -> Result<HttpResponse, actix_web::Error> {
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(Self::hedge_fund_session(controller.get_ref().clone(), session, messages));
+    Ok(response)
+  }
+
+  // The first text frame is the hedge-fund request, then one frame per node event and a final result frame.
+  // A client that disconnects mid-run cancels it so no more LLM calls are made on its behalf
+  async fn hedge_fund_session(controller: Arc<AgentController>, mut session: Session, mut messages: MessageStream) {
+    let request : AgentHedgeFundRequest = loop {
+      match messages.recv().await {
+        Some(Ok(Message::Text(text))) => match serde_json::from_str::<AgentHedgeFundRequest>(&text) {
+          Ok(request) => break request,
+          Err(e) => {
+            let payload = Self::error_payload(ServiceError::InvalidInput(format!("Invalid hedge fund request: {}", e)));
+            let _ = session.text(payload.to_string()).await;
+            let _ = session.close(None).await;
+            return;
+          }
+        },
+        Some(Ok(Message::Ping(bytes))) => {
+          if session.pong(&bytes).await.is_err() {
+            return;
+          }
+        }
+        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+        Some(Ok(_)) => {}
+      }
+    };
+
+    if let Err(errors) = request.validate() {
+      let error = ServiceError::InvalidInput("Invalid hedge fund request".to_string());
+      let payload = serde_json::json!({"error": error.message(), "code": error.code(), "fields": errors});
+      let _ = session.text(payload.to_string()).await;
+      let _ = session.close(None).await;
+      return;
+    }
+
+    let (mut events, mut handle) = controller.hedge_fund_events(request.tickers, request.start_date, request.end_date, request.initial_cash, request.margin_requirement,
+                                                                request.show_reasoning, request.selected_analysts, request.model_name, request.model_provider, request.options);
+    // A node's signals are published just before its "done" status and sent along with it
+    let mut signals : HashMap<String, Value> = HashMap::new();
+    let mut events_open = true;
+
+    let result = loop {
+      tokio::select! {
+        outcome = &mut handle => break outcome,
+        event = events.recv(), if events_open => match event {
+          Ok(event) => {
+            if let Some(frame) = Self::ws_event_frame(event, &mut signals) {
+              if session.text(frame.to_string()).await.is_err() {
+                handle.abort();
+                return;
+              }
+            }
+          }
+          Err(RecvError::Lagged(skipped)) => log::warn!("WebSocket subscriber lagged, {} events skipped", skipped),
+          Err(RecvError::Closed) => events_open = false,
+        },
+        message = messages.recv() => match message {
+          Some(Ok(Message::Ping(bytes))) => {
+            let _ = session.pong(&bytes).await;
+          }
+          Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+            log::info!("WebSocket client disconnected, cancelling the in-flight run");
+            handle.abort();
+            return;
+          }
+          Some(Ok(_)) => {}
+        },
+      }
+    };
+
+    // Events published right before the run finished may still be queued
+    loop {
+      match events.try_recv() {
+        Ok(event) => {
+          if let Some(frame) = Self::ws_event_frame(event, &mut signals) {
+            let _ = session.text(frame.to_string()).await;
+          }
+        }
+        Err(TryRecvError::Lagged(_)) => continue,
+        Err(_) => break,
+      }
+    }
+
+    let payload = match result {
+      Ok(Ok(result)) => serde_json::json!({"status": "complete", "result": result}),
+      Ok(Err(e)) => Self::error_payload(ServiceError::from_error(&e)),
+      Err(e) => Self::error_payload(ServiceError::Internal(e.to_string())),
+    };
+    let _ = session.text(payload.to_string()).await;
+    let _ = session.close(None).await;
+  }
+
+  fn ws_event_frame(event: ReasoningEvent, signals: &mut HashMap<String, Value>) -> Option<Value> {
+    match event.kind.as_str() {
+      "signal" => {
+        signals.insert(event.agent, event.content);
+        None
+      }
+      "status" => {
+        let mut frame = serde_json::json!({"agent": event.agent, "status": event.content});
+        if let Some(signal) = signals.remove(&event.agent) {
+          frame["signal"] = signal;
+        }
+        Some(frame)
+      }
+      _ => Some(serde_json::json!({"agent": event.agent, "reasoning": event.content})),
+    }
+  }
+
+  async fn reasoning(controller: web::Data<Arc<AgentController>>, run_id: web::Path<String>) -> impl Responder {
+    // Server-sent events: one event per reasoning block or node status change, the stream ends with the run
+    let receiver = controller.reasoning_stream(&run_id);
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+      loop {
+        match receiver.recv().await {
+          Ok(event) => {
+            let payload = serde_json::json!({"agent": event.agent, "content": event.content});
+            let frame = format!("event: {}\ndata: {}\n\n", event.kind, payload);
+            return Some((Ok::<Bytes, actix_web::Error>(Bytes::from(frame)), receiver));
+          }
+          Err(RecvError::Lagged(skipped)) => {
+            log::warn!("Reasoning subscriber lagged, {} events skipped", skipped);
+            continue;
+          }
+          Err(RecvError::Closed) => return None,
+        }
+      }
+    });
+
+    HttpResponse::Ok()
+      .content_type("text/event-stream")
+      .insert_header(("Cache-Control", "no-cache"))
+      .streaming(events)
+  }
+
+  async fn get_company_facts(controller: web::Data<Arc<AgentController>>, query: web::Query<CompanyFactsQuery>) -> impl Responder {
+    match controller.get_company_facts(&query.ticker).await {
+      Ok(facts) => HttpResponse::Ok().json(facts),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn get_insider_trades(controller: web::Data<Arc<AgentController>>, query: web::Query<InsiderTradesQuery>) -> impl Responder {
+    match controller.get_insider_trades(&query.ticker, query.start_date.as_deref(), query.end_date.as_deref()).await {
+      Ok(trades) => HttpResponse::Ok().json(trades),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn get_company_news(controller: web::Data<Arc<AgentController>>, query: web::Query<CompanyNewsQuery>) -> impl Responder {
+    match controller.get_company_news(&query.ticker, query.start_date.as_deref(), query.end_date.as_deref(), query.limit).await {
+      Ok(news) => HttpResponse::Ok().json(news),
+      Err(e) => ServiceError::from_error(&e).error_response(),
     }
+  }
+
+  async fn get_financial_metrics(controller: web::Data<Arc<AgentController>>, query: web::Query<FinancialMetricsQuery>) -> impl Responder {
+    match controller.get_financial_metrics(&query.ticker, query.end_date.as_deref(), query.period.as_deref(), query.limit).await {
+      Ok(metrics) => HttpResponse::Ok().json(metrics),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  async fn list_runs(controller: web::Data<Arc<AgentController>>) -> impl Responder {
+    HttpResponse::Ok().json(controller.list_runs())
+  }
 
+  async fn get_run(controller: web::Data<Arc<AgentController>>, id: web::Path<String>) -> impl Responder {
+    match controller.get_run(&id) {
+      Ok(run) => HttpResponse::Ok().json(run),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+  fn validation_error(errors: Vec<FieldError>) -> HttpResponse {
+    let error = ServiceError::InvalidInput("Invalid hedge fund request".to_string());
+    HttpResponse::BadRequest().json(serde_json::json!({
+      "error": error.message(),
+      "code": error.code(),
+      "fields": errors,
+    }))
+  }
 
+  fn error_payload(error: ServiceError) -> Value {
+    serde_json::json!({"error": error.message(), "code": error.code()})
   }
 
+  async fn backtest(controller: web::Data<Arc<AgentController>>, request: web::Json<AgentBacktestRequest>) -> impl Responder {
+    if let Err(errors) = request.validate() {
+      return Self::validation_error(errors);
+    }
 
-}
\ No newline at end of file
+    let request = request.into_inner();
+
+    let result = controller.backtest(request.tickers, &request.start_date, &request.end_date, request.cadence, request.initial_cash, request.margin_requirement,
+                                     request.selected_analysts, request.model_name, request.model_provider, request.options, request.stop_loss).await;
+
+    match result {
+      Ok(data) => HttpResponse::Ok().json(data),
+      Err(e) => ServiceError::from_error(&e).error_response(),
+    }
+  }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn hedge_fund_request(extra: Value) -> AgentHedgeFundRequest {
+    let mut body = json!({ "tickers": ["AAPL"] });
+    body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    serde_json::from_value(body).unwrap()
+  }
+
+  fn rejected_fields(result: Result<(), Vec<FieldError>>) -> Vec<String> {
+    result.err().unwrap_or_default().into_iter().map(|error| error.field).collect()
+  }
+
+  #[test]
+  fn a_plain_request_passes() {
+    assert!(hedge_fund_request(json!({ "start_date": "2024-01-02", "end_date": "2024-03-28", "temperature": 0.7 })).validate().is_ok());
+  }
+
+  #[test]
+  fn timeouts_outside_the_accepted_range_are_rejected() {
+    for timeout in [0.0, -1.0, 1e20] {
+      let request = hedge_fund_request(json!({ "analyst_timeout_secs": timeout, "llm_timeout_secs": timeout }));
+      assert_eq!(rejected_fields(request.validate()), vec!["analyst_timeout_secs", "llm_timeout_secs"]);
+    }
+    assert!(hedge_fund_request(json!({ "analyst_timeout_secs": 30.0, "llm_timeout_secs": 3600.0 })).validate().is_ok());
+  }
+
+  #[test]
+  fn min_analysts_cannot_exceed_the_analysts_in_the_run() {
+    let request = hedge_fund_request(json!({ "selected_analysts": ["warren_buffett", "ben_graham"], "min_analysts": 3 }));
+    assert_eq!(rejected_fields(request.validate()), vec!["min_analysts"]);
+    assert!(hedge_fund_request(json!({ "selected_analysts": ["warren_buffett", "ben_graham"], "min_analysts": 2 })).validate().is_ok());
+    assert_eq!(rejected_fields(hedge_fund_request(json!({ "min_analysts": 1000 })).validate()), vec!["min_analysts"]);
+  }
+
+  #[test]
+  fn a_zero_token_budget_is_rejected() {
+    assert_eq!(rejected_fields(hedge_fund_request(json!({ "token_budget": 0 })).validate()), vec!["token_budget"]);
+    assert!(hedge_fund_request(json!({ "token_budget": 5000 })).validate().is_ok());
+  }
+
+  #[test]
+  fn sampling_knobs_outside_their_range_are_rejected() {
+    let request = hedge_fund_request(json!({ "temperature": 3.5, "decision_temperature": -0.1, "top_p": 0.0 }));
+    assert_eq!(rejected_fields(request.validate()), vec!["temperature", "decision_temperature", "top_p"]);
+    assert_eq!(rejected_fields(hedge_fund_request(json!({ "top_p": 1.5 })).validate()), vec!["top_p"]);
+  }
+
+  #[test]
+  fn backtest_requests_run_the_same_checks() {
+    let request : AgentBacktestRequest = serde_json::from_value(json!({
+      "tickers": ["AAPL", "not a ticker"],
+      "start_date": "2024-06-01",
+      "end_date": "2024-01-01",
+      "temperature": 9.0,
+      "token_budget": 0,
+      "stop_loss": { "pct": 1.5 },
+    })).unwrap();
+    assert_eq!(rejected_fields(request.validate()), vec!["tickers", "start_date", "token_budget", "temperature", "stop_loss.pct"]);
+
+    let request : AgentBacktestRequest = serde_json::from_value(json!({ "tickers": ["AAPL"], "start_date": "2024-01-02", "end_date": "2024-06-28" })).unwrap();
+    assert!(request.validate().is_ok());
+  }
+
+  // Posts the body to /agent/investment and returns the status and the rejected (field, message) pairs
+  async fn post_investment(body: Value) -> (actix_web::http::StatusCode, Vec<(String, String)>) {
+    use crate::app::config::Config;
+    use crate::app::factory::CreateApp;
+    use crate::app::services::{idempotency::IdempotencyStore, run_store::RunStore, watchlists::Watchlists};
+
+    let app = CreateApp::new(Config::for_tests(), Arc::new(RunStore::new(4)), Arc::new(Watchlists::new()), Arc::new(IdempotencyStore::new(std::time::Duration::from_secs(60))));
+    let service = actix_web::test::init_service(app.build_app()).await;
+    let request = actix_web::test::TestRequest::post().uri("/agent/investment").set_json(body).to_request();
+    let response = actix_web::test::call_service(&service, request).await;
+    let status = response.status();
+    let payload : Value = actix_web::test::read_body_json(response).await;
+    let fields = payload["fields"].as_array().cloned().unwrap_or_default().into_iter()
+      .map(|field| (field["field"].as_str().unwrap().to_string(), field["message"].as_str().unwrap().to_string()))
+      .collect();
+    return (status, fields);
+  }
+
+  fn rejection(field: &str, message: &str) -> (actix_web::http::StatusCode, Vec<(String, String)>) {
+    (actix_web::http::StatusCode::BAD_REQUEST, vec![(field.to_string(), message.to_string())])
+  }
+
+  #[actix_web::test]
+  async fn an_empty_ticker_list_is_rejected() {
+    assert_eq!(post_investment(json!({ "tickers": [] })).await, rejection("tickers", "at least one ticker or a watchlist is required"));
+  }
+
+  #[actix_web::test]
+  async fn an_implausible_symbol_is_rejected() {
+    assert_eq!(post_investment(json!({ "tickers": ["AAPL", "$$$"] })).await, rejection("tickers", "'$$$' is not a valid ticker symbol"));
+  }
+
+  #[actix_web::test]
+  async fn a_malformed_date_is_rejected() {
+    assert_eq!(post_investment(json!({ "tickers": ["AAPL"], "end_date": "2024-13-45" })).await, rejection("end_date", "'2024-13-45' is not a valid YYYY-MM-DD date"));
+  }
+
+  #[actix_web::test]
+  async fn a_start_date_after_the_end_date_is_rejected() {
+    let body = json!({ "tickers": ["AAPL"], "start_date": "2024-06-01", "end_date": "2024-01-01" });
+    assert_eq!(post_investment(body).await, rejection("start_date", "start_date 2024-06-01 is after end_date 2024-01-01"));
+  }
+
+  #[actix_web::test]
+  async fn negative_initial_cash_is_rejected() {
+    assert_eq!(post_investment(json!({ "tickers": ["AAPL"], "initial_cash": -1.0 })).await, rejection("initial_cash", "must be greater than or equal to 0"));
+  }
+
+  #[actix_web::test]
+  async fn a_margin_requirement_outside_zero_to_one_is_rejected() {
+    for margin in [1.5, -0.1] {
+      assert_eq!(post_investment(json!({ "tickers": ["AAPL"], "margin_requirement": margin })).await, rejection("margin_requirement", "must be between 0 and 1"));
+    }
+  }
+}
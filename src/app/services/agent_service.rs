@@ -4,14 +4,29 @@ use anyhow::{Result, anyhow, Context, Error};
 use std::result::Result::{Ok, Err};
 use std::future::Future; 
 use std::pin::Pin;
+use std::path::Path;
+use std::time::Instant;
+use tracing::Instrument;
 
 use crate::ai_agent::agents::portfolio_manager::PortfolioManagerAgent;
 use crate::ai_agent::agents::risk_manager::RiskManagerAgent;
-use crate::ai_agent::llm::model_provider::ChatMessage;
+use crate::ai_agent::data::models::Portfolio;
+use crate::ai_agent::llm::circuit_breaker;
+use crate::ai_agent::llm::model_provider::{with_token_budget, with_usage_tracking, ChatMessage};
 use crate::app::config::Config;
+use crate::app::models::errors::ServiceError;
+use crate::app::services::decision_log;
+use crate::app::models::options::{HedgeFundOptions, OutputMode, SizingMode};
 use crate::ai_agent::graph::graph::{CompiledGraph, StateGraph};
+use crate::ai_agent::graph::reasoning::{new_run_id, with_reasoning_channel};
 use crate::ai_agent::graph::state::{AgentState, PartialAgentStateUpdate};
-use crate::ai_agent::utils::analysts::{get_analyst_config, get_analyst_nodes};
+use crate::ai_agent::tools::api::API;
+use crate::ai_agent::utils::analysts::{get_analyst_config, get_analyst_nodes, line_item_requests, register_analyst, resolve_analyst_keys, AgentFunction, LineItemSet};
+use crate::ai_agent::utils::fetch::fetch_per_ticker;
+use crate::ai_agent::utils::signals::{detect_signal_conflicts, signal_consensus, ConsensusWeighting};
+
+const DEFAULT_MODEL_NAME: &str = "gpt-4o";
+const DEFAULT_MODEL_PROVIDER: &str = "OpenAI";
 
 pub struct AgentService {
   config : Config,
@@ -20,28 +35,80 @@ pub struct AgentService {
 
 impl AgentService {
   pub fn new(config: Config) -> Self {
-    let temp_agent: AgentService = AgentService {
-      config: config.clone(), 
+    let mut agent_service: AgentService = AgentService {
+      config: config, 
       default_agent: None
     };
-    let default_workflow: StateGraph = temp_agent.create_workflow(None);  // Create workflow with all analysts
-    let default_agent = Some(default_workflow.compile());
-    AgentService { config, default_agent }
+    agent_service.default_agent = agent_service.build_default_agent();
+    agent_service
+  }
+
+  // Registers a custom analyst and rebuilds the default workflow so it also runs when no analysts are selected.
+  // Entry point for code embedding the service, the server itself only runs the built-in analysts
+  #[allow(unused)]
+  pub fn register_analyst(&mut self, key: &str, display_name: &str, order: usize, agent_function: AgentFunction, line_items: Option<LineItemSet>) -> std::result::Result<(), Error> {
+    register_analyst(key, display_name, order, agent_function, line_items)?;
+    self.default_agent = self.build_default_agent();
+    Ok(())
   }
 
-  pub async fn run_hedge_fund(&self, ticker: Vec<String>, start_date: &str, end_date: &str, portfolio: HashMap<String, Value>, 
+  fn build_default_agent(&self) -> Option<CompiledGraph> {
+    let default_workflow: StateGraph = self.create_workflow(None, true);  // Create workflow with all analysts
+    match default_workflow.compile() {
+      Ok(agent) => Some(agent),
+      Err(e) => {
+        log::error!("Default workflow failed validation: {}", e);
+        None
+      }
+    }
+  }
+
+  pub fn config(&self) -> &Config {
+    &self.config
+  }
+
+  pub async fn run_hedge_fund(&self, ticker: Vec<String>, start_date: &str, end_date: &str, portfolio: Portfolio, 
                               show_reasoning: Option<bool>, selected_analysts: Option<Vec<String>>, 
-                              model_name: Option<&str>, model_provider: Option<&str>) -> std::result::Result<HashMap<String, Value>, Error> {
+                              model_name: Option<&str>, model_provider: Option<&str>, options: HedgeFundOptions) -> std::result::Result<HashMap<String, Value>, Error> {
+    let result = self.run(ticker.clone(), start_date, end_date, portfolio, show_reasoning, selected_analysts, model_name, model_provider, options, true).await?;
+
+    // A failed write is only logged, the run itself succeeded
+    if let Some(path) = self.config.decision_log_file.as_deref() {
+      let (model_name, model_provider) = (model_name.unwrap_or(DEFAULT_MODEL_NAME), model_provider.unwrap_or(DEFAULT_MODEL_PROVIDER));
+      if let Err(e) = decision_log::append_run(Path::new(path), self.config.decision_log_format, &ticker, model_name, model_provider, &result) {
+        log::error!("Failed to append run to the decision log {}: {:#}", path, e);
+      }
+    }
+    Ok(result)
+  }
+
+  // Analysts and the risk manager only, the result has the signals and their consensus but no decisions
+  pub async fn run_signals(&self, ticker: Vec<String>, start_date: &str, end_date: &str, portfolio: Portfolio,
+                           show_reasoning: Option<bool>, selected_analysts: Option<Vec<String>>,
+                           model_name: Option<&str>, model_provider: Option<&str>, options: HedgeFundOptions) -> std::result::Result<HashMap<String, Value>, Error> {
+    self.run(ticker, start_date, end_date, portfolio, show_reasoning, selected_analysts, model_name, model_provider, options, false).await
+  }
+
+  async fn run(&self, ticker: Vec<String>, start_date: &str, end_date: &str, portfolio: Portfolio,
+               show_reasoning: Option<bool>, selected_analysts: Option<Vec<String>>,
+               model_name: Option<&str>, model_provider: Option<&str>, options: HedgeFundOptions, with_portfolio_manager: bool) -> std::result::Result<HashMap<String, Value>, Error> {
     
     let show_reasoning : bool = show_reasoning.unwrap_or(false);
-    let selected_analysts : Vec<String> = selected_analysts.unwrap_or(Vec::new());
-    let model_name : &str = model_name.unwrap_or("gpt-4o");
-    let model_provider : &str = model_provider.unwrap_or("OpenAI");
+    let allow_fractional : bool = options.allow_fractional.unwrap_or(false);
+    let output_mode : OutputMode = options.output_mode.unwrap_or_default();
+    let sizing : SizingMode = options.sizing.unwrap_or_default();
+    let consensus_weighting : ConsensusWeighting = options.consensus_weighting.unwrap_or_default();
+    let selected_analysts : Vec<String> = resolve_analyst_keys(&selected_analysts.unwrap_or(Vec::new())).map_err(|unmatched| {
+      ServiceError::InvalidInput(format!("Unknown analysts: {}, see /agent/analysts for the available keys and names", unmatched.join(", ")))
+    })?;
+    let model_name : &str = model_name.unwrap_or(DEFAULT_MODEL_NAME);
+    let model_provider : &str = model_provider.unwrap_or(DEFAULT_MODEL_PROVIDER);
+    let run_id : String = options.run_id.clone().unwrap_or_else(new_run_id);
 
     let result = {
-      let agent: CompiledGraph  = if !selected_analysts.is_empty() {
-        let workflow : StateGraph = self.create_workflow(Some(selected_analysts.clone())); 
-        let agent : CompiledGraph = workflow.compile();
+      let agent: CompiledGraph  = if !selected_analysts.is_empty() || !with_portfolio_manager {
+        let workflow : StateGraph = self.create_workflow(Some(selected_analysts.clone()), with_portfolio_manager); 
+        let agent : CompiledGraph = workflow.compile()?;
         agent
       }
       else if let Some(default) = self.default_agent.as_ref() {
@@ -52,10 +119,15 @@ impl AgentService {
         return Err(anyhow!("No default agent available"));
       };
 
+      // Without analysts the risk and portfolio managers would run on empty signals and hold everything
+      let wired_analysts : Vec<String> = agent.analyst_nodes();
+      if wired_analysts.is_empty() {
+        return Err(anyhow!("Workflow has no analysts wired in, check the analyst registry in utils/analysts.rs"));
+      }
+      log::info!("Run {} wired analysts: {}", run_id, wired_analysts.join(", "));
+
       let mut initial_state: AgentState = AgentState::new(); 
-      let _ = initial_state.add_message(ChatMessage {
-        role: "user".to_string(), content: "Make trading decisions based on the provided data.".to_string(),
-      });
+      let _ = initial_state.add_message(ChatMessage::new("user", "Make trading decisions based on the provided data."));
 
       let mut data: HashMap<String, Value> = HashMap::new(); 
       data.insert("tickers".to_string(), serde_json::to_value(&ticker)?);
@@ -67,21 +139,109 @@ impl AgentService {
 
       let mut meta_data: HashMap<String, Value> = HashMap::new(); 
       meta_data.insert("show_reasoning".to_string(), serde_json::to_value(show_reasoning)?);
+      meta_data.insert("allow_fractional".to_string(), serde_json::to_value(allow_fractional)?);
+      meta_data.insert("sizing".to_string(), Value::from(sizing.as_str()));
+      if let Some(decision_temperature) = options.decision_temperature {
+        meta_data.insert("decision_temperature".to_string(), serde_json::to_value(decision_temperature)?);
+      }
+      if let Some(temperature) = options.temperature {
+        meta_data.insert("temperature".to_string(), serde_json::to_value(temperature)?);
+      }
+      if let Some(top_p) = options.top_p {
+        meta_data.insert("top_p".to_string(), serde_json::to_value(top_p)?);
+      }
+      if let Some(max_tokens) = options.max_tokens {
+        meta_data.insert("max_tokens".to_string(), serde_json::to_value(max_tokens)?);
+      }
+      meta_data.insert("deterministic".to_string(), Value::from(options.deterministic.unwrap_or(false)));
+      if let Some(analyst_timeout_secs) = options.analyst_timeout_secs {
+        meta_data.insert("analyst_timeout_secs".to_string(), serde_json::to_value(analyst_timeout_secs)?);
+      }
+      if let Some(llm_timeout_secs) = options.llm_timeout_secs {
+        meta_data.insert("llm_timeout_secs".to_string(), serde_json::to_value(llm_timeout_secs)?);
+      }
+      if let Some(max_sector_weight) = options.max_sector_weight {
+        meta_data.insert("max_sector_weight".to_string(), serde_json::to_value(max_sector_weight)?);
+      }
+      if let Some(min_analysts) = options.min_analysts {
+        meta_data.insert("min_analysts".to_string(), serde_json::to_value(min_analysts)?);
+      }
+      if let Some(period) = options.period {
+        meta_data.insert("period".to_string(), Value::from(period.as_str()));
+      }
+      if let Some(line_items) = options.line_items {
+        meta_data.insert("line_items".to_string(), serde_json::to_value(line_items)?);
+      }
+      let analyst_keys : Vec<&str> = wired_analysts.iter().filter_map(|node| node.strip_suffix("_agent")).collect();
+      meta_data.insert("analysts".to_string(), serde_json::to_value(analyst_keys)?);
+      meta_data.insert("fill_gaps".to_string(), serde_json::to_value(options.fill_gaps.unwrap_or(false))?);
+      meta_data.insert("output_mode".to_string(), Value::from(output_mode.as_str()));
+      meta_data.insert("dry_run".to_string(), Value::from(options.dry_run.unwrap_or(false)));
+      if let Some(prompt_overrides) = options.prompt_overrides {
+        meta_data.insert("prompt_overrides".to_string(), serde_json::to_value(prompt_overrides)?);
+      }
+      if let Some(token_budget) = options.token_budget {
+        meta_data.insert("token_budget".to_string(), Value::from(token_budget));
+      }
+      if let Some(valuation_assumptions) = options.valuation_assumptions {
+        meta_data.insert("valuation_assumptions".to_string(), serde_json::to_value(valuation_assumptions)?);
+      }
+      if let Some(ensemble_models) = options.ensemble_models {
+        meta_data.insert("ensemble_models".to_string(), serde_json::to_value(ensemble_models)?);
+      }
       meta_data.insert("model_name".to_string(), serde_json::to_value(model_name)?);
       meta_data.insert("model_provider".to_string(), serde_json::to_value(model_provider)?);
+      meta_data.insert("run_id".to_string(), Value::from(run_id.as_str()));
       let _ = initial_state.merge_metadata(meta_data);
 
-      let final_state : AgentState = agent.invoke(initial_state, self.config.clone()).await?;
+      let started_at = Instant::now();
+      let run_span = tracing::info_span!("run", run_id = %run_id, tickers = %ticker.join(","));
+      let run = with_token_budget(options.token_budget, with_reasoning_channel(&run_id, agent.invoke_parallel(initial_state, self.config.clone())));
+      let ((final_state, budget_remaining), token_usage) = with_usage_tracking(run).instrument(run_span).await;
+      let final_state : AgentState = final_state?;
+      let total_ms = started_at.elapsed().as_millis() as u64;
 
-      let last_message = final_state.messages.last().context("No messages in final state")?;
-            
-      let decisions = self.parse_hedge_fund_response(&last_message.content)?;
       let analyst_signals = final_state.data.get("analyst_signals").cloned().unwrap_or_else(|| serde_json::json!({}));
       
       // Return the results
       let mut result = HashMap::new();
-      result.insert("decisions".to_string(), decisions);
+      result.insert("run_id".to_string(), Value::from(run_id));
+      if with_portfolio_manager {
+        let (decisions_key, decisions) : (&str, Value) = match output_mode {
+          OutputMode::Advisory => {
+            let recommendations = final_state.data.get("recommendations").cloned().context("Portfolio manager produced no recommendations")?;
+            ("recommendations", recommendations)
+          }
+          OutputMode::Orders => {
+            let output = final_state.portfolio_output.as_ref().context("Portfolio manager produced no decisions")?;
+            ("decisions", serde_json::to_value(&output.decisions)?)
+          }
+        };
+        result.insert(decisions_key.to_string(), decisions);
+      }
+      if let Some(output) = final_state.portfolio_output.as_ref() {
+        result.insert("weighted_signals".to_string(), serde_json::to_value(&output.weighted_signals)?);
+      }
+      result.insert("conflicts".to_string(), Value::from(detect_signal_conflicts(&analyst_signals)));
+      result.insert("consensus".to_string(), signal_consensus(&analyst_signals, consensus_weighting));
       result.insert("analyst_signals".to_string(), analyst_signals);
+      result.insert("timings".to_string(), serde_json::json!({
+        "nodes": final_state.metadata.get("timings").cloned().unwrap_or_else(|| serde_json::json!({})),
+        "total_ms": total_ms,
+      }));
+      result.insert("token_usage".to_string(), serde_json::to_value(&token_usage)?);
+      // Process-wide, models whose breaker tripped here or in an earlier run
+      result.insert("circuit_breakers".to_string(), serde_json::to_value(circuit_breaker::snapshot())?);
+      if let (Some(budget), Some(remaining)) = (options.token_budget, budget_remaining) {
+        result.insert("token_budget".to_string(), serde_json::json!({ "budget": budget, "remaining": remaining }));
+      }
+      // Tickers an agent skipped because their data could not be fetched, keyed by agent
+      if let Some(errors) = final_state.data.get("errors") {
+        result.insert("errors".to_string(), errors.clone());
+      }
+      if let Some(reason) = final_state.data.get("short_circuit_reason") {
+        result.insert("status".to_string(), reason.clone());
+      }
       
       Ok(result)
 
@@ -92,14 +252,44 @@ impl AgentService {
 
   }
 
-  pub fn start(_state: AgentState, _config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+  pub fn start(state: AgentState, config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
     Box::pin(async move {
+        Self::prefetch_line_items(&state, config).await;
         Ok(PartialAgentStateUpdate::new())
     })
   }
 
+  // Fetches the union of the analysts' line items once per ticker, their own searches are then answered from the cache.
+  // Failures are only logged, each analyst still fetches what it is missing
+  async fn prefetch_line_items(state: &AgentState, config: Config) {
+    let analyst_keys : Vec<String> = state.metadata.get("analysts").and_then(Value::as_array)
+      .map(|keys| keys.iter().filter_map(Value::as_str).map(String::from).collect())
+      .unwrap_or_default();
+    let requests = line_item_requests(&analyst_keys, &state.metadata);
+    let tickers : Vec<String> = state.data.get("tickers").and_then(Value::as_array)
+      .map(|tickers| tickers.iter().filter_map(Value::as_str).map(String::from).collect())
+      .unwrap_or_default();
+    let end_date : &str = match state.data.get("end_date").and_then(Value::as_str) {
+      Some(end_date) if !requests.is_empty() && !tickers.is_empty() => end_date,
+      _ => return,
+    };
 
-  fn create_workflow(&self, selected_analyst: Option<Vec<String>>) -> StateGraph {
+    let fetch_concurrency : usize = config.ticker_fetch_concurrency;
+    let api = API::new(config);
+    let api_ref : &API = &api;
+    let requests_ref = &requests;
+    fetch_per_ticker(&tickers, fetch_concurrency, |ticker| async move {
+      for request in requests_ref {
+        if let Err(e) = api_ref.search_line_items(&ticker, request.names.clone(), end_date, Some(request.period.as_str()), Some(request.limit)).await {
+          log::warn!("Prefetching {} line items for {} failed, analysts fetch their own: {}", request.period.as_str(), ticker, e);
+        }
+      }
+    }).await;
+  }
+
+
+  // Without the portfolio manager the risk manager is the last node before END
+  fn create_workflow(&self, selected_analyst: Option<Vec<String>>, with_portfolio_manager: bool) -> StateGraph {
     let mut workflow: StateGraph = StateGraph::new(); 
 
     workflow.add_node("start_node".to_string(), Self::start);
@@ -114,13 +304,12 @@ impl AgentService {
 
     for analyst_key in &selected_analysts {
       if let Some((node_name, node_function)) = analyst_nodes.get(analyst_key) {
-        workflow.add_node(node_name.to_string(), *node_function);
+        workflow.add_analyst_node(node_name.to_string(), *node_function);
         workflow.add_edge("start_node".to_string(), node_name.to_string());
       }
     }
 
     workflow.add_node("risk_management_agent".to_string(), RiskManagerAgent::static_risk_management_agent);
-    workflow.add_node("portfolio_manager".to_string(), PortfolioManagerAgent::static_portfolio_management_agent);
 
     for analyst_key in &selected_analysts {
       if let Some((node_name, _node_function)) = analyst_nodes.get(analyst_key) {
@@ -128,24 +317,175 @@ impl AgentService {
       }
     }
 
-    workflow.add_edge("risk_management_agent".to_string(), "portfolio_manager".to_string());
-    workflow.add_edge("portfolio_manager".to_string(), "END".to_string());
+    if with_portfolio_manager {
+      workflow.add_node("portfolio_manager".to_string(), PortfolioManagerAgent::static_portfolio_management_agent);
+      workflow.add_edge("risk_management_agent".to_string(), "portfolio_manager".to_string());
+      workflow.add_edge("portfolio_manager".to_string(), "END".to_string());
+    } else {
+      workflow.add_edge("risk_management_agent".to_string(), "END".to_string());
+    }
     workflow.set_entry_point("start_node");
 
 
     return workflow;
   }
 
+}
 
-  fn parse_hedge_fund_response(&self, response: &str) -> Result<Value> {
-    match serde_json::from_str(response) {
-      Ok(value) => Ok(value),
-      Err(e) => {
-        log::error!("JSON decoding error: {}. Response: {:?}", e, response);
-        Err(anyhow!("Failed to parse hedge fund response: {}", e))
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ai_agent::agents::valuation_analyst::VALUATION_LINE_ITEMS;
+  use crate::ai_agent::agents::warren_buffet::BUFFETT_LINE_ITEMS;
+  use crate::ai_agent::tools::stub_server::StubServer;
+  use crate::app::services::{run_store::RunStore, service::HedgeFundServices, watchlists::Watchlists};
+  use serde_json::json;
+  use std::sync::Arc;
+
+  fn dummy_analyst(_state: AgentState, _config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async { Ok(PartialAgentStateUpdate::new()) })
+  }
+
+  // Every registered analyst must be a node fed by the start node that feeds the risk manager, otherwise it never runs
+  fn assert_wired(workflow: &StateGraph, node_name: &str) {
+    assert!(workflow.nodes().iter().any(|node| node == node_name), "{} is not a node", node_name);
+    assert!(workflow.edges()["start_node"].iter().any(|target| target == node_name), "start_node does not lead to {}", node_name);
+    assert!(workflow.edges()[node_name].iter().any(|target| target == "risk_management_agent"), "{} does not feed the risk manager", node_name);
+  }
+
+  #[tokio::test]
+  async fn overlapping_line_items_are_fetched_once_per_ticker() {
+    // Answers every search with the requested fields set, so later searches are complete from the cache
+    let server = StubServer::start(|request| {
+      let body: Value = serde_json::from_str(&request.body).unwrap();
+      let mut item = json!({ "ticker": body["tickers"][0], "report_period": "2024-03-31", "period": body["period"], "currency": "USD" });
+      for name in body["line_items"].as_array().unwrap() {
+        item[name.as_str().unwrap()] = json!(1.0);
       }
+      (200, json!({ "search_results": [item] }).to_string())
+    });
+    let mut config = Config::for_tests();
+    config.financial_datasets_api_key = Some("test".to_string());
+    config.financial_datasets_base_url = server.base_url.clone();
+
+    let mut state = AgentState::new();
+    state.merge_data(serde_json::from_value(json!({ "tickers": ["PREFONE", "PREFTWO"], "end_date": "2024-06-30" })).unwrap()).unwrap();
+    state.merge_metadata(serde_json::from_value(json!({ "analysts": ["warren_buffett", "valuation_analyst"] })).unwrap()).unwrap();
+    AgentService::prefetch_line_items(&state, config.clone()).await;
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    for ticker in ["PREFONE", "PREFTWO"] {
+      let body : Vec<Value> = requests.iter().map(|request| serde_json::from_str::<Value>(&request.body).unwrap()).filter(|body| body["tickers"][0] == ticker).collect();
+      assert_eq!(body.len(), 1, "{} was searched {} times", ticker, body.len());
+      let names : Vec<&str> = body[0]["line_items"].as_array().unwrap().iter().filter_map(Value::as_str).collect();
+      let mut unique = names.clone();
+      unique.sort();
+      unique.dedup();
+      assert_eq!(names.len(), unique.len(), "duplicate line items in {:?}", names);
+      for name in BUFFETT_LINE_ITEMS.names.iter().chain(VALUATION_LINE_ITEMS.names) {
+        assert!(names.contains(name), "{} missing from {:?}", name, names);
+      }
+      assert_eq!(body[0]["limit"], json!(BUFFETT_LINE_ITEMS.limit.max(VALUATION_LINE_ITEMS.limit)));
+    }
+
+    // Each analyst's own search is then answered from the cache
+    let api = API::new(config);
+    for line_items in [BUFFETT_LINE_ITEMS, VALUATION_LINE_ITEMS] {
+      let names : Vec<String> = line_items.names.iter().map(|name| name.to_string()).collect();
+      api.search_line_items("PREFONE", names, "2024-06-30", Some("ttm"), Some(line_items.limit)).await.unwrap();
     }
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn every_registered_analyst_is_wired_into_the_default_workflow() {
+    let service = AgentService::new(Config::for_tests());
+    let workflow = service.create_workflow(None, true);
+    for (node_name, _) in get_analyst_nodes().values() {
+      assert_wired(&workflow, node_name);
+    }
+
+    let mut expected : Vec<String> = get_analyst_nodes().into_values().map(|(node_name, _)| node_name).collect();
+    expected.sort();
+    let default_agent = service.default_agent.as_ref().expect("default workflow compiles");
+    assert!(expected.iter().all(|node_name| default_agent.analyst_nodes().contains(node_name)));
   }
 
+  #[tokio::test]
+  async fn unknown_selected_analysts_fail_the_run_by_name() {
+    let service = AgentService::new(Config::for_tests());
+    let tickers = vec!["AAPL".to_string()];
+    let error = service.run_hedge_fund(tickers.clone(), "2024-01-01", "2024-03-31", Portfolio::new(&tickers, 100000.0, 0.0), None,
+                                       Some(vec!["Warren Buffett".to_string(), "Charlie Munger".to_string()]), None, None, HedgeFundOptions::default()).await.unwrap_err();
+    assert!(matches!(error.downcast_ref::<ServiceError>(), Some(ServiceError::InvalidInput(message)) if message.starts_with("Unknown analysts: Charlie Munger,")), "{}", error);
+  }
+
+  #[tokio::test]
+  async fn a_default_workflow_without_analysts_is_an_explicit_error() {
+    // What the default workflow compiles to when the analyst registry is empty
+    let mut workflow = StateGraph::new();
+    workflow.add_node("start_node".to_string(), AgentService::start);
+    workflow.add_node("risk_management_agent".to_string(), RiskManagerAgent::static_risk_management_agent);
+    workflow.add_edge("start_node".to_string(), "risk_management_agent".to_string());
+    workflow.add_edge("risk_management_agent".to_string(), "END".to_string());
+    workflow.set_entry_point("start_node");
+
+    let mut service = AgentService::new(Config::for_tests());
+    service.default_agent = Some(workflow.compile().unwrap());
+
+    let tickers = vec!["AAPL".to_string()];
+    let error = service.run_hedge_fund(tickers.clone(), "2024-01-01", "2024-03-31", Portfolio::new(&tickers, 100000.0, 0.0), None,
+                                       None, None, None, HedgeFundOptions::default()).await.unwrap_err();
+    assert!(error.to_string().starts_with("Workflow has no analysts wired in"), "{}", error);
+  }
+
+  // Bullish on every ticker without touching the data API or an LLM
+  fn bullish_analyst(state: AgentState, _config: Config) -> Pin<Box<dyn Future<Output = Result<PartialAgentStateUpdate, Error>> + Send>> {
+    Box::pin(async move {
+      let mut signals = serde_json::Map::new();
+      for ticker in state.data["tickers"].as_array().unwrap() {
+        signals.insert(ticker.as_str().unwrap().to_string(), json!({ "signal": "bullish", "confidence": 80.0, "reasoning": "test" }));
+      }
+      let mut data = HashMap::new();
+      data.insert("analyst_signals".to_string(), json!({ "signals_test_agent": signals }));
+      Ok(PartialAgentStateUpdate::new().with_data(data))
+    })
+  }
+
+  #[tokio::test]
+  async fn the_signals_path_never_reaches_the_portfolio_manager() {
+    let mut service = AgentService::new(Config::for_tests());
+    service.register_analyst("signals_test", "Signals Test", 98, bullish_analyst, None).unwrap();
+
+    // No LLM key is configured, so a portfolio manager call could not succeed
+    let tickers = vec!["SIGONLY".to_string()];
+    let result = service.run_signals(tickers.clone(), "2024-01-01", "2024-03-31", Portfolio::new(&tickers, 100000.0, 0.0), None,
+                                     Some(vec!["signals_test".to_string()]), Some("gpt-4o"), Some("OpenAI"), HedgeFundOptions::default()).await.unwrap();
+
+    assert!(!result.contains_key("decisions") && !result.contains_key("recommendations") && !result.contains_key("weighted_signals"));
+    assert!(result["timings"]["nodes"].get("portfolio_manager").is_none());
+    assert!(result["timings"]["nodes"].get("risk_management_agent").is_some());
+    assert_eq!(result["token_usage"]["calls"], json!(0));
+    assert_eq!(result["analyst_signals"]["signals_test_agent"]["SIGONLY"]["signal"], "bullish");
+    assert_eq!(result["consensus"]["SIGONLY"]["signal"], "bullish");
+    assert_eq!(result["consensus"]["SIGONLY"]["analysts"], json!(1));
+  }
+
+  #[test]
+  fn a_registered_analyst_joins_the_default_workflow() {
+    let mut service = AgentService::new(Config::for_tests());
+    // The registry is process-wide, the key is only registered by this test
+    service.register_analyst("wiring_test", "Wiring Test", 99, dummy_analyst, None).unwrap();
+
+    assert_wired(&service.create_workflow(None, true), "wiring_test_agent");
+    assert!(service.default_agent.as_ref().unwrap().analyst_nodes().contains(&"wiring_test_agent".to_string()));
+
+    // /agent/analysts lists it too
+    let services = HedgeFundServices::new(service, Arc::new(RunStore::new(4)), Arc::new(Watchlists::new()));
+    let listed = services.get_available_analysts().unwrap();
+    assert!(listed.iter().any(|analyst| analyst["key"] == "wiring_test" && analyst["display_name"] == "Wiring Test"), "{:?}", listed);
+  }
 }
 
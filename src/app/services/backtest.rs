@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use anyhow::Error;
+use std::result::Result::{Ok};
+
+use super::agent_service::AgentService;
+use super::portfolio::{apply_decision, portfolio_value};
+use crate::ai_agent::agents::portfolio_manager::Action;
+use crate::ai_agent::data::models::Portfolio;
+use crate::ai_agent::tools::api::API;
+use crate::app::models::errors::ServiceError;
+use crate::app::models::options::HedgeFundOptions;
+
+// Days of history the agents analyse before each decision date
+const LOOKBACK_DAYS: i64 = 90;
+// Days searched backwards for the latest close, covers weekends and holidays
+const PRICE_LOOKBACK_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cadence {
+  Daily,
+  #[default]
+  Weekly,
+  Monthly,
+}
+
+impl Cadence {
+  fn next(&self, date: NaiveDate) -> NaiveDate {
+    match self {
+      Cadence::Daily => {
+        let mut next = date + Duration::days(1);
+        while next.weekday() == Weekday::Sat || next.weekday() == Weekday::Sun {
+          next = next + Duration::days(1);
+        }
+        next
+      }
+      Cadence::Weekly => date + Duration::weeks(1),
+      Cadence::Monthly => date.checked_add_months(Months::new(1)).unwrap_or(date + Duration::days(30)),
+    }
+  }
+
+  fn periods_per_year(&self) -> f64 {
+    match self {
+      Cadence::Daily => 252.0,
+      Cadence::Weekly => 52.0,
+      Cadence::Monthly => 12.0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopType {
+  #[default]
+  Fixed,    // measured from the position's cost basis
+  Trailing, // measured from the best mark since the position was opened
+}
+
+impl StopType {
+  fn trade_action(&self, action: Action) -> String {
+    match self {
+      StopType::Fixed => format!("stop_{}", action.as_str()),
+      StopType::Trailing => format!("trailing_stop_{}", action.as_str()),
+    }
+  }
+}
+
+// Risk overlay applied before every step's decisions, independent of what the agents decide
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StopLoss {
+  pub pct: f64, // fraction of the reference price, 0.1 closes a long 10% below it
+  #[serde(default)]
+  pub stop_type: StopType,
+}
+
+// Best mark seen per open side, the reference for trailing stops
+#[derive(Debug, Clone, Default)]
+struct StopMarks {
+  long_peak: HashMap<String, f64>,
+  short_trough: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EquityPoint {
+  pub date: String,
+  pub portfolio_value: f64,
+  pub cash: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeRecord {
+  pub date: String,
+  pub ticker: String,
+  pub action: String,
+  pub quantity: f64,
+  pub price: f64,
+  pub stop_triggered: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestSummary {
+  pub initial_value: f64,
+  pub final_value: f64,
+  pub total_return_pct: f64,
+  pub max_drawdown_pct: f64,
+  pub sharpe_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestResult {
+  pub equity_curve: Vec<EquityPoint>,
+  pub trades: Vec<TradeRecord>,
+  pub summary: BacktestSummary,
+}
+
+pub struct Backtester<'a> {
+  agent_service: &'a AgentService,
+  tickers: Vec<String>,
+  start_date: NaiveDate,
+  end_date: NaiveDate,
+  cadence: Cadence,
+  portfolio: Portfolio,
+  selected_analysts: Option<Vec<String>>,
+  model_name: Option<String>,
+  model_provider: Option<String>,
+  options: HedgeFundOptions,
+  stop_loss: Option<StopLoss>,
+}
+
+impl<'a> Backtester<'a> {
+
+  pub fn new(agent_service: &'a AgentService, tickers: Vec<String>, start_date: &str, end_date: &str, cadence: Cadence, portfolio: Portfolio,
+             selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions,
+             stop_loss: Option<StopLoss>) -> Result<Self, Error> {
+    let start_date = NaiveDate::parse_from_str(start_date, "%Y-%m-%d").map_err(|e| ServiceError::InvalidInput(format!("Invalid backtest start_date {}: {}", start_date, e)))?;
+    let end_date = NaiveDate::parse_from_str(end_date, "%Y-%m-%d").map_err(|e| ServiceError::InvalidInput(format!("Invalid backtest end_date {}: {}", end_date, e)))?;
+    if start_date > end_date {
+      return Err(ServiceError::InvalidInput(format!("Backtest start_date {} is after end_date {}", start_date, end_date)).into());
+    }
+    if let Some(stop_loss) = &stop_loss {
+      if !(stop_loss.pct > 0.0 && stop_loss.pct < 1.0) {
+        return Err(ServiceError::InvalidInput(format!("stop_loss pct must be between 0 and 1, got {}", stop_loss.pct)).into());
+      }
+    }
+
+    Ok(Backtester { agent_service, tickers, start_date, end_date, cadence, portfolio, selected_analysts, model_name, model_provider, options, stop_loss })
+  }
+
+  pub async fn run(mut self) -> Result<BacktestResult, Error> {
+    let api = API::new(self.agent_service.config().clone());
+    let mut equity_curve : Vec<EquityPoint> = Vec::new();
+    let mut trades : Vec<TradeRecord> = Vec::new();
+    let allow_fractional : bool = self.options.allow_fractional.unwrap_or(false);
+    let mut marks = StopMarks::default();
+
+    let mut current_date = self.start_date;
+    while current_date <= self.end_date {
+      let date_str = current_date.format("%Y-%m-%d").to_string();
+      let lookback_str = (current_date - Duration::days(LOOKBACK_DAYS)).format("%Y-%m-%d").to_string();
+      log::info!("Backtest step {} for {:?}", date_str, self.tickers);
+
+      let prices = self.latest_prices(&api, current_date).await?;
+
+      if let Some(stop_loss) = self.stop_loss {
+        self.apply_stops(stop_loss, &mut marks, &prices, &date_str, &mut trades);
+      }
+
+      let output = self.agent_service.run_hedge_fund(
+        self.tickers.clone(),
+        &lookback_str,
+        &date_str,
+        self.portfolio.clone(),
+        Some(false),
+        self.selected_analysts.clone(),
+        self.model_name.as_deref(),
+        self.model_provider.as_deref(),
+        self.options.clone(),
+      ).await?;
+
+      if let Some(decisions) = output.get("decisions").and_then(Value::as_object) {
+        for (ticker, decision) in decisions {
+          let price = match prices.get(ticker) {
+            Some(price) => *price,
+            None => {
+              log::warn!("Backtest {}: no price for {}, skipping its decision", date_str, ticker);
+              continue;
+            }
+          };
+          let action : Action = decision.get("action").and_then(|action| serde_json::from_value(action.clone()).ok()).unwrap_or(Action::Hold);
+          let quantity = decision.get("quantity").and_then(Value::as_f64).unwrap_or(0.0);
+
+          let executed = apply_decision(&mut self.portfolio, ticker, action, quantity, price, allow_fractional);
+          if executed > 0.0 {
+            trades.push(TradeRecord { date: date_str.clone(), ticker: ticker.clone(), action: action.as_str().to_string(), quantity: executed, price, stop_triggered: false });
+          }
+        }
+      }
+
+      let cash = self.portfolio.cash;
+      equity_curve.push(EquityPoint { date: date_str, portfolio_value: portfolio_value(&self.portfolio, &prices).total_equity, cash });
+
+      current_date = self.cadence.next(current_date);
+    }
+
+    let summary = Self::summarize(&equity_curve, self.cadence);
+    Ok(BacktestResult { equity_curve, trades, summary })
+  }
+
+  // Closes longs that fell and shorts that rallied past the threshold at this step's marks
+  fn apply_stops(&mut self, stop_loss: StopLoss, marks: &mut StopMarks, prices: &HashMap<String, f64>, date_str: &str, trades: &mut Vec<TradeRecord>) {
+    for (ticker, price) in prices {
+      let price = *price;
+      let position = match self.portfolio.positions.get(ticker) {
+        Some(position) => position.clone(),
+        None => continue,
+      };
+
+      if position.long > 0.0 {
+        let peak = marks.long_peak.entry(ticker.clone()).or_insert(position.long_cost_basis);
+        *peak = peak.max(price);
+        let reference = match stop_loss.stop_type {
+          StopType::Fixed => position.long_cost_basis,
+          StopType::Trailing => *peak,
+        };
+        if reference > 0.0 && price <= reference * (1.0 - stop_loss.pct) {
+          let executed = apply_decision(&mut self.portfolio, ticker, Action::Sell, position.long, price, true);
+          if executed > 0.0 {
+            log::info!("Backtest {}: {} long stopped out at {:.2} (reference {:.2})", date_str, ticker, price, reference);
+            trades.push(TradeRecord { date: date_str.to_string(), ticker: ticker.clone(), action: stop_loss.stop_type.trade_action(Action::Sell), quantity: executed, price, stop_triggered: true });
+          }
+        }
+      }
+
+      if position.short > 0.0 {
+        let trough = marks.short_trough.entry(ticker.clone()).or_insert(position.short_cost_basis);
+        *trough = trough.min(price);
+        let reference = match stop_loss.stop_type {
+          StopType::Fixed => position.short_cost_basis,
+          StopType::Trailing => *trough,
+        };
+        if reference > 0.0 && price >= reference * (1.0 + stop_loss.pct) {
+          let executed = apply_decision(&mut self.portfolio, ticker, Action::Cover, position.short, price, true);
+          if executed > 0.0 {
+            log::info!("Backtest {}: {} short stopped out at {:.2} (reference {:.2})", date_str, ticker, price, reference);
+            trades.push(TradeRecord { date: date_str.to_string(), ticker: ticker.clone(), action: stop_loss.stop_type.trade_action(Action::Cover), quantity: executed, price, stop_triggered: true });
+          }
+        }
+      }
+    }
+
+    // Closed sides start from their new cost basis when reopened
+    let positions = &self.portfolio.positions;
+    marks.long_peak.retain(|ticker, _| positions.get(ticker).map_or(false, |position| position.long > 0.0));
+    marks.short_trough.retain(|ticker, _| positions.get(ticker).map_or(false, |position| position.short > 0.0));
+  }
+
+  async fn latest_prices(&self, api: &API, date: NaiveDate) -> Result<HashMap<String, f64>, Error> {
+    let start = (date - Duration::days(PRICE_LOOKBACK_DAYS)).format("%Y-%m-%d").to_string();
+    let end = date.format("%Y-%m-%d").to_string();
+
+    let mut prices = HashMap::new();
+    for ticker in &self.tickers {
+      let mut history = api.get_price(ticker, &start, &end).await?;
+      history.retain(|price| price.time.get(..10).map_or(false, |day| day >= start.as_str() && day <= end.as_str()));
+      history.sort_by(|a, b| a.time.cmp(&b.time));
+      if let Some(last) = history.last() {
+        prices.insert(ticker.clone(), last.close);
+      }
+    }
+    Ok(prices)
+  }
+
+  fn summarize(equity_curve: &[EquityPoint], cadence: Cadence) -> BacktestSummary {
+    let initial_value = equity_curve.first().map_or(0.0, |point| point.portfolio_value);
+    let final_value = equity_curve.last().map_or(0.0, |point| point.portfolio_value);
+    let total_return_pct = if initial_value > 0.0 { (final_value - initial_value) / initial_value * 100.0 } else { 0.0 };
+
+    let mut peak = f64::MIN;
+    let mut max_drawdown = 0.0;
+    for point in equity_curve {
+      peak = peak.max(point.portfolio_value);
+      if peak > 0.0 {
+        max_drawdown = f64::max(max_drawdown, (peak - point.portfolio_value) / peak);
+      }
+    }
+
+    let returns : Vec<f64> = equity_curve.windows(2)
+      .filter(|pair| pair[0].portfolio_value > 0.0)
+      .map(|pair| pair[1].portfolio_value / pair[0].portfolio_value - 1.0)
+      .collect();
+
+    // Annualized, risk-free rate taken as zero
+    let sharpe_ratio = if returns.len() >= 2 {
+      let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+      let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+      let std_dev = variance.sqrt();
+      if std_dev > 0.0 { Some(mean / std_dev * cadence.periods_per_year().sqrt()) } else { None }
+    } else {
+      None
+    };
+
+    BacktestSummary { initial_value, final_value, total_return_pct, max_drawdown_pct: max_drawdown * 100.0, sharpe_ratio }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::app::config::Config;
+
+  fn backtester(service: &AgentService, stop_loss: StopLoss) -> Backtester<'_> {
+    let tickers = vec!["AAPL".to_string()];
+    let mut portfolio = Portfolio::new(&tickers, 100000.0, 0.5);
+    apply_decision(&mut portfolio, "AAPL", Action::Buy, 10.0, 100.0, false);
+    apply_decision(&mut portfolio, "AAPL", Action::Short, 5.0, 100.0, false);
+    Backtester::new(service, tickers, "2024-01-01", "2024-01-31", Cadence::default(), portfolio, None, None, None, HedgeFundOptions::default(), Some(stop_loss)).unwrap()
+  }
+
+  // Runs the stop overlay over one close per step and returns the trades it made
+  fn run_stops(backtester: &mut Backtester, closes: &[f64]) -> Vec<TradeRecord> {
+    let stop_loss = backtester.stop_loss.unwrap();
+    let mut marks = StopMarks::default();
+    let mut trades = Vec::new();
+    for (day, close) in closes.iter().enumerate() {
+      let prices = HashMap::from([("AAPL".to_string(), *close)]);
+      backtester.apply_stops(stop_loss, &mut marks, &prices, &format!("2024-01-{:02}", day + 2), &mut trades);
+    }
+    trades
+  }
+
+  #[test]
+  fn fixed_stop_closes_the_long_below_cost() {
+    let service = AgentService::new(Config::for_tests());
+    let mut backtester = backtester(&service, StopLoss { pct: 0.1, stop_type: StopType::Fixed });
+
+    let trades = run_stops(&mut backtester, &[104.0, 95.0, 90.0, 85.0]);
+    assert_eq!(trades.len(), 1);
+    assert_eq!((trades[0].date.as_str(), trades[0].action.as_str(), trades[0].quantity, trades[0].price), ("2024-01-04", "stop_sell", 10.0, 90.0));
+    assert!(trades[0].stop_triggered);
+    assert_eq!(backtester.portfolio.positions["AAPL"].long, 0.0);
+    assert_eq!(backtester.portfolio.realized_gains["AAPL"].long, -100.0);
+    // The short gained over the same series and stays open
+    assert_eq!(backtester.portfolio.positions["AAPL"].short, 5.0);
+  }
+
+  #[test]
+  fn trailing_stop_follows_the_peak() {
+    let service = AgentService::new(Config::for_tests());
+    let mut backtester = backtester(&service, StopLoss { pct: 0.1, stop_type: StopType::Trailing });
+
+    // A fixed 10% stop would never fire, the trailing one fires 10% off the 120 peak
+    let trades: Vec<TradeRecord> = run_stops(&mut backtester, &[110.0, 120.0, 112.0, 108.0]).into_iter().filter(|trade| trade.action.ends_with("sell")).collect();
+    assert_eq!(trades.len(), 1);
+    assert_eq!((trades[0].date.as_str(), trades[0].action.as_str(), trades[0].price), ("2024-01-05", "trailing_stop_sell", 108.0));
+  }
+
+  #[test]
+  fn fixed_stop_covers_a_short_that_rallies() {
+    let service = AgentService::new(Config::for_tests());
+    let mut backtester = backtester(&service, StopLoss { pct: 0.1, stop_type: StopType::Fixed });
+
+    let trades: Vec<TradeRecord> = run_stops(&mut backtester, &[105.0, 109.0, 111.0]).into_iter().filter(|trade| trade.action.ends_with("cover")).collect();
+    assert_eq!(trades.len(), 1);
+    assert_eq!((trades[0].action.as_str(), trades[0].quantity, trades[0].price), ("stop_cover", 5.0, 111.0));
+    assert_eq!(backtester.portfolio.positions["AAPL"].short, 0.0);
+    assert_eq!(backtester.portfolio.margin_used, 0.0);
+  }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use anyhow::{Context, Error};
+use chrono::Utc;
+use serde_json::Value;
+
+// Runs finishing together would otherwise interleave their lines
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+const CSV_HEADER: &str = "timestamp,run_id,model_provider,model_name,tickers,ticker,action,quantity,confidence,analyst_signals";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecisionLogFormat {
+  #[default]
+  Jsonl, // one line per run
+  Csv,   // one row per ticker
+}
+
+impl FromStr for DecisionLogFormat {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value.trim().to_lowercase().as_str() {
+      "jsonl" | "json" => Ok(DecisionLogFormat::Jsonl),
+      "csv" => Ok(DecisionLogFormat::Csv),
+      other => Err(format!("unknown decision log format {}, expected jsonl or csv", other)),
+    }
+  }
+}
+
+impl fmt::Display for DecisionLogFormat {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DecisionLogFormat::Jsonl => write!(f, "jsonl"),
+      DecisionLogFormat::Csv => write!(f, "csv"),
+    }
+  }
+}
+
+// Appends the run's decisions (or recommendations in advisory mode) and analyst signals to `path`, flushed to disk before returning
+pub fn append_run(path: &Path, format: DecisionLogFormat, tickers: &[String], model_name: &str, model_provider: &str,
+                  result: &HashMap<String, Value>) -> Result<(), Error> {
+  let timestamp = Utc::now().to_rfc3339();
+  let run_id = result.get("run_id").and_then(Value::as_str).unwrap_or_default();
+  let decisions = result.get("decisions").or_else(|| result.get("recommendations")).cloned().unwrap_or_else(|| serde_json::json!({}));
+  let analyst_signals = result.get("analyst_signals").cloned().unwrap_or_else(|| serde_json::json!({}));
+
+  let _guard = WRITE_LOCK.lock().unwrap_or_else(|p| p.into_inner());
+  let mut file = OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("opening {}", path.display()))?;
+
+  let mut lines = String::new();
+  match format {
+    DecisionLogFormat::Jsonl => {
+      let per_ticker : serde_json::Map<String, Value> = tickers.iter().map(|ticker| {
+        let decision = decisions.get(ticker).unwrap_or(&Value::Null);
+        (ticker.clone(), serde_json::json!({
+          "action": decision.get("action"),
+          "quantity": decision.get("quantity"),
+          "confidence": decision.get("confidence"),
+        }))
+      }).collect();
+      let record = serde_json::json!({
+        "timestamp": timestamp,
+        "run_id": run_id,
+        "tickers": tickers,
+        "model_name": model_name,
+        "model_provider": model_provider,
+        "decisions": per_ticker,
+        "analyst_signals": analyst_signals,
+      });
+      lines.push_str(&serde_json::to_string(&record)?);
+      lines.push('\n');
+    }
+    DecisionLogFormat::Csv => {
+      if file.metadata().map(|metadata| metadata.len() == 0).unwrap_or(false) {
+        lines.push_str(CSV_HEADER);
+        lines.push('\n');
+      }
+      let joined_tickers = tickers.join(";");
+      for ticker in tickers {
+        let decision = decisions.get(ticker).unwrap_or(&Value::Null);
+        // Only this ticker's signal from each analyst
+        let signals : serde_json::Map<String, Value> = analyst_signals.as_object().map(|agents| agents.iter()
+          .filter_map(|(agent, signals)| signals.get(ticker).map(|signal| (agent.clone(), signal.clone())))
+          .collect()).unwrap_or_default();
+        let fields = [
+          timestamp.clone(),
+          run_id.to_string(),
+          model_provider.to_string(),
+          model_name.to_string(),
+          joined_tickers.clone(),
+          ticker.clone(),
+          csv_value(decision.get("action")),
+          csv_value(decision.get("quantity")),
+          csv_value(decision.get("confidence")),
+          serde_json::to_string(&signals)?,
+        ];
+        lines.push_str(&fields.iter().map(|field| csv_escape(field)).collect::<Vec<String>>().join(","));
+        lines.push('\n');
+      }
+    }
+  }
+
+  file.write_all(lines.as_bytes()).with_context(|| format!("appending to {}", path.display()))?;
+  file.sync_data().with_context(|| format!("flushing {}", path.display()))?;
+  Ok(())
+}
+
+fn csv_value(value: Option<&Value>) -> String {
+  match value {
+    Some(Value::String(text)) => text.clone(),
+    Some(Value::Null) | None => String::new(),
+    Some(other) => other.to_string(),
+  }
+}
+
+fn csv_escape(field: &str) -> String {
+  if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+    return format!("\"{}\"", field.replace('"', "\"\""));
+  }
+  field.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use std::fs;
+  use std::path::PathBuf;
+
+  fn log_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("decision-log-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+  }
+
+  fn run_result(run_id: &str) -> HashMap<String, Value> {
+    HashMap::from([
+      ("run_id".to_string(), json!(run_id)),
+      ("decisions".to_string(), json!({ "AAPL": { "action": "buy", "quantity": 10.0, "confidence": 80.0, "reasoning": "cheap, for now" } })),
+      ("analyst_signals".to_string(), json!({ "warren_buffett_agent": { "AAPL": { "signal": "bullish", "confidence": 80.0 }, "MSFT": { "signal": "bearish" } } })),
+    ])
+  }
+
+  #[test]
+  fn each_run_appends_a_parseable_jsonl_line() {
+    let path = log_path("runs.jsonl");
+    let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+    append_run(&path, DecisionLogFormat::Jsonl, &tickers, "gpt-4o", "OpenAI", &run_result("run-1")).unwrap();
+    append_run(&path, DecisionLogFormat::Jsonl, &tickers, "gpt-4o", "OpenAI", &run_result("run-2")).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let records : Vec<Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    let record = &records[0];
+    assert!(chrono::DateTime::parse_from_rfc3339(record["timestamp"].as_str().unwrap()).is_ok());
+    assert_eq!(record["run_id"], json!("run-1"));
+    assert_eq!(records[1]["run_id"], json!("run-2"));
+    assert_eq!(record["tickers"], json!(["AAPL", "MSFT"]));
+    assert_eq!((record["model_name"].clone(), record["model_provider"].clone()), (json!("gpt-4o"), json!("OpenAI")));
+    assert_eq!(record["decisions"]["AAPL"], json!({ "action": "buy", "quantity": 10.0, "confidence": 80.0 }));
+    // A ticker without a decision is still listed
+    assert_eq!(record["decisions"]["MSFT"], json!({ "action": null, "quantity": null, "confidence": null }));
+    assert_eq!(record["analyst_signals"]["warren_buffett_agent"]["AAPL"]["signal"], json!("bullish"));
+  }
+
+  #[test]
+  fn csv_rows_share_one_header_and_escape_their_fields() {
+    let path = log_path("runs.csv");
+    let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+    append_run(&path, DecisionLogFormat::Csv, &tickers, "gpt-4o", "OpenAI", &run_result("run-1")).unwrap();
+    append_run(&path, DecisionLogFormat::Csv, &tickers, "gpt-4o", "OpenAI", &run_result("run-2")).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let lines : Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], CSV_HEADER);
+    assert!(lines[1].contains(",run-1,OpenAI,gpt-4o,AAPL;MSFT,AAPL,buy,10.0,80.0,\"{\"\"warren_buffett_agent\"\":"), "{}", lines[1]);
+    assert!(lines[2].contains(",run-1,OpenAI,gpt-4o,AAPL;MSFT,MSFT,,,,"), "{}", lines[2]);
+    assert!(lines[3].contains(",run-2,"));
+  }
+
+  #[test]
+  fn formats_parse_by_name() {
+    assert_eq!(" JSONL ".parse::<DecisionLogFormat>(), Ok(DecisionLogFormat::Jsonl));
+    assert_eq!("csv".parse::<DecisionLogFormat>(), Ok(DecisionLogFormat::Csv));
+    assert!("xml".parse::<DecisionLogFormat>().is_err());
+  }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use anyhow::Error;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use serde_json::Value;
+
+use crate::app::models::errors::ServiceError;
+
+// ServiceError rather than anyhow so every waiter gets the same status code back
+type SharedRun = Shared<BoxFuture<'static, Result<HashMap<String, Value>, ServiceError>>>;
+
+struct Entry {
+  completed_at: Arc<OnceLock<Instant>>, // set when the run finishes, the ttl counts from here
+  run: SharedRun,
+}
+
+// Runs keyed by the client's idempotency key, a retry awaits the in-flight run or gets its result back instead of starting
+// another. In-flight runs are never evicted, completed ones are kept for `ttl` after they finish and failed ones are dropped
+// so a retry runs again.
+pub struct IdempotencyStore {
+  runs: Mutex<HashMap<String, Entry>>,
+  ttl: Duration,
+}
+
+impl IdempotencyStore {
+  pub fn new(ttl: Duration) -> Self {
+    IdempotencyStore { runs: Mutex::new(HashMap::new()), ttl }
+  }
+
+  pub async fn run<F>(&self, key: &str, run: F) -> Result<HashMap<String, Value>, Error>
+  where
+    F: Future<Output = Result<HashMap<String, Value>, Error>> + Send + 'static,
+  {
+    let shared = {
+      let mut runs = self.runs.lock().unwrap();
+      let ttl = self.ttl;
+      runs.retain(|_, entry| match entry.run.peek() {
+        None => true,
+        Some(Err(_)) => false,
+        Some(Ok(_)) => entry.completed_at.get().map_or(true, |completed_at| completed_at.elapsed() < ttl),
+      });
+
+      match runs.get(key) {
+        Some(entry) => {
+          log::info!("Reusing the run for idempotency key {}", key);
+          entry.run.clone()
+        }
+        None => {
+          let completed_at : Arc<OnceLock<Instant>> = Arc::new(OnceLock::new());
+          let finished = completed_at.clone();
+          let shared = run.map(move |result| {
+            let _ = finished.set(Instant::now());
+            result.map_err(|e| ServiceError::from_error(&e))
+          }).boxed().shared();
+          // Keeps the run going when the client that started it disconnects before a retry arrives
+          actix_web::rt::spawn(shared.clone());
+          runs.insert(key.to_string(), Entry { completed_at, run: shared.clone() });
+          shared
+        }
+      }
+    };
+    return shared.await.map_err(Error::from);
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  // Counts how often the underlying run actually starts
+  fn counted_run(runs: &Arc<AtomicUsize>, delay: Duration, fail: bool) -> impl Future<Output = Result<HashMap<String, Value>, Error>> + Send + 'static {
+    let runs = runs.clone();
+    async move {
+      let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+      tokio::time::sleep(delay).await;
+      if fail {
+        return Err(ServiceError::UpstreamUnavailable("provider down".to_string()).into());
+      }
+      Ok(HashMap::from([("run".to_string(), Value::from(run))]))
+    }
+  }
+
+  #[actix_web::test]
+  async fn concurrent_requests_with_the_same_key_share_one_run() {
+    let store = IdempotencyStore::new(Duration::from_secs(60));
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let (first, second) = futures::join!(
+      store.run("key", counted_run(&runs, Duration::from_millis(50), false)),
+      store.run("key", counted_run(&runs, Duration::from_millis(50), false)),
+    );
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(first.unwrap(), second.unwrap());
+
+    store.run("other", counted_run(&runs, Duration::ZERO, false)).await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+  }
+
+  #[actix_web::test]
+  async fn an_in_flight_run_outlives_the_ttl() {
+    let store = Arc::new(IdempotencyStore::new(Duration::from_millis(10)));
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let first = store.run("key", counted_run(&runs, Duration::from_millis(100), false));
+    let retry = async {
+      tokio::time::sleep(Duration::from_millis(40)).await;
+      store.run("key", counted_run(&runs, Duration::ZERO, false)).await
+    };
+    let (first, retry) = futures::join!(first, retry);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(first.unwrap(), retry.unwrap());
+  }
+
+  #[actix_web::test]
+  async fn completed_runs_expire_a_ttl_after_they_finish() {
+    let store = IdempotencyStore::new(Duration::from_millis(50));
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    store.run("key", counted_run(&runs, Duration::from_millis(80), false)).await.unwrap();
+    store.run("key", counted_run(&runs, Duration::ZERO, false)).await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    store.run("key", counted_run(&runs, Duration::ZERO, false)).await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+  }
+
+  #[actix_web::test]
+  async fn a_failed_run_is_retried() {
+    let store = IdempotencyStore::new(Duration::from_secs(60));
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    assert!(store.run("key", counted_run(&runs, Duration::ZERO, true)).await.is_err());
+    assert!(store.run("key", counted_run(&runs, Duration::ZERO, false)).await.is_ok());
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+  }
+}
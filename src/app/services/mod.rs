@@ -1,2 +1,8 @@
 pub mod agent_service;
-pub mod service;
\ No newline at end of file
+pub mod service;
+pub mod backtest;
+pub mod portfolio;
+pub mod run_store;
+pub mod idempotency;
+pub mod decision_log;
+pub mod watchlists;
\ No newline at end of file
@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::Serialize;
+
+use crate::ai_agent::agents::portfolio_manager::Action;
+use crate::ai_agent::data::models::Portfolio;
+
+fn sized(quantity: f64, allow_fractional: bool) -> f64 {
+  if allow_fractional { quantity } else { quantity.floor() }
+}
+
+// Applies one portfolio manager decision at `price` and returns the quantity actually executed.
+// Follows the trading rules given to the portfolio manager: buys are limited by cash, sells by long shares,
+// shorts by the margin requirement and covers by short shares. Anything beyond that is clamped and logged.
+pub fn apply_decision(portfolio: &mut Portfolio, ticker: &str, action: Action, quantity: f64, price: f64, allow_fractional: bool) -> f64 {
+  if action == Action::Hold || quantity <= 0.0 || price <= 0.0 {
+    return 0.0;
+  }
+
+  let margin_requirement = portfolio.margin_requirement;
+  let cash = portfolio.cash;
+  let available_margin = (equity_at_cost(portfolio) - portfolio.margin_used).max(0.0);
+  let position = portfolio.positions.entry(ticker.to_string()).or_default();
+  let mut realized_long = 0.0;
+  let mut realized_short = 0.0;
+  let mut cash_delta = 0.0;
+  let mut margin_delta = 0.0;
+
+  let executed = match action {
+    Action::Buy => {
+      let executed = quantity.min(sized(cash / price, allow_fractional));
+      if executed > 0.0 {
+        position.long_cost_basis = (position.long * position.long_cost_basis + executed * price) / (position.long + executed);
+        position.long += executed;
+        cash_delta = -executed * price;
+      }
+      executed
+    }
+    Action::Sell => {
+      let executed = quantity.min(position.long);
+      if executed > 0.0 {
+        realized_long = (price - position.long_cost_basis) * executed;
+        position.long -= executed;
+        cash_delta = executed * price;
+        if position.long <= 0.0 {
+          position.long_cost_basis = 0.0;
+        }
+      }
+      executed
+    }
+    Action::Short => {
+      // Proceeds are credited to cash while the margin requirement is set aside from it. Proceeds are owed back,
+      // so the limit comes from equity rather than cash, otherwise every short would fund the next one
+      let max_quantity = if margin_requirement > 0.0 { sized(available_margin / (price * margin_requirement), allow_fractional) } else { quantity };
+      let executed = quantity.min(max_quantity);
+      if executed < quantity {
+        log::warn!("Over-leveraged short rejected for {}: {} shares at {:.2} need {:.2} margin, {:.2} available",
+                   ticker, quantity, price, quantity * price * margin_requirement, available_margin);
+      }
+      if executed > 0.0 {
+        let margin_required = executed * price * margin_requirement;
+        position.short_cost_basis = (position.short * position.short_cost_basis + executed * price) / (position.short + executed);
+        position.short += executed;
+        position.short_margin_used += margin_required;
+        margin_delta = margin_required;
+        cash_delta = executed * price - margin_required;
+      }
+      executed
+    }
+    Action::Cover => {
+      let executed = quantity.min(position.short);
+      if executed > 0.0 {
+        let margin_released = position.short_margin_used * executed / position.short;
+        realized_short = (position.short_cost_basis - price) * executed;
+        position.short -= executed;
+        position.short_margin_used -= margin_released;
+        margin_delta = -margin_released;
+        cash_delta = margin_released - executed * price;
+        if position.short <= 0.0 {
+          position.short_cost_basis = 0.0;
+          position.short_margin_used = 0.0;
+        }
+      }
+      executed
+    }
+    Action::Hold => 0.0,
+  };
+
+  if executed < quantity {
+    log::warn!("Clamped {:?} {} from {} to {} shares at {:.2}", action, ticker, quantity, executed, price);
+  }
+  if executed <= 0.0 {
+    return 0.0;
+  }
+
+  let gains = portfolio.realized_gains.entry(ticker.to_string()).or_default();
+  gains.long += realized_long;
+  gains.short += realized_short;
+
+  portfolio.cash += cash_delta;
+  portfolio.margin_used += margin_delta;
+
+  return executed;
+}
+
+// Cash and margin set aside, plus longs less shorts at cost. Short proceeds and the shares owed cancel out,
+// so opening a short leaves it unchanged
+fn equity_at_cost(portfolio: &Portfolio) -> f64 {
+  let positions : f64 = portfolio.positions.values().map(|position| position.long * position.long_cost_basis - position.short * position.short_cost_basis).sum();
+  return portfolio.cash + portfolio.margin_used + positions;
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PositionValuation {
+  pub price: f64,
+  pub long_market_value: f64,
+  pub short_market_value: f64, // cost of buying the short shares back
+  pub unrealized_long_pnl: f64,
+  pub unrealized_short_pnl: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PortfolioValuation {
+  pub total_equity: f64,
+  pub cash: f64,
+  pub margin_used: f64,
+  pub long_market_value: f64,
+  pub short_market_value: f64,
+  pub unrealized_long_pnl: f64,
+  pub unrealized_short_pnl: f64,
+  pub gross_exposure: f64, // longs plus shorts at market
+  pub net_exposure: f64,   // longs less shorts at market
+  pub positions: BTreeMap<String, PositionValuation>, // open positions only
+  pub unpriced_tickers: Vec<String>, // open positions without a price, marked at cost
+}
+
+// Marks every open position to `prices`. Equity is cash plus long market value, minus what it costs to buy back the shorts,
+// plus margin set aside for them
+pub fn portfolio_value(portfolio: &Portfolio, prices: &HashMap<String, f64>) -> PortfolioValuation {
+  let mut valuation = PortfolioValuation { cash: portfolio.cash, margin_used: portfolio.margin_used, ..PortfolioValuation::default() };
+
+  for (ticker, position) in &portfolio.positions {
+    if position.long <= 0.0 && position.short <= 0.0 {
+      continue;
+    }
+    let price = match prices.get(ticker).copied().filter(|price| *price > 0.0) {
+      Some(price) => price,
+      None => {
+        valuation.unpriced_tickers.push(ticker.clone());
+        valuation.long_market_value += position.long * position.long_cost_basis;
+        valuation.short_market_value += position.short * position.short_cost_basis;
+        continue;
+      }
+    };
+
+    let marked = PositionValuation {
+      price,
+      long_market_value: position.long * price,
+      short_market_value: position.short * price,
+      unrealized_long_pnl: (price - position.long_cost_basis) * position.long,
+      unrealized_short_pnl: (position.short_cost_basis - price) * position.short,
+    };
+    valuation.long_market_value += marked.long_market_value;
+    valuation.short_market_value += marked.short_market_value;
+    valuation.unrealized_long_pnl += marked.unrealized_long_pnl;
+    valuation.unrealized_short_pnl += marked.unrealized_short_pnl;
+    valuation.positions.insert(ticker.clone(), marked);
+  }
+
+  valuation.unpriced_tickers.sort();
+  valuation.gross_exposure = valuation.long_market_value + valuation.short_market_value;
+  valuation.net_exposure = valuation.long_market_value - valuation.short_market_value;
+  valuation.total_equity = portfolio.cash + portfolio.margin_used + valuation.net_exposure;
+  return valuation;
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn book(cash: f64, margin_requirement: f64) -> Portfolio {
+    Portfolio::new(&["AAPL".to_string(), "MSFT".to_string()], cash, margin_requirement)
+  }
+
+  #[test]
+  fn short_at_the_margin_limit_executes_in_full() {
+    let mut portfolio = book(10000.0, 0.5);
+    // $10,000 of equity at a 50% requirement carries $20,000 of short exposure
+    assert_eq!(apply_decision(&mut portfolio, "AAPL", Action::Short, 200.0, 100.0, false), 200.0);
+
+    let position = &portfolio.positions["AAPL"];
+    assert_eq!(position.short, 200.0);
+    assert_eq!(position.short_margin_used, 10000.0);
+    assert_eq!(portfolio.margin_used, 10000.0);
+    assert_eq!(portfolio.cash, 20000.0);
+
+    // The margin is used up, so the short proceeds cannot fund another one
+    assert_eq!(apply_decision(&mut portfolio, "MSFT", Action::Short, 1.0, 100.0, false), 0.0);
+    assert_eq!(portfolio.positions["MSFT"].short, 0.0);
+  }
+
+  #[test]
+  fn over_leveraged_short_is_clamped_to_the_margin_limit() {
+    let mut portfolio = book(10000.0, 0.5);
+    assert_eq!(apply_decision(&mut portfolio, "AAPL", Action::Short, 250.0, 100.0, false), 200.0);
+    assert_eq!(portfolio.margin_used, 10000.0);
+
+    let mut fractional = book(10000.0, 0.5);
+    assert_eq!(apply_decision(&mut fractional, "AAPL", Action::Short, 250.0, 300.0, true), 10000.0 / 150.0);
+  }
+
+  #[test]
+  fn cover_releases_margin_in_proportion() {
+    let mut portfolio = book(10000.0, 0.5);
+    apply_decision(&mut portfolio, "AAPL", Action::Short, 100.0, 100.0, false);
+    assert_eq!(apply_decision(&mut portfolio, "AAPL", Action::Cover, 40.0, 90.0, false), 40.0);
+
+    assert_eq!(portfolio.positions["AAPL"].short, 60.0);
+    assert_eq!(portfolio.positions["AAPL"].short_margin_used, 3000.0);
+    assert_eq!(portfolio.margin_used, 3000.0);
+    assert_eq!(portfolio.realized_gains["AAPL"].short, 400.0);
+  }
+
+  fn marked_book() -> Portfolio {
+    let tickers : Vec<String> = ["AAPL", "MSFT", "TSLA", "NVDA", "AMZN", "META"].iter().map(|ticker| ticker.to_string()).collect();
+    let mut portfolio = Portfolio::new(&tickers, 10000.0, 0.5);
+    portfolio.margin_used = 2500.0;
+    let mut set = |ticker: &str, long: f64, long_cost_basis: f64, short: f64, short_cost_basis: f64| {
+      let position = portfolio.positions.get_mut(ticker).unwrap();
+      (position.long, position.long_cost_basis, position.short, position.short_cost_basis) = (long, long_cost_basis, short, short_cost_basis);
+    };
+    set("AAPL", 10.0, 100.0, 0.0, 0.0); // long, up
+    set("MSFT", 5.0, 200.0, 0.0, 0.0);  // long, down
+    set("TSLA", 0.0, 0.0, 10.0, 250.0); // short, price fell
+    set("NVDA", 0.0, 0.0, 4.0, 100.0);  // short, price rose
+    set("AMZN", 2.0, 50.0, 0.0, 0.0);   // long without a price
+    portfolio
+  }
+
+  #[test]
+  fn longs_and_shorts_are_marked_with_their_gains_and_losses() {
+    let prices = HashMap::from([("AAPL".to_string(), 120.0), ("MSFT".to_string(), 180.0), ("TSLA".to_string(), 200.0), ("NVDA".to_string(), 150.0), ("META".to_string(), 300.0)]);
+    let valuation = portfolio_value(&marked_book(), &prices);
+
+    let aapl = &valuation.positions["AAPL"];
+    assert_eq!((aapl.long_market_value, aapl.unrealized_long_pnl), (1200.0, 200.0));
+    let msft = &valuation.positions["MSFT"];
+    assert_eq!((msft.long_market_value, msft.unrealized_long_pnl), (900.0, -100.0));
+    let tsla = &valuation.positions["TSLA"];
+    assert_eq!((tsla.short_market_value, tsla.unrealized_short_pnl), (2000.0, 500.0));
+    let nvda = &valuation.positions["NVDA"];
+    assert_eq!((nvda.short_market_value, nvda.unrealized_short_pnl), (600.0, -200.0));
+
+    // AMZN has no price and is carried at cost, META is flat and left out
+    assert_eq!(valuation.unpriced_tickers, ["AMZN"]);
+    assert_eq!(valuation.positions.keys().collect::<Vec<_>>(), ["AAPL", "MSFT", "NVDA", "TSLA"]);
+
+    assert_eq!(valuation.long_market_value, 2200.0);
+    assert_eq!(valuation.short_market_value, 2600.0);
+    assert_eq!(valuation.unrealized_long_pnl, 100.0);
+    assert_eq!(valuation.unrealized_short_pnl, 300.0);
+    assert_eq!(valuation.gross_exposure, 4800.0);
+    assert_eq!(valuation.net_exposure, -400.0);
+    assert_eq!(valuation.total_equity, 12100.0);
+  }
+
+  #[test]
+  fn marking_at_cost_matches_the_book_equity() {
+    let portfolio = marked_book();
+    let at_cost = HashMap::from([("AAPL".to_string(), 100.0), ("MSFT".to_string(), 200.0), ("TSLA".to_string(), 250.0), ("NVDA".to_string(), 100.0)]);
+    let valuation = portfolio_value(&portfolio, &at_cost);
+    assert_eq!(valuation.unrealized_long_pnl + valuation.unrealized_short_pnl, 0.0);
+    assert_eq!(valuation.total_equity, equity_at_cost(&portfolio));
+  }
+}
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+  pub id: String,
+  pub created_at: DateTime<Utc>,
+  pub duration_ms: u64,
+  pub tickers: Vec<String>,
+  pub start_date: String,
+  pub end_date: String,
+  pub model_name: Option<String>,
+  pub model_provider: Option<String>,
+  pub selected_analysts: Option<Vec<String>>,
+  pub decisions: Value, // orders, or recommendations in advisory mode
+  pub analyst_signals: Value,
+}
+
+// Audit trail of completed hedge-fund runs, oldest records are evicted once `capacity` is reached
+pub struct RunStore {
+  runs: Mutex<VecDeque<RunRecord>>,
+  capacity: usize,
+}
+
+impl RunStore {
+  pub fn new(capacity: usize) -> Self {
+    RunStore { runs: Mutex::new(VecDeque::with_capacity(capacity)), capacity }
+  }
+
+  pub fn record(&self, run: RunRecord) {
+    if self.capacity == 0 {
+      return;
+    }
+    let mut runs = self.runs.lock().unwrap();
+    while runs.len() >= self.capacity {
+      runs.pop_front();
+    }
+    runs.push_back(run);
+  }
+
+  // Newest first
+  pub fn list(&self) -> Vec<RunRecord> {
+    self.runs.lock().unwrap().iter().rev().cloned().collect()
+  }
+
+  pub fn get(&self, id: &str) -> Option<RunRecord> {
+    self.runs.lock().unwrap().iter().find(|run| run.id == id).cloned()
+  }
+}
@@ -1,23 +1,146 @@
 use super::agent_service::AgentService;
+use super::backtest::{Backtester, BacktestResult, Cadence, StopLoss};
+use super::portfolio::{apply_decision, portfolio_value};
+use super::run_store::{RunRecord, RunStore};
+use super::watchlists::{Watchlist, Watchlists};
+use crate::ai_agent::agents::portfolio_manager::Action;
+use crate::ai_agent::data::models::{CompanyFacts, CompanyNews, FinancialMetrics, InsiderTrade, Portfolio, ReportPeriod};
+use crate::ai_agent::tools::api::API;
+use crate::app::config::Config;
+use crate::app::models::errors::ServiceError;
+use crate::app::models::options::HedgeFundOptions;
 use crate::ai_agent::utils::analysts::get_analyst_order;
-use crate::ai_agent::llm::models::{get_available_models, get_ollama_models};
+use crate::ai_agent::llm::models::{get_available_models, get_ollama_models, get_provider_readiness, get_ready_model, llm_timeout};
+use crate::ai_agent::llm::model_provider::{chat_with_timeout, ChatMessage, LLMModelConfig, ModelProvider, ProviderReadiness};
 
 use std::collections::HashMap;
-use chrono::{NaiveDate, Local};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{NaiveDate, Local, Utc};
 use serde_json::Value;
 use anyhow::{Error, Ok};
 use std::result::Result;
 use std::option::Option;
 
 
+const INSIDER_TRADE_LIMIT: i64 = 1000;
+const DEFAULT_NEWS_LIMIT: i64 = 100;
+const MAX_NEWS_LIMIT: i64 = 1000;
+const DEFAULT_METRICS_LIMIT: i64 = 5;
+const READINESS_PING_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_METRICS_LIMIT: i64 = 20;
+// Any listed ticker works, the facts lookup is only there to exercise the financial datasets key
+const VALIDATION_TICKER: &str = "AAPL";
+
 pub struct HedgeFundServices {
-  agent_service : AgentService
+  agent_service : AgentService,
+  run_store : Arc<RunStore>,
+  watchlists : Arc<Watchlists>,
 }
 
 impl HedgeFundServices {
 
-  pub fn new(agent_service: AgentService) -> Self {
-    HedgeFundServices { agent_service: agent_service }
+  pub fn new(agent_service: AgentService, run_store: Arc<RunStore>, watchlists: Arc<Watchlists>) -> Self {
+    HedgeFundServices { agent_service: agent_service, run_store: run_store, watchlists: watchlists }
+  }
+
+  pub fn get_watchlists(&self) -> Vec<Watchlist> {
+    return self.watchlists.list();
+  }
+
+  // Unions the watchlist's tickers into the explicit ones, an unknown name is rejected as bad input
+  fn resolve_tickers(&self, tickers: Vec<String>, watchlist: Option<&str>) -> Result<Vec<String>, Error> {
+    let name = match watchlist {
+      Some(name) => name,
+      None => return Ok(tickers),
+    };
+    return self.watchlists.expand(tickers, name).ok_or_else(|| {
+      ServiceError::InvalidInput(format!("Unknown watchlist: {}, see /agent/watchlists for the available names", name)).into()
+    });
+  }
+
+  pub async fn get_company_facts(&self, ticker: &str) -> Result<CompanyFacts, Error> {
+    let ticker = ticker.trim();
+    if ticker.is_empty() {
+      return Err(ServiceError::InvalidInput("A ticker is required".to_string()).into());
+    }
+
+    let api = API::new(self.agent_service.config().clone());
+    return Ok(api.get_company_facts(&ticker.to_uppercase()).await?);
+  }
+
+  // Defaults to the 90 days up to today, the same window a hedge-fund run uses
+  pub async fn get_insider_trades(&self, ticker: &str, start_date: Option<&str>, end_date: Option<&str>) -> Result<Vec<InsiderTrade>, Error> {
+    let ticker = ticker.trim();
+    if ticker.is_empty() {
+      return Err(ServiceError::InvalidInput("A ticker is required".to_string()).into());
+    }
+
+    let (start_date, end_date) = Self::date_window(start_date, end_date)?;
+    let api = API::new(self.agent_service.config().clone());
+    return Ok(api.get_insider_trade(&ticker.to_uppercase(), &end_date, Some(&start_date), INSIDER_TRADE_LIMIT).await?);
+  }
+
+  // Same 90 day default window as insider trades, `limit` is the page size used while paginating back to start_date
+  pub async fn get_company_news(&self, ticker: &str, start_date: Option<&str>, end_date: Option<&str>, limit: Option<i64>) -> Result<Vec<CompanyNews>, Error> {
+    let ticker = ticker.trim();
+    if ticker.is_empty() {
+      return Err(ServiceError::InvalidInput("A ticker is required".to_string()).into());
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_NEWS_LIMIT);
+    if limit < 1 || limit > MAX_NEWS_LIMIT {
+      return Err(ServiceError::InvalidInput(format!("limit must be between 1 and {}", MAX_NEWS_LIMIT)).into());
+    }
+
+    let (start_date, end_date) = Self::date_window(start_date, end_date)?;
+    let api = API::new(self.agent_service.config().clone());
+    return Ok(api.get_company_news(&ticker.to_uppercase(), &end_date, Some(&start_date), limit).await?);
+  }
+
+  // The same cached lookup the analysts use, so the response shows exactly what they saw. An out of range limit is clamped
+  pub async fn get_financial_metrics(&self, ticker: &str, end_date: Option<&str>, period: Option<&str>, limit: Option<i64>) -> Result<Vec<FinancialMetrics>, Error> {
+    let ticker = ticker.trim();
+    if ticker.is_empty() {
+      return Err(ServiceError::InvalidInput("A ticker is required".to_string()).into());
+    }
+
+    let period : ReportPeriod = period.unwrap_or("ttm").parse().map_err(ServiceError::InvalidInput)?;
+
+    let end_date: NaiveDate = match end_date {
+      Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| ServiceError::InvalidInput(format!("Invalid end_date {}: {}", date, e)))?,
+      None => Local::now().naive_local().date(),
+    };
+    let limit = limit.unwrap_or(DEFAULT_METRICS_LIMIT).clamp(1, MAX_METRICS_LIMIT);
+
+    let api = API::new(self.agent_service.config().clone());
+    let end_date = end_date.format("%Y-%m-%d").to_string();
+    return Ok(api.get_financial_metrics(&ticker.to_uppercase(), &end_date, Some(period.as_str()), Some(limit)).await?);
+  }
+
+  // Validates an optional YYYY-MM-DD range, defaulting to the 90 days up to today
+  fn date_window(start_date: Option<&str>, end_date: Option<&str>) -> Result<(String, String), Error> {
+    let end_date: NaiveDate = match end_date {
+      Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| ServiceError::InvalidInput(format!("Invalid end_date {}: {}", date, e)))?,
+      None => Local::now().naive_local().date(),
+    };
+    let start_date: NaiveDate = match start_date {
+      Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| ServiceError::InvalidInput(format!("Invalid start_date {}: {}", date, e)))?,
+      None => end_date - chrono::Duration::days(90),
+    };
+    if start_date > end_date {
+      return Err(ServiceError::InvalidInput(format!("start_date {} is after end_date {}", start_date, end_date)).into());
+    }
+    return Ok((start_date.format("%Y-%m-%d").to_string(), end_date.format("%Y-%m-%d").to_string()));
+  }
+
+  pub fn list_runs(&self) -> Vec<RunRecord> {
+    return self.run_store.list();
+  }
+
+  pub fn get_run(&self, id: &str) -> Result<RunRecord, Error> {
+    return self.run_store.get(id).ok_or_else(|| ServiceError::NotFound(format!("No run with id {}", id)).into());
   }
 
   pub fn get_available_models(&self) -> Result<(Vec<HashMap<String, String>>, Vec<HashMap<String, String>>), Error> {
@@ -40,6 +163,92 @@ impl HedgeFundServices {
     return Ok((standard_models, ollama_models));
   }
 
+  // Pre-flight check of the LLM provider and the financial datasets key, each check reports its own error
+  pub async fn validate_model(&self, model_name: &str, model_provider: &str) -> Result<Value, Error> {
+    let provider = ModelProvider::from_str(model_provider).map_err(ServiceError::InvalidInput)?;
+    if model_name.trim().is_empty() {
+      return Err(ServiceError::InvalidInput("A model_name is required".to_string()).into());
+    }
+
+    let config = self.agent_service.config();
+    let llm_config = LLMModelConfig {
+      provider,
+      model_name: model_name.trim().to_string(),
+      api_key: None,
+      base_url: None,
+      temperature: Some(0.0),
+      max_tokens: Some(5),
+      top_p: None,
+      json_mode: false,
+      seed: None,
+    };
+    let limit = llm_timeout(&HashMap::new(), config);
+    let llm_check = async {
+      let client = get_ready_model(&llm_config, config)?;
+      let messages = vec![ChatMessage::new("user", "Reply with OK")];
+      match chat_with_timeout(client.as_ref(), messages, &llm_config, limit).await? {
+        Some(_) => Ok(()),
+        None => Err(ServiceError::UpstreamUnavailable(format!("{} did not respond within {:.0}s", llm_config.provider, limit.as_secs_f64())).into()),
+      }
+    };
+    let llm = Self::check_result(llm_check.await);
+
+    let api = API::new(config.clone());
+    let financial_data = Self::check_result(api.fetch_company_facts(VALIDATION_TICKER).await.map(|_| ()).map_err(Error::from));
+
+    let ok = llm["ok"] == Value::Bool(true) && financial_data["ok"] == Value::Bool(true);
+    return Ok(serde_json::json!({ "ok": ok, "llm": llm, "financial_data": financial_data }));
+  }
+
+  fn check_result(result: Result<(), Error>) -> Value {
+    match result {
+      Err(e) => {
+        let error = ServiceError::from_error(&e);
+        serde_json::json!({ "ok": false, "code": error.code(), "error": error.message() })
+      }
+      _ => serde_json::json!({ "ok": true }),
+    }
+  }
+
+  // Readiness probe: the data API key is set and the API answers, and at least one implemented LLM provider has a key.
+  // Providers are not called, a completion on every probe would cost tokens
+  pub async fn readiness(&self) -> Result<(bool, Value), Error> {
+    let config = self.agent_service.config();
+
+    let data_api_key = config.financial_datasets_api_key.is_some();
+    let data_api = match API::new(config.clone()).ping(READINESS_PING_TIMEOUT).await {
+      Err(e) => serde_json::json!({ "ok": false, "url": config.financial_datasets_base_url, "error": e.to_string() }),
+      _ => serde_json::json!({ "ok": true, "url": config.financial_datasets_base_url }),
+    };
+
+    let providers : HashMap<String, &str> = ModelProvider::all().iter()
+      .map(|provider| (provider.to_string(), get_provider_readiness(provider, config).as_str()))
+      .collect();
+    let llm_ready = providers.values().any(|status| *status == ProviderReadiness::Ready.as_str());
+
+    let ready = data_api_key && data_api["ok"] == Value::Bool(true) && llm_ready;
+    return Ok((ready, serde_json::json!({
+      "status": if ready { "ready" } else { "unavailable" },
+      "checks": {
+        "financial_datasets_key": { "ok": data_api_key },
+        "financial_data_api": data_api,
+        "llm_providers": { "ok": llm_ready, "providers": providers },
+      },
+    })));
+  }
+
+  pub fn get_provider_status(&self) -> Result<Vec<HashMap<String, String>>, Error> {
+    let config = self.agent_service.config();
+    let providers = ModelProvider::all().iter().map(|provider| {
+      let mut map = HashMap::new();
+      map.insert("provider".to_string(), provider.to_string());
+      map.insert("status".to_string(), get_provider_readiness(provider, config).as_str().to_string());
+      map
+    }).collect();
+
+    return Ok(providers);
+  }
+
   pub fn get_available_analysts(&self) -> Result<Vec<HashMap<String, String>>, Error> {
     let analysts = get_analyst_order().iter().map(|(display_name, key)| {
       let mut map = HashMap::new(); 
@@ -52,14 +261,56 @@ impl HedgeFundServices {
   }
 
 
-  pub async fn hedge_fund(&self, tickers: Vec<String>, start_date: Option<&str>, end_date: Option<&str>, 
-                          initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>, 
-                          selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>) -> Result<HashMap<String, Value>, Error> {
-    
-    let initial_cash: f64 = initial_cash.unwrap_or(100000.0);
-    let margin_requirement: f64 = margin_requirement.unwrap_or(0.0); 
+  pub fn initial_portfolio(tickers: &[String], initial_cash: f64, margin_requirement: f64) -> Portfolio {
+    return Portfolio::new(tickers, initial_cash, margin_requirement);
+  }
+
+  // Request values win, DEFAULT_INITIAL_CASH and DEFAULT_MARGIN_REQUIREMENT fill in what the request omits
+  fn starting_capital(initial_cash: Option<f64>, margin_requirement: Option<f64>, config: &Config) -> (f64, f64) {
+    return (initial_cash.unwrap_or(config.default_initial_cash), margin_requirement.unwrap_or(config.default_margin_requirement));
+  }
+
+  // A supplied book carries its own cash and margin requirement, it only gains flat positions for tickers it does not list
+  fn starting_portfolio(tickers: &[String], initial_cash: f64, margin_requirement: f64, supplied: Option<Portfolio>) -> Portfolio {
+    match supplied {
+      Some(mut portfolio) => {
+        portfolio.ensure_tickers(tickers);
+        portfolio
+      }
+      None => Self::initial_portfolio(tickers, initial_cash, margin_requirement),
+    }
+  }
+
+  // Latest close per ticker as the risk manager saw it
+  fn current_prices(result: &HashMap<String, Value>) -> HashMap<String, f64> {
+    let risk_analysis = result.get("analyst_signals").and_then(|signals| signals.get("risk_management_agent")).and_then(Value::as_object);
+    risk_analysis.map(|analysis| analysis.iter()
+      .filter_map(|(ticker, analysis)| analysis.get("current_price").and_then(Value::as_f64).map(|price| (ticker.clone(), price)))
+      .collect()).unwrap_or_default()
+  }
+
+  fn apply_decisions(mut portfolio: Portfolio, result: &HashMap<String, Value>, prices: &HashMap<String, f64>, allow_fractional: bool) -> Portfolio {
+    // Decisions execute at the latest close the risk manager saw
+    let decisions = match result.get("decisions").and_then(Value::as_object) {
+      Some(decisions) => decisions,
+      None => return portfolio,
+    };
+
+    for (ticker, decision) in decisions {
+      let price = match prices.get(ticker) {
+        Some(price) => *price,
+        None => continue,
+      };
+      let action : Action = decision.get("action").and_then(|action| serde_json::from_value(action.clone()).ok()).unwrap_or(Action::Hold);
+      let quantity = decision.get("quantity").and_then(Value::as_f64).unwrap_or(0.0);
+      apply_decision(&mut portfolio, ticker, action, quantity, price, allow_fractional);
+    }
 
+    return portfolio;
+  }
 
+  // Missing dates default to the 90 days up to today
+  fn run_window(start_date: Option<&str>, end_date: Option<&str>) -> (String, String) {
     let end_date: String = match end_date {
       Some(date) => date.to_string(), 
       None => Local::now().format("%Y-%m-%d").to_string(),
@@ -74,45 +325,171 @@ impl HedgeFundServices {
         start_date_obj.format("%Y-%m-%d").to_string()
       }
     };
+    return (start_date, end_date);
+  }
+
+  // Analyst signals and their consensus without a portfolio-manager call, nothing is traded or recorded in the run history
+  pub async fn signals(&self, tickers: Vec<String>, start_date: Option<&str>, end_date: Option<&str>,
+                       initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>,
+                       selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions) -> Result<HashMap<String, Value>, Error> {
 
-    let mut portfolio = HashMap::new(); 
-    portfolio.insert("cash".to_string(), Value::from(initial_cash)); 
-    portfolio.insert("margin_requirement".to_string(), Value::from(margin_requirement)); 
-    portfolio.insert("margin_used".to_string(), Value::from(0.0)); 
-
-    let mut positions: HashMap<String, Value> = HashMap::new(); 
-    for ticker in &tickers {
-      let mut position: HashMap<String, Value> = HashMap::new(); 
-      position.insert("long".to_string(), Value::from(0)); 
-      position.insert("short".to_string(), Value::from(0));
-      position.insert("long_cost_basis".to_string(), Value::from(0.0)); 
-      position.insert("short_cost_basis".to_string(), Value::from(0.0)); 
-      position.insert("short_margin_used".to_string(), Value::from(0.0)); 
-      positions.insert(ticker.clone(), Value::Object(position.into_iter().collect())); 
+    let tickers = self.resolve_tickers(tickers, options.watchlist.as_deref())?;
+    if tickers.is_empty() {
+      return Err(ServiceError::InvalidInput("At least one ticker is required".to_string()).into());
     }
 
-    portfolio.insert("positions".to_string(), Value::Object(positions.into_iter().collect())); 
+    let (initial_cash, margin_requirement) : (f64, f64) = Self::starting_capital(initial_cash, margin_requirement, self.agent_service.config());
+    let (start_date, end_date) : (String, String) = Self::run_window(start_date, end_date);
+    // The risk manager still sizes its limits against a portfolio
+    let portfolio = Self::starting_portfolio(&tickers, initial_cash, margin_requirement, options.portfolio.clone());
 
-    let mut realized_gains: HashMap<String, Value> = HashMap::new();
-    for ticker in &tickers {
-      let mut gains : HashMap<String, Value> = HashMap::new(); 
-      gains.insert("long".to_string(), Value::from(0.0)); 
-      gains.insert("short".to_string(), Value::from(0.0)); 
-      realized_gains.insert("realized_gains".to_string(), Value::Object(gains.into_iter().collect())); 
+    return self.agent_service.run_signals(tickers, &start_date, &end_date, portfolio, show_reasoning, selected_analysts,
+                                          model_name.as_deref(), model_provider.as_deref(), options).await;
+  }
+
+  pub async fn hedge_fund(&self, tickers: Vec<String>, start_date: Option<&str>, end_date: Option<&str>, 
+                          initial_cash: Option<f64>, margin_requirement: Option<f64>, show_reasoning: Option<bool>, 
+                          selected_analysts: Option<Vec<String>>, model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions) -> Result<HashMap<String, Value>, Error> {
+    
+    let tickers = self.resolve_tickers(tickers, options.watchlist.as_deref())?;
+    if tickers.is_empty() {
+      return Err(ServiceError::InvalidInput("At least one ticker is required".to_string()).into());
     }
 
-    portfolio.insert("realized_gains".to_string(), Value::Object(realized_gains.into_iter().collect())); 
-  
-    return self.agent_service.run_hedge_fund(
-      tickers,
+    let (initial_cash, margin_requirement) : (f64, f64) = Self::starting_capital(initial_cash, margin_requirement, self.agent_service.config());
+    let (start_date, end_date) : (String, String) = Self::run_window(start_date, end_date);
+
+    let portfolio = Self::starting_portfolio(&tickers, initial_cash, margin_requirement, options.portfolio.clone());
+    let allow_fractional : bool = options.allow_fractional.unwrap_or(false);
+    let started_at = Instant::now();
+    let created_at = Utc::now();
+
+    let mut result = self.agent_service.run_hedge_fund(
+      tickers.clone(),
       &start_date,
       &end_date,
-      portfolio,
+      portfolio.clone(),
       show_reasoning,
-      selected_analysts,
+      selected_analysts.clone(),
       model_name.as_deref(),
       model_provider.as_deref(),
-    ).await;
+      options,
+    ).await?;
+
+    let prices : HashMap<String, f64> = Self::current_prices(&result);
+    let updated_portfolio = Self::apply_decisions(portfolio.clone(), &result, &prices, allow_fractional);
+    // Both books marked at the same closes, the starting one before any decision executes
+    result.insert("initial_valuation".to_string(), serde_json::to_value(portfolio_value(&portfolio, &prices))?);
+    result.insert("valuation".to_string(), serde_json::to_value(portfolio_value(&updated_portfolio, &prices))?);
+    result.insert("portfolio".to_string(), serde_json::to_value(&updated_portfolio)?);
+
+    self.run_store.record(RunRecord {
+      id: result.get("run_id").and_then(Value::as_str).unwrap_or_default().to_string(),
+      created_at,
+      duration_ms: started_at.elapsed().as_millis() as u64,
+      tickers,
+      start_date,
+      end_date,
+      model_name,
+      model_provider,
+      selected_analysts,
+      decisions: result.get("decisions").or_else(|| result.get("recommendations")).cloned().unwrap_or(Value::Null),
+      analyst_signals: result.get("analyst_signals").cloned().unwrap_or(Value::Null),
+    });
+    return Ok(result);
+  }
+
+  pub async fn backtest(&self, tickers: Vec<String>, start_date: &str, end_date: &str, cadence: Option<Cadence>,
+                        initial_cash: Option<f64>, margin_requirement: Option<f64>, selected_analysts: Option<Vec<String>>,
+                        model_name: Option<String>, model_provider: Option<String>, options: HedgeFundOptions,
+                        stop_loss: Option<StopLoss>) -> Result<BacktestResult, Error> {
+
+    let tickers = self.resolve_tickers(tickers, options.watchlist.as_deref())?;
+    if tickers.is_empty() {
+      return Err(ServiceError::InvalidInput("At least one ticker is required".to_string()).into());
+    }
+
+    let (initial_cash, margin_requirement) : (f64, f64) = Self::starting_capital(initial_cash, margin_requirement, self.agent_service.config());
+    let portfolio = Self::starting_portfolio(&tickers, initial_cash, margin_requirement, options.portfolio.clone());
+
+    let backtester = Backtester::new(&self.agent_service, tickers, start_date, end_date, cadence.unwrap_or_default(), portfolio,
+                                     selected_analysts, model_name, model_provider, options, stop_loss)?;
+    return backtester.run().await;
   }
 
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn supplied_portfolio_is_reconciled_with_the_run_tickers() {
+    let supplied : Portfolio = serde_json::from_value(json!({
+      "cash": 5000.0,
+      "margin_requirement": 0.25,
+      "margin_used": 0.0,
+      "positions": {
+        "AAPL": { "long": 10.0, "short": 0.0, "long_cost_basis": 150.0, "short_cost_basis": 0.0, "short_margin_used": 0.0 },
+      },
+      "realized_gains": {
+        "AAPL": { "long": 20.0, "short": 0.0 },
+      },
+    })).unwrap();
+
+    let tickers = vec!["AAPL".to_string(), "MSFT".to_string()];
+    let portfolio = HedgeFundServices::starting_portfolio(&tickers, 100000.0, 0.5, Some(supplied));
+
+    assert_eq!(portfolio.cash, 5000.0);
+    assert_eq!(portfolio.margin_requirement, 0.25);
+    assert_eq!(portfolio.positions["AAPL"].long, 10.0);
+    assert_eq!(portfolio.realized_gains["AAPL"].long, 20.0);
+    assert!(portfolio.positions.contains_key("MSFT"));
+    assert!(portfolio.realized_gains.contains_key("MSFT"));
+  }
+
+  #[test]
+  fn configured_defaults_fill_in_omitted_capital() {
+    let mut config = Config::for_tests();
+    config.default_initial_cash = 25000.0;
+    config.default_margin_requirement = 0.3;
+
+    assert_eq!(HedgeFundServices::starting_capital(None, None, &config), (25000.0, 0.3));
+    assert_eq!(HedgeFundServices::starting_capital(Some(5000.0), None, &config), (5000.0, 0.3));
+    assert_eq!(HedgeFundServices::starting_capital(None, Some(0.5), &config), (25000.0, 0.5));
+  }
+
+  #[test]
+  fn without_a_supplied_portfolio_the_run_starts_flat() {
+    let tickers = vec!["AAPL".to_string()];
+    let portfolio = HedgeFundServices::starting_portfolio(&tickers, 100000.0, 0.5, None);
+    assert_eq!(portfolio, Portfolio::new(&tickers, 100000.0, 0.5));
+  }
+
+  fn services_with_watchlists() -> HedgeFundServices {
+    return HedgeFundServices::new(AgentService::new(Config::for_tests()), Arc::new(RunStore::new(4)), Arc::new(Watchlists::new()));
+  }
+
+  fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+
+  #[test]
+  fn a_watchlist_expands_into_the_run_tickers() {
+    let services = services_with_watchlists();
+    assert_eq!(services.resolve_tickers(Vec::new(), Some("FAANG")).unwrap(), names(&["META", "AAPL", "AMZN", "NFLX", "GOOGL"]));
+    assert_eq!(services.resolve_tickers(names(&["TSLA", "aapl"]), Some("faang")).unwrap(), names(&["TSLA", "aapl", "META", "AMZN", "NFLX", "GOOGL"]));
+    assert_eq!(services.resolve_tickers(names(&["TSLA"]), None).unwrap(), names(&["TSLA"]));
+  }
+
+  #[test]
+  fn an_unknown_watchlist_is_a_bad_request() {
+    let error = services_with_watchlists().resolve_tickers(names(&["TSLA"]), Some("dow30")).unwrap_err();
+    let service_error = ServiceError::from_error(&error);
+    assert!(matches!(service_error, ServiceError::InvalidInput(_)), "{:?}", service_error);
+    assert_eq!(actix_web::ResponseError::status_code(&service_error), actix_web::http::StatusCode::BAD_REQUEST);
+    assert!(service_error.message().contains("dow30"));
+  }
+}
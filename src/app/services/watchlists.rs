@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Error};
+use serde::Serialize;
+
+// Used when no watchlist file is configured, entries in the file replace a built-in list of the same name
+const BUILTIN_WATCHLISTS: &[(&str, &[&str])] = &[
+  ("faang", &["META", "AAPL", "AMZN", "NFLX", "GOOGL"]),
+  ("magnificent7", &["AAPL", "MSFT", "GOOGL", "AMZN", "NVDA", "META", "TSLA"]),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Watchlist {
+  pub name: String,
+  pub tickers: Vec<String>,
+}
+
+// Named ticker baskets, names are matched case-insensitively
+pub struct Watchlists {
+  lists: BTreeMap<String, Vec<String>>,
+}
+
+impl Watchlists {
+  pub fn new() -> Self {
+    let mut lists = BTreeMap::new();
+    for (name, tickers) in BUILTIN_WATCHLISTS {
+      lists.insert(name.to_string(), tickers.iter().map(|ticker| ticker.to_string()).collect());
+    }
+    Watchlists { lists }
+  }
+
+  // The file holds a JSON object of name to tickers, e.g. {"dow30": ["AAPL", "AMGN", ...]}
+  pub fn load(path: Option<&Path>) -> Self {
+    let mut watchlists = Self::new();
+    let path = match path {
+      Some(path) => path,
+      None => return watchlists,
+    };
+
+    match Self::read_file(path) {
+      Ok(lists) => {
+        log::info!("Loaded {} watchlists from {}", lists.len(), path.display());
+        for (name, tickers) in lists {
+          watchlists.insert(&name, tickers);
+        }
+      }
+      Err(e) => log::error!("Failed to load watchlists from {}, using the built-in lists: {:#}", path.display(), e),
+    }
+    watchlists
+  }
+
+  fn read_file(path: &Path) -> Result<BTreeMap<String, Vec<String>>, Error> {
+    let contents = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    serde_json::from_str(&contents).context("expected an object of watchlist name to ticker array")
+  }
+
+  fn insert(&mut self, name: &str, tickers: Vec<String>) {
+    let tickers = tickers.iter()
+      .map(|ticker| ticker.trim().to_uppercase())
+      .filter(|ticker| !ticker.is_empty())
+      .collect();
+    self.lists.insert(name.trim().to_lowercase(), tickers);
+  }
+
+  pub fn get(&self, name: &str) -> Option<&[String]> {
+    self.lists.get(&name.trim().to_lowercase()).map(Vec::as_slice)
+  }
+
+  pub fn list(&self) -> Vec<Watchlist> {
+    self.lists.iter().map(|(name, tickers)| Watchlist { name: name.clone(), tickers: tickers.clone() }).collect()
+  }
+
+  // Explicit tickers come first, the watchlist only adds the ones not already requested
+  pub fn expand(&self, tickers: Vec<String>, name: &str) -> Option<Vec<String>> {
+    let mut expanded = tickers;
+    for ticker in self.get(name)? {
+      if !expanded.iter().any(|requested| requested.eq_ignore_ascii_case(ticker)) {
+        expanded.push(ticker.clone());
+      }
+    }
+    Some(expanded)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn a_watchlist_file_adds_lists_and_replaces_builtins() {
+    let path = std::env::temp_dir().join(format!("watchlists-{}.json", std::process::id()));
+    fs::write(&path, r#"{"Dow3": [" aapl", "MSFT", ""], "faang": ["META"]}"#).unwrap();
+    let watchlists = Watchlists::load(Some(&path));
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(watchlists.get("dow3").unwrap(), ["AAPL", "MSFT"]);
+    assert_eq!(watchlists.get("FAANG").unwrap(), ["META"]);
+    assert!(watchlists.get("magnificent7").is_some());
+    assert!(watchlists.get("dow30").is_none());
+  }
+
+  #[test]
+  fn an_unreadable_watchlist_file_keeps_the_builtins() {
+    let watchlists = Watchlists::load(Some(Path::new("/nonexistent/watchlists.json")));
+    assert_eq!(watchlists.list().len(), BUILTIN_WATCHLISTS.len());
+  }
+}
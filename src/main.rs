@@ -1,8 +1,16 @@
 use actix_web::HttpServer;
 use std::env; 
+use std::path::Path;
+use std::sync::Arc;
 
 use crate::app::config::Config;
 use crate::app::factory::CreateApp;
+use crate::app::services::idempotency::IdempotencyStore;
+use crate::app::services::run_store::RunStore;
+use crate::app::services::watchlists::Watchlists;
+use crate::ai_agent::data::cache;
+use crate::ai_agent::llm::models::refresh_ollama_models;
+use crate::ai_agent::llm::prompt::load_prompt_templates;
 
 mod app; 
 mod ai_agent;
@@ -12,14 +20,28 @@ async fn main() -> std::io::Result<()> {
   if env::var_os("RUST_LOG").is_none() {
     env::set_var("RUST_LOG", "actix_web=debug,debug"); // Default to info for actix_web and your app
   }
-  env_logger::init();
+  // Spans (run, agent, ticker) are attached to every event; `log` records are bridged in.
+  tracing_subscriber::fmt()
+    .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+    .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+    .init();
 
   dotenv::dotenv().ok();
 
   let config : Config = Config::load();
 
+  cache::init_cache(config.cache_file.as_deref().map(Path::new), config.cache_ttl);
+  let cache_file : Option<String> = config.cache_file.clone();
+  load_prompt_templates(Path::new(&config.prompts_dir));
+
+  refresh_ollama_models().await;
+
+  let run_store : Arc<RunStore> = Arc::new(RunStore::new(config.run_history_size));
+  let watchlists : Arc<Watchlists> = Arc::new(Watchlists::load(config.watchlist_file.as_deref().map(Path::new)));
+  let idempotency : Arc<IdempotencyStore> = Arc::new(IdempotencyStore::new(config.idempotency_ttl));
+
   let server_builder = HttpServer::new(move || {
-    let factory: CreateApp = CreateApp::new(config.clone());
+    let factory: CreateApp = CreateApp::new(config.clone(), run_store.clone(), watchlists.clone(), idempotency.clone());
     factory.build_app().wrap(actix_web::middleware::Logger::default())
   });
 
@@ -27,5 +49,13 @@ async fn main() -> std::io::Result<()> {
 
   server.run().await?;
 
+  // run() resolves once the server has shut down gracefully
+  if let Some(cache_file) = cache_file {
+    match cache::save_cache_to_disk(Path::new(&cache_file)) {
+      Ok(_) => log::info!("Saved cache to {}", cache_file),
+      Err(e) => log::error!("Failed to save cache to {}: {:#}", cache_file, e),
+    }
+  }
+
   Ok(())
 }
\ No newline at end of file